@@ -1,4 +1,3 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -14,19 +13,55 @@ use opentelemetry_prometheus::PrometheusExporter;
 #[cfg(feature = "metrics")]
 use prometheus::{Encoder, TextEncoder};
 
+use garage_model::admin_token_table::*;
+use garage_model::audit_table::*;
 use garage_model::garage::Garage;
+use garage_table::EmptyKey;
+use garage_util::config::{ClientAddrConfig, UnixOrTCPSocketAddress};
 use garage_util::error::Error as GarageError;
 
 use crate::generic_server::*;
 
+use crate::admin::audit::*;
 use crate::admin::bucket::*;
 use crate::admin::cluster::*;
 use crate::admin::error::*;
 use crate::admin::key::*;
-use crate::admin::router::{Authorization, Endpoint};
+use crate::admin::maintenance::*;
+use crate::admin::object::*;
+use crate::admin::router::Endpoint;
+use crate::admin::token::*;
+
+/// Name under which operations made with the admin token
+/// of the configuration file are recorded in the audit log.
+/// Names starting with `@` cannot be given to scoped tokens.
+const CONFIG_ADMIN_TOKEN_NAME: &str = "@admin_token";
+
+/// Fields of a request body that must not be recorded in the audit log
+const AUDIT_SECRET_FIELDS: &[&str] = &["secretAccessKey"];
+
+/// Parameters that designate the target of an operation in the audit log,
+/// by order of preference
+const AUDIT_TARGET_FIELDS: &[&str] = &[
+	"id",
+	"bucketId",
+	"accessKeyId",
+	"globalAlias",
+	"name",
+	"blockHashes",
+];
+
+/// Identity of the client of an admin API request
+struct Caller {
+	/// Name of the admin token that was used
+	name: String,
+	/// Scopes of the admin token that was used
+	scopes: AdminScopes,
+}
 
 pub struct AdminApiServer {
 	garage: Arc<Garage>,
+	maintenance: Arc<dyn NodeMaintenance>,
 	#[cfg(feature = "metrics")]
 	exporter: PrometheusExporter,
 	metrics_token: Option<String>,
@@ -36,19 +71,15 @@ pub struct AdminApiServer {
 impl AdminApiServer {
 	pub fn new(
 		garage: Arc<Garage>,
+		maintenance: Arc<dyn NodeMaintenance>,
 		#[cfg(feature = "metrics")] exporter: PrometheusExporter,
 	) -> Self {
 		let cfg = &garage.config.admin;
-		let metrics_token = cfg
-			.metrics_token
-			.as_ref()
-			.map(|tok| format!("Bearer {}", tok));
-		let admin_token = cfg
-			.admin_token
-			.as_ref()
-			.map(|tok| format!("Bearer {}", tok));
+		let metrics_token = cfg.metrics_token.clone();
+		let admin_token = cfg.admin_token.clone();
 		Self {
 			garage,
+			maintenance,
 			#[cfg(feature = "metrics")]
 			exporter,
 			metrics_token,
@@ -58,12 +89,19 @@ impl AdminApiServer {
 
 	pub async fn run(
 		self,
-		bind_addr: SocketAddr,
+		bind_addr: UnixOrTCPSocketAddress,
+		unix_socket_mode: Option<u32>,
+		client_addr_config: ClientAddrConfig,
 		shutdown_signal: impl Future<Output = ()>,
 	) -> Result<(), GarageError> {
 		let region = self.garage.config.s3_api.s3_region.clone();
 		ApiServer::new(region, self)
-			.run_server(bind_addr, shutdown_signal)
+			.run_server(
+				bind_addr,
+				unix_socket_mode,
+				client_addr_config,
+				shutdown_signal,
+			)
 			.await
 	}
 
@@ -76,6 +114,112 @@ impl AdminApiServer {
 			.body(Body::empty())?)
 	}
 
+	/// Check that the request is authorized to access the given scope,
+	/// and return the name and the scopes of the token it was made with.
+	///
+	/// The admin token from the configuration file gives access to all scopes,
+	/// and the metrics token gives access to the metrics only. Other tokens
+	/// are looked up in the admin token table.
+	async fn check_authorization(
+		&self,
+		req: &Request<Body>,
+		scope: AdminScope,
+	) -> Result<Caller, Error> {
+		let metrics_caller = || Caller {
+			name: "@metrics_token".into(),
+			scopes: AdminScopes::new(vec![AdminScope::Metrics]),
+		};
+		if scope == AdminScope::Metrics && self.metrics_token.is_none() {
+			return Ok(metrics_caller());
+		}
+
+		let token = match req.headers().get("Authorization") {
+			None => {
+				if self.admin_token.is_none() && scope != AdminScope::Metrics {
+					return Err(Error::forbidden(
+						"Admin token isn't configured, admin API access is disabled for security.",
+					));
+				}
+				return Err(Error::forbidden("Authorization token must be provided"));
+			}
+			Some(v) => v
+				.to_str()
+				.ok()
+				.and_then(|hv| hv.trim().strip_prefix("Bearer "))
+				.map(str::trim)
+				.ok_or_else(|| Error::forbidden("Invalid authorization token provided"))?,
+		};
+
+		if self.admin_token.as_deref() == Some(token) {
+			return Ok(Caller {
+				name: CONFIG_ADMIN_TOKEN_NAME.into(),
+				scopes: AdminScopes::new(AdminScope::ALL.to_vec()),
+			});
+		}
+		if scope == AdminScope::Metrics && self.metrics_token.as_deref() == Some(token) {
+			return Ok(metrics_caller());
+		}
+
+		let caller = match AdminApiToken::parse(token) {
+			Some((id, secret)) => self
+				.garage
+				.admin_token_table
+				.get(&EmptyKey, &id.to_string())
+				.await?
+				.and_then(|t| {
+					Some(Caller {
+						scopes: t.check_secret(secret)?.clone(),
+						name: t.params()?.name.get().clone(),
+					})
+				}),
+			None => None,
+		};
+		match caller {
+			Some(caller) if caller.scopes.contains(scope) => Ok(caller),
+			Some(_) => Err(Error::forbidden(format!(
+				"This token does not have the `{}` scope",
+				scope.name()
+			))),
+			None => Err(Error::forbidden("Invalid authorization token provided")),
+		}
+	}
+
+	/// Build the audit log entry of a request, from the parameters given
+	/// in its URL and in its JSON body
+	fn audit_entry(&self, endpoint: &Endpoint, caller: &Caller, body: &[u8]) -> AuditEntry {
+		let mut parameters = endpoint
+			.url_parameters()
+			.into_iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect::<Vec<_>>();
+		if let Ok(serde_json::Value::Object(fields)) = serde_json::from_slice(body) {
+			for (name, value) in fields {
+				if AUDIT_SECRET_FIELDS.contains(&name.as_str()) || value.is_null() {
+					continue;
+				}
+				let value = match value {
+					serde_json::Value::String(s) => s,
+					v => v.to_string(),
+				};
+				parameters.push((name, value));
+			}
+		}
+
+		let target = AUDIT_TARGET_FIELDS
+			.iter()
+			.find_map(|f| parameters.iter().position(|(k, _)| k == f))
+			.map(|i| parameters.remove(i).1)
+			.unwrap_or_default();
+
+		let mut entry = self.garage.audit_entry(
+			AuditActor::AdminApi(caller.name.clone()),
+			endpoint.name(),
+			&target,
+		);
+		entry.parameters = parameters;
+		entry
+	}
+
 	fn handle_metrics(&self) -> Result<Response<Body>, Error> {
 		#[cfg(feature = "metrics")]
 		{
@@ -122,30 +266,26 @@ impl ApiHandler for AdminApiServer {
 		req: Request<Body>,
 		endpoint: Endpoint,
 	) -> Result<Response<Body>, Error> {
-		let expected_auth_header =
-			match endpoint.authorization_type() {
-				Authorization::MetricsToken => self.metrics_token.as_ref(),
-				Authorization::AdminToken => match &self.admin_token {
-					None => return Err(Error::forbidden(
-						"Admin token isn't configured, admin API access is disabled for security.",
-					)),
-					Some(t) => Some(t),
-				},
-			};
-
-		if let Some(h) = expected_auth_header {
-			match req.headers().get("Authorization") {
-				None => return Err(Error::forbidden("Authorization token must be provided")),
-				Some(v) => {
-					let authorized = v.to_str().map(|hv| hv.trim() == h).unwrap_or(false);
-					if !authorized {
-						return Err(Error::forbidden("Invalid authorization token provided"));
-					}
-				}
-			}
-		}
+		let caller = match endpoint.required_scope() {
+			Some(scope) => self.check_authorization(&req, scope).await?,
+			None => Caller {
+				name: String::new(),
+				scopes: AdminScopes::default(),
+			},
+		};
+
+		// Keep a copy of the body of requests that are recorded in the
+		// audit log, they are small JSON documents
+		let (req, audit) = if endpoint.is_audited() {
+			let (parts, body) = req.into_parts();
+			let body = hyper::body::to_bytes(body).await?;
+			let audit = self.audit_entry(&endpoint, &caller, &body);
+			(Request::from_parts(parts, Body::from(body)), Some(audit))
+		} else {
+			(req, None)
+		};
 
-		match endpoint {
+		let resp = match endpoint {
 			Endpoint::Options => self.handle_options(&req),
 			Endpoint::Metrics => self.handle_metrics(),
 			Endpoint::GetClusterStatus => handle_get_cluster_status(&self.garage).await,
@@ -155,6 +295,7 @@ impl ApiHandler for AdminApiServer {
 			Endpoint::UpdateClusterLayout => handle_update_cluster_layout(&self.garage, req).await,
 			Endpoint::ApplyClusterLayout => handle_apply_cluster_layout(&self.garage, req).await,
 			Endpoint::RevertClusterLayout => handle_revert_cluster_layout(&self.garage, req).await,
+			Endpoint::SimulateClusterLayout => handle_simulate_cluster_layout(&self.garage).await,
 			// Keys
 			Endpoint::ListKeys => handle_list_keys(&self.garage).await,
 			Endpoint::GetKeyInfo { id, search } => {
@@ -163,6 +304,7 @@ impl ApiHandler for AdminApiServer {
 			Endpoint::CreateKey => handle_create_key(&self.garage, req).await,
 			Endpoint::ImportKey => handle_import_key(&self.garage, req).await,
 			Endpoint::UpdateKey { id } => handle_update_key(&self.garage, id, req).await,
+			Endpoint::RotateKey { id } => handle_rotate_key(&self.garage, id, req).await,
 			Endpoint::DeleteKey { id } => handle_delete_key(&self.garage, id).await,
 			// Buckets
 			Endpoint::ListBuckets => handle_list_buckets(&self.garage).await,
@@ -196,7 +338,63 @@ impl ApiHandler for AdminApiServer {
 				access_key_id,
 				alias,
 			} => handle_local_unalias_bucket(&self.garage, id, access_key_id, alias).await,
+			// Objects
+			Endpoint::GetObjectInfo { bucket, key } => {
+				handle_get_object_info(&self.garage, bucket, key).await
+			}
+			// Admin tokens
+			Endpoint::ListAdminTokens => handle_list_admin_tokens(&self.garage).await,
+			Endpoint::CreateAdminToken => {
+				handle_create_admin_token(&self.garage, &caller.scopes, req).await
+			}
+			Endpoint::GetAdminTokenInfo { id } => {
+				handle_get_admin_token_info(&self.garage, id).await
+			}
+			Endpoint::DeleteAdminToken { id } => handle_delete_admin_token(&self.garage, id).await,
+			// Audit log
+			Endpoint::ListAuditLog {
+				since,
+				until,
+				actor,
+				limit,
+			} => handle_list_audit_log(&self.garage, since, until, actor, limit).await,
+			// Maintenance
+			Endpoint::ListWorkers { node } => {
+				handle_list_workers(&self.garage, self.maintenance.as_ref(), node).await
+			}
+			Endpoint::SetWorkerVariable { node } => {
+				handle_set_worker_variable(&self.garage, self.maintenance.as_ref(), node, req).await
+			}
+			Endpoint::LaunchRepair { node } => {
+				handle_launch_repair(&self.garage, self.maintenance.as_ref(), node, req).await
+			}
+			Endpoint::ControlScrub { node } => {
+				handle_control_scrub(&self.garage, self.maintenance.as_ref(), node, req).await
+			}
+			Endpoint::GetScrubReports { node } => {
+				handle_get_scrub_reports(&self.garage, self.maintenance.as_ref(), node).await
+			}
+			Endpoint::GetNodeStatistics { node, detailed } => {
+				handle_get_node_statistics(&self.garage, self.maintenance.as_ref(), node, detailed)
+					.await
+			}
+			// Blocks
+			Endpoint::ListBlockErrors { node } => {
+				handle_list_block_errors(&self.garage, self.maintenance.as_ref(), node).await
+			}
+			Endpoint::GetBlockInfo { node, hash } => {
+				handle_get_block_info(&self.garage, self.maintenance.as_ref(), node, hash).await
+			}
+			Endpoint::RetryBlockResync { node } => {
+				handle_retry_block_resync(&self.garage, self.maintenance.as_ref(), node, req).await
+			}
+			Endpoint::PurgeBlocks => handle_purge_blocks(self.maintenance.as_ref(), req).await,
+		};
+
+		if let (Ok(_), Some(audit)) = (&resp, audit) {
+			self.garage.record_audit(audit).await;
 		}
+		resp
 	}
 }
 
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyper::{Body, Response};
+use serde::Serialize;
+
+use garage_table::*;
+use garage_util::time::*;
+
+use garage_model::audit_table::*;
+use garage_model::garage::Garage;
+
+use crate::admin::error::*;
+use crate::helpers::json_ok_response;
+
+/// Number of entries returned if no limit is specified
+const DEFAULT_AUDIT_LOG_LIMIT: usize = 100;
+
+pub async fn handle_list_audit_log(
+	garage: &Arc<Garage>,
+	since: Option<String>,
+	until: Option<String>,
+	actor: Option<String>,
+	limit: Option<usize>,
+) -> Result<Response<Body>, Error> {
+	let since = since.map(|d| parse_date(&d)).transpose()?.unwrap_or(0);
+	let filter = AuditFilter {
+		until: until.map(|d| parse_date(&d)).transpose()?,
+		actor,
+	};
+
+	let res = garage
+		.audit_table
+		.get_range(
+			&EmptyKey,
+			Some(AuditEntry::sort_key_prefix(since)),
+			Some(filter),
+			limit.unwrap_or(DEFAULT_AUDIT_LOG_LIMIT),
+			EnumerationOrder::Forward,
+		)
+		.await?
+		.into_iter()
+		.map(|e| AuditLogResultItem {
+			timestamp: msec_to_rfc3339(e.timestamp),
+			node: hex::encode(e.node),
+			actor: e.actor.to_string(),
+			operation: e.operation,
+			target: e.target,
+			parameters: e.parameters.into_iter().collect(),
+		})
+		.collect::<Vec<_>>();
+
+	Ok(json_ok_response(&res)?)
+}
+
+fn parse_date(date: &str) -> Result<u64, Error> {
+	rfc3339_to_msec(date).ok_or_else(|| {
+		Error::bad_request(format!("Invalid date (expected RFC3339 format): {}", date))
+	})
+}
+
+#[derive(Serialize)]
+struct AuditLogResultItem {
+	timestamp: String,
+	node: String,
+	actor: String,
+	operation: String,
+	target: String,
+	parameters: HashMap<String, String>,
+}
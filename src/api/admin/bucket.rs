@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryInto;
 use std::sync::Arc;
 
 use hyper::{Body, Request, Response, StatusCode};
@@ -17,7 +18,7 @@ use garage_model::permission::*;
 use garage_model::s3::object_table::*;
 
 use crate::admin::error::*;
-use crate::admin::key::ApiBucketKeyPerm;
+use crate::admin::key::{ApiBucketKeyPerm, ApiRateLimits};
 use crate::common_error::CommonError;
 use crate::helpers::{json_ok_response, parse_json_body};
 
@@ -192,11 +193,7 @@ async fn bucket_info_results(
 						permissions: p
 							.authorized_buckets
 							.get(&bucket.id)
-							.map(|p| ApiBucketKeyPerm {
-								read: p.allow_read,
-								write: p.allow_write,
-								owner: p.allow_owner,
-							})
+							.map(ApiBucketKeyPerm::from)
 							.unwrap_or_default(),
 						bucket_local_aliases: p
 							.local_aliases
@@ -218,6 +215,13 @@ async fn bucket_info_results(
 				max_size: quotas.max_size,
 				max_objects: quotas.max_objects,
 			},
+			rate_limits: (*state.rate_limits.get()).into(),
+			erasure_coding: (*state.erasure_coding.get()).into(),
+			content_defined_chunking: (*state.content_defined_chunking.get()).into(),
+			compression: (*state.compression.get()).into(),
+			cold_tier: ApiColdTier {
+				after_days: *state.cold_tier_after_days.get(),
+			},
 		};
 
 	Ok(json_ok_response(&res)?)
@@ -236,6 +240,76 @@ struct GetBucketInfoResult {
 	bytes: i64,
 	unfinished_uploads: i64,
 	quotas: ApiBucketQuotas,
+	rate_limits: ApiRateLimits,
+	erasure_coding: ApiBucketOverride,
+	content_defined_chunking: ApiBucketOverride,
+	compression: ApiBucketCompression,
+	cold_tier: ApiColdTier,
+}
+
+/// When the data blocks of objects of a bucket are moved to the cold tier
+/// (`after_days` = None: never)
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiColdTier {
+	after_days: Option<u64>,
+}
+
+/// Bucket setting that enables or disables a feature configured on the cluster,
+/// or uses the default of the cluster (e.g. erasure coding)
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum ApiBucketOverride {
+	Enabled,
+	Disabled,
+	/// Use the default of the cluster
+	Default,
+}
+
+impl From<Option<bool>> for ApiBucketOverride {
+	fn from(ec: Option<bool>) -> Self {
+		match ec {
+			Some(true) => Self::Enabled,
+			Some(false) => Self::Disabled,
+			None => Self::Default,
+		}
+	}
+}
+
+impl From<ApiBucketOverride> for Option<bool> {
+	fn from(ec: ApiBucketOverride) -> Self {
+		match ec {
+			ApiBucketOverride::Enabled => Some(true),
+			ApiBucketOverride::Disabled => Some(false),
+			ApiBucketOverride::Default => None,
+		}
+	}
+}
+
+/// Compression of the data blocks of a bucket: a zstd level, `"none"`,
+/// or `"default"` to use the compression level of the cluster
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum ApiBucketCompression {
+	Level(i32),
+	Mode(ApiBucketCompressionMode),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum ApiBucketCompressionMode {
+	None,
+	Default,
+}
+
+impl From<Option<BucketCompression>> for ApiBucketCompression {
+	fn from(c: Option<BucketCompression>) -> Self {
+		match c {
+			Some(BucketCompression::Level(level)) => Self::Level(level),
+			Some(BucketCompression::Off) => Self::Mode(ApiBucketCompressionMode::None),
+			None => Self::Mode(ApiBucketCompressionMode::Default),
+		}
+	}
 }
 
 #[derive(Serialize)]
@@ -309,19 +383,16 @@ pub async fn handle_create_bucket(
 			.set_local_bucket_alias(bucket.id, &la.access_key_id, &la.alias)
 			.await?;
 
-		if la.allow.read || la.allow.write || la.allow.owner {
+		let caps = la.allow.capabilities();
+		if !caps.is_empty() {
+			let mut perm = BucketKeyPerm {
+				timestamp: now_msec(),
+				..BucketKeyPerm::NO_PERMISSIONS
+			};
+			perm.allow(&caps, &la.allow.prefixes)?;
 			garage
 				.bucket_helper()
-				.set_bucket_key_permissions(
-					bucket.id,
-					&la.access_key_id,
-					BucketKeyPerm {
-						timestamp: now_msec(),
-						allow_read: la.allow.read,
-						allow_write: la.allow.write,
-						allow_owner: la.allow.owner,
-					},
-				)
+				.set_bucket_key_permissions(bucket.id, &la.access_key_id, perm)
 				.await?;
 		}
 	}
@@ -434,6 +505,51 @@ pub async fn handle_update_bucket(
 		});
 	}
 
+	if let Some(rl) = req.rate_limits {
+		state.rate_limits.update(rl.try_into()?);
+	}
+
+	if let Some(ec) = req.erasure_coding {
+		if ec == ApiBucketOverride::Enabled && garage.config.erasure_coding.is_none() {
+			return Err(Error::bad_request(
+				"Erasure coding is not configured on this cluster.",
+			));
+		}
+		state.erasure_coding.update(ec.into());
+	}
+
+	if let Some(cdc) = req.content_defined_chunking {
+		if cdc == ApiBucketOverride::Enabled && garage.config.content_defined_chunking.is_none() {
+			return Err(Error::bad_request(
+				"Content-defined chunking is not configured on this cluster.",
+			));
+		}
+		state.content_defined_chunking.update(cdc.into());
+	}
+
+	if let Some(c) = req.compression {
+		let compression = match c {
+			ApiBucketCompression::Level(level) => Some(
+				BucketCompression::level(level)
+					.ok_or_bad_request(format!("Invalid compression level: {}", level))?,
+			),
+			ApiBucketCompression::Mode(ApiBucketCompressionMode::None) => {
+				Some(BucketCompression::Off)
+			}
+			ApiBucketCompression::Mode(ApiBucketCompressionMode::Default) => None,
+		};
+		state.compression.update(compression);
+	}
+
+	if let Some(cold_tier) = req.cold_tier {
+		if cold_tier.after_days.is_some() && garage.config.cold_tier.is_none() {
+			return Err(Error::bad_request(
+				"No cold tier is configured on this cluster.",
+			));
+		}
+		state.cold_tier_after_days.update(cold_tier.after_days);
+	}
+
 	garage.bucket_table.insert(&bucket).await?;
 
 	bucket_info_results(garage, bucket_id).await
@@ -444,6 +560,11 @@ pub async fn handle_update_bucket(
 struct UpdateBucketRequest {
 	website_access: Option<UpdateBucketWebsiteAccess>,
 	quotas: Option<ApiBucketQuotas>,
+	rate_limits: Option<ApiRateLimits>,
+	erasure_coding: Option<ApiBucketOverride>,
+	content_defined_chunking: Option<ApiBucketOverride>,
+	compression: Option<ApiBucketCompression>,
+	cold_tier: Option<ApiColdTier>,
 }
 
 #[derive(Deserialize)]
@@ -482,14 +603,11 @@ pub async fn handle_bucket_change_key_perm(
 		.cloned()
 		.unwrap_or(BucketKeyPerm::NO_PERMISSIONS);
 
-	if req.permissions.read {
-		perm.allow_read = new_perm_flag;
-	}
-	if req.permissions.write {
-		perm.allow_write = new_perm_flag;
-	}
-	if req.permissions.owner {
-		perm.allow_owner = new_perm_flag;
+	let p = &req.permissions;
+	if new_perm_flag {
+		perm.allow(&p.capabilities(), &p.prefixes)?;
+	} else {
+		perm.deny(&p.capabilities(), &p.prefixes)?;
 	}
 
 	garage
@@ -9,6 +9,8 @@ use garage_util::crdt::*;
 use garage_util::data::*;
 
 use garage_rpc::layout::*;
+use garage_rpc::ring::PARTITION_BITS;
+use garage_rpc::system::DiskSpace;
 
 use garage_model::garage::Garage;
 
@@ -33,6 +35,8 @@ pub async fn handle_get_cluster_status(garage: &Arc<Garage>) -> Result<Response<
 						is_up: i.is_up,
 						last_seen_secs_ago: i.last_seen_secs_ago,
 						hostname: i.status.hostname,
+						data_partition: i.status.data_disk_avail.map(Into::into),
+						metadata_partition: i.status.meta_disk_avail.map(Into::into),
 					},
 				)
 			})
@@ -126,6 +130,23 @@ struct KnownNodeResp {
 	is_up: bool,
 	last_seen_secs_ago: Option<u64>,
 	hostname: String,
+	data_partition: Option<FreeSpaceResp>,
+	metadata_partition: Option<FreeSpaceResp>,
+}
+
+#[derive(Serialize)]
+struct FreeSpaceResp {
+	available: u64,
+	total: u64,
+}
+
+impl From<DiskSpace> for FreeSpaceResp {
+	fn from(space: DiskSpace) -> Self {
+		Self {
+			available: space.available,
+			total: space.total,
+		}
+	}
 }
 
 pub async fn handle_update_cluster_layout(
@@ -162,7 +183,7 @@ pub async fn handle_apply_cluster_layout(
 	let param = parse_json_body::<ApplyRevertLayoutRequest>(req).await?;
 
 	let layout = garage.system.get_cluster_layout();
-	let layout = layout.apply_staged_changes(Some(param.version))?;
+	let layout = layout.apply_staged_changes(Some(param.version), true)?;
 	garage.system.update_cluster_layout(&layout).await?;
 
 	Ok(Response::builder()
@@ -185,6 +206,58 @@ pub async fn handle_revert_cluster_layout(
 		.body(Body::empty())?)
 }
 
+pub async fn handle_simulate_cluster_layout(garage: &Arc<Garage>) -> Result<Response<Body>, Error> {
+	let layout = garage.system.get_cluster_layout();
+	let sizes = garage.block_manager.estimate_partition_sizes().await?;
+	let simulation = layout.simulate_staged_changes(sizes.as_deref())?;
+
+	let res = SimulateClusterLayoutResponse {
+		new_version: simulation.new_version,
+		moved_partitions: simulation.moved_partitions,
+		total_partitions: 1 << PARTITION_BITS,
+		zone_redundant: simulation.zone_redundant,
+		bytes_to_transfer: simulation.nodes.iter().map(|n| n.bytes_gained).sum(),
+		nodes: simulation
+			.nodes
+			.into_iter()
+			.map(|n| NodeLayoutChangeResponse {
+				id: hex::encode(n.id),
+				partitions_before: n.partitions_before,
+				partitions_after: n.partitions_after,
+				partitions_gained: n.partitions_gained,
+				partitions_lost: n.partitions_lost,
+				bytes_gained: n.bytes_gained,
+				bytes_lost: n.bytes_lost,
+			})
+			.collect(),
+	};
+
+	Ok(json_ok_response(&res)?)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulateClusterLayoutResponse {
+	new_version: u64,
+	moved_partitions: usize,
+	total_partitions: usize,
+	zone_redundant: bool,
+	bytes_to_transfer: Option<u64>,
+	nodes: Vec<NodeLayoutChangeResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeLayoutChangeResponse {
+	id: String,
+	partitions_before: usize,
+	partitions_after: usize,
+	partitions_gained: usize,
+	partitions_lost: usize,
+	bytes_gained: Option<u64>,
+	bytes_lost: Option<u64>,
+}
+
 type UpdateClusterLayoutRequest = HashMap<String, Option<NodeRole>>;
 
 #[derive(Deserialize)]
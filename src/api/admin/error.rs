@@ -27,6 +27,14 @@ pub enum Error {
 		_0
 	)]
 	KeyAlreadyExists(String),
+
+	/// The admin API token does not exist
+	#[error(display = "Admin token not found: {}", _0)]
+	NoSuchAdminToken(String),
+
+	/// The object does not exist in the bucket
+	#[error(display = "Object not found: {}", _0)]
+	NoSuchObject(String),
 }
 
 impl<T> From<T> for Error
@@ -58,6 +66,8 @@ impl Error {
 			Error::Common(c) => c.aws_code(),
 			Error::NoSuchAccessKey(_) => "NoSuchAccessKey",
 			Error::KeyAlreadyExists(_) => "KeyAlreadyExists",
+			Error::NoSuchAdminToken(_) => "NoSuchAdminToken",
+			Error::NoSuchObject(_) => "NoSuchKey",
 		}
 	}
 }
@@ -67,7 +77,9 @@ impl ApiError for Error {
 	fn http_status_code(&self) -> StatusCode {
 		match self {
 			Error::Common(c) => c.http_status_code(),
-			Error::NoSuchAccessKey(_) => StatusCode::NOT_FOUND,
+			Error::NoSuchAccessKey(_) | Error::NoSuchAdminToken(_) | Error::NoSuchObject(_) => {
+				StatusCode::NOT_FOUND
+			}
 			Error::KeyAlreadyExists(_) => StatusCode::CONFLICT,
 		}
 	}
@@ -1,13 +1,18 @@
 use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::sync::Arc;
 
 use hyper::{Body, Request, Response, StatusCode};
-use serde::{Deserialize, Serialize};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use garage_table::*;
+use garage_util::time::*;
 
 use garage_model::garage::Garage;
 use garage_model::key_table::*;
+use garage_model::permission::{BucketCapability, BucketKeyPerm};
+use garage_model::rate_limits::RateLimits;
 
 use crate::admin::error::*;
 use crate::helpers::{json_ok_response, parse_json_body};
@@ -86,7 +91,16 @@ pub async fn handle_import_key(
 		return Err(Error::KeyAlreadyExists(req.access_key_id.to_string()));
 	}
 
-	let imported_key = Key::import(&req.access_key_id, &req.secret_access_key, &req.name);
+	let mut imported_key = Key::import(&req.access_key_id, &req.secret_access_key, &req.name);
+	let key_state = imported_key.state.as_option_mut().unwrap();
+	if let Some(enabled) = req.enabled {
+		key_state.enabled.update(enabled);
+	}
+	if let Some(expires_at) = req.expires_at {
+		key_state
+			.expires_at
+			.update(Some(parse_expiration_date(&expires_at)?));
+	}
 	garage.key_table.insert(&imported_key).await?;
 
 	key_info_results(garage, imported_key).await
@@ -98,6 +112,8 @@ struct ImportKeyRequest {
 	access_key_id: String,
 	secret_access_key: String,
 	name: String,
+	enabled: Option<bool>,
+	expires_at: Option<String>,
 }
 
 pub async fn handle_update_key(
@@ -124,6 +140,27 @@ pub async fn handle_update_key(
 			key_state.allow_create_bucket.update(false);
 		}
 	}
+	if let Some(enabled) = req.enabled {
+		key_state.enabled.update(enabled);
+	}
+	if let Some(expires_at) = req.expires_at {
+		let expires_at = expires_at.map(|d| parse_expiration_date(&d)).transpose()?;
+		key_state.expires_at.update(expires_at);
+	}
+	if let Some(rl) = req.rate_limits {
+		key_state.rate_limits.update(rl.try_into()?);
+	}
+	if let Some(allowed_ips) = req.allowed_ips {
+		let allowed_ips = allowed_ips
+			.iter()
+			.map(|net| {
+				net.parse::<IpNetwork>().map_err(|_| {
+					Error::bad_request(format!("Invalid IP address or network: {}", net))
+				})
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		key_state.allowed_ips.update(IpAllowlist(allowed_ips));
+	}
 
 	garage.key_table.insert(&key).await?;
 
@@ -131,10 +168,72 @@ pub async fn handle_update_key(
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct UpdateKeyRequest {
 	name: Option<String>,
 	allow: Option<KeyPerm>,
 	deny: Option<KeyPerm>,
+	enabled: Option<bool>,
+	#[serde(default, deserialize_with = "deserialize_present")]
+	expires_at: Option<Option<String>>,
+	rate_limits: Option<ApiRateLimits>,
+	allowed_ips: Option<Vec<String>>,
+}
+
+/// Deserialize a field that is present (even if null) as `Some`, so that an
+/// absent field (`None`) can be distinguished from a null one (`Some(None)`)
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	T: Deserialize<'de>,
+	D: Deserializer<'de>,
+{
+	T::deserialize(deserializer).map(Some)
+}
+
+pub(crate) fn parse_expiration_date(date: &str) -> Result<u64, Error> {
+	rfc3339_to_msec(date).ok_or_else(|| {
+		Error::bad_request(format!(
+			"Invalid expiration date (expected RFC3339 format): {}",
+			date
+		))
+	})
+}
+
+pub async fn handle_rotate_key(
+	garage: &Arc<Garage>,
+	id: String,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<RotateKeyRequest>(req).await?;
+
+	let mut key = garage.key_helper().get_existing_key(&id).await?;
+
+	let key_state = key.state.as_option_mut().unwrap();
+
+	let expires_at = req
+		.expires_at
+		.map(|d| parse_expiration_date(&d))
+		.transpose()?;
+	let grace_period = req.grace_period.unwrap_or(DEFAULT_ROTATION_GRACE_PERIOD);
+	key_state.rotate_secret(
+		expires_at,
+		now_msec().saturating_add(grace_period.saturating_mul(1000)),
+	);
+
+	garage.key_table.insert(&key).await?;
+
+	key_info_results(garage, key).await
+}
+
+/// Time during which the previous secrets of a key remain valid
+/// after a rotation, if not specified otherwise (in seconds)
+const DEFAULT_ROTATION_GRACE_PERIOD: u64 = 24 * 3600;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RotateKeyRequest {
+	grace_period: Option<u64>,
+	expires_at: Option<String>,
 }
 
 pub async fn handle_delete_key(garage: &Arc<Garage>, id: String) -> Result<Response<Body>, Error> {
@@ -175,13 +274,39 @@ async fn key_info_results(garage: &Arc<Garage>, key: Key) -> Result<Response<Bod
 		}
 	}
 
+	let now = now_msec();
 	let res = GetKeyInfoResult {
 		name: key_state.name.get().clone(),
 		access_key_id: key.key_id.clone(),
-		secret_access_key: key_state.secret_key.clone(),
+		secret_access_key: key_state.current_secret().to_string(),
+		secrets: key_state
+			.all_secrets()
+			.into_iter()
+			.map(|(secret, v)| KeyInfoSecretResult {
+				secret_access_key: secret.to_string(),
+				created: if v.created > 0 {
+					Some(msec_to_rfc3339(v.created))
+				} else {
+					None
+				},
+				expires_at: v.expires_at.map(msec_to_rfc3339),
+				expired: !v.is_valid(now),
+			})
+			.collect::<Vec<_>>(),
 		permissions: KeyPerm {
 			create_bucket: *key_state.allow_create_bucket.get(),
 		},
+		enabled: *key_state.enabled.get(),
+		expires_at: key_state.expires_at.get().map(msec_to_rfc3339),
+		expired: key_state.is_expired(now),
+		rate_limits: (*key_state.rate_limits.get()).into(),
+		allowed_ips: key_state
+			.allowed_ips
+			.get()
+			.0
+			.iter()
+			.map(|net| net.to_string())
+			.collect(),
 		buckets: relevant_buckets
 			.into_iter()
 			.map(|(_, bucket)| {
@@ -205,11 +330,7 @@ async fn key_info_results(garage: &Arc<Garage>, key: Key) -> Result<Response<Bod
 					permissions: key_state
 						.authorized_buckets
 						.get(&bucket.id)
-						.map(|p| ApiBucketKeyPerm {
-							read: p.allow_read,
-							write: p.allow_write,
-							owner: p.allow_owner,
-						})
+						.map(ApiBucketKeyPerm::from)
 						.unwrap_or_default(),
 				}
 			})
@@ -225,10 +346,25 @@ struct GetKeyInfoResult {
 	name: String,
 	access_key_id: String,
 	secret_access_key: String,
+	secrets: Vec<KeyInfoSecretResult>,
 	permissions: KeyPerm,
+	enabled: bool,
+	expires_at: Option<String>,
+	expired: bool,
+	rate_limits: ApiRateLimits,
+	allowed_ips: Vec<String>,
 	buckets: Vec<KeyInfoBucketResult>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyInfoSecretResult {
+	secret_access_key: String,
+	created: Option<String>,
+	expires_at: Option<String>,
+	expired: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct KeyPerm {
@@ -253,4 +389,108 @@ pub(crate) struct ApiBucketKeyPerm {
 	pub(crate) write: bool,
 	#[serde(default)]
 	pub(crate) owner: bool,
+	#[serde(default)]
+	pub(crate) list: bool,
+	#[serde(default)]
+	pub(crate) create: bool,
+	#[serde(default)]
+	pub(crate) overwrite: bool,
+	#[serde(default)]
+	pub(crate) delete: bool,
+	#[serde(default)]
+	pub(crate) prefixes: Vec<String>,
+}
+
+impl ApiBucketKeyPerm {
+	pub(crate) fn capabilities(&self) -> Vec<BucketCapability> {
+		let mut caps = vec![];
+		if self.read {
+			caps.extend(BucketCapability::READ);
+		}
+		if self.write {
+			caps.extend(BucketCapability::WRITE);
+		}
+		if self.owner {
+			caps.extend(BucketCapability::OWNER);
+		}
+		for (flag, cap) in [
+			(self.list, BucketCapability::List),
+			(self.create, BucketCapability::Create),
+			(self.overwrite, BucketCapability::Overwrite),
+			(self.delete, BucketCapability::Delete),
+		] {
+			if flag {
+				caps.push(cap);
+			}
+		}
+		caps
+	}
+}
+
+impl From<&BucketKeyPerm> for ApiBucketKeyPerm {
+	fn from(p: &BucketKeyPerm) -> Self {
+		Self {
+			read: p.allows_all(&BucketCapability::READ),
+			write: p.allows_all(&BucketCapability::WRITE),
+			owner: p.allows(BucketCapability::Owner),
+			list: p.allows(BucketCapability::List),
+			create: p.allows(BucketCapability::Create),
+			overwrite: p.allows(BucketCapability::Overwrite),
+			delete: p.allows(BucketCapability::Delete),
+			prefixes: p.prefixes.clone(),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ApiRateLimits {
+	#[serde(default)]
+	read_requests: Option<u64>,
+	#[serde(default)]
+	write_requests: Option<u64>,
+	#[serde(default)]
+	list_requests: Option<u64>,
+	#[serde(default)]
+	upload_bytes: Option<u64>,
+	#[serde(default)]
+	download_bytes: Option<u64>,
+}
+
+impl From<RateLimits> for ApiRateLimits {
+	fn from(rl: RateLimits) -> Self {
+		Self {
+			read_requests: rl.read_requests,
+			write_requests: rl.write_requests,
+			list_requests: rl.list_requests,
+			upload_bytes: rl.upload_bytes,
+			download_bytes: rl.download_bytes,
+		}
+	}
+}
+
+impl TryFrom<ApiRateLimits> for RateLimits {
+	type Error = Error;
+
+	fn try_from(rl: ApiRateLimits) -> Result<Self, Error> {
+		let limits = [
+			rl.read_requests,
+			rl.write_requests,
+			rl.list_requests,
+			rl.upload_bytes,
+			rl.download_bytes,
+		];
+		if limits.contains(&Some(0)) {
+			return Err(Error::bad_request(
+				"Rate limits must be greater than zero, use null for no limit",
+			));
+		}
+		Ok(Self {
+			read_requests: rl.read_requests,
+			write_requests: rl.write_requests,
+			list_requests: rl.list_requests,
+			upload_bytes: rl.upload_bytes,
+			download_bytes: rl.download_bytes,
+		})
+	}
 }
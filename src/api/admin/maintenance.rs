@@ -0,0 +1,654 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use hyper::{Body, Request, Response};
+use serde::{Deserialize, Serialize};
+
+use garage_block::manager::CorruptedBlockStatus;
+use garage_block::repair::ScrubRunStatus;
+use garage_block::resync::BlockResyncErrorInfo;
+
+use garage_util::background::{WorkerInfo, WorkerState};
+use garage_util::data::*;
+use garage_util::time::{msec_to_rfc3339, now_msec};
+
+use garage_model::garage::Garage;
+use garage_model::s3::version_table::Version;
+
+use crate::admin::error::*;
+use crate::helpers::{json_ok_response, parse_json_body};
+
+/// Maintenance operations that are run by the Garage daemon of a node.
+/// They are implemented in the `garage` crate, which forwards them
+/// to the relevant node of the cluster through admin RPC.
+#[async_trait]
+pub trait NodeMaintenance: Send + Sync {
+	/// Get the state of the background workers of a node
+	async fn list_workers(&self, node: Uuid) -> Result<HashMap<usize, WorkerInfo>, HelperError>;
+
+	/// Change a parameter of the background workers of a node
+	async fn set_worker_variable(
+		&self,
+		node: Uuid,
+		variable: WorkerVariable,
+	) -> Result<String, HelperError>;
+
+	/// Launch a repair operation on a node
+	async fn launch_repair(&self, node: Uuid, repair: RepairType) -> Result<String, HelperError>;
+
+	/// Send a command to the scrub worker of a node
+	async fn control_scrub(&self, node: Uuid, command: ScrubCommand)
+		-> Result<String, HelperError>;
+
+	/// Gather the statistics of a node, as a human-readable text
+	async fn node_statistics(&self, node: Uuid, detailed: bool) -> Result<String, HelperError>;
+
+	/// List the blocks that have a resync error on a node
+	async fn list_block_errors(&self, node: Uuid)
+		-> Result<Vec<BlockResyncErrorInfo>, HelperError>;
+
+	/// Get the reference count of a block on a node, and the versions
+	/// of objects that reference it
+	async fn block_info(&self, node: Uuid, hash: String) -> Result<BlockInfo, HelperError>;
+
+	/// Get the reports of the last scrub runs of a node, with the objects
+	/// affected by the corrupted blocks that were found
+	async fn scrub_reports(&self, node: Uuid) -> Result<Vec<ScrubReportInfo>, HelperError>;
+
+	/// Retry now the resync of blocks that have a resync error on a node,
+	/// either the given ones or all of them
+	async fn retry_block_resync(
+		&self,
+		node: Uuid,
+		all: bool,
+		blocks: Vec<String>,
+	) -> Result<String, HelperError>;
+
+	/// Delete the objects and versions that reference the given blocks
+	async fn purge_blocks(&self, blocks: Vec<String>) -> Result<String, HelperError>;
+}
+
+/// Information about a data block stored on a node
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockInfo {
+	pub hash: Hash,
+	/// Reference count of the block on the node
+	pub refcount: u64,
+	/// Versions of objects referencing the block, or only their identifier
+	/// if they no longer exist in the version table
+	pub versions: Vec<Result<Version, Uuid>>,
+}
+
+/// Report of a scrub run of a node
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScrubReportInfo {
+	/// Start and end time of the scrub, in milliseconds since UNIX Epoch
+	pub start_time: u64,
+	pub end_time: Option<u64>,
+	pub status: ScrubRunStatus,
+	pub blocks_checked: u64,
+	pub bytes_checked: u64,
+	pub corrupted_blocks: Vec<CorruptedBlockInfo>,
+}
+
+/// A block found corrupted during a scrub, and the objects that use it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CorruptedBlockInfo {
+	pub hash: Hash,
+	/// Time at which the corruption was detected, in milliseconds since UNIX Epoch
+	pub detected: u64,
+	pub status: CorruptedBlockStatus,
+	/// Bucket ID and key of the objects whose current versions reference the block
+	pub objects: Vec<(Uuid, String)>,
+	/// Whether there were too many such objects to list them all
+	#[serde(default)]
+	pub objects_truncated: bool,
+}
+
+impl ScrubReportInfo {
+	/// Number of corrupted blocks that are in the given state
+	pub fn count_corrupted(&self, status: CorruptedBlockStatus) -> usize {
+		self.corrupted_blocks
+			.iter()
+			.filter(|b| b.status == status)
+			.count()
+	}
+}
+
+/// Parameter of the background workers that can be changed at runtime
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkerVariable {
+	ScrubTranquility(u32),
+	ResyncWorkerCount(usize),
+	ResyncTranquility(u32),
+	TieringTranquility(u32),
+}
+
+/// Repair operation that can be launched on a node
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum RepairType {
+	/// Full sync of metadata tables
+	Tables,
+	/// Resync/rebalance of the set of stored blocks
+	Blocks,
+	/// Propagation of object deletions to the version table
+	Versions,
+	/// Propagation of version deletions to the block ref table
+	BlockRefs,
+	/// Move of stored blocks to the data directory where they should be stored
+	Rebalance,
+	/// Move of the blocks of old objects to the cold tier
+	Tiering,
+}
+
+/// Command for the scrub worker of a node
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrubCommand {
+	Start,
+	Pause,
+	Resume,
+	Cancel,
+	SetTranquility(u32),
+}
+
+pub async fn handle_list_workers(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+) -> Result<Response<Body>, Error> {
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		let mut workers = maintenance
+			.list_workers(node)
+			.await?
+			.into_iter()
+			.map(|(id, info)| WorkerInfoResponse::new(id, info))
+			.collect::<Vec<_>>();
+		workers.sort_by_key(|w| w.id);
+		Ok(workers)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_set_worker_variable(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let variable = parse_json_body::<WorkerVariable>(req).await?;
+
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		maintenance
+			.set_worker_variable(node, variable)
+			.await
+			.map(MessageResponse::from)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_launch_repair(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<LaunchRepairRequest>(req).await?;
+
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		maintenance
+			.launch_repair(node, req.repair_type)
+			.await
+			.map(MessageResponse::from)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct LaunchRepairRequest {
+	repair_type: RepairType,
+}
+
+pub async fn handle_control_scrub(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<ControlScrubRequest>(req).await?;
+
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		maintenance
+			.control_scrub(node, req.command)
+			.await
+			.map(MessageResponse::from)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct ControlScrubRequest {
+	command: ScrubCommand,
+}
+
+pub async fn handle_get_node_statistics(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	detailed: Option<bool>,
+) -> Result<Response<Body>, Error> {
+	let detailed = detailed.unwrap_or(false);
+
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		let freeform = maintenance.node_statistics(node, detailed).await?;
+		Ok(NodeStatisticsResponse { freeform })
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_list_block_errors(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+) -> Result<Response<Body>, Error> {
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		let now = now_msec();
+		let errors = maintenance
+			.list_block_errors(node)
+			.await?
+			.into_iter()
+			.map(|e| BlockErrorResponse {
+				block_hash: hex::encode(e.hash),
+				refcount: e.refcount,
+				error_count: e.error_count,
+				last_try_secs_ago: now.saturating_sub(e.last_try) / 1000,
+				next_try_in_secs: e.next_try.saturating_sub(now) / 1000,
+			})
+			.collect::<Vec<_>>();
+		Ok(errors)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_get_block_info(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	hash: String,
+) -> Result<Response<Body>, Error> {
+	if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Err(Error::bad_request(format!("Invalid block hash: {}", hash)));
+	}
+
+	let nodes = resolve_nodes(garage, node)?;
+	let hash = &hash;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		let info = maintenance.block_info(node, hash.clone()).await?;
+		Ok(BlockInfoResponse::new(info))
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_get_scrub_reports(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+) -> Result<Response<Body>, Error> {
+	let nodes = resolve_nodes(garage, node)?;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		let reports = maintenance
+			.scrub_reports(node)
+			.await?
+			.into_iter()
+			.map(ScrubReportResponse::new)
+			.collect::<Vec<_>>();
+		Ok(reports)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+pub async fn handle_retry_block_resync(
+	garage: &Arc<Garage>,
+	maintenance: &dyn NodeMaintenance,
+	node: Option<String>,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<RetryBlockResyncRequest>(req).await?;
+
+	let nodes = resolve_nodes(garage, node)?;
+	let req = &req;
+	let res = MultiNodeResponse::gather(nodes, |node| async move {
+		maintenance
+			.retry_block_resync(node, req.all, req.block_hashes.clone())
+			.await
+			.map(MessageResponse::from)
+	})
+	.await;
+
+	Ok(json_ok_response(&res)?)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetryBlockResyncRequest {
+	#[serde(default)]
+	all: bool,
+	#[serde(default)]
+	block_hashes: Vec<String>,
+}
+
+/// Purge the objects referencing blocks that cannot be recovered.
+/// This only modifies metadata tables, so it is done by the node
+/// that receives the request. As it deletes objects, it must be
+/// confirmed with `"confirm": true` in the request.
+pub async fn handle_purge_blocks(
+	maintenance: &dyn NodeMaintenance,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<PurgeBlocksRequest>(req).await?;
+	if !req.confirm {
+		return Err(Error::bad_request(
+			"Purging blocks deletes the objects that use them, set `confirm` to true to proceed",
+		));
+	}
+	if req.block_hashes.is_empty() {
+		return Err(Error::bad_request("No block to purge"));
+	}
+
+	let message = maintenance.purge_blocks(req.block_hashes).await?;
+
+	Ok(json_ok_response(&MessageResponse { message })?)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PurgeBlocksRequest {
+	block_hashes: Vec<String>,
+	#[serde(default)]
+	confirm: bool,
+}
+
+/// Find the nodes designated by the `node` parameter of a request:
+/// `self` (the default) for the node that handles the request,
+/// `*` for all nodes of the cluster layout, or a prefix of a node ID.
+fn resolve_nodes(garage: &Arc<Garage>, node: Option<String>) -> Result<Vec<Uuid>, Error> {
+	match node.as_deref() {
+		None | Some("self") => Ok(vec![garage.system.id]),
+		Some("*") => Ok(garage.system.ring.borrow().layout.node_ids().to_vec()),
+		Some(prefix) => {
+			let prefix = prefix.to_lowercase();
+			let matching = garage
+				.system
+				.get_known_nodes()
+				.into_iter()
+				.map(|n| n.id)
+				.filter(|id| hex::encode(id).starts_with(&prefix))
+				.collect::<Vec<_>>();
+			match matching.len() {
+				1 => Ok(matching),
+				0 => Err(Error::bad_request(format!("No node matches {}", prefix))),
+				_ => Err(Error::bad_request(format!(
+					"Several nodes match {}, please be more specific",
+					prefix
+				))),
+			}
+		}
+	}
+}
+
+/// Response to an operation run on one or several nodes,
+/// with the result or the error for each node
+#[derive(Serialize)]
+struct MultiNodeResponse<T> {
+	success: HashMap<String, T>,
+	error: HashMap<String, String>,
+}
+
+impl<T> MultiNodeResponse<T> {
+	async fn gather<F, Fut>(nodes: Vec<Uuid>, f: F) -> Self
+	where
+		F: Fn(Uuid) -> Fut,
+		Fut: std::future::Future<Output = Result<T, HelperError>>,
+	{
+		let results = join_all(nodes.iter().map(|node| f(*node))).await;
+
+		let mut res = Self {
+			success: HashMap::new(),
+			error: HashMap::new(),
+		};
+		for (node, result) in nodes.iter().zip(results) {
+			match result {
+				Ok(r) => {
+					res.success.insert(hex::encode(node), r);
+				}
+				Err(e) => {
+					res.error.insert(hex::encode(node), e.to_string());
+				}
+			}
+		}
+		res
+	}
+}
+
+#[derive(Serialize)]
+struct MessageResponse {
+	message: String,
+}
+
+impl From<String> for MessageResponse {
+	fn from(message: String) -> Self {
+		Self { message }
+	}
+}
+
+#[derive(Serialize)]
+struct NodeStatisticsResponse {
+	freeform: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockErrorResponse {
+	block_hash: String,
+	refcount: u64,
+	error_count: u64,
+	last_try_secs_ago: u64,
+	next_try_in_secs: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockInfoResponse {
+	block_hash: String,
+	refcount: u64,
+	versions: Vec<BlockVersionResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockVersionResponse {
+	version_id: String,
+	deleted: bool,
+	/// The version no longer exists in the version table
+	garbage_collected: bool,
+	bucket_id: Option<String>,
+	key: Option<String>,
+}
+
+impl BlockInfoResponse {
+	fn new(info: BlockInfo) -> Self {
+		let versions = info
+			.versions
+			.into_iter()
+			.map(|v| match v {
+				Ok(v) => BlockVersionResponse {
+					version_id: hex::encode(v.uuid),
+					deleted: v.deleted.get(),
+					garbage_collected: false,
+					bucket_id: Some(hex::encode(v.bucket_id)),
+					key: Some(v.key),
+				},
+				Err(uuid) => BlockVersionResponse {
+					version_id: hex::encode(uuid),
+					deleted: true,
+					garbage_collected: true,
+					bucket_id: None,
+					key: None,
+				},
+			})
+			.collect();
+		Self {
+			block_hash: hex::encode(info.hash),
+			refcount: info.refcount,
+			versions,
+		}
+	}
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScrubReportResponse {
+	start_time: String,
+	end_time: Option<String>,
+	status: &'static str,
+	blocks_checked: u64,
+	bytes_checked: u64,
+	repaired: usize,
+	unrepairable: usize,
+	corrupted_blocks: Vec<CorruptedBlockResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CorruptedBlockResponse {
+	block_hash: String,
+	detected: String,
+	status: &'static str,
+	objects: Vec<CorruptedBlockObjectResponse>,
+	objects_truncated: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CorruptedBlockObjectResponse {
+	bucket_id: String,
+	key: String,
+}
+
+impl ScrubReportResponse {
+	fn new(report: ScrubReportInfo) -> Self {
+		let repaired = report.count_corrupted(CorruptedBlockStatus::Repaired);
+		let unrepairable = report.count_corrupted(CorruptedBlockStatus::Unrepairable);
+		let corrupted_blocks = report
+			.corrupted_blocks
+			.into_iter()
+			.map(|b| CorruptedBlockResponse {
+				block_hash: hex::encode(b.hash),
+				detected: msec_to_rfc3339(b.detected),
+				status: match b.status {
+					CorruptedBlockStatus::Repaired => "repaired",
+					CorruptedBlockStatus::NotNeeded => "notNeeded",
+					CorruptedBlockStatus::Unrepairable => "unrepairable",
+					CorruptedBlockStatus::Pending => "pending",
+				},
+				objects: b
+					.objects
+					.into_iter()
+					.map(|(bucket_id, key)| CorruptedBlockObjectResponse {
+						bucket_id: hex::encode(bucket_id),
+						key,
+					})
+					.collect(),
+				objects_truncated: b.objects_truncated,
+			})
+			.collect();
+		Self {
+			start_time: msec_to_rfc3339(report.start_time),
+			end_time: report.end_time.map(msec_to_rfc3339),
+			status: match report.status {
+				ScrubRunStatus::Running => "running",
+				ScrubRunStatus::Paused => "paused",
+				ScrubRunStatus::Completed => "completed",
+				ScrubRunStatus::Cancelled => "cancelled",
+				ScrubRunStatus::Interrupted => "interrupted",
+			},
+			blocks_checked: report.blocks_checked,
+			bytes_checked: report.bytes_checked,
+			repaired,
+			unrepairable,
+			corrupted_blocks,
+		}
+	}
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkerInfoResponse {
+	id: usize,
+	name: String,
+	state: &'static str,
+	throttled_secs: Option<f32>,
+	info: Option<String>,
+	errors: usize,
+	consecutive_errors: usize,
+	last_error: Option<WorkerLastErrorResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkerLastErrorResponse {
+	message: String,
+	secs_ago: u64,
+}
+
+impl WorkerInfoResponse {
+	fn new(id: usize, info: WorkerInfo) -> Self {
+		let (state, throttled_secs) = match info.state {
+			WorkerState::Busy => ("busy", None),
+			WorkerState::Throttled(t) => ("throttled", Some(t)),
+			WorkerState::Idle => ("idle", None),
+			WorkerState::Done => ("done", None),
+		};
+		Self {
+			id,
+			name: info.name,
+			state,
+			throttled_secs,
+			info: info.info,
+			errors: info.errors,
+			consecutive_errors: info.consecutive_errors,
+			last_error: info.last_error.map(|(message, t)| WorkerLastErrorResponse {
+				message,
+				secs_ago: now_msec().saturating_sub(t) / 1000,
+			}),
+		}
+	}
+}
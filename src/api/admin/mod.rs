@@ -1,7 +1,11 @@
 pub mod api_server;
 mod error;
+pub mod maintenance;
 mod router;
 
+mod audit;
 mod bucket;
 mod cluster;
 mod key;
+mod object;
+mod token;
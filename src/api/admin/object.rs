@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use hyper::{Body, Response};
+use serde::Serialize;
+
+use garage_util::time::*;
+
+use garage_model::garage::Garage;
+use garage_model::helper::object::*;
+use garage_model::s3::object_table::*;
+
+use crate::admin::error::*;
+use crate::helpers::json_ok_response;
+
+pub async fn handle_get_object_info(
+	garage: &Arc<Garage>,
+	bucket: String,
+	key: String,
+) -> Result<Response<Body>, Error> {
+	let bucket_id = garage
+		.bucket_helper()
+		.resolve_global_bucket_name(&bucket)
+		.await?
+		.ok_or_else(|| HelperError::NoSuchBucket(bucket.to_string()))?;
+
+	let info = garage
+		.object_helper()
+		.get_object_info(bucket_id, &key)
+		.await?
+		.ok_or_else(|| Error::NoSuchObject(key.to_string()))?;
+
+	Ok(json_ok_response(&GetObjectInfoResult::new(info))?)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetObjectInfoResult {
+	bucket_id: String,
+	key: String,
+	versions: Vec<ObjectVersionResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectVersionResult {
+	uuid: String,
+	timestamp: String,
+	state: &'static str,
+	delete_marker: bool,
+	inline: bool,
+	size: Option<u64>,
+	etag: Option<String>,
+	/// Whether the version is marked as deleted in the version table,
+	/// None if it has no data blocks or if it could not be found
+	version_deleted: Option<bool>,
+	blocks: Vec<ObjectBlockResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectBlockResult {
+	part_number: u64,
+	offset: u64,
+	hash: String,
+	size: u64,
+	replicas: Vec<BlockReplicaResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockReplicaResult {
+	node: String,
+	stored: Option<bool>,
+	refcount: Option<u64>,
+	error: Option<String>,
+}
+
+impl GetObjectInfoResult {
+	fn new(info: ObjectInfo) -> Self {
+		let versions = info
+			.versions
+			.into_iter()
+			.map(|v| {
+				let ov = v.object_version;
+				let (state, data) = match &ov.state {
+					ObjectVersionState::Uploading(_) => ("uploading", None),
+					ObjectVersionState::Complete(d) => ("complete", Some(d)),
+					ObjectVersionState::Aborted => ("aborted", None),
+				};
+				let meta = match data {
+					Some(ObjectVersionData::Inline(meta, _))
+					| Some(ObjectVersionData::FirstBlock(meta, _)) => Some(meta),
+					_ => None,
+				};
+				ObjectVersionResult {
+					uuid: hex::encode(ov.uuid),
+					timestamp: msec_to_rfc3339(ov.timestamp),
+					state,
+					delete_marker: matches!(data, Some(ObjectVersionData::DeleteMarker)),
+					inline: matches!(data, Some(ObjectVersionData::Inline(_, _))),
+					size: meta.map(|m| m.size),
+					etag: meta.map(|m| m.etag.clone()),
+					version_deleted: v.version.as_ref().map(|v| v.deleted.get()),
+					blocks: v
+						.blocks
+						.into_iter()
+						.map(|b| ObjectBlockResult {
+							part_number: b.part_number,
+							offset: b.offset,
+							hash: hex::encode(b.hash),
+							size: b.size,
+							replicas: b
+								.replicas
+								.into_iter()
+								.map(|r| {
+									let (status, error) = match r.status {
+										Ok(s) => (Some(s), None),
+										Err(e) => (None, Some(e)),
+									};
+									BlockReplicaResult {
+										node: hex::encode(r.node),
+										stored: status.map(|s| s.stored),
+										refcount: status.map(|s| s.refcount),
+										error,
+									}
+								})
+								.collect(),
+						})
+						.collect(),
+				}
+			})
+			.collect();
+
+		Self {
+			bucket_id: hex::encode(info.bucket_id),
+			key: info.key,
+			versions,
+		}
+	}
+}
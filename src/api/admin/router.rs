@@ -2,14 +2,11 @@ use std::borrow::Cow;
 
 use hyper::{Method, Request};
 
+use garage_model::admin_token_table::AdminScope;
+
 use crate::admin::error::*;
 use crate::router_macros::*;
 
-pub enum Authorization {
-	MetricsToken,
-	AdminToken,
-}
-
 router_match! {@func
 
 /// List of all Admin API endpoints.
@@ -24,6 +21,7 @@ pub enum Endpoint {
 	UpdateClusterLayout,
 	ApplyClusterLayout,
 	RevertClusterLayout,
+	SimulateClusterLayout,
 	// Keys
 	ListKeys,
 	CreateKey,
@@ -38,6 +36,9 @@ pub enum Endpoint {
 	UpdateKey {
 		id: String,
 	},
+	RotateKey {
+		id: String,
+	},
 	// Buckets
 	ListBuckets,
 	CreateBucket,
@@ -73,6 +74,59 @@ pub enum Endpoint {
 		access_key_id: String,
 		alias: String,
 	},
+	// Objects
+	GetObjectInfo {
+		bucket: String,
+		key: String,
+	},
+	// Admin tokens
+	ListAdminTokens,
+	CreateAdminToken,
+	GetAdminTokenInfo {
+		id: String,
+	},
+	DeleteAdminToken {
+		id: String,
+	},
+	// Audit log
+	ListAuditLog {
+		since: Option<String>,
+		until: Option<String>,
+		actor: Option<String>,
+		limit: Option<usize>,
+	},
+	// Maintenance
+	ListWorkers {
+		node: Option<String>,
+	},
+	SetWorkerVariable {
+		node: Option<String>,
+	},
+	LaunchRepair {
+		node: Option<String>,
+	},
+	ControlScrub {
+		node: Option<String>,
+	},
+	GetScrubReports {
+		node: Option<String>,
+	},
+	GetNodeStatistics {
+		node: Option<String>,
+		detailed: Option<bool>,
+	},
+	// Blocks
+	ListBlockErrors {
+		node: Option<String>,
+	},
+	GetBlockInfo {
+		node: Option<String>,
+		hash: String,
+	},
+	RetryBlockResync {
+		node: Option<String>,
+	},
+	PurgeBlocks,
 }}
 
 impl Endpoint {
@@ -96,12 +150,14 @@ impl Endpoint {
 			POST "/v0/layout" => UpdateClusterLayout,
 			POST "/v0/layout/apply" => ApplyClusterLayout,
 			POST "/v0/layout/revert" => RevertClusterLayout,
+			GET "/v0/layout/simulate" => SimulateClusterLayout,
 			// API key endpoints
 			GET "/v0/key" if id => GetKeyInfo (query_opt::id, query_opt::search),
 			GET "/v0/key" if search => GetKeyInfo (query_opt::id, query_opt::search),
 			POST "/v0/key" if id => UpdateKey (query::id),
 			POST "/v0/key" => CreateKey,
 			POST "/v0/key/import" => ImportKey,
+			POST "/v0/key/rotate" if id => RotateKey (query::id),
 			DELETE "/v0/key" if id => DeleteKey (query::id),
 			GET "/v0/key" => ListKeys,
 			// Bucket endpoints
@@ -119,6 +175,27 @@ impl Endpoint {
 			DELETE "/v0/bucket/alias/global" => GlobalUnaliasBucket (query::id, query::alias),
 			PUT "/v0/bucket/alias/local" => LocalAliasBucket (query::id, query::access_key_id, query::alias),
 			DELETE "/v0/bucket/alias/local" => LocalUnaliasBucket (query::id, query::access_key_id, query::alias),
+			// Object endpoints
+			GET "/v0/object" => GetObjectInfo (query::bucket, query::key),
+			// Admin token endpoints
+			GET "/v0/token" if id => GetAdminTokenInfo (query::id),
+			GET "/v0/token" => ListAdminTokens,
+			POST "/v0/token" => CreateAdminToken,
+			DELETE "/v0/token" if id => DeleteAdminToken (query::id),
+			// Audit log
+			GET "/v0/audit" => ListAuditLog (query_opt::since, query_opt::until, query_opt::actor, opt_parse::limit),
+			// Maintenance
+			GET "/v0/worker" => ListWorkers (query_opt::node),
+			POST "/v0/worker/set" => SetWorkerVariable (query_opt::node),
+			POST "/v0/repair" => LaunchRepair (query_opt::node),
+			POST "/v0/scrub" => ControlScrub (query_opt::node),
+			GET "/v0/scrub" => GetScrubReports (query_opt::node),
+			GET "/v0/stats" => GetNodeStatistics (query_opt::node, opt_parse::detailed),
+			// Blocks
+			GET "/v0/block/errors" => ListBlockErrors (query_opt::node),
+			GET "/v0/block" if hash => GetBlockInfo (query_opt::node, query::hash),
+			POST "/v0/block/retry" => RetryBlockResync (query_opt::node),
+			POST "/v0/block/purge" => PurgeBlocks,
 		]);
 
 		if let Some(message) = query.nonempty_message() {
@@ -127,11 +204,109 @@ impl Endpoint {
 
 		Ok(res)
 	}
-	/// Get the kind of authorization which is required to perform the operation.
-	pub fn authorization_type(&self) -> Authorization {
+	/// Get the scope that an admin token must have to perform the operation,
+	/// or None if the operation requires no authorization.
+	pub fn required_scope(&self) -> Option<AdminScope> {
+		match self {
+			Self::Options => None,
+			Self::Metrics => Some(AdminScope::Metrics),
+			Self::GetClusterStatus
+			| Self::GetClusterLayout
+			| Self::SimulateClusterLayout
+			| Self::ListWorkers { .. }
+			| Self::GetNodeStatistics { .. }
+			| Self::GetScrubReports { .. }
+			| Self::ListBlockErrors { .. }
+			| Self::GetBlockInfo { .. } => Some(AdminScope::Status),
+			Self::ConnectClusterNodes
+			| Self::UpdateClusterLayout
+			| Self::ApplyClusterLayout
+			| Self::RevertClusterLayout => Some(AdminScope::Layout),
+			Self::ListKeys
+			| Self::CreateKey
+			| Self::ImportKey
+			| Self::GetKeyInfo { .. }
+			| Self::DeleteKey { .. }
+			| Self::UpdateKey { .. }
+			| Self::RotateKey { .. } => Some(AdminScope::Keys),
+			Self::ListBuckets
+			| Self::CreateBucket
+			| Self::GetBucketInfo { .. }
+			| Self::DeleteBucket { .. }
+			| Self::UpdateBucket { .. }
+			| Self::BucketAllowKey
+			| Self::BucketDenyKey
+			| Self::GlobalAliasBucket { .. }
+			| Self::GlobalUnaliasBucket { .. }
+			| Self::LocalAliasBucket { .. }
+			| Self::LocalUnaliasBucket { .. }
+			| Self::GetObjectInfo { .. } => Some(AdminScope::Buckets),
+			Self::ListAdminTokens
+			| Self::CreateAdminToken
+			| Self::GetAdminTokenInfo { .. }
+			| Self::DeleteAdminToken { .. } => Some(AdminScope::Tokens),
+			Self::ListAuditLog { .. } => Some(AdminScope::Audit),
+			Self::SetWorkerVariable { .. }
+			| Self::LaunchRepair { .. }
+			| Self::ControlScrub { .. }
+			| Self::RetryBlockResync { .. }
+			| Self::PurgeBlocks => Some(AdminScope::Maintenance),
+		}
+	}
+
+	/// Whether the operation modifies the state of the cluster,
+	/// in which case it is recorded in the audit log
+	pub fn is_audited(&self) -> bool {
+		!matches!(
+			self,
+			Self::Options
+				| Self::Metrics
+				| Self::GetClusterStatus
+				| Self::GetClusterLayout
+				| Self::SimulateClusterLayout
+				| Self::ListKeys
+				| Self::GetKeyInfo { .. }
+				| Self::ListBuckets
+				| Self::GetBucketInfo { .. }
+				| Self::GetObjectInfo { .. }
+				| Self::ListAdminTokens
+				| Self::GetAdminTokenInfo { .. }
+				| Self::ListAuditLog { .. }
+				| Self::ListWorkers { .. }
+				| Self::GetNodeStatistics { .. }
+				| Self::GetScrubReports { .. }
+				| Self::ListBlockErrors { .. }
+				| Self::GetBlockInfo { .. }
+		)
+	}
+
+	/// Parameters of the operation that are given in the URL
+	pub fn url_parameters(&self) -> Vec<(&'static str, &str)> {
 		match self {
-			Self::Metrics => Authorization::MetricsToken,
-			_ => Authorization::AdminToken,
+			Self::DeleteKey { id }
+			| Self::UpdateKey { id }
+			| Self::RotateKey { id }
+			| Self::DeleteBucket { id }
+			| Self::UpdateBucket { id }
+			| Self::DeleteAdminToken { id } => vec![("id", id)],
+			Self::GlobalAliasBucket { id, alias } | Self::GlobalUnaliasBucket { id, alias } => {
+				vec![("id", id), ("alias", alias)]
+			}
+			Self::LocalAliasBucket {
+				id,
+				access_key_id,
+				alias,
+			}
+			| Self::LocalUnaliasBucket {
+				id,
+				access_key_id,
+				alias,
+			} => vec![("id", id), ("accessKeyId", access_key_id), ("alias", alias)],
+			Self::SetWorkerVariable { node: Some(node) }
+			| Self::LaunchRepair { node: Some(node) }
+			| Self::ControlScrub { node: Some(node) }
+			| Self::RetryBlockResync { node: Some(node) } => vec![("node", node)],
+			_ => vec![],
 		}
 	}
 }
@@ -141,5 +316,14 @@ generateQueryParameters! {
 	"search" => search,
 	"globalAlias" => global_alias,
 	"alias" => alias,
-	"accessKeyId" => access_key_id
+	"accessKeyId" => access_key_id,
+	"since" => since,
+	"until" => until,
+	"actor" => actor,
+	"limit" => limit,
+	"node" => node,
+	"detailed" => detailed,
+	"hash" => hash,
+	"bucket" => bucket,
+	"key" => key
 }
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use hyper::{Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use garage_table::*;
+use garage_util::time::*;
+
+use garage_model::admin_token_table::*;
+use garage_model::garage::Garage;
+
+use crate::admin::error::*;
+use crate::admin::key::parse_expiration_date;
+use crate::helpers::{json_ok_response, parse_json_body};
+
+pub async fn handle_list_admin_tokens(garage: &Arc<Garage>) -> Result<Response<Body>, Error> {
+	let res = garage
+		.admin_token_table
+		.get_range(
+			&EmptyKey,
+			None,
+			Some(DeletedFilter::NotDeleted),
+			10000,
+			EnumerationOrder::Forward,
+		)
+		.await?
+		.iter()
+		.map(|t| ListAdminTokenResultItem {
+			id: t.token_id.clone(),
+			name: t.params().unwrap().name.get().clone(),
+		})
+		.collect::<Vec<_>>();
+
+	Ok(json_ok_response(&res)?)
+}
+
+#[derive(Serialize)]
+struct ListAdminTokenResultItem {
+	id: String,
+	name: String,
+}
+
+pub async fn handle_get_admin_token_info(
+	garage: &Arc<Garage>,
+	id: String,
+) -> Result<Response<Body>, Error> {
+	let token = get_existing_token(garage, &id).await?;
+
+	Ok(json_ok_response(&AdminTokenInfoResult::new(&token, None))?)
+}
+
+/// Create a new admin token. The scopes of the new token must be a subset
+/// of the scopes of the token used to make the request, so that a token
+/// cannot be used to gain more privileges than it already has.
+pub async fn handle_create_admin_token(
+	garage: &Arc<Garage>,
+	caller_scopes: &AdminScopes,
+	req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+	let req = parse_json_body::<CreateAdminTokenRequest>(req).await?;
+
+	// Reserved for the tokens of the configuration file
+	if req.name.starts_with('@') {
+		return Err(Error::bad_request("Token names cannot start with `@`"));
+	}
+
+	let scopes = parse_scopes(&req.scopes)?;
+	if let Some(s) = scopes.0.iter().find(|s| !caller_scopes.contains(**s)) {
+		return Err(Error::forbidden(format!(
+			"Cannot create a token with scope `{}`, which the current token does not have",
+			s.name()
+		)));
+	}
+	let expires_at = req
+		.expires_at
+		.map(|d| parse_expiration_date(&d))
+		.transpose()?;
+
+	let (token, secret_token) = AdminApiToken::new(&req.name, scopes, expires_at);
+	garage.admin_token_table.insert(&token).await?;
+
+	Ok(json_ok_response(&AdminTokenInfoResult::new(
+		&token,
+		Some(secret_token),
+	))?)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateAdminTokenRequest {
+	name: String,
+	scopes: Vec<String>,
+	expires_at: Option<String>,
+}
+
+pub async fn handle_delete_admin_token(
+	garage: &Arc<Garage>,
+	id: String,
+) -> Result<Response<Body>, Error> {
+	let mut token = get_existing_token(garage, &id).await?;
+
+	token.state = crdt::Deletable::delete();
+	garage.admin_token_table.insert(&token).await?;
+
+	Ok(Response::builder()
+		.status(StatusCode::NO_CONTENT)
+		.body(Body::empty())?)
+}
+
+async fn get_existing_token(garage: &Arc<Garage>, id: &str) -> Result<AdminApiToken, Error> {
+	garage
+		.admin_token_table
+		.get(&EmptyKey, &id.to_string())
+		.await?
+		.filter(|t| !t.is_deleted())
+		.ok_or_else(|| Error::NoSuchAdminToken(id.to_string()))
+}
+
+fn parse_scopes(scopes: &[String]) -> Result<AdminScopes, Error> {
+	let scopes = scopes
+		.iter()
+		.map(|s| {
+			AdminScope::from_name(s).ok_or_bad_request(format!("Invalid admin token scope: {}", s))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+	Ok(AdminScopes::new(scopes))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminTokenInfoResult {
+	id: String,
+	name: String,
+	created: String,
+	scopes: Vec<&'static str>,
+	expires_at: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	secret_token: Option<String>,
+}
+
+impl AdminTokenInfoResult {
+	fn new(token: &AdminApiToken, secret_token: Option<String>) -> Self {
+		let params = token.params().unwrap();
+		Self {
+			id: token.token_id.clone(),
+			name: params.name.get().clone(),
+			created: msec_to_rfc3339(params.created),
+			scopes: params.scopes.get().0.iter().map(|s| s.name()).collect(),
+			expires_at: params.expires_at.get().map(msec_to_rfc3339),
+			secret_token,
+		}
+	}
+}
@@ -0,0 +1,459 @@
+//! Determining the address of the client that sent a request, for when
+//! Garage is placed behind a load balancer or a reverse proxy.
+//!
+//! The address of a client can be given by the load balancer using
+//! the PROXY protocol (v1 or v2) at the start of the connection, and
+//! by trusted reverse proxies using the `Forwarded` or `X-Forwarded-For` headers.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::{HeaderMap, HeaderValue, FORWARDED};
+use hyper::{Body, Request};
+use ipnetwork::IpNetwork;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use garage_util::error::Error;
+use garage_util::net::canonical_ip;
+
+const PROXY_V1_PREFIX: &[u8] = b"PROXY";
+const PROXY_V1_MAX_LEN: usize = 107;
+const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// IP address of the client that sent a request, stored in the request's
+/// extensions by the HTTP server when it is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Get the IP address of the client that sent a request, if known
+pub fn client_ip(req: &Request<Body>) -> Option<IpAddr> {
+	req.extensions().get::<ClientIp>().map(|x| x.0)
+}
+
+/// A connection accepted by an HTTP server, along with the address of its peer.
+/// The peer address is not known for connections on Unix sockets, unless it has
+/// been given in a PROXY protocol header.
+#[pin_project::pin_project]
+pub struct ClientConn<C> {
+	#[pin]
+	conn: C,
+	peer: Option<SocketAddr>,
+}
+
+impl<C> ClientConn<C> {
+	pub fn new(conn: C, peer: Option<SocketAddr>) -> Self {
+		Self { conn, peer }
+	}
+
+	pub fn peer(&self) -> Option<SocketAddr> {
+		self.peer
+	}
+}
+
+impl<C: AsyncRead + Unpin> ClientConn<C> {
+	/// Read the PROXY protocol header at the start of the connection,
+	/// replacing the peer address by the one it contains
+	pub async fn read_proxy_header(mut self) -> Result<Self, Error> {
+		if let Some(source) = read_proxy_header(&mut self.conn).await? {
+			self.peer = Some(source);
+		}
+		Ok(self)
+	}
+}
+
+impl<C: AsyncRead> AsyncRead for ClientConn<C> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<std::io::Result<()>> {
+		self.project().conn.poll_read(cx, buf)
+	}
+}
+
+impl<C: AsyncWrite> AsyncWrite for ClientConn<C> {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<std::io::Result<usize>> {
+		self.project().conn.poll_write(cx, buf)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.project().conn.poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+		self.project().conn.poll_shutdown(cx)
+	}
+}
+
+/// Read a PROXY protocol header (v1 or v2) at the start of a connection,
+/// leaving the stream positionned at the beginning of the proxied data.
+/// Returns the source address given in the header, or None if the proxy
+/// did not give one (UNKNOWN protocol in v1, LOCAL command in v2).
+async fn read_proxy_header<R: AsyncRead + Unpin>(
+	conn: &mut R,
+) -> Result<Option<SocketAddr>, Error> {
+	let mut header = vec![0u8; PROXY_V1_PREFIX.len()];
+	conn.read_exact(&mut header[..]).await?;
+
+	if header == PROXY_V1_PREFIX {
+		// The v1 header is a line of text: we must read it byte per byte
+		// so as not to consume any of the data that comes after it
+		while !header.ends_with(b"\r\n") {
+			if header.len() >= PROXY_V1_MAX_LEN {
+				return Err(Error::Message("PROXY protocol v1 header too long".into()));
+			}
+			header.push(conn.read_u8().await?);
+		}
+		parse_proxy_v1(&header[..header.len() - 2])
+	} else if header[..] == PROXY_V2_SIGNATURE[..header.len()] {
+		header.resize(16, 0);
+		conn.read_exact(&mut header[PROXY_V1_PREFIX.len()..])
+			.await?;
+		if header[..12] != *PROXY_V2_SIGNATURE {
+			return Err(Error::Message("Invalid PROXY protocol v2 signature".into()));
+		}
+		let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+		let mut addresses = vec![0u8; len];
+		conn.read_exact(&mut addresses[..]).await?;
+		parse_proxy_v2(header[12], header[13], &addresses[..])
+	} else {
+		Err(Error::Message(
+			"Connection does not start with a PROXY protocol header".into(),
+		))
+	}
+}
+
+fn parse_proxy_v1(line: &[u8]) -> Result<Option<SocketAddr>, Error> {
+	let invalid = || Error::Message("Invalid PROXY protocol v1 header".into());
+
+	let line = std::str::from_utf8(line).map_err(|_| invalid())?;
+	let mut fields = line.split(' ');
+	if fields.next() != Some("PROXY") {
+		return Err(invalid());
+	}
+	match fields.next() {
+		Some("TCP4") | Some("TCP6") => {
+			let ip = fields
+				.next()
+				.and_then(|x| x.parse::<IpAddr>().ok())
+				.ok_or_else(invalid)?;
+			let _dest_ip = fields.next().ok_or_else(invalid)?;
+			let port = fields
+				.next()
+				.and_then(|x| x.parse::<u16>().ok())
+				.ok_or_else(invalid)?;
+			Ok(Some(SocketAddr::new(ip, port)))
+		}
+		Some("UNKNOWN") => Ok(None),
+		_ => Err(invalid()),
+	}
+}
+
+fn parse_proxy_v2(ver_cmd: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>, Error> {
+	if ver_cmd >> 4 != 2 {
+		return Err(Error::Message(format!(
+			"Unsupported PROXY protocol version: {}",
+			ver_cmd >> 4
+		)));
+	}
+	match ver_cmd & 0x0F {
+		// LOCAL command: the connection was made by the proxy itself (e.g. health checks)
+		0 => return Ok(None),
+		1 => (),
+		cmd => {
+			return Err(Error::Message(format!(
+				"Invalid PROXY protocol v2 command: {}",
+				cmd
+			)))
+		}
+	}
+
+	let too_short = || Error::Message("PROXY protocol v2 address block too short".into());
+	match family >> 4 {
+		// AF_INET: source address, destination address, source port, destination port
+		1 => {
+			let block = addresses.get(..12).ok_or_else(too_short)?;
+			let ip = Ipv4Addr::new(block[0], block[1], block[2], block[3]);
+			let port = u16::from_be_bytes([block[8], block[9]]);
+			Ok(Some(SocketAddr::new(ip.into(), port)))
+		}
+		// AF_INET6
+		2 => {
+			let block = addresses.get(..36).ok_or_else(too_short)?;
+			let mut ip = [0u8; 16];
+			ip.copy_from_slice(&block[..16]);
+			let port = u16::from_be_bytes([block[32], block[33]]);
+			Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+		}
+		// AF_UNSPEC or AF_UNIX: no IP address to give
+		_ => Ok(None),
+	}
+}
+
+/// Determine the IP address of the client that sent a request, given the address
+/// of the peer it was received from. If this peer is a trusted proxy, the client
+/// address is taken from the `Forwarded` header, or if it is absent from the
+/// `X-Forwarded-For` header, skipping over any other trusted proxies on the way.
+/// Peers of unknown address (connections on a Unix socket) are trusted if
+/// any trusted proxy is configured. If a hop that must be examined is not an IP
+/// address (obfuscated or invalid), the client is unknown and None is returned,
+/// so that the address of a trusted proxy is never taken for that of the client.
+/// IPv4-mapped IPv6 addresses, which is how IPv4 clients appear on a dual-stack
+/// socket, are converted to IPv4 addresses.
+pub fn resolve_client_ip(
+	peer: Option<IpAddr>,
+	headers: &HeaderMap<HeaderValue>,
+	trusted_proxies: &[IpNetwork],
+) -> Option<IpAddr> {
+	let peer = peer.map(canonical_ip);
+	if trusted_proxies.is_empty() {
+		return peer;
+	}
+	let is_trusted = |ip: &Option<IpAddr>| match ip {
+		Some(ip) => trusted_proxies.iter().any(|net| net.contains(*ip)),
+		None => true,
+	};
+
+	let mut hops = if headers.contains_key(FORWARDED) {
+		forwarded_for(headers)
+	} else {
+		x_forwarded_for(headers)
+	};
+
+	// The last hop is the one that was added by the proxy closest to us,
+	// so we walk the list backwards until we find one that we don't trust
+	let mut client = peer;
+	while is_trusted(&client) {
+		match hops.pop() {
+			Some(Some(ip)) => client = Some(canonical_ip(ip)),
+			Some(None) => return None,
+			None => break,
+		}
+	}
+	client
+}
+
+/// Addresses in the `for=` parameters of the `Forwarded` headers (RFC 7239).
+/// Identifiers that are not IP addresses (obfuscated or unknown) are None.
+fn forwarded_for(headers: &HeaderMap<HeaderValue>) -> Vec<Option<IpAddr>> {
+	headers
+		.get_all(FORWARDED)
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|element| {
+			element
+				.split(';')
+				.filter_map(|pair| pair.split_once('='))
+				.find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+				.and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+		})
+		.collect()
+}
+
+/// Addresses in the `X-Forwarded-For` headers
+fn x_forwarded_for(headers: &HeaderMap<HeaderValue>) -> Vec<Option<IpAddr>> {
+	headers
+		.get_all("x-forwarded-for")
+		.iter()
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.map(|node| parse_node(node.trim()))
+		.collect()
+}
+
+/// Parse a node identifier, which can be an IP address, optionnally with a port
+/// number (in which case IPv6 addresses are enclosed in brackets)
+fn parse_node(node: &str) -> Option<IpAddr> {
+	if let Ok(ip) = node.parse::<IpAddr>() {
+		return Some(ip);
+	}
+	if let Ok(addr) = node.parse::<SocketAddr>() {
+		return Some(addr.ip());
+	}
+	node.strip_prefix('[')
+		.and_then(|x| x.strip_suffix(']'))
+		.and_then(|x| x.parse::<Ipv6Addr>().ok())
+		.map(IpAddr::V6)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ip(s: &str) -> IpAddr {
+		s.parse().unwrap()
+	}
+
+	fn headers(h: &[(&'static str, &'static str)]) -> HeaderMap<HeaderValue> {
+		let mut headers = HeaderMap::new();
+		for (k, v) in h {
+			headers.append(*k, HeaderValue::from_static(v));
+		}
+		headers
+	}
+
+	async fn read_header(data: &[u8]) -> Result<(Option<SocketAddr>, Vec<u8>), Error> {
+		let mut conn = data;
+		let addr = read_proxy_header(&mut conn).await?;
+		Ok((addr, conn.to_vec()))
+	}
+
+	#[tokio::test]
+	async fn test_proxy_v1() {
+		let (addr, rest) = read_header(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 3900\r\nGET /")
+			.await
+			.unwrap();
+		assert_eq!(addr, Some("192.0.2.1:56324".parse().unwrap()));
+		assert_eq!(rest, b"GET /");
+
+		let (addr, _) = read_header(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 3900\r\n")
+			.await
+			.unwrap();
+		assert_eq!(addr, Some("[2001:db8::1]:56324".parse().unwrap()));
+
+		let (addr, rest) = read_header(b"PROXY UNKNOWN\r\nGET /").await.unwrap();
+		assert_eq!(addr, None);
+		assert_eq!(rest, b"GET /");
+
+		assert!(read_header(b"PROXY TCP4 192.0.2.1\r\n").await.is_err());
+		assert!(read_header(b"GET / HTTP/1.1\r\n").await.is_err());
+		assert!(read_header(&[&b"PROXY "[..], &[b'a'; 200][..]].concat())
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_proxy_v2() {
+		let mut data = PROXY_V2_SIGNATURE.to_vec();
+		data.extend_from_slice(&[0x21, 0x11, 0, 12]);
+		data.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 2, 0xdc, 0x04, 0x0f, 0x3c]);
+		data.extend_from_slice(b"GET /");
+		let (addr, rest) = read_header(&data).await.unwrap();
+		assert_eq!(addr, Some("192.0.2.1:56324".parse().unwrap()));
+		assert_eq!(rest, b"GET /");
+
+		let mut data = PROXY_V2_SIGNATURE.to_vec();
+		data.extend_from_slice(&[0x21, 0x21, 0, 39]);
+		data.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+		data.extend_from_slice(&[0u8; 16]);
+		data.extend_from_slice(&[0xdc, 0x04, 0x0f, 0x3c]);
+		// unknown TLV, which must be skipped
+		data.extend_from_slice(&[0xee, 0, 0]);
+		data.extend_from_slice(b"GET /");
+		let (addr, rest) = read_header(&data).await.unwrap();
+		assert_eq!(addr, Some("[2001:db8::1]:56324".parse().unwrap()));
+		assert_eq!(rest, b"GET /");
+
+		// LOCAL command
+		let mut data = PROXY_V2_SIGNATURE.to_vec();
+		data.extend_from_slice(&[0x20, 0x00, 0, 0]);
+		assert_eq!(read_header(&data).await.unwrap().0, None);
+
+		// Bad version
+		let mut data = PROXY_V2_SIGNATURE.to_vec();
+		data.extend_from_slice(&[0x11, 0x11, 0, 0]);
+		assert!(read_header(&data).await.is_err());
+	}
+
+	#[test]
+	fn test_resolve_client_ip() {
+		let trusted = vec![
+			"10.0.0.0/8".parse::<IpNetwork>().unwrap(),
+			"fc00::/7".parse::<IpNetwork>().unwrap(),
+		];
+		let xff = headers(&[("x-forwarded-for", "198.51.100.1, 203.0.113.7, 10.1.1.1")]);
+
+		// No trusted proxies: headers are ignored
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &xff, &[]),
+			Some(ip("10.0.0.1"))
+		);
+		// Untrusted peer: headers are ignored
+		assert_eq!(
+			resolve_client_ip(Some(ip("192.0.2.1")), &xff, &trusted),
+			Some(ip("192.0.2.1"))
+		);
+		// Trusted peer: take the first untrusted hop from the right,
+		// the client cannot spoof its address by adding more hops
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &xff, &trusted),
+			Some(ip("203.0.113.7"))
+		);
+		// Unix socket
+		assert_eq!(
+			resolve_client_ip(None, &xff, &trusted),
+			Some(ip("203.0.113.7"))
+		);
+		assert_eq!(resolve_client_ip(None, &xff, &[]), None);
+		// Trusted peer but no header
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &headers(&[]), &trusted),
+			Some(ip("10.0.0.1"))
+		);
+		// Invalid hop: the client is unknown, and is not taken to be the proxy
+		assert_eq!(
+			resolve_client_ip(
+				Some(ip("10.0.0.1")),
+				&headers(&[("x-forwarded-for", "198.51.100.1, garbage, 10.1.1.1")]),
+				&trusted
+			),
+			None
+		);
+		assert_eq!(
+			resolve_client_ip(
+				Some(ip("10.0.0.1")),
+				&headers(&[("x-forwarded-for", "garbage")]),
+				&trusted
+			),
+			None
+		);
+		// Invalid hops beyond the first untrusted one are never examined
+		assert_eq!(
+			resolve_client_ip(
+				Some(ip("10.0.0.1")),
+				&headers(&[("x-forwarded-for", "garbage, 198.51.100.1, 10.1.1.1")]),
+				&trusted
+			),
+			Some(ip("198.51.100.1"))
+		);
+
+		// Forwarded header has priority
+		let fwd = headers(&[
+			("x-forwarded-for", "198.51.100.1"),
+			(
+				"forwarded",
+				"for=192.0.2.43, for=\"[2001:db8:cafe::17]:4711\";proto=https",
+			),
+			("forwarded", "For=\"[fd00::1]\";by=10.0.0.1"),
+		]);
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &fwd, &trusted),
+			Some(ip("2001:db8:cafe::17"))
+		);
+		let fwd = headers(&[("forwarded", "for=_hidden, for=10.2.2.2")]);
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &fwd, &trusted),
+			None
+		);
+
+		// IPv4 peers and hops on a dual-stack socket
+		assert_eq!(
+			resolve_client_ip(Some(ip("::ffff:10.0.0.1")), &xff, &trusted),
+			Some(ip("203.0.113.7"))
+		);
+		assert_eq!(
+			resolve_client_ip(Some(ip("::ffff:192.0.2.1")), &xff, &[]),
+			Some(ip("192.0.2.1"))
+		);
+		let xff = headers(&[("x-forwarded-for", "198.51.100.1, ::ffff:10.1.1.1")]);
+		assert_eq!(
+			resolve_client_ip(Some(ip("10.0.0.1")), &xff, &trusted),
+			Some(ip("198.51.100.1"))
+		);
+	}
+}
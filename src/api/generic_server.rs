@@ -1,16 +1,25 @@
-use std::net::SocketAddr;
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 
 use futures::future::Future;
+use futures::stream::{self, Stream, StreamExt};
 
 use hyper::header::HeaderValue;
-use hyper::server::conn::AddrStream;
+use hyper::server::accept::{self, Accept};
+use hyper::server::conn::AddrIncoming;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
 use hyper::{HeaderMap, StatusCode};
 
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{UnixListener, UnixStream};
+
 use opentelemetry::{
 	global,
 	metrics::{Counter, ValueRecorder},
@@ -18,8 +27,17 @@ use opentelemetry::{
 	Context, KeyValue,
 };
 
+use garage_util::config::{ClientAddrConfig, UnixOrTCPSocketAddress};
 use garage_util::error::Error as GarageError;
 use garage_util::metrics::{gen_trace_id, RecordDuration};
+use garage_util::net::canonical_ip;
+
+use crate::client_addr::*;
+
+/// Maximum time a client has to send its PROXY protocol header
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of connections whose PROXY protocol header is being read at once
+const MAX_PENDING_PROXY_HEADERS: usize = 256;
 
 pub(crate) trait ApiEndpoint: Send + Sync + 'static {
 	fn name(&self) -> &'static str;
@@ -90,44 +108,38 @@ impl<A: ApiHandler> ApiServer<A> {
 
 	pub async fn run_server(
 		self: Arc<Self>,
-		bind_addr: SocketAddr,
+		bind_addr: UnixOrTCPSocketAddress,
+		unix_socket_mode: Option<u32>,
+		client_addr_config: ClientAddrConfig,
 		shutdown_signal: impl Future<Output = ()>,
 	) -> Result<(), GarageError> {
-		let service = make_service_fn(|conn: &AddrStream| {
-			let this = self.clone();
-
-			let client_addr = conn.remote_addr();
-			async move {
-				Ok::<_, GarageError>(service_fn(move |req: Request<Body>| {
-					let this = this.clone();
-
-					this.handler(req, client_addr)
-				}))
-			}
-		});
-
-		let server = Server::bind(&bind_addr).serve(service);
-
-		let graceful = server.with_graceful_shutdown(shutdown_signal);
 		info!(
-			"{} API server listening on http://{}",
+			"{} API server listening on {}",
 			A::API_NAME_DISPLAY,
 			bind_addr
 		);
 
-		graceful.await?;
-		Ok(())
+		serve_http(
+			&bind_addr,
+			unix_socket_mode,
+			client_addr_config,
+			move |req, client_addr| self.clone().handler(req, client_addr),
+			shutdown_signal,
+		)
+		.await
 	}
 
 	async fn handler(
 		self: Arc<Self>,
 		req: Request<Body>,
-		addr: SocketAddr,
+		addr: String,
 	) -> Result<Response<Body>, GarageError> {
 		let uri = req.uri().clone();
 		info!("{} {} {}", addr, req.method(), uri);
 		debug!("{:?}", req);
 
+		let client_ip = client_ip(&req).map(|ip| ip.to_string()).unwrap_or_default();
+
 		let tracer = opentelemetry::global::tracer("garage");
 		let span = tracer
 			.span_builder(format!("{} API call (unknown)", A::API_NAME_DISPLAY))
@@ -135,6 +147,7 @@ impl<A: ApiHandler> ApiServer<A> {
 			.with_attributes(vec![
 				KeyValue::new("method", format!("{}", req.method())),
 				KeyValue::new("uri", req.uri().to_string()),
+				KeyValue::new("client_ip", client_ip),
 			])
 			.start(&tracer);
 
@@ -209,3 +222,171 @@ impl<A: ApiHandler> ApiServer<A> {
 		res
 	}
 }
+
+/// Serve HTTP requests on the given address with `handler` until `shutdown_signal`
+/// completes. The handler is given the requests with the IP address of their client
+/// recorded in their extensions (see `client_ip`), and a description of
+/// the client to be used in logs.
+pub async fn serve_http<H, F, E>(
+	bind_addr: &UnixOrTCPSocketAddress,
+	unix_socket_mode: Option<u32>,
+	client_addr_config: ClientAddrConfig,
+	handler: H,
+	shutdown_signal: impl Future<Output = ()>,
+) -> Result<(), GarageError>
+where
+	H: Fn(Request<Body>, String) -> F + Clone + Send + Sync + 'static,
+	F: Future<Output = Result<Response<Body>, E>> + Send + 'static,
+	E: std::error::Error + Send + Sync + 'static,
+{
+	match bind_addr {
+		UnixOrTCPSocketAddress::TCPSocket(addr) => {
+			let mut incoming = AddrIncoming::bind(addr)?;
+			let conns = stream::poll_fn(move |cx| Pin::new(&mut incoming).poll_accept(cx))
+				.filter_map(|conn| async move {
+					let conn = conn.ok()?;
+					let peer = conn.remote_addr();
+					Some(ClientConn::new(conn, Some(peer)))
+				});
+			serve_connections(
+				conns,
+				bind_addr.to_string(),
+				client_addr_config,
+				handler,
+				shutdown_signal,
+			)
+			.await
+		}
+		UnixOrTCPSocketAddress::UnixSocket(path) => {
+			let conns =
+				bind_unix_socket(path, unix_socket_mode)?.map(|conn| ClientConn::new(conn, None));
+			serve_connections(
+				conns,
+				bind_addr.to_string(),
+				client_addr_config,
+				handler,
+				shutdown_signal,
+			)
+			.await
+		}
+	}
+}
+
+async fn serve_connections<C, S, H, F, E>(
+	conns: S,
+	listener_name: String,
+	client_addr_config: ClientAddrConfig,
+	handler: H,
+	shutdown_signal: impl Future<Output = ()>,
+) -> Result<(), GarageError>
+where
+	C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+	S: Stream<Item = ClientConn<C>> + Send + 'static,
+	H: Fn(Request<Body>, String) -> F + Clone + Send + Sync + 'static,
+	F: Future<Output = Result<Response<Body>, E>> + Send + 'static,
+	E: std::error::Error + Send + Sync + 'static,
+{
+	let ClientAddrConfig {
+		proxy_protocol,
+		trusted_proxies,
+	} = client_addr_config;
+	let trusted_proxies = Arc::new(trusted_proxies);
+
+	// If the PROXY protocol is used, the header of every connection is read
+	// before handing it to Hyper. This is done concurrently for incoming
+	// connections, so that a slow client does not block the others.
+	let listener_name = Arc::new(listener_name);
+	let conns_listener_name = listener_name.clone();
+	let conns = conns
+		.map(move |conn| {
+			let listener_name = conns_listener_name.clone();
+			async move {
+				if !proxy_protocol {
+					return Some(conn);
+				}
+				let peer = match conn.peer() {
+					Some(peer) => peer.to_string(),
+					None => listener_name.to_string(),
+				};
+				match tokio::time::timeout(PROXY_HEADER_TIMEOUT, conn.read_proxy_header()).await {
+					Ok(Ok(conn)) => Some(conn),
+					Ok(Err(e)) => {
+						warn!("Rejecting connection from {}: {}", peer, e);
+						None
+					}
+					Err(_) => {
+						warn!(
+							"Rejecting connection from {}: no PROXY header received",
+							peer
+						);
+						None
+					}
+				}
+			}
+		})
+		.buffer_unordered(MAX_PENDING_PROXY_HEADERS)
+		.filter_map(|conn| async move { conn.map(Ok::<_, std::io::Error>) });
+
+	let service = make_service_fn(move |conn: &ClientConn<C>| {
+		let peer = conn.peer();
+		let listener_name = listener_name.clone();
+		let trusted_proxies = trusted_proxies.clone();
+		let handler = handler.clone();
+		async move {
+			Ok::<_, GarageError>(service_fn(move |mut req: Request<Body>| {
+				let client_ip =
+					resolve_client_ip(peer.map(|p| p.ip()), req.headers(), &trusted_proxies);
+				let client_desc = match (client_ip, peer) {
+					(Some(ip), Some(peer)) if ip == canonical_ip(peer.ip()) => peer.to_string(),
+					(Some(ip), _) => ip.to_string(),
+					(None, _) => listener_name.to_string(),
+				};
+				if let Some(ip) = client_ip {
+					req.extensions_mut().insert(ClientIp(ip));
+				}
+
+				handler(req, client_desc)
+			}))
+		}
+	});
+
+	let server = Server::builder(accept::from_stream(conns)).serve(service);
+	server.with_graceful_shutdown(shutdown_signal).await?;
+	Ok(())
+}
+
+/// Bind a Unix domain socket at the given path and return the stream of incoming
+/// connections on it. A socket file left over by a previous run is removed first,
+/// and the socket's permissions are set to `mode` if it is specified.
+fn bind_unix_socket(
+	path: &Path,
+	mode: Option<u32>,
+) -> Result<impl Stream<Item = UnixStream>, GarageError> {
+	if let Ok(meta) = fs::symlink_metadata(path) {
+		if !meta.file_type().is_socket() {
+			return Err(GarageError::Message(format!(
+				"Cannot bind Unix socket at {}: file exists and is not a socket",
+				path.display()
+			)));
+		}
+		fs::remove_file(path)?;
+	}
+
+	let listener = UnixListener::bind(path)?;
+
+	if let Some(mode) = mode {
+		fs::set_permissions(path, Permissions::from_mode(mode))?;
+	}
+
+	Ok(stream::unfold(listener, |listener| async move {
+		loop {
+			match listener.accept().await {
+				Ok((conn, _)) => return Some((conn, listener)),
+				Err(e) => {
+					error!("Error accepting connection on Unix socket: {}", e);
+					tokio::time::sleep(Duration::from_millis(100)).await;
+				}
+			}
+		}
+	}))
+}
@@ -1,4 +1,3 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -8,10 +7,12 @@ use hyper::{Body, Method, Request, Response};
 
 use opentelemetry::{trace::SpanRef, KeyValue};
 
+use garage_util::config::{ClientAddrConfig, UnixOrTCPSocketAddress};
 use garage_util::error::Error as GarageError;
 
 use garage_model::garage::Garage;
 
+use crate::client_addr::client_ip;
 use crate::generic_server::*;
 use crate::k2v::error::*;
 
@@ -37,12 +38,19 @@ pub(crate) struct K2VApiEndpoint {
 impl K2VApiServer {
 	pub async fn run(
 		garage: Arc<Garage>,
-		bind_addr: SocketAddr,
+		bind_addr: UnixOrTCPSocketAddress,
+		unix_socket_mode: Option<u32>,
+		client_addr_config: ClientAddrConfig,
 		s3_region: String,
 		shutdown_signal: impl Future<Output = ()>,
 	) -> Result<(), GarageError> {
 		ApiServer::new(s3_region, K2VApiServer { garage })
-			.run_server(bind_addr, shutdown_signal)
+			.run_server(
+				bind_addr,
+				unix_socket_mode,
+				client_addr_config,
+				shutdown_signal,
+			)
 			.await
 	}
 }
@@ -83,11 +91,17 @@ impl ApiHandler for K2VApiServer {
 		}
 
 		let (api_key, mut content_sha256) = check_payload_signature(&garage, "k2v", &req).await?;
-		let api_key = api_key
+		let (api_key, secret_key) = api_key
 			.ok_or_else(|| Error::forbidden("Garage does not support anonymous access yet"))?;
 
+		if !api_key.allows_client_ip(client_ip(&req)) {
+			return Err(Error::forbidden(
+				"This key cannot be used from this IP address.",
+			));
+		}
+
 		let req = parse_streaming_body(
-			&api_key,
+			&secret_key,
 			req,
 			&mut content_sha256,
 			&garage.config.s3_api.s3_region,
@@ -103,17 +117,18 @@ impl ApiHandler for K2VApiServer {
 			.get_existing_bucket(bucket_id)
 			.await?;
 
-		let allowed = match endpoint.authorization_type() {
-			Authorization::Read => api_key.allow_read(&bucket_id),
-			Authorization::Write => api_key.allow_write(&bucket_id),
-			Authorization::Owner => api_key.allow_owner(&bucket_id),
-			_ => unreachable!(),
-		};
-
-		if !allowed {
+		let key_perm = api_key.bucket_permissions(&bucket_id);
+		if !key_perm.allows(endpoint.required_capability()) {
 			return Err(Error::forbidden("Operation is not allowed for this key."));
 		}
 
+		// Prefix restrictions are only supported on the S3 API
+		if !key_perm.prefixes.is_empty() {
+			return Err(Error::forbidden(
+				"This key only has permissions on some prefixes of the bucket, which is not supported by the K2V API.",
+			));
+		}
+
 		// Look up what CORS rule might apply to response.
 		// Requests for methods different than GET, HEAD or POST
 		// are always preflighted, i.e. the browser should make
@@ -132,7 +147,10 @@ impl ApiHandler for K2VApiServer {
 			Endpoint::InsertItem {
 				partition_key,
 				sort_key,
-			} => handle_insert_item(garage, req, bucket_id, &partition_key, &sort_key).await,
+			} => {
+				handle_insert_item(garage, req, bucket_id, &key_perm, &partition_key, &sort_key)
+					.await
+			}
 			Endpoint::ReadItem {
 				partition_key,
 				sort_key,
@@ -161,7 +179,9 @@ impl ApiHandler for K2VApiServer {
 				limit,
 				reverse,
 			} => handle_read_index(garage, bucket_id, prefix, start, end, limit, reverse).await,
-			Endpoint::InsertBatch {} => handle_insert_batch(garage, bucket_id, req).await,
+			Endpoint::InsertBatch {} => {
+				handle_insert_batch(garage, bucket_id, &key_perm, req).await
+			}
 			Endpoint::ReadBatch {} => handle_read_batch(garage, bucket_id, req).await,
 			Endpoint::DeleteBatch {} => handle_delete_batch(garage, bucket_id, req).await,
 			Endpoint::Options => unreachable!(),
@@ -11,6 +11,7 @@ use garage_table::{EnumerationOrder, TableSchema};
 use garage_model::garage::Garage;
 use garage_model::k2v::causality::*;
 use garage_model::k2v::item_table::*;
+use garage_model::permission::{BucketCapability, BucketKeyPerm};
 
 use crate::helpers::*;
 use crate::k2v::error::*;
@@ -19,12 +20,23 @@ use crate::k2v::range::read_range;
 pub async fn handle_insert_batch(
 	garage: Arc<Garage>,
 	bucket_id: Uuid,
+	key_perm: &BucketKeyPerm,
 	req: Request<Body>,
 ) -> Result<Response<Body>, Error> {
 	let items = parse_json_body::<Vec<InsertBatchItem>>(req).await?;
 
 	let mut items2 = vec![];
 	for it in items {
+		if it.ct.is_some() && !key_perm.allows(BucketCapability::Overwrite) {
+			return Err(Error::forbidden(
+				"This key is not allowed to overwrite existing values.",
+			));
+		}
+		if it.v.is_none() && !key_perm.allows(BucketCapability::Delete) {
+			return Err(Error::forbidden(
+				"This key is not allowed to delete values.",
+			));
+		}
 		let ct = it
 			.ct
 			.map(|s| CausalContext::parse(&s))
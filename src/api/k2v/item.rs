@@ -9,6 +9,7 @@ use garage_util::data::*;
 use garage_model::garage::Garage;
 use garage_model::k2v::causality::*;
 use garage_model::k2v::item_table::*;
+use garage_model::permission::{BucketCapability, BucketKeyPerm};
 
 use crate::k2v::error::*;
 
@@ -125,6 +126,7 @@ pub async fn handle_insert_item(
 	garage: Arc<Garage>,
 	req: Request<Body>,
 	bucket_id: Uuid,
+	key_perm: &BucketKeyPerm,
 	partition_key: &str,
 	sort_key: &str,
 ) -> Result<Response<Body>, Error> {
@@ -137,6 +139,12 @@ pub async fn handle_insert_item(
 		.transpose()
 		.ok_or_bad_request("Invalid causality token")?;
 
+	if causal_context.is_some() && !key_perm.allows(BucketCapability::Overwrite) {
+		return Err(Error::forbidden(
+			"This key is not allowed to overwrite existing values.",
+		));
+	}
+
 	let body = hyper::body::to_bytes(req.into_body()).await?;
 	let value = DvvsValue::Value(body.to_vec());
 
@@ -4,6 +4,8 @@ use std::borrow::Cow;
 
 use hyper::{Method, Request};
 
+use garage_model::permission::BucketCapability;
+
 use crate::helpers::Authorization;
 use crate::router_macros::{generateQueryParameters, router_match};
 
@@ -228,6 +230,18 @@ impl Endpoint {
 			Authorization::Write
 		}
 	}
+	/// Get the capability on the bucket which is required to perform the operation.
+	///
+	/// Inserting values that replace existing ones (i.e. with a causality token)
+	/// also requires the `Overwrite` capability, this is checked when handling them.
+	pub fn required_capability(&self) -> BucketCapability {
+		match self {
+			Self::ReadIndex { .. } => BucketCapability::List,
+			Self::InsertItem { .. } | Self::InsertBatch { .. } => BucketCapability::Create,
+			Self::DeleteItem { .. } | Self::DeleteBatch { .. } => BucketCapability::Delete,
+			_ => BucketCapability::Read,
+		}
+	}
 }
 
 // parameter name => struct field
@@ -2,11 +2,13 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod client_addr;
 pub mod common_error;
 
 mod encoding;
 pub mod generic_server;
 pub mod helpers;
+pub mod rate_limit;
 mod router_macros;
 /// This mode is public only to help testing. Don't expect stability here
 pub mod signature;
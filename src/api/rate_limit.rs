@@ -0,0 +1,258 @@
+//! Enforcement of the rate limits that can be set on access keys and buckets
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use hyper::Body;
+use tokio::sync::Mutex as AsyncMutex;
+
+use garage_util::data::Uuid;
+use garage_util::token_bucket::TokenBucket;
+
+use garage_model::rate_limits::RateLimits;
+
+/// Class of operation, each class has its own request rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+	Read,
+	Write,
+	List,
+}
+
+/// Direction of a data transfer, for bandwidth limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+	Upload,
+	Download,
+}
+
+/// Entity on which rate limits are set
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitScope {
+	Key(String),
+	Bucket(Uuid),
+}
+
+struct ScopeLimiters {
+	limits: RateLimits,
+	read: Option<TokenBucket>,
+	write: Option<TokenBucket>,
+	list: Option<TokenBucket>,
+	upload: Option<Arc<AsyncMutex<TokenBucket>>>,
+	download: Option<Arc<AsyncMutex<TokenBucket>>>,
+}
+
+impl ScopeLimiters {
+	fn new(limits: RateLimits) -> Self {
+		// Allow bursts of at most one second worth of tokens.
+		// Limits of zero are refused when they are set, and are
+		// ignored here if some were nevertheless stored.
+		let bucket = |rate: Option<u64>| {
+			rate.filter(|r| *r > 0)
+				.map(|r| TokenBucket::new_with_capacity(r, r))
+		};
+		Self {
+			limits,
+			read: bucket(limits.read_requests),
+			write: bucket(limits.write_requests),
+			list: bucket(limits.list_requests),
+			upload: bucket(limits.upload_bytes).map(|b| Arc::new(AsyncMutex::new(b))),
+			download: bucket(limits.download_bytes).map(|b| Arc::new(AsyncMutex::new(b))),
+		}
+	}
+}
+
+/// Keeps track of the token buckets of all keys and buckets that have
+/// rate limits. Limits are enforced independently on each node.
+#[derive(Default)]
+pub struct RateLimiter {
+	scopes: Mutex<HashMap<RateLimitScope, ScopeLimiters>>,
+}
+
+impl RateLimiter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn with_limiters<F, R>(&self, scope: &RateLimitScope, limits: &RateLimits, f: F) -> Option<R>
+	where
+		F: FnOnce(&mut ScopeLimiters) -> R,
+	{
+		let mut scopes = self.scopes.lock().unwrap();
+		if limits.is_unlimited() {
+			scopes.remove(scope);
+			return None;
+		}
+		let limiters = scopes
+			.entry(scope.clone())
+			.or_insert_with(|| ScopeLimiters::new(*limits));
+		if limiters.limits != *limits {
+			*limiters = ScopeLimiters::new(*limits);
+		}
+		Some(f(limiters))
+	}
+
+	/// Take a token for a request of the given class,
+	/// returns false if the request rate limit has been exceeded.
+	pub fn check_request(
+		&self,
+		scope: &RateLimitScope,
+		limits: &RateLimits,
+		class: RequestClass,
+	) -> bool {
+		self.with_limiters(scope, limits, |l| {
+			let bucket = match class {
+				RequestClass::Read => l.read.as_mut(),
+				RequestClass::Write => l.write.as_mut(),
+				RequestClass::List => l.list.as_mut(),
+			};
+			bucket.map(|b| b.try_take(1)).unwrap_or(true)
+		})
+		.unwrap_or(true)
+	}
+
+	/// Build a throttle that enforces the bandwidth limits of all the given scopes
+	pub fn bandwidth_throttle(
+		&self,
+		scopes: &[(RateLimitScope, &RateLimits)],
+		transfer: Transfer,
+	) -> BandwidthThrottle {
+		let buckets = scopes
+			.iter()
+			.filter_map(|(scope, limits)| {
+				self.with_limiters(scope, limits, |l| match transfer {
+					Transfer::Upload => l.upload.clone(),
+					Transfer::Download => l.download.clone(),
+				})
+				.flatten()
+			})
+			.collect();
+		BandwidthThrottle(buckets)
+	}
+}
+
+/// Limits the rate at which the data of a body is transferred
+#[derive(Clone, Default)]
+pub struct BandwidthThrottle(Vec<Arc<AsyncMutex<TokenBucket>>>);
+
+impl BandwidthThrottle {
+	pub fn is_unlimited(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn throttle_body(&self, body: Body) -> Body {
+		if self.is_unlimited() {
+			return body;
+		}
+		Body::wrap_stream(self.throttle_stream(body))
+	}
+
+	pub fn throttle_stream<'a, S, E>(&self, stream: S) -> BoxStream<'a, Result<Bytes, E>>
+	where
+		S: Stream<Item = Result<Bytes, E>> + Send + 'a,
+		E: Send + 'a,
+	{
+		if self.is_unlimited() {
+			return stream.boxed();
+		}
+		let buckets = self.0.clone();
+		stream
+			.then(move |chunk| {
+				let buckets = buckets.clone();
+				async move {
+					if let Ok(bytes) = &chunk {
+						for bucket in buckets.iter() {
+							bucket.lock().await.take(bytes.len() as u64).await;
+						}
+					}
+					chunk
+				}
+			})
+			.boxed()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_request_limits() {
+		let limiter = RateLimiter::new();
+		let scope = RateLimitScope::Key("GK123".into());
+		let limits = RateLimits {
+			write_requests: Some(2),
+			..Default::default()
+		};
+
+		assert!(limiter.check_request(&scope, &limits, RequestClass::Write));
+		assert!(limiter.check_request(&scope, &limits, RequestClass::Write));
+		assert!(!limiter.check_request(&scope, &limits, RequestClass::Write));
+		// Other classes are not limited
+		assert!(limiter.check_request(&scope, &limits, RequestClass::Read));
+
+		// Changing the limits resets the token buckets
+		let new_limits = RateLimits {
+			write_requests: Some(1),
+			..Default::default()
+		};
+		assert!(limiter.check_request(&scope, &new_limits, RequestClass::Write));
+		assert!(!limiter.check_request(&scope, &new_limits, RequestClass::Write));
+
+		// Removing the limits removes the scope
+		assert!(limiter.check_request(&scope, &RateLimits::default(), RequestClass::Write));
+		assert!(limiter.scopes.lock().unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_bandwidth_throttle() {
+		let limiter = RateLimiter::new();
+		let key_limits = RateLimits {
+			download_bytes: Some(1000),
+			..Default::default()
+		};
+		let bucket_limits = RateLimits {
+			upload_bytes: Some(1000),
+			..Default::default()
+		};
+		let scopes = [
+			(RateLimitScope::Key("GK123".into()), &key_limits),
+			(
+				RateLimitScope::Bucket(Uuid::from([0u8; 32])),
+				&bucket_limits,
+			),
+		];
+
+		assert_eq!(
+			limiter
+				.bandwidth_throttle(&scopes[..], Transfer::Download)
+				.0
+				.len(),
+			1
+		);
+		assert_eq!(
+			limiter
+				.bandwidth_throttle(&scopes[..], Transfer::Upload)
+				.0
+				.len(),
+			1
+		);
+		assert!(limiter
+			.bandwidth_throttle(&scopes[..1], Transfer::Upload)
+			.is_unlimited());
+	}
+
+	#[test]
+	fn test_zero_bandwidth_limit() {
+		let limiter = RateLimiter::new();
+		let limits = RateLimits {
+			download_bytes: Some(0),
+			..Default::default()
+		};
+		let scopes = [(RateLimitScope::Key("GK123".into()), &limits)];
+		assert!(limiter
+			.bandwidth_throttle(&scopes[..], Transfer::Download)
+			.is_unlimited());
+	}
+}
@@ -1,4 +1,3 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -9,11 +8,14 @@ use hyper::{Body, Request, Response};
 
 use opentelemetry::{trace::SpanRef, KeyValue};
 
+use garage_util::config::{ClientAddrConfig, UnixOrTCPSocketAddress};
 use garage_util::error::Error as GarageError;
 
+use garage_model::audit_table::AuditActor;
 use garage_model::garage::Garage;
 use garage_model::key_table::Key;
 
+use crate::client_addr::client_ip;
 use crate::generic_server::*;
 use crate::s3::error::*;
 
@@ -21,6 +23,7 @@ use crate::signature::payload::check_payload_signature;
 use crate::signature::streaming::*;
 
 use crate::helpers::*;
+use crate::rate_limit::*;
 use crate::s3::bucket::*;
 use crate::s3::copy::*;
 use crate::s3::cors::*;
@@ -34,6 +37,7 @@ use crate::s3::website::*;
 
 pub struct S3ApiServer {
 	garage: Arc<Garage>,
+	rate_limiter: Arc<RateLimiter>,
 }
 
 pub(crate) struct S3ApiEndpoint {
@@ -44,13 +48,22 @@ pub(crate) struct S3ApiEndpoint {
 impl S3ApiServer {
 	pub async fn run(
 		garage: Arc<Garage>,
-		addr: SocketAddr,
+		addr: UnixOrTCPSocketAddress,
+		unix_socket_mode: Option<u32>,
+		client_addr_config: ClientAddrConfig,
 		s3_region: String,
+		rate_limiter: Arc<RateLimiter>,
 		shutdown_signal: impl Future<Output = ()>,
 	) -> Result<(), GarageError> {
-		ApiServer::new(s3_region, S3ApiServer { garage })
-			.run_server(addr, shutdown_signal)
-			.await
+		ApiServer::new(
+			s3_region,
+			S3ApiServer {
+				garage,
+				rate_limiter,
+			},
+		)
+		.run_server(addr, unix_socket_mode, client_addr_config, shutdown_signal)
+		.await
 	}
 
 	async fn handle_request_without_bucket(
@@ -113,18 +126,37 @@ impl ApiHandler for S3ApiServer {
 
 		// Some endpoints are processed early, before we even check for an API key
 		if let Endpoint::PostObject = endpoint {
-			return handle_post_object(garage, req, bucket_name.unwrap()).await;
+			return handle_post_object(garage, &self.rate_limiter, req, bucket_name.unwrap()).await;
 		}
 		if let Endpoint::Options = endpoint {
 			return handle_options_s3api(garage, &req, bucket_name).await;
 		}
 
 		let (api_key, mut content_sha256) = check_payload_signature(&garage, "s3", &req).await?;
-		let api_key = api_key
+		let (api_key, secret_key) = api_key
 			.ok_or_else(|| Error::forbidden("Garage does not support anonymous access yet"))?;
 
+		if !api_key.allows_client_ip(client_ip(&req)) {
+			return Err(Error::forbidden(
+				"This key cannot be used from this IP address.",
+			));
+		}
+
+		let request_class = endpoint.request_class();
+		let key_scope = RateLimitScope::Key(api_key.key_id.clone());
+		let key_limits = api_key
+			.params()
+			.map(|p| *p.rate_limits.get())
+			.unwrap_or_default();
+		if !self
+			.rate_limiter
+			.check_request(&key_scope, &key_limits, request_class)
+		{
+			return Err(Error::SlowDown);
+		}
+
 		let req = parse_streaming_body(
-			&api_key,
+			&secret_key,
 			req,
 			&mut content_sha256,
 			&garage.config.s3_api.s3_region,
@@ -154,26 +186,87 @@ impl ApiHandler for S3ApiServer {
 			.get_existing_bucket(bucket_id)
 			.await?;
 
-		let allowed = match endpoint.authorization_type() {
-			Authorization::Read => api_key.allow_read(&bucket_id),
-			Authorization::Write => api_key.allow_write(&bucket_id),
-			Authorization::Owner => api_key.allow_owner(&bucket_id),
-			_ => unreachable!(),
-		};
-
-		if !allowed {
+		let key_perm = api_key.bucket_permissions(&bucket_id);
+		let capability = endpoint.required_capability().unwrap();
+		if !key_perm.allows(capability) {
 			return Err(Error::forbidden("Operation is not allowed for this key."));
 		}
 
+		if let Some(key) = endpoint.get_key() {
+			if !key_perm.applies_to_object(key) {
+				return Err(Error::forbidden(
+					"Operation is not allowed for this key on this object.",
+				));
+			}
+		}
+
+		if let Endpoint::PutObject { key }
+		| Endpoint::CopyObject { key }
+		| Endpoint::CreateMultipartUpload { key }
+		| Endpoint::CompleteMultipartUpload { key, .. } = &endpoint
+		{
+			check_overwrite_allowed(&garage, &key_perm, bucket_id, key).await?;
+		}
+
+		let bucket_scope = RateLimitScope::Bucket(bucket_id);
+		let bucket_limits = bucket
+			.params()
+			.map(|p| *p.rate_limits.get())
+			.unwrap_or_default();
+		if !self
+			.rate_limiter
+			.check_request(&bucket_scope, &bucket_limits, request_class)
+		{
+			return Err(Error::SlowDown);
+		}
+
+		let limit_scopes = [(key_scope, &key_limits), (bucket_scope, &bucket_limits)];
+		let upload_throttle = self
+			.rate_limiter
+			.bandwidth_throttle(&limit_scopes[..], Transfer::Upload);
+		let download_throttle = self
+			.rate_limiter
+			.bandwidth_throttle(&limit_scopes[..], Transfer::Download);
+		let req = req.map(|body| upload_throttle.throttle_body(body));
+
 		let matching_cors_rule = find_matching_cors_rule(&bucket, &req)?;
 
+		// Changes to the bucket itself are recorded in the audit log
+		let audit = matches!(
+			endpoint,
+			Endpoint::DeleteBucket {}
+				| Endpoint::PutBucketWebsite {}
+				| Endpoint::DeleteBucketWebsite {}
+				| Endpoint::PutBucketCors {}
+				| Endpoint::DeleteBucketCors {}
+		)
+		.then(|| {
+			garage
+				.audit_entry(
+					AuditActor::S3Key(api_key.key_id.clone()),
+					endpoint.name(),
+					&bucket_name,
+				)
+				.with_param("bucket_id", hex::encode(bucket_id))
+		});
+
 		let resp = match endpoint {
 			Endpoint::HeadObject {
 				key, part_number, ..
 			} => handle_head(garage, &req, bucket_id, &key, part_number).await,
 			Endpoint::GetObject {
 				key, part_number, ..
-			} => handle_get(garage, &req, bucket_id, &key, part_number).await,
+			} => {
+				handle_get(
+					garage,
+					&req,
+					bucket_id,
+					&key,
+					part_number,
+					&download_throttle,
+				)
+				.await
+			}
 			Endpoint::UploadPart {
 				key,
 				part_number,
@@ -182,7 +275,7 @@ impl ApiHandler for S3ApiServer {
 				handle_put_part(
 					garage,
 					req,
-					bucket_id,
+					&bucket,
 					&key,
 					part_number,
 					&upload_id,
@@ -202,7 +295,7 @@ impl ApiHandler for S3ApiServer {
 					garage,
 					&api_key,
 					&req,
-					bucket_id,
+					&bucket,
 					&key,
 					part_number,
 					&upload_id,
@@ -259,6 +352,7 @@ impl ApiHandler for S3ApiServer {
 							page_size: max_keys.map(|p| p.clamp(1, 1000)).unwrap_or(1000),
 							prefix: prefix.unwrap_or_default(),
 							urlencode_resp: encoding_type.map(|e| e == "url").unwrap_or(false),
+							allowed_prefixes: key_perm.prefixes.clone(),
 						},
 						is_v2: false,
 						marker,
@@ -289,6 +383,7 @@ impl ApiHandler for S3ApiServer {
 								page_size: max_keys.map(|p| p.clamp(1, 1000)).unwrap_or(1000),
 								urlencode_resp: encoding_type.map(|e| e == "url").unwrap_or(false),
 								prefix: prefix.unwrap_or_default(),
+								allowed_prefixes: key_perm.prefixes.clone(),
 							},
 							is_v2: true,
 							marker: None,
@@ -322,6 +417,7 @@ impl ApiHandler for S3ApiServer {
 							page_size: max_uploads.map(|p| p.clamp(1, 1000)).unwrap_or(1000),
 							prefix: prefix.unwrap_or_default(),
 							urlencode_resp: encoding_type.map(|e| e == "url").unwrap_or(false),
+							allowed_prefixes: key_perm.prefixes.clone(),
 						},
 						key_marker,
 						upload_id_marker,
@@ -349,7 +445,7 @@ impl ApiHandler for S3ApiServer {
 				.await
 			}
 			Endpoint::DeleteObjects {} => {
-				handle_delete_objects(garage, bucket_id, req, content_sha256).await
+				handle_delete_objects(garage, bucket_id, &key_perm, req, content_sha256).await
 			}
 			Endpoint::GetBucketWebsite {} => handle_get_website(&bucket).await,
 			Endpoint::PutBucketWebsite {} => {
@@ -364,6 +460,10 @@ impl ApiHandler for S3ApiServer {
 			endpoint => Err(Error::NotImplemented(endpoint.name().to_owned())),
 		};
 
+		if let (Ok(_), Some(audit)) = (&resp, audit) {
+			self.garage.record_audit(audit).await;
+		}
+
 		// If request was a success and we have a CORS rule that applies to it,
 		// add the corresponding CORS headers to the response
 		let mut resp_ok = resp?;
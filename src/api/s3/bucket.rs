@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use hyper::{Body, Request, Response, StatusCode};
 
+use garage_model::audit_table::AuditActor;
 use garage_model::bucket_alias_table::*;
 use garage_model::bucket_table::Bucket;
 use garage_model::garage::Garage;
@@ -181,6 +182,15 @@ pub async fn handle_create_bucket(
 			.bucket_helper()
 			.set_local_bucket_alias(bucket.id, &api_key.key_id, &bucket_name)
 			.await?;
+
+		let audit = garage
+			.audit_entry(
+				AuditActor::S3Key(api_key.key_id.clone()),
+				"CreateBucket",
+				&bucket_name,
+			)
+			.with_param("bucket_id", hex::encode(bucket.id));
+		garage.record_audit(audit).await;
 	}
 
 	Ok(Response::builder()
@@ -15,8 +15,10 @@ use garage_table::*;
 use garage_util::data::*;
 use garage_util::time::*;
 
+use garage_model::bucket_table::Bucket;
 use garage_model::garage::Garage;
 use garage_model::key_table::Key;
+use garage_model::permission::BucketCapability;
 use garage_model::s3::block_ref_table::*;
 use garage_model::s3::object_table::*;
 use garage_model::s3::version_table::*;
@@ -172,11 +174,13 @@ pub async fn handle_upload_part_copy(
 	garage: Arc<Garage>,
 	api_key: &Key,
 	req: &Request<Body>,
-	dest_bucket_id: Uuid,
+	dest_bucket: &Bucket,
 	dest_key: &str,
 	part_number: u64,
 	upload_id: &str,
 ) -> Result<Response<Body>, Error> {
+	let dest_bucket_id = dest_bucket.id;
+	let put_params = garage.put_block_params(dest_bucket);
 	let copy_precondition = CopyPreconditionHeaders::parse(req)?;
 
 	let dest_version_uuid = decode_upload_id(upload_id)?;
@@ -368,12 +372,16 @@ pub async fn handle_upload_part_copy(
 		};
 
 		let garage2 = garage.clone();
+		let put_params = &put_params;
 		let res = futures::try_join!(
 			// Thing 1: if the block is not exactly a block that existed before,
 			// we need to insert that data as a new block.
 			async move {
 				if must_upload {
-					garage2.block_manager.rpc_put_block(final_hash, data).await
+					garage2
+						.block_manager
+						.rpc_put_block(final_hash, data, put_params)
+						.await
 				} else {
 					Ok(())
 				}
@@ -426,7 +434,7 @@ async fn get_copy_source(
 		.resolve_bucket(&source_bucket.to_string(), api_key)
 		.await?;
 
-	if !api_key.allow_read(&source_bucket_id) {
+	if !api_key.allows(&source_bucket_id, BucketCapability::Read) {
 		return Err(Error::forbidden(format!(
 			"Reading from bucket {} not allowed for this key",
 			source_bucket
@@ -435,6 +443,16 @@ async fn get_copy_source(
 
 	let source_key = source_key.ok_or_bad_request("No source key specified")?;
 
+	if !api_key
+		.bucket_permissions(&source_bucket_id)
+		.applies_to_object(source_key)
+	{
+		return Err(Error::forbidden(format!(
+			"Reading object {} from bucket {} not allowed for this key",
+			source_key, source_bucket
+		)));
+	}
+
 	let source_object = garage
 		.object_table
 		.get(&source_bucket_id, &source_key.to_string())
@@ -6,6 +6,7 @@ use garage_util::data::*;
 use garage_util::time::*;
 
 use garage_model::garage::Garage;
+use garage_model::permission::BucketKeyPerm;
 use garage_model::s3::object_table::*;
 
 use crate::s3::error::*;
@@ -76,6 +77,7 @@ pub async fn handle_delete(
 pub async fn handle_delete_objects(
 	garage: Arc<Garage>,
 	bucket_id: Uuid,
+	key_perm: &BucketKeyPerm,
 	req: Request<Body>,
 	content_sha256: Option<Hash>,
 ) -> Result<Response<Body>, Error> {
@@ -92,7 +94,14 @@ pub async fn handle_delete_objects(
 	let mut ret_errors = Vec::new();
 
 	for obj in cmd.objects.iter() {
-		match handle_delete_internal(&garage, bucket_id, &obj.key).await {
+		let res = if key_perm.applies_to_object(&obj.key) {
+			handle_delete_internal(&garage, bucket_id, &obj.key).await
+		} else {
+			Err(Error::forbidden(
+				"Operation is not allowed for this key on this object.",
+			))
+		};
+		match res {
 			Ok((deleted_version, delete_marker_version)) => {
 				if cmd.quiet {
 					continue;
@@ -73,6 +73,11 @@ pub enum Error {
 	/// The client sent a request for an action not supported by garage
 	#[error(display = "Unimplemented action: {}", _0)]
 	NotImplemented(String),
+
+	// Category: rate limiting
+	/// The request rate limit of the access key or of the bucket was exceeded
+	#[error(display = "Please reduce your request rate")]
+	SlowDown,
 }
 
 impl<T> From<T> for Error
@@ -141,6 +146,7 @@ impl Error {
 			Error::EntityTooSmall => "EntityTooSmall",
 			Error::AuthorizationHeaderMalformed(_) => "AuthorizationHeaderMalformed",
 			Error::NotImplemented(_) => "NotImplemented",
+			Error::SlowDown => "SlowDown",
 			Error::InvalidXml(_) => "MalformedXML",
 			Error::InvalidRange(_) => "InvalidRange",
 			Error::InvalidUtf8Str(_) | Error::InvalidUtf8String(_) | Error::InvalidHeader(_) => {
@@ -159,6 +165,7 @@ impl ApiError for Error {
 			Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
 			Error::InvalidRange(_) => StatusCode::RANGE_NOT_SATISFIABLE,
 			Error::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+			Error::SlowDown => StatusCode::SERVICE_UNAVAILABLE,
 			Error::AuthorizationHeaderMalformed(_)
 			| Error::InvalidPart
 			| Error::InvalidPartOrder
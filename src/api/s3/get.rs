@@ -20,6 +20,7 @@ use garage_model::garage::Garage;
 use garage_model::s3::object_table::*;
 use garage_model::s3::version_table::*;
 
+use crate::rate_limit::BandwidthThrottle;
 use crate::s3::error::*;
 
 const X_AMZ_MP_PARTS_COUNT: &str = "x-amz-mp-parts-count";
@@ -183,6 +184,7 @@ pub async fn handle_get(
 	bucket_id: Uuid,
 	key: &str,
 	part_number: Option<u64>,
+	throttle: &BandwidthThrottle,
 ) -> Result<Response<Body>, Error> {
 	let object = garage
 		.object_table
@@ -218,7 +220,7 @@ pub async fn handle_get(
 			));
 		}
 		(Some(pn), None) => {
-			return handle_get_part(garage, last_v, last_v_data, last_v_meta, pn).await;
+			return handle_get_part(garage, last_v, last_v_data, last_v_meta, pn, throttle).await;
 		}
 		(None, Some(range)) => {
 			return handle_get_range(
@@ -228,6 +230,7 @@ pub async fn handle_get(
 				last_v_meta,
 				range.start,
 				range.start + range.length,
+				throttle,
 			)
 			.await;
 		}
@@ -242,7 +245,7 @@ pub async fn handle_get(
 		ObjectVersionData::DeleteMarker => unreachable!(),
 		ObjectVersionData::Inline(_, bytes) => {
 			let body: Body = Body::from(bytes.to_vec());
-			Ok(resp_builder.body(body)?)
+			Ok(resp_builder.body(throttle.throttle_body(body))?)
 		}
 		ObjectVersionData::FirstBlock(_, first_block_hash) => {
 			let (tx, rx) = mpsc::channel(2);
@@ -297,7 +300,7 @@ pub async fn handle_get(
 			let body_stream = tokio_stream::wrappers::ReceiverStream::new(rx).flatten();
 
 			let body = hyper::body::Body::wrap_stream(body_stream);
-			Ok(resp_builder.body(body)?)
+			Ok(resp_builder.body(throttle.throttle_body(body))?)
 		}
 	}
 }
@@ -309,6 +312,7 @@ async fn handle_get_range(
 	version_meta: &ObjectVersionMeta,
 	begin: u64,
 	end: u64,
+	throttle: &BandwidthThrottle,
 ) -> Result<Response<Body>, Error> {
 	let resp_builder = object_headers(version, version_meta)
 		.header(CONTENT_LENGTH, format!("{}", end - begin))
@@ -323,7 +327,7 @@ async fn handle_get_range(
 		ObjectVersionData::Inline(_meta, bytes) => {
 			if end as usize <= bytes.len() {
 				let body: Body = Body::from(bytes[begin as usize..end as usize].to_vec());
-				Ok(resp_builder.body(body)?)
+				Ok(resp_builder.body(throttle.throttle_body(body))?)
 			} else {
 				Err(Error::internal_error(
 					"Requested range not present in inline bytes when it should have been",
@@ -338,7 +342,7 @@ async fn handle_get_range(
 				.ok_or(Error::NoSuchKey)?;
 
 			let body = body_from_blocks_range(garage, version.blocks.items(), begin, end);
-			Ok(resp_builder.body(body)?)
+			Ok(resp_builder.body(throttle.throttle_body(body))?)
 		}
 	}
 }
@@ -349,6 +353,7 @@ async fn handle_get_part(
 	version_data: &ObjectVersionData,
 	version_meta: &ObjectVersionMeta,
 	part_number: u64,
+	throttle: &BandwidthThrottle,
 ) -> Result<Response<Body>, Error> {
 	let resp_builder =
 		object_headers(object_version, version_meta).status(StatusCode::PARTIAL_CONTENT);
@@ -365,7 +370,7 @@ async fn handle_get_part(
 					format!("bytes {}-{}/{}", 0, bytes.len() - 1, bytes.len()),
 				)
 				.header(X_AMZ_MP_PARTS_COUNT, "1")
-				.body(Body::from(bytes.to_vec()))?)
+				.body(throttle.throttle_body(Body::from(bytes.to_vec())))?)
 		}
 		ObjectVersionData::FirstBlock(_, _) => {
 			let version = garage
@@ -387,7 +392,7 @@ async fn handle_get_part(
 					format!("bytes {}-{}/{}", begin, end - 1, version_meta.size),
 				)
 				.header(X_AMZ_MP_PARTS_COUNT, format!("{}", n_parts))
-				.body(body)?)
+				.body(throttle.throttle_body(body))?)
 		}
 		_ => unreachable!(),
 	}
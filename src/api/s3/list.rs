@@ -32,6 +32,19 @@ pub struct ListQueryCommon {
 	pub page_size: usize,
 	pub prefix: String,
 	pub urlencode_resp: bool,
+	/// If the key only has permissions on some prefixes of the bucket,
+	/// objects outside of these prefixes are not listed
+	pub allowed_prefixes: Vec<String>,
+}
+
+impl ListQueryCommon {
+	fn is_listable(&self, key: &str) -> bool {
+		self.allowed_prefixes.is_empty()
+			|| self
+				.allowed_prefixes
+				.iter()
+				.any(|p| key.starts_with(p.as_str()))
+	}
 }
 
 #[derive(Debug)]
@@ -415,7 +428,10 @@ where
 		let server_more = objects.len() >= count;
 
 		let prev_req_cursor = cursor.clone();
-		let mut iter = objects.iter().peekable();
+		let mut iter = objects
+			.iter()
+			.filter(|o| query.is_listable(&o.key))
+			.peekable();
 
 		// Drop the first key if needed
 		// Only AfterKey requires it according to the S3 spec and our implem.
@@ -450,7 +466,21 @@ where
 		}
 
 		if prev_req_cursor == cursor {
-			unreachable!("No progress has been done in the loop. This is a bug, please report it.");
+			match objects.last() {
+				// All the objects of this batch were filtered out because
+				// of the key's prefix restrictions, continue after them
+				Some(last) if !query.allowed_prefixes.is_empty() => {
+					if !last.key.starts_with(&query.prefix) {
+						return Ok(None);
+					}
+					cursor = RangeBegin::AfterKey {
+						key: last.key.clone(),
+					};
+				}
+				_ => unreachable!(
+					"No progress has been done in the loop. This is a bug, please report it."
+				),
+			}
 		}
 	}
 }
@@ -967,6 +997,7 @@ mod tests {
 				urlencode_resp: false,
 				bucket_name: "a".to_string(),
 				bucket_id: Uuid::from([0x00; 32]),
+				allowed_prefixes: vec![],
 			},
 			key_marker: None,
 			upload_id_marker: None,
@@ -1166,6 +1197,38 @@ mod tests {
 		Ok(())
 	}
 
+	#[tokio::test]
+	async fn test_fetch_uploads_allowed_prefixes() -> Result<(), Error> {
+		let mut query = query();
+		query.common.page_size = 1;
+		query.common.allowed_prefixes = vec!["d".to_string()];
+
+		let mut fake_io = |_, k: Option<String>, _| async move {
+			Ok(match k.as_deref() {
+				Some("") => vec![
+					Object::new(bucket(), "a/x".to_string(), vec![objup_version([0x01; 32])]),
+					Object::new(bucket(), "a/y".to_string(), vec![objup_version([0x01; 32])]),
+				],
+				Some("a/y") => vec![
+					Object::new(bucket(), "a/y".to_string(), vec![objup_version([0x01; 32])]),
+					Object::new(bucket(), "d".to_string(), vec![objup_version([0x02; 32])]),
+				],
+				Some("d") => vec![],
+				_ => panic!("wrong value {:?}", k),
+			})
+		};
+
+		let mut acc = query.build_accumulator();
+		let page =
+			fetch_list_entries(&query.common, query.begin()?, &mut acc, &mut fake_io).await?;
+		assert_eq!(page, None);
+		assert_eq!(acc.common_prefixes.len(), 0);
+		assert_eq!(acc.keys.len(), 1);
+		assert_eq!(acc.keys.values().next().unwrap().key, "d");
+
+		Ok(())
+	}
+
 	fn version() -> Version {
 		let uuid = Uuid::from([0x08; 32]);
 
@@ -13,14 +13,18 @@ use multer::{Constraints, Multipart, SizeLimit};
 use serde::Deserialize;
 
 use garage_model::garage::Garage;
+use garage_model::permission::BucketCapability;
 
+use crate::client_addr::ClientIp;
+use crate::rate_limit::{RateLimitScope, RateLimiter, RequestClass, Transfer};
 use crate::s3::error::*;
-use crate::s3::put::{get_headers, save_stream};
+use crate::s3::put::{check_overwrite_allowed, get_headers, save_stream};
 use crate::s3::xml as s3_xml;
 use crate::signature::payload::{parse_date, verify_v4};
 
 pub async fn handle_post_object(
 	garage: Arc<Garage>,
+	rate_limiter: &RateLimiter,
 	req: Request<Body>,
 	bucket_name: String,
 ) -> Result<Response<Body>, Error> {
@@ -114,7 +118,7 @@ pub async fn handle_post_object(
 	};
 
 	let date = parse_date(date)?;
-	let api_key = verify_v4(
+	let (api_key, _) = verify_v4(
 		&garage,
 		"s3",
 		credential,
@@ -124,20 +128,55 @@ pub async fn handle_post_object(
 	)
 	.await?;
 
+	if !api_key.allows_client_ip(head.extensions.get::<ClientIp>().map(|x| x.0)) {
+		return Err(Error::forbidden(
+			"This key cannot be used from this IP address.",
+		));
+	}
+
 	let bucket_id = garage
 		.bucket_helper()
 		.resolve_bucket(&bucket_name, &api_key)
 		.await?;
 
-	if !api_key.allow_write(&bucket_id) {
+	let key_perm = api_key.bucket_permissions(&bucket_id);
+	if !key_perm.allows(BucketCapability::Create) {
 		return Err(Error::forbidden("Operation is not allowed for this key."));
 	}
 
+	if !key_perm.applies_to_object(&key) {
+		return Err(Error::forbidden(
+			"Operation is not allowed for this key on this object.",
+		));
+	}
+
+	check_overwrite_allowed(&garage, &key_perm, bucket_id, &key).await?;
+
 	let bucket = garage
 		.bucket_helper()
 		.get_existing_bucket(bucket_id)
 		.await?;
 
+	let key_scope = RateLimitScope::Key(api_key.key_id.clone());
+	let key_limits = api_key
+		.params()
+		.map(|p| *p.rate_limits.get())
+		.unwrap_or_default();
+	let bucket_scope = RateLimitScope::Bucket(bucket_id);
+	let bucket_limits = bucket
+		.params()
+		.map(|p| *p.rate_limits.get())
+		.unwrap_or_default();
+	if !rate_limiter.check_request(&key_scope, &key_limits, RequestClass::Write)
+		|| !rate_limiter.check_request(&bucket_scope, &bucket_limits, RequestClass::Write)
+	{
+		return Err(Error::SlowDown);
+	}
+	let upload_throttle = rate_limiter.bandwidth_throttle(
+		&[(key_scope, &key_limits), (bucket_scope, &bucket_limits)],
+		Transfer::Upload,
+	);
+
 	let decoded_policy = base64::decode(&policy).ok_or_bad_request("Invalid policy")?;
 	let decoded_policy: Policy =
 		serde_json::from_slice(&decoded_policy).ok_or_bad_request("Invalid policy")?;
@@ -227,7 +266,7 @@ pub async fn handle_post_object(
 
 	let headers = get_headers(&params)?;
 
-	let stream = field.map(|r| r.map_err(Into::into));
+	let stream = upload_throttle.throttle_stream(field.map(|r| r.map_err(Into::into)));
 	let (_, md5) = save_stream(
 		garage,
 		headers,
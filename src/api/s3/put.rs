@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 
+use fastcdc::v2020::FastCDC;
 use futures::prelude::*;
 use hyper::body::{Body, Bytes};
 use hyper::header::{HeaderMap, HeaderValue};
@@ -20,10 +21,11 @@ use garage_util::data::*;
 use garage_util::error::Error as GarageError;
 use garage_util::time::*;
 
-use garage_block::manager::INLINE_THRESHOLD;
+use garage_block::manager::{PutBlockParams, INLINE_THRESHOLD};
 use garage_model::bucket_table::Bucket;
-use garage_model::garage::Garage;
+use garage_model::garage::{Chunking, Garage};
 use garage_model::index_counter::CountedItem;
+use garage_model::permission::{BucketCapability, BucketKeyPerm};
 use garage_model::s3::block_ref_table::*;
 use garage_model::s3::object_table::*;
 use garage_model::s3::version_table::*;
@@ -32,6 +34,31 @@ use crate::s3::error::*;
 use crate::s3::xml as s3_xml;
 use crate::signature::verify_signed_content;
 
+/// Writing an object at a key where an object already exists requires
+/// the `Overwrite` capability in addition to the `Create` capability
+pub async fn check_overwrite_allowed(
+	garage: &Garage,
+	key_perm: &BucketKeyPerm,
+	bucket_id: Uuid,
+	key: &str,
+) -> Result<(), Error> {
+	if key_perm.allows(BucketCapability::Overwrite) {
+		return Ok(());
+	}
+	let exists = garage
+		.object_table
+		.get(&bucket_id, &key.to_string())
+		.await?
+		.map(|o| o.versions().iter().any(|v| v.is_data()))
+		.unwrap_or(false);
+	if exists {
+		return Err(Error::forbidden(
+			"This key is not allowed to overwrite existing objects.",
+		));
+	}
+	Ok(())
+}
+
 pub async fn handle_put(
 	garage: Arc<Garage>,
 	req: Request<Body>,
@@ -77,7 +104,7 @@ pub(crate) async fn save_stream<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
 	let version_uuid = gen_uuid();
 	let version_timestamp = now_msec();
 
-	let mut chunker = StreamChunker::new(body, garage.config.block_size);
+	let mut chunker = StreamChunker::new(body, garage.chunking(bucket));
 	let first_block = chunker.next().await?.unwrap_or_default();
 
 	// If body is small enough, store it directly in the object table
@@ -143,6 +170,7 @@ pub(crate) async fn save_stream<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
 		let (total_size, data_md5sum, data_sha256sum) = read_and_put_blocks(
 			&garage,
 			&version,
+			&garage.put_block_params(bucket),
 			1,
 			first_block,
 			first_block_hash,
@@ -279,6 +307,7 @@ async fn check_quotas(
 async fn read_and_put_blocks<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
 	garage: &Garage,
 	version: &Version,
+	put_params: &PutBlockParams,
 	part_number: u64,
 	first_block: Bytes,
 	first_block_hash: Hash,
@@ -307,9 +336,10 @@ async fn read_and_put_blocks<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
 		first_block_hash,
 		first_block.len() as u64,
 	);
-	let mut put_curr_block = garage
-		.block_manager
-		.rpc_put_block(first_block_hash, first_block);
+	let mut put_curr_block =
+		garage
+			.block_manager
+			.rpc_put_block(first_block_hash, first_block, put_params);
 
 	loop {
 		let (_, _, next_block) = futures::try_join!(
@@ -336,7 +366,9 @@ async fn read_and_put_blocks<S: Stream<Item = Result<Bytes, Error>> + Unpin>(
 				block_hash,
 				block_len as u64,
 			);
-			put_curr_block = garage.block_manager.rpc_put_block(block_hash, block);
+			put_curr_block = garage
+				.block_manager
+				.rpc_put_block(block_hash, block, put_params);
 			next_offset += block_len;
 		} else {
 			break;
@@ -385,22 +417,27 @@ async fn put_block_meta(
 struct StreamChunker<S: Stream<Item = Result<Bytes, Error>>> {
 	stream: S,
 	read_all: bool,
-	block_size: usize,
+	chunking: Chunking,
 	buf: BytesBuf,
 }
 
 impl<S: Stream<Item = Result<Bytes, Error>> + Unpin> StreamChunker<S> {
-	fn new(stream: S, block_size: usize) -> Self {
+	fn new(stream: S, chunking: Chunking) -> Self {
 		Self {
 			stream,
 			read_all: false,
-			block_size,
+			chunking,
 			buf: BytesBuf::new(),
 		}
 	}
 
 	async fn next(&mut self) -> Result<Option<Bytes>, Error> {
-		while !self.read_all && self.buf.len() < self.block_size {
+		let max_size = match self.chunking {
+			Chunking::FixedSize(block_size) => block_size,
+			Chunking::ContentDefined { max_size, .. } => max_size,
+		};
+
+		while !self.read_all && self.buf.len() < max_size {
 			if let Some(block) = self.stream.next().await {
 				let bytes = block?;
 				trace!("Body next: {} bytes", bytes.len());
@@ -411,9 +448,28 @@ impl<S: Stream<Item = Result<Bytes, Error>> + Unpin> StreamChunker<S> {
 		}
 
 		if self.buf.is_empty() {
-			Ok(None)
-		} else {
-			Ok(Some(self.buf.take_max(self.block_size)))
+			return Ok(None);
+		}
+
+		match self.chunking {
+			Chunking::FixedSize(block_size) => Ok(Some(self.buf.take_max(block_size))),
+			Chunking::ContentDefined {
+				min_size,
+				avg_size,
+				max_size,
+			} => {
+				// The cut point only depends on the data between the start of the chunk
+				// and max_size bytes later, so it is the same whatever follows
+				let data = self.buf.take_all();
+				let cut = FastCDC::new(&data, min_size as u32, avg_size as u32, max_size as u32)
+					.next()
+					.map(|chunk| chunk.length)
+					.unwrap_or(data.len());
+				if cut < data.len() {
+					self.buf.extend(data.slice(cut..));
+				}
+				Ok(Some(data.slice(..cut)))
+			}
 		}
 	}
 }
@@ -467,12 +523,13 @@ pub async fn handle_create_multipart_upload(
 pub async fn handle_put_part(
 	garage: Arc<Garage>,
 	req: Request<Body>,
-	bucket_id: Uuid,
+	bucket: &Bucket,
 	key: &str,
 	part_number: u64,
 	upload_id: &str,
 	content_sha256: Option<Hash>,
 ) -> Result<Response<Body>, Error> {
+	let bucket_id = bucket.id;
 	let version_uuid = decode_upload_id(upload_id)?;
 
 	let content_md5 = match req.headers().get("content-md5") {
@@ -484,7 +541,7 @@ pub async fn handle_put_part(
 	let key = key.to_string();
 
 	let body = req.into_body().map_err(Error::from);
-	let mut chunker = StreamChunker::new(body, garage.config.block_size);
+	let mut chunker = StreamChunker::new(body, garage.chunking(bucket));
 
 	let (object, version, first_block) = futures::try_join!(
 		garage
@@ -528,6 +585,7 @@ pub async fn handle_put_part(
 	let (_, data_md5sum, data_sha256sum) = read_and_put_blocks(
 		&garage,
 		&version,
+		&garage.put_block_params(bucket),
 		part_number,
 		first_block,
 		first_block_hash,
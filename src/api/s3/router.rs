@@ -3,7 +3,10 @@ use std::borrow::Cow;
 use hyper::header::HeaderValue;
 use hyper::{HeaderMap, Method, Request};
 
+use garage_model::permission::BucketCapability;
+
 use crate::helpers::Authorization;
+use crate::rate_limit::RequestClass;
 use crate::router_macros::{generateQueryParameters, router_match};
 use crate::s3::error::*;
 
@@ -516,7 +519,6 @@ impl Endpoint {
 	}
 
 	/// Get the key the request target. Returns None for requests which don't use a key.
-	#[allow(dead_code)]
 	pub fn get_key(&self) -> Option<&str> {
 		router_match! {
 			@extract
@@ -620,6 +622,81 @@ impl Endpoint {
 			Authorization::Write
 		}
 	}
+
+	/// Get the capability on the bucket which is required to perform the operation.
+	/// Returns None for requests which are not made on a bucket.
+	///
+	/// Operations that write an object also require the `Overwrite` capability
+	/// if an object already exists at that key, this is checked when handling them.
+	pub fn required_capability(&self) -> Option<BucketCapability> {
+		let list = router_match! {
+			@match
+			self,
+			[
+				ListMultipartUploads,
+				ListObjects,
+				ListObjectsV2,
+				ListObjectVersions,
+				ListParts,
+			]
+		};
+		let create = router_match! {
+			@match
+			self,
+			[
+				AbortMultipartUpload,
+				CompleteMultipartUpload,
+				CopyObject,
+				CreateMultipartUpload,
+				PostObject,
+				PutObject,
+				UploadPart,
+				UploadPartCopy,
+			]
+		};
+		let delete = router_match! {
+			@match
+			self,
+			[
+				DeleteObject,
+				DeleteObjects,
+			]
+		};
+		let cap = match self.authorization_type() {
+			Authorization::None => return None,
+			Authorization::Read if list => BucketCapability::List,
+			Authorization::Read => BucketCapability::Read,
+			Authorization::Owner => BucketCapability::Owner,
+			Authorization::Write if create => BucketCapability::Create,
+			Authorization::Write if delete => BucketCapability::Delete,
+			// Other write operations modify existing objects or bucket settings
+			Authorization::Write => BucketCapability::Overwrite,
+		};
+		Some(cap)
+	}
+
+	/// Get the class of operation this endpoint belongs to, for rate limiting purposes.
+	pub fn request_class(&self) -> RequestClass {
+		let list = router_match! {
+			@match
+			self,
+			[
+				ListBuckets,
+				ListObjects,
+				ListObjectsV2,
+				ListObjectVersions,
+				ListMultipartUploads,
+				ListParts,
+			]
+		};
+		if list {
+			RequestClass::List
+		} else if let Authorization::Read = self.authorization_type() {
+			RequestClass::Read
+		} else {
+			RequestClass::Write
+		}
+	}
 }
 
 // parameter name => struct field
@@ -821,6 +898,31 @@ mod tests {
 		 */
 	}
 
+	#[test]
+	fn test_required_capability() {
+		let cap = |method, uri| parse(method, uri, None, None).0.required_capability();
+		assert_eq!(cap("GET", "/"), None);
+		assert_eq!(cap("GET", "/my_bucket/key"), Some(BucketCapability::Read));
+		assert_eq!(
+			cap("GET", "/my_bucket/?list-type=2"),
+			Some(BucketCapability::List)
+		);
+		assert_eq!(cap("PUT", "/my_bucket/key"), Some(BucketCapability::Create));
+		assert_eq!(
+			cap("PUT", "/my_bucket/key?tagging"),
+			Some(BucketCapability::Overwrite)
+		);
+		assert_eq!(
+			cap("DELETE", "/my_bucket/key"),
+			Some(BucketCapability::Delete)
+		);
+		assert_eq!(
+			cap("POST", "/my_bucket/?delete"),
+			Some(BucketCapability::Delete)
+		);
+		assert_eq!(cap("DELETE", "/my_bucket/"), Some(BucketCapability::Owner));
+	}
+
 	#[test]
 	fn invalid_endpoint() {
 		let req = Request::builder()
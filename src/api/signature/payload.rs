@@ -7,6 +7,7 @@ use sha2::{Digest, Sha256};
 
 use garage_table::*;
 use garage_util::data::Hash;
+use garage_util::time::now_msec;
 
 use garage_model::garage::Garage;
 use garage_model::key_table::*;
@@ -17,11 +18,13 @@ use super::{compute_scope, signing_hmac};
 use crate::encoding::uri_encode;
 use crate::signature::error::*;
 
+/// Check the signature of a request, returns the key that was used to sign it
+/// along with the matching secret, and the expected hash of the payload if any
 pub async fn check_payload_signature(
 	garage: &Garage,
 	service: &str,
 	request: &Request<Body>,
-) -> Result<(Option<Key>, Option<Hash>), Error> {
+) -> Result<(Option<(Key, String)>, Option<Hash>), Error> {
 	let mut headers = HashMap::new();
 	for (key, val) in request.headers() {
 		headers.insert(key.to_string(), val.to_str()?.to_string());
@@ -290,7 +293,7 @@ pub async fn verify_v4(
 	date: &DateTime<Utc>,
 	signature: &str,
 	payload: &[u8],
-) -> Result<Key, Error> {
+) -> Result<(Key, String), Error> {
 	let (key_id, scope) = parse_credential(credential)?;
 
 	let scope_expected = compute_scope(date, &garage.config.s3_api.s3_region, service);
@@ -306,18 +309,26 @@ pub async fn verify_v4(
 		.ok_or_else(|| Error::forbidden(format!("No such key: {}", &key_id)))?;
 	let key_p = key.params().unwrap();
 
-	let mut hmac = signing_hmac(
-		date,
-		&key_p.secret_key,
-		&garage.config.s3_api.s3_region,
-		service,
-	)
-	.ok_or_internal_error("Unable to build signing HMAC")?;
-	hmac.update(payload);
-	let our_signature = hex::encode(hmac.finalize().into_bytes());
-	if signature != our_signature {
-		return Err(Error::forbidden("Invalid signature".to_string()));
+	// During a secret rotation, several secrets can be valid at the same time
+	let mut secret_key = None;
+	for secret in key_p.valid_secrets(now_msec()) {
+		let mut hmac = signing_hmac(date, secret, &garage.config.s3_api.s3_region, service)
+			.ok_or_internal_error("Unable to build signing HMAC")?;
+		hmac.update(payload);
+		let our_signature = hex::encode(hmac.finalize().into_bytes());
+		if signature == our_signature {
+			secret_key = Some(secret.to_string());
+			break;
+		}
+	}
+	let secret_key = secret_key.ok_or_else(|| Error::forbidden("Invalid signature".to_string()))?;
+
+	if !key_p.is_active() {
+		return Err(Error::forbidden(format!(
+			"Key {} is disabled or expired",
+			&key_id
+		)));
 	}
 
-	Ok(key)
+	Ok((key, secret_key))
 }
@@ -3,7 +3,6 @@ use std::pin::Pin;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use futures::prelude::*;
 use futures::task;
-use garage_model::key_table::Key;
 use hmac::Mac;
 use hyper::body::Bytes;
 use hyper::{Body, Request};
@@ -15,7 +14,7 @@ use super::{compute_scope, sha256sum, HmacSha256, LONG_DATETIME};
 use crate::signature::error::*;
 
 pub fn parse_streaming_body(
-	api_key: &Key,
+	secret_key: &str,
 	req: Request<Body>,
 	content_sha256: &mut Option<Hash>,
 	region: &str,
@@ -27,12 +26,6 @@ pub fn parse_streaming_body(
 				.take()
 				.ok_or_bad_request("No signature provided")?;
 
-			let secret_key = &api_key
-				.state
-				.as_option()
-				.ok_or_internal_error("Deleted key state")?
-				.secret_key;
-
 			let date = req
 				.headers()
 				.get("x-amz-date")
@@ -5,7 +5,14 @@ use zstd::stream::{decode_all as zstd_decode, Encoder};
 use garage_util::data::*;
 use garage_util::error::*;
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+/// Size of the samples of a block used to estimate whether it is compressible
+const COMPRESSIBILITY_SAMPLE_SIZE: usize = 4096;
+/// Number of samples used to estimate whether a block is compressible
+const COMPRESSIBILITY_SAMPLES: usize = 8;
+/// Entropy (in bits per byte) above which data is considered incompressible
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.5;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub enum DataBlockHeader {
 	Plain,
 	Compressed,
@@ -67,11 +74,17 @@ impl DataBlock {
 		}
 	}
 
+	/// Build a block from data, compressing it with the given zstd level unless it
+	/// looks incompressible or compression does not make it smaller
 	pub async fn from_buffer(data: Bytes, level: Option<i32>) -> DataBlock {
 		tokio::task::spawn_blocking(move || {
 			if let Some(level) = level {
-				if let Ok(data) = zstd_encode(&data[..], level) {
-					return DataBlock::Compressed(data.into());
+				if is_compressible(&data[..]) {
+					if let Ok(compressed) = zstd_encode(&data[..], level) {
+						if compressed.len() < data.len() {
+							return DataBlock::Compressed(compressed.into());
+						}
+					}
 				}
 			}
 			DataBlock::Plain(data)
@@ -80,6 +93,13 @@ impl DataBlock {
 		.unwrap()
 	}
 
+	pub fn header(&self) -> DataBlockHeader {
+		match self {
+			DataBlock::Plain(_) => DataBlockHeader::Plain,
+			DataBlock::Compressed(_) => DataBlockHeader::Compressed,
+		}
+	}
+
 	pub fn into_parts(self) -> (DataBlockHeader, Bytes) {
 		match self {
 			DataBlock::Plain(data) => (DataBlockHeader::Plain, data),
@@ -95,6 +115,39 @@ impl DataBlock {
 	}
 }
 
+/// Estimate whether data is worth compressing, from the entropy of the
+/// distribution of bytes in a few samples of it. Data that is already
+/// compressed or encrypted (JPEG images, videos, archives...) looks like
+/// random bytes, with an entropy close to 8 bits per byte.
+pub fn is_compressible(data: &[u8]) -> bool {
+	let mut counts = [0u64; 256];
+	let mut count = |sample: &[u8]| {
+		for b in sample.iter() {
+			counts[*b as usize] += 1;
+		}
+	};
+
+	if data.len() <= COMPRESSIBILITY_SAMPLE_SIZE * COMPRESSIBILITY_SAMPLES {
+		count(data);
+	} else {
+		let step = (data.len() - COMPRESSIBILITY_SAMPLE_SIZE) / (COMPRESSIBILITY_SAMPLES - 1);
+		for i in 0..COMPRESSIBILITY_SAMPLES {
+			count(&data[i * step..i * step + COMPRESSIBILITY_SAMPLE_SIZE]);
+		}
+	}
+
+	let total = counts.iter().sum::<u64>() as f64;
+	let entropy = counts
+		.iter()
+		.filter(|c| **c > 0)
+		.map(|c| {
+			let p = *c as f64 / total;
+			-p * p.log2()
+		})
+		.sum::<f64>();
+	entropy < INCOMPRESSIBLE_ENTROPY
+}
+
 fn zstd_encode<R: std::io::Read>(mut source: R, level: i32) -> std::io::Result<Vec<u8>> {
 	let mut result = Vec::<u8>::new();
 	let mut encoder = Encoder::new(&mut result, level)?;
@@ -103,3 +156,27 @@ fn zstd_encode<R: std::io::Read>(mut source: R, level: i32) -> std::io::Result<V
 	encoder.finish()?;
 	Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_compressible() {
+		let mut x = 1u32;
+		let random = (0..1_000_000)
+			.map(|_| {
+				x = x.wrapping_mul(1103515245).wrapping_add(12345);
+				(x >> 16) as u8
+			})
+			.collect::<Vec<_>>();
+		assert!(!is_compressible(&random));
+		assert!(!is_compressible(&random[..10_000]));
+
+		let text = "Garage is an S3-compatible distributed object storage service. "
+			.repeat(20_000)
+			.into_bytes();
+		assert!(is_compressible(&text));
+		assert!(is_compressible(&[0u8; 100_000]));
+	}
+}
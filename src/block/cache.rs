@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use garage_util::config::BlockCacheConfig;
+use garage_util::data::*;
+use garage_util::error::*;
+
+/// Part of the cache where a block was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheTier {
+	Ram,
+	Disk,
+}
+
+impl CacheTier {
+	pub(crate) fn as_str(&self) -> &'static str {
+		match self {
+			CacheTier::Ram => "ram",
+			CacheTier::Disk => "disk",
+		}
+	}
+}
+
+/// Cache of the (uncompressed and verified) content of frequently read blocks,
+/// kept in RAM and optionally in a directory on a local disk.
+/// Blocks never change once written, so cached blocks never need to be invalidated.
+pub(crate) struct BlockCache {
+	ram: Option<Mutex<LruIndex<Bytes>>>,
+	disk: Option<DiskCache>,
+}
+
+struct DiskCache {
+	dir: PathBuf,
+	index: Mutex<LruIndex<()>>,
+}
+
+impl BlockCache {
+	pub(crate) fn new(config: &BlockCacheConfig) -> Result<Self, Error> {
+		let ram = match &config.ram_size {
+			Some(size) => Some(Mutex::new(LruIndex::new(parse_size("ram_size", size)?))),
+			None => None,
+		};
+
+		let disk = match (&config.disk_dir, &config.disk_size) {
+			(Some(dir), Some(size)) => Some(DiskCache::open(
+				dir.clone(),
+				parse_size("disk_size", size)?,
+			)?),
+			(None, None) => None,
+			_ => {
+				return Err(Error::Message(
+					"disk_dir and disk_size of the block cache must be given together".into(),
+				))
+			}
+		};
+
+		if ram.is_none() && disk.is_none() {
+			return Err(Error::Message(
+				"The block cache must have a ram_size or a disk_dir".into(),
+			));
+		}
+
+		Ok(Self { ram, disk })
+	}
+
+	/// Get the content of a block from the cache, if it is there
+	pub(crate) async fn get(&self, hash: &Hash) -> Option<(Bytes, CacheTier)> {
+		if let Some(ram) = &self.ram {
+			if let Some(data) = ram.lock().unwrap().get(hash) {
+				return Some((data.clone(), CacheTier::Ram));
+			}
+		}
+
+		if let Some(disk) = &self.disk {
+			if let Some(data) = disk.get(hash).await {
+				self.put_ram(hash, &data);
+				return Some((data, CacheTier::Disk));
+			}
+		}
+
+		None
+	}
+
+	/// Add the content of a block that has been read to the cache
+	pub(crate) async fn put(&self, hash: &Hash, data: &Bytes) {
+		self.put_ram(hash, data);
+
+		if let Some(disk) = &self.disk {
+			if let Err(e) = disk.put(hash, data).await {
+				warn!("Could not write block {:?} to the disk cache: {}", hash, e);
+			}
+		}
+	}
+
+	fn put_ram(&self, hash: &Hash, data: &Bytes) {
+		if let Some(ram) = &self.ram {
+			ram.lock()
+				.unwrap()
+				.insert(*hash, data.len() as u64, data.clone());
+		}
+	}
+}
+
+impl DiskCache {
+	fn open(dir: PathBuf, capacity: u64) -> Result<Self, Error> {
+		std::fs::create_dir_all(&dir)?;
+
+		// Blocks cached by a previous run are still valid, index them
+		// (they are checked when they are read)
+		let mut index = LruIndex::new(capacity);
+		let mut to_delete = vec![];
+		for ent in std::fs::read_dir(&dir)? {
+			let ent = ent?;
+			let hash = ent
+				.file_name()
+				.to_str()
+				.and_then(|name| hex::decode(name).ok())
+				.and_then(|bytes| Hash::try_from(&bytes));
+			match hash {
+				Some(hash) => {
+					let evicted = index.insert(hash, ent.metadata()?.len(), ());
+					to_delete.extend(evicted.iter().map(|h| dir.join(hex::encode(h))));
+				}
+				None => to_delete.push(ent.path()),
+			}
+		}
+		for path in to_delete {
+			std::fs::remove_file(path)?;
+		}
+
+		Ok(Self {
+			dir,
+			index: Mutex::new(index),
+		})
+	}
+
+	fn path(&self, hash: &Hash) -> PathBuf {
+		self.dir.join(hex::encode(hash))
+	}
+
+	async fn get(&self, hash: &Hash) -> Option<Bytes> {
+		self.index.lock().unwrap().get(hash)?;
+
+		let data = fs::read(self.path(hash)).await.ok().map(Bytes::from);
+		match data {
+			Some(data) if blake2sum(&data) == *hash => Some(data),
+			_ => {
+				warn!("Block {:?} is missing or corrupted in the disk cache", hash);
+				self.index.lock().unwrap().remove(hash);
+				let _ = fs::remove_file(self.path(hash)).await;
+				None
+			}
+		}
+	}
+
+	async fn put(&self, hash: &Hash, data: &Bytes) -> Result<(), Error> {
+		let size = data.len() as u64;
+		{
+			let mut index = self.index.lock().unwrap();
+			if size > index.capacity || index.get(hash).is_some() {
+				return Ok(());
+			}
+		}
+
+		// The same block can be put concurrently by several readers,
+		// each of them writes it to a different temporary file
+		let path = self.path(hash);
+		let mut tmp_path = path.clone();
+		tmp_path.set_extension(format!("tmp-{:016x}", rand::random::<u64>()));
+		let mut f = fs::File::create(&tmp_path).await?;
+		f.write_all(data).await?;
+		drop(f);
+		fs::rename(&tmp_path, &path).await?;
+
+		// Make room for the block, evicted blocks being removed from the index
+		// only once their file is deleted so that no file is left behind
+		loop {
+			let evicted = {
+				let mut index = self.index.lock().unwrap();
+				if index.get(hash).is_some() || index.size + size <= index.capacity {
+					index.insert(*hash, size, ());
+					return Ok(());
+				}
+				index.lru_entries(size)
+			};
+			for old in evicted {
+				match fs::remove_file(self.path(&old)).await {
+					Ok(()) => (),
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+					Err(e) => {
+						let _ = fs::remove_file(&path).await;
+						return Err(e.into());
+					}
+				}
+				self.index.lock().unwrap().remove(&old);
+			}
+		}
+	}
+}
+
+fn parse_size(name: &str, size: &str) -> Result<u64, Error> {
+	size.parse::<bytesize::ByteSize>()
+		.map(|s| s.as_u64())
+		.map_err(|e| Error::Message(format!("Invalid {} of the block cache: {}", name, e)))
+}
+
+// ---- LRU index ----
+
+/// Entries of a cache with a maximum total size, evicted in least-recently-used order
+struct LruIndex<V> {
+	capacity: u64,
+	size: u64,
+	next_use: u64,
+	entries: HashMap<Hash, LruEntry<V>>,
+	by_use: BTreeMap<u64, Hash>,
+}
+
+struct LruEntry<V> {
+	last_use: u64,
+	size: u64,
+	value: V,
+}
+
+impl<V> LruIndex<V> {
+	fn new(capacity: u64) -> Self {
+		Self {
+			capacity,
+			size: 0,
+			next_use: 0,
+			entries: HashMap::new(),
+			by_use: BTreeMap::new(),
+		}
+	}
+
+	/// Get an entry, marking it as the most recently used
+	fn get(&mut self, hash: &Hash) -> Option<&V> {
+		let entry = self.entries.get_mut(hash)?;
+		self.by_use.remove(&entry.last_use);
+		entry.last_use = self.next_use;
+		self.by_use.insert(self.next_use, *hash);
+		self.next_use += 1;
+		Some(&entry.value)
+	}
+
+	/// Add an entry (unless it is larger than the capacity),
+	/// returning the entries that were evicted to make room for it
+	fn insert(&mut self, hash: Hash, size: u64, value: V) -> Vec<Hash> {
+		if size > self.capacity || self.get(&hash).is_some() {
+			return vec![];
+		}
+
+		let mut evicted = vec![];
+		while self.size + size > self.capacity {
+			let first_use = *self.by_use.keys().next().unwrap();
+			let old = self.by_use.remove(&first_use).unwrap();
+			let old_entry = self.entries.remove(&old).unwrap();
+			self.size -= old_entry.size;
+			evicted.push(old);
+		}
+
+		self.entries.insert(
+			hash,
+			LruEntry {
+				last_use: self.next_use,
+				size,
+				value,
+			},
+		);
+		self.by_use.insert(self.next_use, hash);
+		self.next_use += 1;
+		self.size += size;
+		evicted
+	}
+
+	/// Least recently used entries that must be evicted
+	/// to make room for an entry of the given size
+	fn lru_entries(&self, size: u64) -> Vec<Hash> {
+		let mut freed = 0;
+		self.by_use
+			.values()
+			.take_while(|hash| {
+				let more = self.size - freed + size > self.capacity;
+				freed += self.entries[*hash].size;
+				more
+			})
+			.copied()
+			.collect()
+	}
+
+	fn remove(&mut self, hash: &Hash) {
+		if let Some(entry) = self.entries.remove(hash) {
+			self.by_use.remove(&entry.last_use);
+			self.size -= entry.size;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_lru_index() {
+		let h = |i: u8| blake2sum(&[i]);
+		let mut lru = LruIndex::new(100);
+
+		assert!(lru.insert(h(1), 40, 1).is_empty());
+		assert!(lru.insert(h(2), 40, 2).is_empty());
+		// Entries larger than the capacity are not cached
+		assert!(lru.insert(h(3), 101, 3).is_empty());
+		assert!(lru.get(&h(3)).is_none());
+
+		// Block 1 was used more recently than block 2, so block 2 is evicted
+		assert_eq!(lru.get(&h(1)), Some(&1));
+		assert_eq!(lru.insert(h(4), 40, 4), vec![h(2)]);
+		assert!(lru.get(&h(2)).is_none());
+
+		// Evicting several entries to make room
+		assert_eq!(lru.insert(h(5), 90, 5), vec![h(1), h(4)]);
+		assert_eq!(lru.size, 90);
+
+		assert!(lru.insert(h(6), 5, 6).is_empty());
+		assert_eq!(lru.lru_entries(5), vec![]);
+		assert_eq!(lru.lru_entries(10), vec![h(5)]);
+		assert_eq!(lru.lru_entries(100), vec![h(5), h(6)]);
+
+		lru.remove(&h(5));
+		lru.remove(&h(6));
+		assert_eq!(lru.size, 0);
+		assert!(lru.by_use.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_disk_cache() {
+		let dir = std::env::temp_dir().join(format!(
+			"garage-test-disk-cache-{:016x}",
+			rand::random::<u64>()
+		));
+		let cache = DiskCache::open(dir.clone(), 100).unwrap();
+		let block = |i: u8| Bytes::from(vec![i; 40]);
+		let h = |i: u8| blake2sum(&block(i));
+
+		// Concurrent puts of the same block
+		let (h1, b1) = (h(1), block(1));
+		let (r1, r2) = futures::join!(cache.put(&h1, &b1), cache.put(&h1, &b1));
+		r1.unwrap();
+		r2.unwrap();
+		cache.put(&h(2), &block(2)).await.unwrap();
+		assert_eq!(cache.get(&h(1)).await, Some(block(1)));
+
+		// Block 2 is evicted to make room for block 3, and its file is deleted
+		cache.put(&h(3), &block(3)).await.unwrap();
+		assert!(cache.get(&h(2)).await.is_none());
+		let mut files = std::fs::read_dir(&dir)
+			.unwrap()
+			.map(|ent| ent.unwrap().file_name().into_string().unwrap())
+			.collect::<Vec<_>>();
+		files.sort();
+		let mut expected = vec![hex::encode(h(1)), hex::encode(h(3))];
+		expected.sort();
+		assert_eq!(files, expected);
+
+		// Blocks larger than the cache are not written
+		let large = Bytes::from(vec![4; 101]);
+		cache.put(&blake2sum(&large), &large).await.unwrap();
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+		// Cached blocks are found again when the cache is reopened
+		drop(cache);
+		let cache = DiskCache::open(dir.clone(), 100).unwrap();
+		assert_eq!(cache.get(&h(3)).await, Some(block(3)));
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
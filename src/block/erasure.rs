@@ -0,0 +1,390 @@
+//! Reed-Solomon erasure coding of data blocks
+use std::convert::TryInto;
+use std::path::Path;
+
+use bytes::Bytes;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use garage_util::data::*;
+use garage_util::error::*;
+
+use crate::block::*;
+
+/// Maximum length of the header of a shard file, which is much larger than
+/// the few dozen bytes it actually takes
+const MAX_SHARD_HEADER_LEN: usize = 4096;
+
+/// How the data of a block is stored on the nodes responsible for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockStorageMode {
+	/// Each node stores a full copy of the block
+	Replicated,
+	/// The block is split in `data_shards` shards, and `parity_shards` parity
+	/// shards are computed, any `data_shards` shards being enough to reconstruct
+	/// it. Each of the first `data_shards + parity_shards` nodes storing the block
+	/// stores one shard, the other nodes storing an empty placeholder shard.
+	ErasureCoded {
+		data_shards: usize,
+		parity_shards: usize,
+	},
+}
+
+/// Header of a shard of an erasure coded block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataShardHeader {
+	/// Index of the shard: data shards come first, then parity shards,
+	/// then placeholders (see `DataShard::placeholder`)
+	pub index: usize,
+	pub data_shards: usize,
+	pub parity_shards: usize,
+	/// Whether the encoded block is compressed
+	pub block: DataBlockHeader,
+	/// Length of the encoded block, the last data shard being padded with zeroes
+	pub block_len: usize,
+	/// Checksum of the data of this shard, as a shard cannot be verified
+	/// against the hash of the block on its own
+	pub checksum: Hash,
+}
+
+/// A shard of an erasure coded block
+#[derive(Debug, Clone)]
+pub struct DataShard {
+	pub header: DataShardHeader,
+	pub data: Bytes,
+}
+
+impl DataShard {
+	/// Empty shard with the given index, stored by the nodes that do not store
+	/// one of the shards of a block so that they know that they do not need to
+	/// fetch it. It is ignored when reconstructing the block.
+	pub fn placeholder(header: &DataShardHeader, index: usize) -> Self {
+		Self {
+			header: DataShardHeader {
+				index,
+				checksum: blake2sum(&[]),
+				..*header
+			},
+			data: Bytes::new(),
+		}
+	}
+
+	/// Whether this shard is a placeholder that contains no data of the block
+	pub fn is_placeholder(&self) -> bool {
+		self.header.index >= self.header.data_shards + self.header.parity_shards
+	}
+
+	/// Verify the integrity of the shard data
+	pub fn verify(&self, hash: Hash) -> Result<(), Error> {
+		if blake2sum(&self.data[..]) == self.header.checksum {
+			Ok(())
+		} else {
+			Err(Error::CorruptData(hash))
+		}
+	}
+
+	/// Content of the file in which the shard is stored on disk:
+	/// the length of the header on 4 bytes, the header, and the data
+	pub fn to_file_content(&self) -> Result<Bytes, Error> {
+		let header = rmp_to_vec_all_named(&self.header)?;
+		let mut content = Vec::with_capacity(4 + header.len() + self.data.len());
+		content.extend_from_slice(&(header.len() as u32).to_be_bytes());
+		content.extend_from_slice(&header);
+		content.extend_from_slice(&self.data);
+		Ok(content.into())
+	}
+
+	/// Parse the content of a shard file, see `to_file_content`
+	pub fn from_file_content(content: Bytes) -> Result<Self, Error> {
+		let header_len = Self::header_len(&content)?;
+		let header = rmp_serde::decode::from_read_ref(&content[4..4 + header_len])?;
+		Ok(Self {
+			header,
+			data: content.slice(4 + header_len..),
+		})
+	}
+
+	/// Length of the header at the beginning of a shard file
+	pub fn header_len(content: &[u8]) -> Result<usize, Error> {
+		let len = content
+			.get(..4)
+			.ok_or_message("Truncated shard file")?
+			.try_into()
+			.map(u32::from_be_bytes)
+			.unwrap() as usize;
+		if len > MAX_SHARD_HEADER_LEN {
+			return Err(Error::Message(format!(
+				"Invalid shard header length: {}",
+				len
+			)));
+		}
+		if content.len() < 4 + len {
+			return Err(Error::Message("Truncated shard file".into()));
+		}
+		Ok(len)
+	}
+}
+
+/// What a node stores for a block: a full copy, or one of its shards
+pub(crate) enum StoredBlock {
+	Full(DataBlock),
+	Shard(DataShard),
+}
+
+impl StoredBlock {
+	/// Size of the data stored
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Full(block) => block.inner_buffer().len(),
+			Self::Shard(shard) => shard.data.len(),
+		}
+	}
+
+	/// Verify the integrity of the data stored
+	pub fn verify(&self, hash: Hash) -> Result<(), Error> {
+		match self {
+			Self::Full(block) => block.verify(hash),
+			Self::Shard(shard) => shard.verify(hash),
+		}
+	}
+}
+
+/// Read only the header of a shard file
+pub(crate) async fn read_shard_header(path: &Path) -> Result<DataShardHeader, Error> {
+	let mut f = fs::File::open(path).await?;
+	let mut len = [0u8; 4];
+	f.read_exact(&mut len).await?;
+	let len = u32::from_be_bytes(len) as usize;
+	if len > MAX_SHARD_HEADER_LEN {
+		return Err(Error::Message(format!(
+			"Invalid shard header length: {}",
+			len
+		)));
+	}
+	let mut header = vec![0u8; len];
+	f.read_exact(&mut header).await?;
+	Ok(rmp_serde::decode::from_read_ref(&header)?)
+}
+
+/// Split a block in `data_shards` data shards and compute `parity_shards` parity shards
+pub fn encode_block(
+	block: &DataBlock,
+	data_shards: usize,
+	parity_shards: usize,
+) -> Result<Vec<DataShard>, Error> {
+	let rs = reed_solomon(data_shards, parity_shards)?;
+
+	let buf = block.inner_buffer();
+	let mut shard_len = buf.len() / data_shards;
+	if shard_len == 0 || shard_len * data_shards < buf.len() {
+		shard_len += 1;
+	}
+	let mut shards = (0..data_shards + parity_shards)
+		.map(|i| {
+			let start = std::cmp::min(i * shard_len, buf.len());
+			let end = std::cmp::min(start + shard_len, buf.len());
+			let mut shard = vec![0u8; shard_len];
+			if i < data_shards {
+				shard[..end - start].copy_from_slice(&buf[start..end]);
+			}
+			shard
+		})
+		.collect::<Vec<_>>();
+	rs.encode(&mut shards)
+		.map_err(|e| Error::Message(format!("Erasure coding failed: {:?}", e)))?;
+
+	let block_header = block.header();
+	Ok(shards
+		.into_iter()
+		.enumerate()
+		.map(|(index, data)| DataShard {
+			header: DataShardHeader {
+				index,
+				data_shards,
+				parity_shards,
+				block: block_header,
+				block_len: buf.len(),
+				checksum: blake2sum(&data[..]),
+			},
+			data: data.into(),
+		})
+		.collect())
+}
+
+/// Get the shard that should be stored by the node at position `index`
+/// among the nodes storing a block, from all the shards of the block
+pub fn node_shard(shards: &[DataShard], index: usize) -> DataShard {
+	match shards.get(index) {
+		Some(shard) => shard.clone(),
+		None => DataShard::placeholder(&shards[0].header, index),
+	}
+}
+
+/// Reconstruct a block from at least `data_shards` distinct shards of it.
+/// Placeholder shards are ignored.
+pub fn decode_block(shards: &[DataShard]) -> Result<DataBlock, Error> {
+	let header = shards
+		.first()
+		.ok_or_message("No shard to reconstruct block from")?
+		.header;
+	let rs = reed_solomon(header.data_shards, header.parity_shards)?;
+
+	let mut slots: Vec<Option<Vec<u8>>> = vec![None; header.data_shards + header.parity_shards];
+	for shard in shards.iter() {
+		let h = &shard.header;
+		if h.data_shards != header.data_shards
+			|| h.parity_shards != header.parity_shards
+			|| h.block_len != header.block_len
+		{
+			return Err(Error::Message(
+				"Shards of the block have inconsistent headers".into(),
+			));
+		}
+		if !shard.is_placeholder() {
+			slots[h.index] = Some(shard.data.to_vec());
+		}
+	}
+	rs.reconstruct_data(&mut slots)
+		.map_err(|e| Error::Message(format!("Unable to reconstruct block: {:?}", e)))?;
+
+	let mut buf = slots
+		.into_iter()
+		.take(header.data_shards)
+		.map(Option::unwrap)
+		.collect::<Vec<_>>()
+		.concat();
+	buf.truncate(header.block_len);
+	Ok(DataBlock::from_parts(header.block, buf.into()))
+}
+
+/// Number of distinct shards in a set of shards, not counting placeholders
+pub fn distinct_shards(shards: &[DataShard]) -> usize {
+	let mut indices = shards
+		.iter()
+		.filter(|s| !s.is_placeholder())
+		.map(|s| s.header.index)
+		.collect::<Vec<_>>();
+	indices.sort_unstable();
+	indices.dedup();
+	indices.len()
+}
+
+fn reed_solomon(data_shards: usize, parity_shards: usize) -> Result<ReedSolomon, Error> {
+	ReedSolomon::new(data_shards, parity_shards).map_err(|e| {
+		Error::Message(format!(
+			"Invalid erasure coding parameters ({} data shards, {} parity shards): {:?}",
+			data_shards, parity_shards, e
+		))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_erasure_coding() {
+		let data = (0..10_001u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+		let hash = blake2sum(&data[..]);
+		let block = DataBlock::Plain(data.clone().into());
+
+		let shards = encode_block(&block, 3, 2).unwrap();
+		assert_eq!(shards.len(), 5);
+		for shard in shards.iter() {
+			shard.verify(hash).unwrap();
+			assert_eq!(shard.data.len(), 3334);
+		}
+
+		// Any 3 shards are enough to reconstruct the block
+		for missing in [[0, 1], [1, 4], [3, 4], [0, 2]] {
+			let remaining = shards
+				.iter()
+				.filter(|s| !missing.contains(&s.header.index))
+				.cloned()
+				.collect::<Vec<_>>();
+			assert_eq!(distinct_shards(&remaining), 3);
+			let decoded = decode_block(&remaining).unwrap();
+			assert!(!decoded.is_compressed());
+			assert_eq!(decoded.verify_get(hash).unwrap(), &data[..]);
+		}
+		assert!(decode_block(&shards[..2]).is_err());
+
+		// Shards survive a round trip through their file format
+		let content = shards[4].to_file_content().unwrap();
+		let parsed = DataShard::from_file_content(content).unwrap();
+		assert_eq!(parsed.header, shards[4].header);
+		assert_eq!(parsed.data, shards[4].data);
+
+		// Corruption of a shard is detected by its checksum
+		let mut corrupted = shards[1].clone();
+		let mut bad_data = corrupted.data.to_vec();
+		bad_data[10] ^= 1;
+		corrupted.data = bad_data.into();
+		assert!(corrupted.verify(hash).is_err());
+
+		// Invalid header lengths are rejected
+		let mut content = shards[0].to_file_content().unwrap().to_vec();
+		content[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+		assert!(DataShard::from_file_content(content.into()).is_err());
+	}
+
+	#[test]
+	fn test_erasure_coding_placeholders() {
+		let data = (0..5_000u32).map(|i| (i * 13) as u8).collect::<Vec<_>>();
+		let hash = blake2sum(&data[..]);
+		let block = DataBlock::Plain(data.clone().into());
+
+		// 2 data shards and 1 parity shard on 5 nodes:
+		// the last two nodes store placeholders
+		let shards = encode_block(&block, 2, 1).unwrap();
+		let stored = (0..5).map(|i| node_shard(&shards, i)).collect::<Vec<_>>();
+		assert!(stored[..3].iter().all(|s| !s.is_placeholder()));
+		for (i, shard) in stored[3..].iter().enumerate() {
+			assert!(shard.is_placeholder());
+			assert_eq!(shard.header.index, 3 + i);
+			assert!(shard.data.is_empty());
+			shard.verify(hash).unwrap();
+		}
+
+		// Placeholders are not counted and do not help reconstruct the block
+		let remaining = vec![stored[0].clone(), stored[3].clone(), stored[4].clone()];
+		assert_eq!(distinct_shards(&remaining), 1);
+		assert!(decode_block(&remaining).is_err());
+
+		let remaining = vec![stored[3].clone(), stored[2].clone(), stored[0].clone()];
+		assert_eq!(distinct_shards(&remaining), 2);
+		let decoded = decode_block(&remaining).unwrap();
+		assert_eq!(decoded.verify_get(hash).unwrap(), &data[..]);
+	}
+
+	#[test]
+	fn test_erasure_coding_resync() {
+		// A node that lost its shard reconstructs the block from the shards
+		// of the other nodes and computes its shard again: it must be identical
+		// to the one it stored, so that all the shards stay consistent
+		let data = (0..100_000u32)
+			.map(|i| (i * 31 + 7) as u8)
+			.collect::<Vec<_>>();
+		let hash = blake2sum(&data[..]);
+		let block = DataBlock::Plain(data.into());
+
+		let shards = encode_block(&block, 3, 2).unwrap();
+		for lost in 0..5 {
+			// Shards received from the other nodes, through their file format
+			let received = shards
+				.iter()
+				.filter(|s| s.header.index != lost)
+				.map(|s| DataShard::from_file_content(s.to_file_content().unwrap()).unwrap())
+				.collect::<Vec<_>>();
+			let decoded = decode_block(&received[1..]).unwrap();
+			decoded.verify(hash).unwrap();
+
+			let recomputed = node_shard(&encode_block(&decoded, 3, 2).unwrap(), lost);
+			assert_eq!(recomputed.header, shards[lost].header);
+			assert_eq!(recomputed.data, shards[lost].data);
+			recomputed.verify(hash).unwrap();
+		}
+	}
+}
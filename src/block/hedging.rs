@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use garage_util::config::BlockReadHedgingConfig;
+use garage_util::data::*;
+use garage_util::error::*;
+
+use garage_rpc::rpc_helper::OrderTag;
+use garage_rpc::*;
+
+use crate::manager::*;
+
+/// Number of recent block read latencies used to compute the hedging delay
+const LATENCY_SAMPLES: usize = 256;
+/// Number of block reads that must have been measured before reads are hedged
+const MIN_LATENCY_SAMPLES: usize = 16;
+
+/// Delay after which a block read is hedged, i.e. the block is also
+/// requested from another node, computed from the latency of recent reads
+pub(crate) struct ReadHedging {
+	percentile: f64,
+	min_delay: Duration,
+	latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl ReadHedging {
+	pub(crate) fn new(config: &BlockReadHedgingConfig) -> Result<Self, Error> {
+		let percentile = config.percentile.unwrap_or(95.0);
+		if !(percentile > 0.0 && percentile <= 100.0) {
+			return Err(Error::Message(format!(
+				"Invalid percentile for block read hedging: {} (must be between 0 and 100)",
+				percentile
+			)));
+		}
+		Ok(Self {
+			percentile,
+			min_delay: Duration::from_millis(config.min_delay_msec.unwrap_or(10)),
+			latencies: Mutex::new(VecDeque::with_capacity(LATENCY_SAMPLES)),
+		})
+	}
+
+	/// Record the time a node took to answer a block read
+	pub(crate) fn record(&self, latency: Duration) {
+		let mut latencies = self.latencies.lock().unwrap();
+		if latencies.len() == LATENCY_SAMPLES {
+			latencies.pop_front();
+		}
+		latencies.push_back(latency);
+	}
+
+	/// Delay after which a block read is hedged,
+	/// or None if not enough reads have been measured yet
+	pub(crate) fn delay(&self) -> Option<Duration> {
+		let mut latencies = self
+			.latencies
+			.lock()
+			.unwrap()
+			.iter()
+			.copied()
+			.collect::<Vec<_>>();
+		if latencies.len() < MIN_LATENCY_SAMPLES {
+			return None;
+		}
+		latencies.sort();
+		let rank = (self.percentile / 100.0 * latencies.len() as f64).ceil() as usize;
+		let latency = latencies[rank.clamp(1, latencies.len()) - 1];
+		Some(std::cmp::max(latency, self.min_delay))
+	}
+}
+
+type GetBlockResponse = Result<Resp<BlockRpc>, Error>;
+
+/// Requests for a block to the nodes that might store it, sent one after
+/// the other in the given order. A request is sent to the next node when the
+/// previous response was not sufficient, or when no response was received
+/// within the hedging delay. Requests still in flight are cancelled
+/// when this is dropped.
+pub(crate) struct GetBlockRequests<'a> {
+	manager: &'a BlockManager,
+	hash: Hash,
+	order_tag: Option<OrderTag>,
+	nodes: std::vec::IntoIter<Uuid>,
+	in_flight: FuturesUnordered<BoxFuture<'a, (Uuid, GetBlockResponse)>>,
+	hedge_delay: Option<Duration>,
+}
+
+impl<'a> GetBlockRequests<'a> {
+	pub(crate) fn new(
+		manager: &'a BlockManager,
+		hash: &Hash,
+		order_tag: Option<OrderTag>,
+		nodes: Vec<Uuid>,
+	) -> Self {
+		let hedge_delay = manager.read_hedging.as_ref().and_then(|h| h.delay());
+		Self {
+			manager,
+			hash: *hash,
+			order_tag,
+			nodes: nodes.into_iter(),
+			in_flight: FuturesUnordered::new(),
+			hedge_delay,
+		}
+	}
+
+	/// Send a request to the next node, and return the first response
+	/// received from one of the nodes that were sent a request
+	pub(crate) async fn next(&mut self) -> Option<(Uuid, GetBlockResponse)> {
+		self.send_next();
+		loop {
+			if self.in_flight.is_empty() {
+				return None;
+			}
+			let hedge_delay = self.hedge_delay.filter(|_| self.nodes.len() > 0);
+			tokio::select! {
+				Some(res) = self.in_flight.next() => return Some(res),
+				_ = tokio::time::sleep(hedge_delay.unwrap_or_default()), if hedge_delay.is_some() => {
+					debug!("Block {:?} was not returned in {:?}, asking another node", self.hash, hedge_delay.unwrap());
+					self.manager.metrics.hedged_read_counter.add(1);
+					self.send_next();
+				}
+			}
+		}
+	}
+
+	fn send_next(&mut self) {
+		let node = match self.nodes.next() {
+			Some(node) => node,
+			None => return,
+		};
+		let manager = self.manager;
+		let msg = BlockRpc::GetBlock(self.hash, self.order_tag);
+		self.in_flight.push(Box::pin(async move {
+			let node_id = NodeID::from(node);
+			let start = Instant::now();
+			let rpc = manager
+				.endpoint
+				.call_streaming(&node_id, msg, PRIO_NORMAL | PRIO_SECONDARY);
+			let res = match tokio::time::timeout(manager.system.rpc.rpc_timeout(), rpc).await {
+				Ok(Ok(resp)) => {
+					if let Some(hedging) = &manager.read_hedging {
+						hedging.record(start.elapsed());
+					}
+					Ok(resp)
+				}
+				Ok(Err(e)) => Err(e.into()),
+				Err(_) => Err(Error::Timeout),
+			};
+			(node, res)
+		}));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hedging_delay() {
+		let hedging = ReadHedging::new(&BlockReadHedgingConfig {
+			percentile: Some(90.0),
+			min_delay_msec: Some(5),
+		})
+		.unwrap();
+		for i in 1..MIN_LATENCY_SAMPLES {
+			hedging.record(Duration::from_millis(i as u64));
+		}
+		assert_eq!(hedging.delay(), None);
+
+		for i in MIN_LATENCY_SAMPLES..=100 {
+			hedging.record(Duration::from_millis(i as u64));
+		}
+		assert_eq!(hedging.delay(), Some(Duration::from_millis(90)));
+
+		// Old samples are forgotten
+		for _ in 0..LATENCY_SAMPLES {
+			hedging.record(Duration::from_millis(1));
+		}
+		assert_eq!(hedging.delay(), Some(Duration::from_millis(5)));
+
+		assert!(ReadHedging::new(&BlockReadHedgingConfig {
+			percentile: Some(0.0),
+			min_delay_msec: None,
+		})
+		.is_err());
+	}
+}
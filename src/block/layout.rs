@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use garage_util::config::DataDirEnum;
+use garage_util::data::Hash;
+use garage_util::error::{Error, OkOrMessage};
+
+use garage_rpc::system::DiskSpace;
+
+/// Number of partitions used to spread blocks among data directories
+const DRIVE_NPART: usize = 1024;
+
+/// Bytes of the block hash that give its partition among data directories.
+/// The first two bytes are not used as they give the subdirectory of the block.
+const HASH_DRIVE_BYTES: (usize, usize) = (2, 3);
+
+/// File written in each data directory to detect drives that are not mounted
+const MARKER_FILE_NAME: &str = "garage-marker";
+
+/// Placement of blocks among the data directories of a node
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DataLayout {
+	pub(crate) data_dirs: Vec<DataDir>,
+
+	/// Content of the marker file of each data directory
+	markers: HashMap<PathBuf, String>,
+
+	/// Primary location (index in data_dirs) of each partition,
+	/// where new blocks are written
+	part_prim: Vec<usize>,
+	/// Other locations where blocks of each partition can be stored,
+	/// until the rebalance worker has moved them to their primary location
+	part_sec: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DataDir {
+	pub(crate) path: PathBuf,
+	pub(crate) state: DataDirState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DataDirState {
+	Active { capacity: u64 },
+	ReadOnly,
+}
+
+impl DataLayout {
+	/// Compute the placement of blocks for a node that had no previous layout
+	pub(crate) fn initialize(dirs: &DataDirEnum) -> Result<Self, Error> {
+		Self::compute(make_data_dirs(dirs)?, None)
+	}
+
+	/// Compute the placement of blocks after the data directories have changed
+	/// in the configuration. Partitions keep their primary location if possible;
+	/// when it changes, the previous one is kept as a secondary location.
+	pub(crate) fn update(&self, dirs: &DataDirEnum) -> Result<Self, Error> {
+		Self::compute(make_data_dirs(dirs)?, Some(self))
+	}
+
+	fn compute(data_dirs: Vec<DataDir>, previous: Option<&Self>) -> Result<Self, Error> {
+		let targets = partition_targets(&data_dirs)?;
+		let mut counts = vec![0; data_dirs.len()];
+		let mut part_prim = vec![None; DRIVE_NPART];
+		let mut part_sec = vec![vec![]; DRIVE_NPART];
+		let mut markers = HashMap::new();
+
+		if let Some(prev) = previous {
+			let new_index = prev
+				.data_dirs
+				.iter()
+				.map(|d| data_dirs.iter().position(|d2| d2.path == d.path))
+				.collect::<Vec<_>>();
+			for (p, (prim, sec)) in part_prim.iter_mut().zip(part_sec.iter_mut()).enumerate() {
+				*sec = prev.part_sec[p]
+					.iter()
+					.filter_map(|i| new_index[*i])
+					.collect();
+				if let Some(i) = new_index[prev.part_prim[p]] {
+					if counts[i] < targets[i] {
+						*prim = Some(i);
+						counts[i] += 1;
+					} else {
+						sec.push(i);
+					}
+				}
+			}
+			for dir in data_dirs.iter() {
+				if let Some(m) = prev.markers.get(&dir.path) {
+					markers.insert(dir.path.clone(), m.clone());
+				}
+			}
+		}
+
+		// Give the partitions that have no primary location yet
+		// to the directories that have less partitions than their target
+		let mut dir = 0;
+		let part_prim = part_prim
+			.into_iter()
+			.map(|prim| {
+				prim.unwrap_or_else(|| {
+					while counts[dir] >= targets[dir] {
+						dir += 1;
+					}
+					counts[dir] += 1;
+					dir
+				})
+			})
+			.collect::<Vec<_>>();
+
+		for (prim, sec) in part_prim.iter().zip(part_sec.iter_mut()) {
+			sec.sort_unstable();
+			sec.dedup();
+			sec.retain(|i| i != prim);
+		}
+
+		Ok(Self {
+			data_dirs,
+			markers,
+			part_prim,
+			part_sec,
+		})
+	}
+
+	/// Check that each data directory contains the marker file that was
+	/// written when it was added, to avoid writing blocks in the mount point
+	/// of a drive that is not mounted. Marker files are created for new directories.
+	///
+	/// Directories whose marker is missing are considered failed: no block is
+	/// written to them anymore, or, if no other directory can store blocks,
+	/// they are considered empty and get a new marker. Returns true in that
+	/// case, as the blocks they held must then be resynchronized.
+	pub(crate) fn check_markers(&mut self) -> Result<bool, Error> {
+		let mut failed = vec![];
+		for (i, dir) in self.data_dirs.iter().enumerate() {
+			let mut marker_path = dir.path.clone();
+			marker_path.push(MARKER_FILE_NAME);
+			let existing = std::fs::read_to_string(&marker_path).ok();
+			match (self.markers.get(&dir.path), existing) {
+				(Some(expected), Some(found)) if *expected == found => (),
+				(Some(expected), _) => {
+					error!(
+						"Data directory {} does not contain the expected marker file {}. \
+						If the drive is not mounted, mount it and restart Garage. \
+						If it has been replaced by an empty drive, write `{}` to that file \
+						and restart Garage.",
+						dir.path.display(),
+						marker_path.display(),
+						expected
+					);
+					failed.push(i);
+				}
+				(None, Some(found)) => {
+					self.markers.insert(dir.path.clone(), found);
+				}
+				(None, None) => {
+					let marker = hex::encode(rand::random::<[u8; 16]>());
+					std::fs::write(&marker_path, &marker).ok_or_message(format!(
+						"Unable to write marker file {}",
+						marker_path.display()
+					))?;
+					self.markers.insert(dir.path.clone(), marker);
+				}
+			}
+		}
+
+		if failed.is_empty() {
+			return Ok(false);
+		}
+
+		let mut data_dirs = self.data_dirs.clone();
+		for i in failed.iter() {
+			data_dirs[*i].state = DataDirState::ReadOnly;
+		}
+		match Self::compute(data_dirs, Some(self)) {
+			Ok(layout) => {
+				error!(
+					"Blocks will not be written to data directories with a missing marker \
+					until it is restored, their blocks are resynchronized from other nodes."
+				);
+				*self = layout;
+			}
+			Err(_) => {
+				error!(
+					"No other data directory can store blocks: data directories with a \
+					missing marker are considered empty, their blocks are resynchronized \
+					from other nodes."
+				);
+				for i in failed {
+					let dir = &self.data_dirs[i].path;
+					let mut marker_path = dir.clone();
+					marker_path.push(MARKER_FILE_NAME);
+					let marker = hex::encode(rand::random::<[u8; 16]>());
+					std::fs::write(&marker_path, &marker).ok_or_message(format!(
+						"Unable to write marker file {}",
+						marker_path.display()
+					))?;
+					self.markers.insert(dir.clone(), marker);
+				}
+			}
+		}
+		Ok(true)
+	}
+
+	/// Directory in which a block should be written
+	pub(crate) fn primary_block_dir(&self, hash: &Hash) -> PathBuf {
+		let part = self.partition_of(hash);
+		self.block_dir(self.part_prim[part], hash)
+	}
+
+	/// Data directory in which a block should be written
+	pub(crate) fn primary_data_dir(&self, hash: &Hash) -> &PathBuf {
+		let part = self.partition_of(hash);
+		&self.data_dirs[self.part_prim[part]].path
+	}
+
+	/// Other directories in which a block can be found
+	pub(crate) fn secondary_block_dirs<'a>(
+		&'a self,
+		hash: &'a Hash,
+	) -> impl Iterator<Item = PathBuf> + 'a {
+		let part = self.partition_of(hash);
+		self.part_sec[part]
+			.iter()
+			.map(move |i| self.block_dir(*i, hash))
+	}
+
+	/// Whether some blocks might not be in their primary location
+	pub(crate) fn has_secondary_locations(&self) -> bool {
+		self.part_sec.iter().any(|sec| !sec.is_empty())
+	}
+
+	/// Forget the secondary locations, once all blocks have been moved
+	/// to their primary location
+	pub(crate) fn without_secondary_locations(&self) -> Self {
+		Self {
+			part_sec: vec![vec![]; DRIVE_NPART],
+			..self.clone()
+		}
+	}
+
+	fn partition_of(&self, hash: &Hash) -> usize {
+		let bytes = [
+			hash.as_slice()[HASH_DRIVE_BYTES.0],
+			hash.as_slice()[HASH_DRIVE_BYTES.1],
+		];
+		u16::from_be_bytes(bytes) as usize % DRIVE_NPART
+	}
+
+	fn block_dir(&self, dir: usize, hash: &Hash) -> PathBuf {
+		let mut path = self.data_dirs[dir].path.clone();
+		path.push(hex::encode(&hash.as_slice()[0..1]));
+		path.push(hex::encode(&hash.as_slice()[1..2]));
+		path
+	}
+}
+
+/// Minimum free space to keep in a filesystem, below which new blocks are refused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MinFreeSpace {
+	Bytes(u64),
+	Percent(f64),
+}
+
+impl MinFreeSpace {
+	/// Parse a size (e.g. `10G`) or a percentage of the size of the filesystem (e.g. `5%`)
+	pub(crate) fn parse(option: &str, s: &str) -> Result<Self, Error> {
+		let invalid = || {
+			Error::Message(format!(
+				"Invalid {}: {} (expected a size or a percentage)",
+				option, s
+			))
+		};
+		match s.strip_suffix('%') {
+			Some(pct) => {
+				let pct = pct.trim().parse::<f64>().map_err(|_| invalid())?;
+				if !(0.0..100.0).contains(&pct) {
+					return Err(invalid());
+				}
+				Ok(Self::Percent(pct))
+			}
+			None => Ok(Self::Bytes(
+				s.parse::<bytesize::ByteSize>()
+					.map_err(|_| invalid())?
+					.as_u64(),
+			)),
+		}
+	}
+
+	/// Whether a filesystem has less free space than this
+	pub(crate) fn is_reached(&self, space: &DiskSpace) -> bool {
+		match self {
+			Self::Bytes(bytes) => space.available < *bytes,
+			Self::Percent(pct) => (space.available as f64) < space.total as f64 * pct / 100.0,
+		}
+	}
+
+	/// Check that the filesystem of a directory has at least this free space
+	pub(crate) fn check(&self, dir: &Path) -> Result<(), Error> {
+		match DiskSpace::of(&[dir]) {
+			Some(space) if self.is_reached(&space) => Err(Error::Message(format!(
+				"Not enough free space in {} ({} available)",
+				dir.display(),
+				bytesize::ByteSize::b(space.available).to_string_as(true)
+			))),
+			_ => Ok(()),
+		}
+	}
+}
+
+fn make_data_dirs(dirs: &DataDirEnum) -> Result<Vec<DataDir>, Error> {
+	match dirs {
+		DataDirEnum::Single(path) => Ok(vec![DataDir {
+			path: path.clone(),
+			state: DataDirState::Active { capacity: 1 },
+		}]),
+		DataDirEnum::Multiple(dirs) => {
+			let mut ret: Vec<DataDir> = vec![];
+			for dir in dirs.iter() {
+				if ret.iter().any(|d| d.path == dir.path) {
+					return Err(Error::Message(format!(
+						"Data directory {} is specified twice",
+						dir.path.display()
+					)));
+				}
+				let state = match (&dir.capacity, dir.read_only) {
+					(_, true) => DataDirState::ReadOnly,
+					(Some(cap), false) => {
+						let capacity = cap.parse::<bytesize::ByteSize>().map_err(|e| {
+							Error::Message(format!(
+								"Invalid capacity for data directory {}: {}",
+								dir.path.display(),
+								e
+							))
+						})?;
+						DataDirState::Active {
+							capacity: capacity.as_u64(),
+						}
+					}
+					(None, false) => {
+						return Err(Error::Message(format!(
+							"Data directory {} must have a capacity or be read-only",
+							dir.path.display()
+						)));
+					}
+				};
+				ret.push(DataDir {
+					path: dir.path.clone(),
+					state,
+				});
+			}
+			Ok(ret)
+		}
+	}
+}
+
+/// Number of partitions that each data directory should be the primary
+/// location of, proportionally to its capacity
+fn partition_targets(data_dirs: &[DataDir]) -> Result<Vec<usize>, Error> {
+	let capacities = data_dirs
+		.iter()
+		.map(|d| match d.state {
+			DataDirState::Active { capacity } => capacity as u128,
+			DataDirState::ReadOnly => 0,
+		})
+		.collect::<Vec<_>>();
+	let total = capacities.iter().sum::<u128>();
+	if total == 0 {
+		return Err(Error::Message(
+			"At least one data directory must be active and have a nonzero capacity".into(),
+		));
+	}
+
+	let npart = DRIVE_NPART as u128;
+	let mut targets = capacities
+		.iter()
+		.map(|c| (c * npart / total) as usize)
+		.collect::<Vec<_>>();
+
+	// Give the remaining partitions to the directories with the largest remainders
+	let mut by_remainder = (0..data_dirs.len())
+		.filter(|i| capacities[*i] > 0)
+		.collect::<Vec<_>>();
+	by_remainder.sort_by_key(|i| std::cmp::Reverse(capacities[*i] * npart % total));
+	let remaining = DRIVE_NPART - targets.iter().sum::<usize>();
+	for i in by_remainder.into_iter().take(remaining) {
+		targets[i] += 1;
+	}
+
+	Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use garage_util::config::DataDir as ConfigDataDir;
+
+	fn config(dirs: &[(&str, Option<&str>, bool)]) -> DataDirEnum {
+		DataDirEnum::Multiple(
+			dirs.iter()
+				.map(|(path, capacity, read_only)| ConfigDataDir {
+					path: PathBuf::from(path),
+					capacity: capacity.map(String::from),
+					read_only: *read_only,
+				})
+				.collect(),
+		)
+	}
+
+	fn partition_counts(layout: &DataLayout) -> Vec<usize> {
+		let mut counts = vec![0; layout.data_dirs.len()];
+		for p in layout.part_prim.iter() {
+			counts[*p] += 1;
+		}
+		counts
+	}
+
+	#[test]
+	fn test_data_layout() {
+		let layout = DataLayout::initialize(&config(&[
+			("/mnt/a", Some("1T"), false),
+			("/mnt/b", Some("3T"), false),
+		]))
+		.unwrap();
+		assert_eq!(partition_counts(&layout), vec![256, 768]);
+		assert!(!layout.has_secondary_locations());
+
+		// Adding a drive only moves the partitions it becomes the primary location of
+		let layout2 = layout
+			.update(&config(&[
+				("/mnt/a", Some("1T"), false),
+				("/mnt/b", Some("3T"), false),
+				("/mnt/c", Some("4T"), false),
+			]))
+			.unwrap();
+		assert_eq!(partition_counts(&layout2), vec![128, 384, 512]);
+		let mut moved = 0;
+		for p in 0..DRIVE_NPART {
+			if layout2.part_prim[p] == layout.part_prim[p] {
+				assert!(layout2.part_sec[p].is_empty());
+			} else {
+				assert_eq!(layout2.part_prim[p], 2);
+				assert_eq!(layout2.part_sec[p], vec![layout.part_prim[p]]);
+				moved += 1;
+			}
+		}
+		assert_eq!(moved, 512);
+
+		// A read-only drive is never the primary location of a partition
+		let layout3 = layout2
+			.without_secondary_locations()
+			.update(&config(&[
+				("/mnt/a", None, true),
+				("/mnt/b", Some("3T"), false),
+				("/mnt/c", Some("4T"), false),
+			]))
+			.unwrap();
+		let counts = partition_counts(&layout3);
+		assert_eq!(counts[0], 0);
+		assert_eq!(counts[1] + counts[2], DRIVE_NPART);
+		assert!(layout3.part_sec.iter().filter(|s| s.contains(&0)).count() == 128);
+
+		// Removed drives are forgotten
+		let layout4 = layout3
+			.update(&config(&[
+				("/mnt/b", Some("3T"), false),
+				("/mnt/c", Some("4T"), false),
+			]))
+			.unwrap();
+		assert_eq!(layout4.data_dirs.len(), 2);
+		assert!(layout4.part_sec.iter().flatten().all(|i| *i < 2));
+
+		assert!(DataLayout::initialize(&config(&[("/mnt/a", None, false)])).is_err());
+		assert!(DataLayout::initialize(&config(&[("/mnt/a", None, true)])).is_err());
+	}
+	#[test]
+	fn test_missing_markers() {
+		let base = std::env::temp_dir().join(format!(
+			"garage-test-markers-{}",
+			hex::encode(rand::random::<[u8; 8]>())
+		));
+		let (a, b) = (base.join("a"), base.join("b"));
+		std::fs::create_dir_all(&a).unwrap();
+		std::fs::create_dir_all(&b).unwrap();
+
+		let dirs = config(&[
+			(a.to_str().unwrap(), Some("1T"), false),
+			(b.to_str().unwrap(), Some("1T"), false),
+		]);
+		let mut layout = DataLayout::initialize(&dirs).unwrap();
+		assert!(!layout.check_markers().unwrap());
+		assert!(!layout.check_markers().unwrap());
+
+		// A directory whose marker is missing is no longer written to
+		std::fs::remove_file(a.join(MARKER_FILE_NAME)).unwrap();
+		assert!(layout.check_markers().unwrap());
+		assert_eq!(layout.data_dirs[0].state, DataDirState::ReadOnly);
+		assert_eq!(partition_counts(&layout), vec![0, DRIVE_NPART]);
+		assert!(layout.has_secondary_locations());
+
+		// ... unless there is no other directory, it is then considered empty
+		let mut layout =
+			DataLayout::initialize(&config(&[(a.to_str().unwrap(), Some("1T"), false)])).unwrap();
+		assert!(!layout.check_markers().unwrap());
+		std::fs::remove_file(a.join(MARKER_FILE_NAME)).unwrap();
+		assert!(layout.check_markers().unwrap());
+		assert_eq!(partition_counts(&layout), vec![DRIVE_NPART]);
+		assert!(!layout.check_markers().unwrap());
+
+		std::fs::remove_dir_all(&base).unwrap();
+	}
+
+	#[test]
+	fn test_min_free_space() {
+		let space = DiskSpace {
+			available: 3_000_000_000,
+			total: 100_000_000_000,
+		};
+
+		let min_free = MinFreeSpace::parse("data_min_free_space", "5%").unwrap();
+		assert_eq!(min_free, MinFreeSpace::Percent(5.0));
+		assert!(min_free.is_reached(&space));
+		assert!(!MinFreeSpace::parse("data_min_free_space", "2.5%")
+			.unwrap()
+			.is_reached(&space));
+
+		assert_eq!(
+			MinFreeSpace::parse("data_min_free_space", "10G").unwrap(),
+			MinFreeSpace::Bytes(10_000_000_000)
+		);
+		assert!(MinFreeSpace::parse("data_min_free_space", "10G")
+			.unwrap()
+			.is_reached(&space));
+		assert!(!MinFreeSpace::parse("data_min_free_space", "1G")
+			.unwrap()
+			.is_reached(&space));
+
+		assert!(MinFreeSpace::parse("data_min_free_space", "150%").is_err());
+		assert!(MinFreeSpace::parse("data_min_free_space", "lots").is_err());
+	}
+}
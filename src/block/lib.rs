@@ -1,10 +1,15 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod erasure;
 pub mod manager;
 pub mod repair;
 pub mod resync;
+pub mod tier;
 
 mod block;
+mod cache;
+mod hedging;
+mod layout;
 mod metrics;
 mod rc;
@@ -1,8 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
@@ -15,17 +16,20 @@ use tokio::sync::{mpsc, Mutex, MutexGuard};
 
 use opentelemetry::{
 	trace::{FutureExt as OtelFutureExt, TraceContextExt, Tracer},
-	Context,
+	Context, KeyValue,
 };
 
 use garage_rpc::rpc_helper::netapp::stream::{stream_asyncread, ByteStream};
 
 use garage_db as db;
 
+use garage_util::config::Config;
 use garage_util::data::*;
 use garage_util::error::*;
 use garage_util::metrics::RecordDuration;
+use garage_util::persister::Persister;
 
+use garage_rpc::ring::PARTITION_BITS;
 use garage_rpc::rpc_helper::OrderTag;
 use garage_rpc::system::System;
 use garage_rpc::*;
@@ -33,14 +37,23 @@ use garage_rpc::*;
 use garage_table::replication::{TableReplication, TableShardedReplication};
 
 use crate::block::*;
+use crate::cache::*;
+use crate::erasure::*;
+use crate::hedging::*;
+use crate::layout::*;
 use crate::metrics::*;
 use crate::rc::*;
 use crate::repair::*;
 use crate::resync::*;
+use crate::tier::*;
 
 /// Size under which data will be stored inlined in database instead of as files
 pub const INLINE_THRESHOLD: usize = 3072;
 
+/// Number of blocks whose size is read from disk to estimate the
+/// average size of the blocks stored on this node
+const BLOCK_SIZE_SAMPLE: usize = 1000;
+
 // The delay between the moment when the reference counter
 // drops to zero, and the moment where we allow ourselves
 // to delete the block locally.
@@ -62,6 +75,77 @@ pub enum BlockRpc {
 	NeedBlockQuery(Hash),
 	/// Response : whether the node do require that block
 	NeedBlockReply(bool),
+	/// Ask other node whether they store this block, and how many references they have to it
+	BlockStatusQuery(Hash),
+	/// Response : state of the block on the node
+	BlockStatusReply(BlockNodeStatus),
+	/// Message to send a shard of an erasure coded block, either because requested,
+	/// or for first delivery of new block
+	PutShard {
+		hash: Hash,
+		header: DataShardHeader,
+	},
+}
+
+/// State of a block on a node
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BlockNodeStatus {
+	/// The block is stored in the data directory of the node
+	pub stored: bool,
+	/// Reference count of the block on the node
+	pub refcount: u64,
+}
+
+/// State of a block on one of the nodes that should store it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockReplicaStatus {
+	pub node: Uuid,
+	/// State of the block, or the error returned by the node
+	pub status: Result<BlockNodeStatus, String>,
+}
+
+/// Whether a block found corrupted during a scrub has been repaired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CorruptedBlockStatus {
+	/// A valid copy of the block has been stored again on this node
+	Repaired,
+	/// The block is no longer referenced and does not need to be repaired
+	NotNeeded,
+	/// Fetching the block from other nodes has failed
+	Unrepairable,
+	/// The block is still waiting in the resync queue
+	Pending,
+}
+
+/// Parameters for storing a new block, which depend on the bucket it is written to
+#[derive(Debug, Clone)]
+pub struct PutBlockParams {
+	/// How the block is stored on the nodes responsible for it
+	pub storage_mode: BlockStorageMode,
+	/// Zstd compression level, or None to store the block uncompressed
+	pub compression_level: Option<i32>,
+	/// Bucket the block is written to, used in metrics
+	pub bucket_id: Uuid,
+}
+
+/// Deduplication statistics of the blocks stored on a node
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BlockDedupStats {
+	/// Number of blocks with a nonzero reference count
+	pub blocks: u64,
+	/// Number of references to these blocks from object versions
+	pub references: u64,
+}
+
+impl BlockDedupStats {
+	/// Average number of references to each block
+	pub fn ratio(&self) -> f64 {
+		if self.blocks == 0 {
+			1.0
+		} else {
+			self.references as f64 / self.blocks as f64
+		}
+	}
 }
 
 impl Rpc for BlockRpc {
@@ -72,10 +156,9 @@ impl Rpc for BlockRpc {
 pub struct BlockManager {
 	/// Replication strategy, allowing to find on which node blocks should be located
 	pub replication: TableShardedReplication,
-	/// Directory in which block are stored
-	pub data_dir: PathBuf,
-
-	compression_level: Option<i32>,
+	/// Placement of blocks among the data directories of this node
+	pub(crate) data_layout: ArcSwap<DataLayout>,
+	data_layout_persister: Persister<DataLayout>,
 
 	mutation_lock: [Mutex<BlockManagerLocked>; 256],
 
@@ -87,6 +170,15 @@ pub struct BlockManager {
 
 	pub(crate) metrics: BlockManagerMetrics,
 
+	min_free_space: Option<MinFreeSpace>,
+	metadata_min_free_space: Option<MinFreeSpace>,
+	data_fsync: bool,
+	cache: Option<BlockCache>,
+	pub(crate) read_hedging: Option<ReadHedging>,
+	cold_tier: Option<ColdTier>,
+	pub tiering: TieringParams,
+
+	pub(crate) scrub_reports: ScrubReports,
 	tx_scrub_command: mpsc::Sender<ScrubWorkerCommand>,
 }
 
@@ -98,11 +190,49 @@ struct BlockManagerLocked();
 impl BlockManager {
 	pub fn new(
 		db: &db::Db,
-		data_dir: PathBuf,
-		compression_level: Option<i32>,
+		config: &Config,
 		replication: TableShardedReplication,
 		system: Arc<System>,
-	) -> Arc<Self> {
+	) -> Result<Arc<Self>, Error> {
+		let data_dir = &config.data_dir;
+		let data_layout_persister: Persister<DataLayout> =
+			Persister::new(&system.metadata_dir, "data_layout");
+		let mut data_layout = match data_layout_persister.load() {
+			Ok(layout) => layout.update(data_dir)?,
+			Err(_) => DataLayout::initialize(data_dir)?,
+		};
+		let resync_needed = data_layout.check_markers()?;
+		data_layout_persister
+			.save(&data_layout)
+			.ok_or_message("Unable to save data layout")?;
+		let rebalance_needed = data_layout.has_secondary_locations();
+
+		let min_free_space = config
+			.data_min_free_space
+			.as_deref()
+			.map(|s| MinFreeSpace::parse("data_min_free_space", s))
+			.transpose()?;
+		let metadata_min_free_space = config
+			.metadata_min_free_space
+			.as_deref()
+			.map(|s| MinFreeSpace::parse("metadata_min_free_space", s))
+			.transpose()?;
+		let cache = config
+			.block_cache
+			.as_ref()
+			.map(BlockCache::new)
+			.transpose()?;
+		let read_hedging = config
+			.block_read_hedging
+			.as_ref()
+			.map(ReadHedging::new)
+			.transpose()?;
+		let cold_tier = config
+			.cold_tier
+			.as_ref()
+			.map(|c| ColdTier::new(c, db, system.id))
+			.transpose()?;
+
 		let rc = db
 			.open_tree("block_local_rc")
 			.expect("Unable to open block_local_rc tree");
@@ -117,17 +247,27 @@ impl BlockManager {
 		let metrics = BlockManagerMetrics::new(resync.queue.clone(), resync.errors.clone());
 
 		let (scrub_tx, scrub_rx) = mpsc::channel(1);
+		let scrub_reports = ScrubReports::new(&system.metadata_dir);
+		let tiering = TieringParams::new(&system.metadata_dir);
 
 		let block_manager = Arc::new(Self {
 			replication,
-			data_dir,
-			compression_level,
+			data_layout: ArcSwap::new(Arc::new(data_layout)),
+			data_layout_persister,
 			mutation_lock: [(); 256].map(|_| Mutex::new(BlockManagerLocked())),
 			rc,
 			resync,
 			system,
 			endpoint,
 			metrics,
+			min_free_space,
+			metadata_min_free_space,
+			data_fsync: config.data_fsync,
+			cache,
+			read_hedging,
+			cold_tier,
+			tiering,
+			scrub_reports,
 			tx_scrub_command: scrub_tx,
 		});
 		block_manager.endpoint.set_handler(block_manager.clone());
@@ -142,10 +282,30 @@ impl BlockManager {
 		let scrub_worker = ScrubWorker::new(block_manager.clone(), scrub_rx);
 		block_manager.system.background.spawn_worker(scrub_worker);
 
-		block_manager
+		// If data directories have been lost, fetch their blocks from other nodes
+		if resync_needed {
+			info!("Some data directories have failed, launching a repair of stored blocks");
+			let repair_worker = RepairWorker::new(block_manager.clone());
+			block_manager.system.background.spawn_worker(repair_worker);
+		}
+
+		// If data directories have changed, move blocks to their new location
+		if rebalance_needed {
+			info!("Data directories have changed, launching a rebalance of stored blocks");
+			let rebalance_worker = RebalanceWorker::new(block_manager.clone());
+			block_manager
+				.system
+				.background
+				.spawn_worker(rebalance_worker);
+		}
+
+		Ok(block_manager)
 	}
 
-	/// Ask nodes that might have a (possibly compressed) block for it
+	/// Ask nodes that might have a (possibly compressed) block for it,
+	/// reconstructing it from the shards they return if it is erasure coded.
+	/// Nodes are asked one after the other, starting with the closest ones,
+	/// and the next one is also asked if a node is slow to answer (see [`GetBlockRequests`]).
 	/// Return it as a stream with a header
 	async fn rpc_get_raw_block_streaming(
 		&self,
@@ -155,35 +315,32 @@ impl BlockManager {
 		let who = self.replication.read_nodes(hash);
 		let who = self.system.rpc.request_order(&who);
 
-		for node in who.iter() {
-			let node_id = NodeID::from(*node);
-			let rpc = self.endpoint.call_streaming(
-				&node_id,
-				BlockRpc::GetBlock(*hash, order_tag),
-				PRIO_NORMAL | PRIO_SECONDARY,
-			);
-			tokio::select! {
-				res = rpc => {
-					let res = match res {
-						Ok(res) => res,
-						Err(e) => {
-							debug!("Node {:?} returned error: {}", node, e);
-							continue;
-						}
-					};
-					let (header, stream) = match res.into_parts() {
-						(Ok(BlockRpc::PutBlock { hash: _, header }), Some(stream)) => (header, stream),
-						_ => {
-							debug!("Node {:?} returned a malformed response", node);
-							continue;
-						}
-					};
+		let mut requests = GetBlockRequests::new(self, hash, order_tag, who);
+		let mut shards = vec![];
+		while let Some((node, res)) = requests.next().await {
+			let res = match res {
+				Ok(res) => res,
+				Err(e) => {
+					debug!("Node {:?} returned error: {}", node, e);
+					continue;
+				}
+			};
+			match res.into_parts() {
+				(Ok(BlockRpc::PutBlock { hash: _, header }), Some(stream)) => {
 					return Ok((header, stream));
 				}
-				_ = tokio::time::sleep(self.system.rpc.rpc_timeout()) => {
-					debug!("Node {:?} didn't return block in time, trying next.", node);
+				(Ok(BlockRpc::PutShard { hash: _, header }), Some(stream)) => {
+					collect_shard(hash, &mut shards, header, stream, &node).await;
+					if let Some((block, _)) = decode_shards(hash, &shards).await {
+						let (header, bytes) = block.into_parts();
+						let stream: ByteStream = Box::pin(futures::stream::iter([Ok(bytes)]));
+						return Ok((header, stream));
+					}
 				}
-			};
+				_ => {
+					debug!("Node {:?} returned a malformed response", node);
+				}
+			}
 		}
 
 		Err(Error::Message(format!(
@@ -192,50 +349,54 @@ impl BlockManager {
 		)))
 	}
 
-	/// Ask nodes that might have a (possibly compressed) block for it
-	/// Return its entire body
+	/// Ask nodes that might have a (possibly compressed) block for it,
+	/// reconstructing it from the shards they return if it is erasure coded.
+	/// Return its entire body, and how it is stored
+	/// Shards of the block that are already known can be given in `shards`
 	pub(crate) async fn rpc_get_raw_block(
 		&self,
 		hash: &Hash,
 		order_tag: Option<OrderTag>,
-	) -> Result<DataBlock, Error> {
+		mut shards: Vec<DataShard>,
+	) -> Result<(DataBlock, BlockStorageMode), Error> {
+		if let Some(res) = decode_shards(hash, &shards).await {
+			return Ok(res);
+		}
+
 		let who = self.replication.read_nodes(hash);
 		let who = self.system.rpc.request_order(&who);
 
-		for node in who.iter() {
-			let node_id = NodeID::from(*node);
-			let rpc = self.endpoint.call_streaming(
-				&node_id,
-				BlockRpc::GetBlock(*hash, order_tag),
-				PRIO_NORMAL | PRIO_SECONDARY,
-			);
-			tokio::select! {
-				res = rpc => {
-					let res = match res {
-						Ok(res) => res,
-						Err(e) => {
-							debug!("Node {:?} returned error: {}", node, e);
-							continue;
-						}
-					};
-					let (header, stream) = match res.into_parts() {
-						(Ok(BlockRpc::PutBlock { hash: _, header }), Some(stream)) => (header, stream),
-						_ => {
-							debug!("Node {:?} returned a malformed response", node);
-							continue;
-						}
-					};
+		let mut requests = GetBlockRequests::new(self, hash, order_tag, who);
+		while let Some((node, res)) = requests.next().await {
+			let res = match res {
+				Ok(res) => res,
+				Err(e) => {
+					debug!("Node {:?} returned error: {}", node, e);
+					continue;
+				}
+			};
+			match res.into_parts() {
+				(Ok(BlockRpc::PutBlock { hash: _, header }), Some(stream)) => {
 					match read_stream_to_end(stream).await {
-						Ok(bytes) => return Ok(DataBlock::from_parts(header, bytes)),
+						Ok(bytes) => {
+							let block = DataBlock::from_parts(header, bytes);
+							return Ok((block, BlockStorageMode::Replicated));
+						}
 						Err(e) => {
 							debug!("Error reading stream from node {:?}: {}", node, e);
 						}
 					}
 				}
-				_ = tokio::time::sleep(self.system.rpc.rpc_timeout()) => {
-					debug!("Node {:?} didn't return block in time, trying next.", node);
+				(Ok(BlockRpc::PutShard { hash: _, header }), Some(stream)) => {
+					collect_shard(hash, &mut shards, header, stream, &node).await;
+					if let Some(res) = decode_shards(hash, &shards).await {
+						return Ok(res);
+					}
 				}
-			};
+				_ => {
+					debug!("Node {:?} returned a malformed response", node);
+				}
+			}
 		}
 
 		Err(Error::Message(format!(
@@ -247,7 +408,9 @@ impl BlockManager {
 	// ---- Public interface ----
 
 	/// Ask nodes that might have a block for it,
-	/// return it as a stream
+	/// return it as a stream.
+	/// If the block cache is enabled, the block is looked up in the cache
+	/// first, and otherwise read entirely and added to the cache
 	pub async fn rpc_get_block_streaming(
 		&self,
 		hash: &Hash,
@@ -256,6 +419,11 @@ impl BlockManager {
 		Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send + Sync + 'static>>,
 		Error,
 	> {
+		if self.cache.is_some() {
+			let data = self.rpc_get_block(hash, order_tag).await?;
+			return Ok(Box::pin(futures::stream::iter([Ok(data)])));
+		}
+
 		let (header, stream) = self.rpc_get_raw_block_streaming(hash, order_tag).await?;
 		match header {
 			DataBlockHeader::Plain => Ok(stream),
@@ -269,44 +437,323 @@ impl BlockManager {
 		}
 	}
 
-	/// Ask nodes that might have a block for it
+	/// Ask nodes that might have a block for it, or get it from
+	/// the block cache if it is enabled
 	pub async fn rpc_get_block(
 		&self,
 		hash: &Hash,
 		order_tag: Option<OrderTag>,
 	) -> Result<Bytes, Error> {
-		self.rpc_get_raw_block(hash, order_tag)
+		if let Some(cache) = &self.cache {
+			if let Some((data, tier)) = cache.get(hash).await {
+				self.metrics
+					.cache_hit_counter
+					.add(1, &[KeyValue::new("tier", tier.as_str())]);
+				return Ok(data);
+			}
+			self.metrics.cache_miss_counter.add(1);
+		}
+
+		let data = self
+			.rpc_get_raw_block(hash, order_tag, vec![])
 			.await?
-			.verify_get(*hash)
+			.0
+			.verify_get(*hash)?;
+
+		if let Some(cache) = &self.cache {
+			cache.put(hash, &data).await;
+		}
+		Ok(data)
 	}
 
-	/// Send block to nodes that should have it
-	pub async fn rpc_put_block(&self, hash: Hash, data: Bytes) -> Result<(), Error> {
+	/// Send block to nodes that should have it, as full copies or as
+	/// erasure coded shards
+	pub async fn rpc_put_block(
+		&self,
+		hash: Hash,
+		data: Bytes,
+		params: &PutBlockParams,
+	) -> Result<(), Error> {
 		let who = self.replication.write_nodes(&hash);
+		let mode = params.storage_mode;
+
+		// For erasure coded blocks, one node more than the number of data shards
+		// must have received an actual shard (and not a placeholder) so that
+		// the loss of a node can be tolerated
+		let quorum = match mode {
+			BlockStorageMode::Replicated => self.replication.write_quorum(),
+			BlockStorageMode::ErasureCoded { parity_shards, .. } => {
+				std::cmp::min(who.len(), who.len().saturating_sub(parity_shards) + 1)
+			}
+		};
+
+		let input_len = data.len() as u64;
+		let block = DataBlock::from_buffer(data, params.compression_level).await;
+		let metrics_tags = [KeyValue::new("bucket", hex::encode(params.bucket_id))];
+		self.metrics
+			.compression_input_bytes
+			.add(input_len, &metrics_tags);
+		self.metrics
+			.compression_output_bytes
+			.add(block.inner_buffer().len() as u64, &metrics_tags);
+
+		self.rpc_send_block(
+			hash,
+			block,
+			mode,
+			&who[..],
+			RequestStrategy::with_priority(PRIO_NORMAL | PRIO_SECONDARY).with_quorum(quorum),
+		)
+		.await
+	}
 
-		let (header, bytes) = DataBlock::from_buffer(data, self.compression_level)
+	/// Send a block to some of the nodes that should store it: a full copy,
+	/// or the shard that each of them should store if it is erasure coded
+	pub(crate) async fn rpc_send_block(
+		&self,
+		hash: Hash,
+		block: DataBlock,
+		mode: BlockStorageMode,
+		to: &[Uuid],
+		strategy: RequestStrategy,
+	) -> Result<(), Error> {
+		match mode {
+			BlockStorageMode::Replicated => {
+				let (header, bytes) = block.into_parts();
+				let put_block_rpc =
+					Req::new(BlockRpc::PutBlock { hash, header })?.with_stream_from_buffer(bytes);
+				self.system
+					.rpc
+					.try_call_many(&self.endpoint, to, put_block_rpc, strategy)
+					.await?;
+			}
+			BlockStorageMode::ErasureCoded {
+				data_shards,
+				parity_shards,
+			} => {
+				let who = self.replication.write_nodes(&hash);
+				let shards = self
+					.encode_block(&hash, block, data_shards, parity_shards)
+					.await?;
+				let mut msgs = vec![];
+				for node in to.iter() {
+					let shard = who
+						.iter()
+						.position(|n| n == node)
+						.map(|i| node_shard(&shards, i))
+						.ok_or_message(
+							"Cannot send a shard to a node that does not store the block",
+						)?;
+					let req = Req::new(BlockRpc::PutShard {
+						hash,
+						header: shard.header,
+					})?
+					.with_stream_from_buffer(shard.data.clone());
+					msgs.push((*node, req));
+				}
+				self.system
+					.rpc
+					.try_call_many_distinct(&self.endpoint, msgs, strategy)
+					.await?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Compute the data and parity shards of a block, to be stored
+	/// by the first nodes among the nodes that store it
+	async fn encode_block(
+		&self,
+		hash: &Hash,
+		block: DataBlock,
+		data_shards: usize,
+		parity_shards: usize,
+	) -> Result<Vec<DataShard>, Error> {
+		let nodes = self.replication.write_nodes(hash).len();
+		if data_shards == 0 || parity_shards == 0 || data_shards + parity_shards > nodes {
+			return Err(Error::Message(format!(
+				"Cannot store a block as {} data shards and {} parity shards on {} nodes",
+				data_shards, parity_shards, nodes
+			)));
+		}
+		tokio::task::spawn_blocking(move || encode_block(&block, data_shards, parity_shards))
 			.await
-			.into_parts();
-		let put_block_rpc =
-			Req::new(BlockRpc::PutBlock { hash, header })?.with_stream_from_buffer(bytes);
+			.unwrap()
+	}
+
+	/// Get number of items in the refcount table
+	pub fn rc_len(&self) -> Result<usize, Error> {
+		Ok(self.rc.rc.len()?)
+	}
 
-		self.system
+	/// Count the blocks referenced on this node, and the references to them
+	pub fn dedup_stats(&self) -> Result<BlockDedupStats, Error> {
+		let mut stats = BlockDedupStats::default();
+		for item in self.rc.rc.iter()? {
+			let (_, rc) = item?;
+			let count = RcEntry::parse(&rc).as_u64();
+			if count > 0 {
+				stats.blocks += 1;
+				stats.references += count;
+			}
+		}
+		Ok(stats)
+	}
+
+	/// Get the reference count of a block on this node
+	pub fn get_block_rc(&self, hash: &Hash) -> Result<u64, Error> {
+		Ok(self.rc.get_block_rc(hash)?.as_u64())
+	}
+
+	/// Find a block that has a reference counter entry on this node
+	/// from a prefix of its hash in hexadecimal
+	pub fn find_block_hash_by_prefix(&self, prefix: &str) -> Result<Hash, Error> {
+		if prefix.len() < 4 {
+			return Err(Error::Message(
+				"Please specify at least 4 characters of the block hash".into(),
+			));
+		}
+		if !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+			return Err(Error::Message(format!("Invalid block hash: {}", prefix)));
+		}
+		let prefix_bin = hex::decode(&prefix[..prefix.len() & !1])
+			.map_err(|_| Error::Message(format!("Invalid block hash: {}", prefix)))?;
+
+		let mut found = None;
+		for item in self.rc.rc.range(&prefix_bin[..]..)? {
+			let (k, _v) = item?;
+			if !k.starts_with(&prefix_bin) {
+				break;
+			}
+			let hash = match Hash::try_from(&k[..]) {
+				Some(h) => h,
+				None => continue,
+			};
+			if hex::encode(hash.as_slice()).starts_with(prefix) {
+				if found.is_some() {
+					return Err(Error::Message(format!(
+						"Several blocks match prefix {}",
+						prefix
+					)));
+				}
+				found = Some(hash);
+			}
+		}
+		found.ok_or_else(|| Error::Message(format!("No block matches prefix {}", prefix)))
+	}
+
+	/// List the blocks that had an error during their last resync
+	pub fn list_resync_errors(&self) -> Result<Vec<BlockResyncErrorInfo>, Error> {
+		let mut blocks = Vec::with_capacity(self.resync.errors_len()?);
+		for ent in self.resync.errors.iter()? {
+			let (hash, cnt) = ent?;
+			let cnt = ErrorCounter::decode(&cnt);
+			blocks.push(BlockResyncErrorInfo {
+				hash: Hash::try_from(&hash).unwrap(),
+				refcount: 0,
+				error_count: cnt.errors,
+				last_try: cnt.last_try,
+				next_try: cnt.next_try(),
+			});
+		}
+		for block in blocks.iter_mut() {
+			block.refcount = self.get_block_rc(&block.hash)?;
+		}
+		Ok(blocks)
+	}
+
+	/// Estimate the number of bytes stored in each partition of the ring,
+	/// from the number of blocks that have a nonzero reference count on this
+	/// node and their average size on disk. Partitions that this node does not
+	/// store are assumed to contain as much data as the average partition
+	/// it does store. Returns None if this node stores no partition.
+	pub async fn estimate_partition_sizes(&self) -> Result<Option<Vec<u64>>, Error> {
+		let ring = self.system.ring.borrow().clone();
+		let mut block_counts = vec![0u64; 1 << PARTITION_BITS];
+		let mut sample = Vec::with_capacity(BLOCK_SIZE_SAMPLE);
+		for item in self.rc.rc.iter()? {
+			let (hash, rc) = item?;
+			if !RcEntry::parse(&rc).is_nonzero() {
+				continue;
+			}
+			let hash = match Hash::try_from(&hash[..]) {
+				Some(h) => h,
+				None => continue,
+			};
+			block_counts[ring.partition_of(&hash) as usize] += 1;
+			if sample.len() < BLOCK_SIZE_SAMPLE {
+				sample.push(hash);
+			}
+		}
+
+		let mut sample_bytes = 0;
+		for hash in sample.iter() {
+			if let Some(block_path) = self.find_block(hash).await {
+				sample_bytes += fs::metadata(block_path.path()).await?.len();
+			}
+		}
+		let avg_block_size = match sample.len() {
+			0 => 0,
+			n => sample_bytes / n as u64,
+		};
+
+		let local_partitions = ring
+			.partitions()
+			.into_iter()
+			.filter(|(_, first_hash)| {
+				self.replication
+					.write_nodes(first_hash)
+					.contains(&self.system.id)
+			})
+			.map(|(p, _)| p as usize)
+			.collect::<Vec<_>>();
+		if local_partitions.is_empty() {
+			return Ok(None);
+		}
+
+		let mut sizes = vec![0u64; 1 << PARTITION_BITS];
+		for p in local_partitions.iter() {
+			sizes[*p] = block_counts[*p] * avg_block_size;
+		}
+		let avg_partition_size =
+			local_partitions.iter().map(|p| sizes[*p]).sum::<u64>() / local_partitions.len() as u64;
+		for (p, size) in sizes.iter_mut().enumerate() {
+			if !local_partitions.contains(&p) {
+				*size = avg_partition_size;
+			}
+		}
+
+		Ok(Some(sizes))
+	}
+
+	/// Ask the nodes that should store a block whether they actually store it
+	pub async fn rpc_get_replica_status(
+		&self,
+		hash: &Hash,
+	) -> Result<Vec<BlockReplicaStatus>, Error> {
+		let who = self.replication.write_nodes(hash);
+		let resps = self
+			.system
 			.rpc
-			.try_call_many(
+			.call_many(
 				&self.endpoint,
 				&who[..],
-				put_block_rpc,
-				RequestStrategy::with_priority(PRIO_NORMAL | PRIO_SECONDARY)
-					.with_quorum(self.replication.write_quorum()),
+				BlockRpc::BlockStatusQuery(*hash),
+				RequestStrategy::with_priority(PRIO_NORMAL),
 			)
 			.await?;
 
-		Ok(())
-	}
-
-	/// Get number of items in the refcount table
-	pub fn rc_len(&self) -> Result<usize, Error> {
-		Ok(self.rc.rc.len()?)
+		Ok(resps
+			.into_iter()
+			.map(|(node, resp)| {
+				let status = match resp {
+					Ok(BlockRpc::BlockStatusReply(status)) => Ok(status),
+					Ok(m) => Err(Error::unexpected_rpc_message(m).to_string()),
+					Err(e) => Err(e.to_string()),
+				};
+				BlockReplicaStatus { node, status }
+			})
+			.collect())
 	}
 
 	/// Send command to start/stop/manager scrub worker
@@ -314,6 +761,25 @@ impl BlockManager {
 		let _ = self.tx_scrub_command.send(cmd).await;
 	}
 
+	/// Get the reports of the last scrub runs on this node, most recent last
+	pub fn scrub_reports(&self) -> Vec<ScrubReport> {
+		self.scrub_reports.list()
+	}
+
+	/// Find out whether a block that was found corrupted has since been
+	/// fetched again from other nodes
+	pub async fn corrupted_block_status(&self, hash: &Hash) -> Result<CorruptedBlockStatus, Error> {
+		if self.find_block(hash).await.is_some() || self.in_cold_tier(hash)? {
+			Ok(CorruptedBlockStatus::Repaired)
+		} else if self.get_block_rc(hash)? == 0 {
+			Ok(CorruptedBlockStatus::NotNeeded)
+		} else if self.resync.errors.get(hash.as_slice())?.is_some() {
+			Ok(CorruptedBlockStatus::Unrepairable)
+		} else {
+			Ok(CorruptedBlockStatus::Pending)
+		}
+	}
+
 	//// ----- Managing the reference counter ----
 
 	/// Increment the number of time a block is used, putting it to resynchronization if it is
@@ -377,16 +843,89 @@ impl BlockManager {
 		stream: Option<ByteStream>,
 	) -> Result<(), Error> {
 		let stream = stream.ok_or_message("missing stream")?;
+		self.check_free_space(&hash).await?;
 		let bytes = read_stream_to_end(stream).await?;
 		let data = DataBlock::from_parts(header, bytes);
-		self.write_block(&hash, &data).await
+		self.write_block(&hash, &StoredBlock::Full(data)).await
+	}
+
+	async fn handle_put_shard(
+		&self,
+		hash: Hash,
+		header: DataShardHeader,
+		stream: Option<ByteStream>,
+	) -> Result<(), Error> {
+		let stream = stream.ok_or_message("missing stream")?;
+		self.check_free_space(&hash).await?;
+		let data = read_stream_to_end(stream).await?;
+		let shard = DataShard { header, data };
+		shard.verify(hash)?;
+		self.write_block(&hash, &StoredBlock::Shard(shard)).await
 	}
 
-	/// Write a block to disk
-	pub(crate) async fn write_block(&self, hash: &Hash, data: &DataBlock) -> Result<(), Error> {
+	/// Refuse a block that this node does not store yet if the data directory
+	/// where it would be written has less free space than `data_min_free_space`,
+	/// or the metadata directory less than `metadata_min_free_space`,
+	/// so that writers fail fast instead of filling the disk
+	pub(crate) async fn check_free_space(&self, hash: &Hash) -> Result<(), Error> {
+		if self.min_free_space.is_none() && self.metadata_min_free_space.is_none() {
+			return Ok(());
+		}
+		if self.find_block(hash).await.is_some() || self.in_cold_tier(hash)? {
+			return Ok(());
+		}
+
+		let data_dir = self.data_layout.load().primary_data_dir(hash).clone();
+		let checks = [
+			(&self.min_free_space, data_dir.as_path()),
+			(
+				&self.metadata_min_free_space,
+				self.system.metadata_dir.as_path(),
+			),
+		];
+		for (min_free_space, dir) in checks.iter() {
+			if let Some(min_free_space) = min_free_space {
+				if let Err(e) = min_free_space.check(dir) {
+					self.metrics.refused_put_counter.add(1);
+					return Err(e).err_context(format!("Cannot store block {:?}", hash));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Store a block fetched from other nodes: only the shard that
+	/// this node is responsible for if the block is erasure coded
+	pub(crate) async fn write_fetched_block(
+		&self,
+		hash: &Hash,
+		block: DataBlock,
+		mode: BlockStorageMode,
+	) -> Result<(), Error> {
+		let who = self.replication.write_nodes(hash);
+		match (mode, who.iter().position(|n| *n == self.system.id)) {
+			(
+				BlockStorageMode::ErasureCoded {
+					data_shards,
+					parity_shards,
+				},
+				Some(index),
+			) => {
+				let shards = self
+					.encode_block(hash, block, data_shards, parity_shards)
+					.await?;
+				let shard = node_shard(&shards, index);
+				self.write_block(hash, &StoredBlock::Shard(shard)).await
+			}
+			_ => self.write_block(hash, &StoredBlock::Full(block)).await,
+		}
+	}
+
+	/// Write a block or a shard of a block to disk
+	pub(crate) async fn write_block(&self, hash: &Hash, data: &StoredBlock) -> Result<(), Error> {
 		let tracer = opentelemetry::global::tracer("garage");
 
-		let write_size = data.inner_buffer().len() as u64;
+		let write_size = data.len() as u64;
 
 		self.lock_mutate(hash)
 			.await
@@ -408,13 +947,21 @@ impl BlockManager {
 			Err(e) => return Resp::new(Err(e)),
 		};
 
-		let (header, data) = block.into_parts();
-
-		let resp = Resp::new(Ok(BlockRpc::PutBlock {
-			hash: *hash,
-			header,
-		}))
-		.with_stream_from_buffer(data);
+		let resp = match block {
+			StoredBlock::Full(block) => {
+				let (header, data) = block.into_parts();
+				Resp::new(Ok(BlockRpc::PutBlock {
+					hash: *hash,
+					header,
+				}))
+				.with_stream_from_buffer(data)
+			}
+			StoredBlock::Shard(shard) => Resp::new(Ok(BlockRpc::PutShard {
+				hash: *hash,
+				header: shard.header,
+			}))
+			.with_stream_from_buffer(shard.data),
+		};
 
 		if let Some(order_tag) = order_tag {
 			resp.with_order_tag(order_tag)
@@ -423,45 +970,33 @@ impl BlockManager {
 		}
 	}
 
-	/// Read block from disk, verifying it's integrity
-	pub(crate) async fn read_block(&self, hash: &Hash) -> Result<DataBlock, Error> {
+	/// Read block (or the shard of it stored on this node) from disk, verifying it's integrity
+	pub(crate) async fn read_block(&self, hash: &Hash) -> Result<StoredBlock, Error> {
 		let data = self
 			.read_block_internal(hash)
 			.bound_record_duration(&self.metrics.block_read_duration)
 			.await?;
 
-		self.metrics
-			.bytes_read
-			.add(data.inner_buffer().len() as u64);
+		self.metrics.bytes_read.add(data.len() as u64);
 
 		Ok(data)
 	}
 
-	async fn read_block_internal(&self, hash: &Hash) -> Result<DataBlock, Error> {
-		let mut path = self.block_path(hash);
-		let compressed = match self.is_block_compressed(hash).await {
-			Ok(c) => c,
-			Err(e) => {
+	async fn read_block_internal(&self, hash: &Hash) -> Result<StoredBlock, Error> {
+		let block_path = match self.find_block(hash).await {
+			Some(p) => p,
+			None if self.in_cold_tier(hash)? => return self.read_cold_block(hash).await,
+			None => {
 				// Not found but maybe we should have had it ??
 				self.resync
 					.put_to_resync(hash, 2 * self.system.rpc.rpc_timeout())?;
-				return Err(Into::into(e));
+				return Err(Error::Message(format!(
+					"block {:?} not found on this node",
+					hash
+				)));
 			}
 		};
-		if compressed {
-			path.set_extension("zst");
-		}
-		let mut f = fs::File::open(&path).await?;
-
-		let mut data = vec![];
-		f.read_to_end(&mut data).await?;
-		drop(f);
-
-		let data = if compressed {
-			DataBlock::Compressed(data.into())
-		} else {
-			DataBlock::Plain(data.into())
-		};
+		let data = block_path.read().await?;
 
 		if data.verify(*hash).is_err() {
 			self.metrics.corruption_counter.add(1);
@@ -477,6 +1012,62 @@ impl BlockManager {
 		Ok(data)
 	}
 
+	/// Read a block that is in the cold tier, verifying its integrity
+	async fn read_cold_block(&self, hash: &Hash) -> Result<StoredBlock, Error> {
+		let cold_tier = self.cold_tier.as_ref().unwrap();
+		self.metrics.cold_tier_read_counter.add(1);
+		match cold_tier.read(hash).await? {
+			Some(block) if block.verify(*hash).is_ok() => Ok(StoredBlock::Full(block)),
+			res => {
+				// Missing or corrupted: fetch it again from other nodes
+				if res.is_some() {
+					self.metrics.corruption_counter.add(1);
+					cold_tier.delete(hash).await?;
+				}
+				self.resync.put_to_resync(hash, Duration::from_millis(0))?;
+				Err(Error::CorruptData(*hash))
+			}
+		}
+	}
+
+	/// Whether this node stores a block in the cold tier
+	fn in_cold_tier(&self, hash: &Hash) -> Result<bool, Error> {
+		match &self.cold_tier {
+			Some(cold_tier) => cold_tier.contains(hash),
+			None => Ok(false),
+		}
+	}
+
+	/// Whether a cold tier is configured on this node
+	pub fn has_cold_tier(&self) -> bool {
+		self.cold_tier.is_some()
+	}
+
+	/// Number of blocks of this node that are stored in the cold tier
+	pub fn cold_tier_len(&self) -> Result<Option<usize>, Error> {
+		self.cold_tier.as_ref().map(|c| c.len()).transpose()
+	}
+
+	/// Whether a block has a full copy in the data directories of this node
+	/// that can be moved to the cold tier
+	pub(crate) async fn can_move_to_cold_tier(&self, hash: &Hash) -> Result<bool, Error> {
+		Ok(self.cold_tier.is_some()
+			&& !self.in_cold_tier(hash)?
+			&& matches!(
+				self.find_block(hash).await,
+				Some(DataBlockPath::Plain(_) | DataBlockPath::Compressed(_))
+			))
+	}
+
+	/// Move a block from the data directories of this node to the cold tier.
+	/// Returns the number of bytes moved.
+	pub(crate) async fn move_to_cold_tier(&self, hash: &Hash) -> Result<u64, Error> {
+		self.lock_mutate(hash)
+			.await
+			.move_to_cold_tier(hash, self)
+			.await
+	}
+
 	/// Check if this node has a block and whether it needs it
 	pub(crate) async fn check_block_status(&self, hash: &Hash) -> Result<BlockStatus, Error> {
 		self.lock_mutate(hash)
@@ -487,7 +1078,7 @@ impl BlockManager {
 
 	/// Check if this node should have a block, but don't actually have it
 	async fn need_block(&self, hash: &Hash) -> Result<bool, Error> {
-		let BlockStatus { exists, needed } = self.check_block_status(hash).await?;
+		let BlockStatus { exists, needed, .. } = self.check_block_status(hash).await?;
 		Ok(needed.is_nonzero() && !exists)
 	}
 
@@ -499,31 +1090,81 @@ impl BlockManager {
 			.await
 	}
 
-	/// Utility: gives the path of the directory in which a block should be found
-	fn block_dir(&self, hash: &Hash) -> PathBuf {
-		let mut path = self.data_dir.clone();
-		path.push(hex::encode(&hash.as_slice()[0..1]));
-		path.push(hex::encode(&hash.as_slice()[1..2]));
-		path
+	/// Move a block to its primary location if it is stored elsewhere.
+	/// Returns the number of bytes moved.
+	pub(crate) async fn fix_block_location(&self, hash: &Hash) -> Result<u64, Error> {
+		self.lock_mutate(hash)
+			.await
+			.fix_block_location(hash, self)
+			.await
+	}
+
+	/// Forget the previous locations of blocks once they have all been moved
+	/// to their primary location, unless the data layout has changed in the meantime
+	pub(crate) async fn clear_secondary_locations(
+		&self,
+		previous: &Arc<DataLayout>,
+	) -> Result<(), Error> {
+		let new_layout = Arc::new(previous.without_secondary_locations());
+		let prev = self
+			.data_layout
+			.compare_and_swap(previous, new_layout.clone());
+		if Arc::ptr_eq(&prev, previous) {
+			self.data_layout_persister.save_async(&new_layout).await?;
+		}
+		Ok(())
+	}
+
+	/// Check whether a shard is the one that this node should store for a block,
+	/// i.e. it has the index of this node among the nodes storing the block.
+	/// Nodes that should not store the block accept any shard of it.
+	fn is_expected_shard(&self, hash: &Hash, header: &DataShardHeader) -> bool {
+		let who = self.replication.write_nodes(hash);
+		match who.iter().position(|n| *n == self.system.id) {
+			Some(index) => {
+				header.index == index && header.data_shards + header.parity_shards <= who.len()
+			}
+			None => true,
+		}
 	}
 
-	/// Utility: give the full path where a block should be found, minus extension if block is
-	/// compressed
-	fn block_path(&self, hash: &Hash) -> PathBuf {
-		let mut path = self.block_dir(hash);
-		path.push(hex::encode(hash.as_ref()));
-		path
+	/// Check whether a shard stored on disk is the one this node should store
+	async fn is_expected_shard_file(&self, hash: &Hash, path: &Path) -> bool {
+		match read_shard_header(path).await {
+			Ok(header) => self.is_expected_shard(hash, &header),
+			Err(e) => {
+				warn!("Unable to read header of shard {:?}: {}", path, e);
+				false
+			}
+		}
 	}
 
-	/// Utility: check if block is stored compressed. Error if block is not stored
-	async fn is_block_compressed(&self, hash: &Hash) -> Result<bool, Error> {
-		let mut path = self.block_path(hash);
-		path.set_extension("zst");
-		if fs::metadata(&path).await.is_ok() {
-			return Ok(true);
+	/// Utility: find the location of a block in the data directories,
+	/// looking first in the directory where it should be written
+	async fn find_block(&self, hash: &Hash) -> Option<DataBlockPath> {
+		let data_layout = self.data_layout.load_full();
+		let dirs = Some(data_layout.primary_block_dir(hash))
+			.into_iter()
+			.chain(data_layout.secondary_block_dirs(hash));
+		let filename = hex::encode(hash.as_ref());
+
+		for dir in dirs {
+			let mut path = dir;
+			path.push(&filename);
+			path.set_extension("zst");
+			if fs::metadata(&path).await.is_ok() {
+				return Some(DataBlockPath::Compressed(path));
+			}
+			path.set_extension("shard");
+			if fs::metadata(&path).await.is_ok() {
+				return Some(DataBlockPath::Shard(path));
+			}
+			path.set_extension("");
+			if fs::metadata(&path).await.is_ok() {
+				return Some(DataBlockPath::Plain(path));
+			}
 		}
-		path.set_extension("");
-		fs::metadata(&path).await.map(|_| false).map_err(Into::into)
+		None
 	}
 
 	async fn lock_mutate(&self, hash: &Hash) -> MutexGuard<'_, BlockManagerLocked> {
@@ -546,17 +1187,34 @@ impl StreamingEndpointHandler<BlockRpc> for BlockManager {
 					.await
 					.map(|_| BlockRpc::Ok),
 			),
+			BlockRpc::PutShard { hash, header } => Resp::new(
+				self.handle_put_shard(*hash, *header, message.take_stream())
+					.await
+					.map(|_| BlockRpc::Ok),
+			),
 			BlockRpc::GetBlock(h, order_tag) => self.handle_get_block(h, *order_tag).await,
 			BlockRpc::NeedBlockQuery(h) => {
 				Resp::new(self.need_block(h).await.map(BlockRpc::NeedBlockReply))
 			}
+			BlockRpc::BlockStatusQuery(h) => Resp::new(self.check_block_status(h).await.map(
+				|BlockStatus { exists, needed, .. }| {
+					BlockRpc::BlockStatusReply(BlockNodeStatus {
+						stored: exists,
+						refcount: needed.as_u64(),
+					})
+				},
+			)),
 			m => Resp::new(Err(Error::unexpected_rpc_message(m))),
 		}
 	}
 }
 
 pub(crate) struct BlockStatus {
+	/// This node stores the data it should store for the block
 	pub(crate) exists: bool,
+	/// This node stores some data of the block, possibly a shard
+	/// that it should not store
+	pub(crate) stored: bool,
 	pub(crate) needed: RcEntry,
 }
 
@@ -566,69 +1224,123 @@ impl BlockManagerLocked {
 		hash: &Hash,
 		mgr: &BlockManager,
 	) -> Result<BlockStatus, Error> {
-		let exists = mgr.is_block_compressed(hash).await.is_ok();
+		// A shard that is not the one this node should store (e.g. after a
+		// layout change) is treated as missing, so that the right one is fetched
+		let (exists, stored) = match mgr.find_block(hash).await {
+			Some(DataBlockPath::Shard(path)) => {
+				(mgr.is_expected_shard_file(hash, &path).await, true)
+			}
+			Some(_) => (true, true),
+			None => {
+				let in_cold_tier = mgr.in_cold_tier(hash)?;
+				(in_cold_tier, in_cold_tier)
+			}
+		};
 		let needed = mgr.rc.get_block_rc(hash)?;
 
-		Ok(BlockStatus { exists, needed })
+		Ok(BlockStatus {
+			exists,
+			stored,
+			needed,
+		})
 	}
 
 	async fn write_block(
 		&self,
 		hash: &Hash,
-		data: &DataBlock,
+		data: &StoredBlock,
 		mgr: &BlockManager,
 	) -> Result<(), Error> {
-		let compressed = data.is_compressed();
-		let data = data.inner_buffer();
+		// A block in the cold tier is already stored by this node
+		if mgr.in_cold_tier(hash)? {
+			return Ok(());
+		}
 
-		let mut path = mgr.block_dir(hash);
-		let directory = path.clone();
-		path.push(hex::encode(hash));
+		let to_delete = match (mgr.find_block(hash).await, data) {
+			(None, _) => None,
+			// A full copy of the block is never replaced by a shard
+			(Some(DataBlockPath::Compressed(_)), _) => return Ok(()),
+			(Some(DataBlockPath::Plain(_)), StoredBlock::Shard(_)) => return Ok(()),
+			(Some(DataBlockPath::Plain(_)), StoredBlock::Full(DataBlock::Plain(_))) => {
+				return Ok(())
+			}
+			(Some(DataBlockPath::Plain(plain_path)), StoredBlock::Full(_)) => Some(plain_path),
+			(Some(DataBlockPath::Shard(shard_path)), StoredBlock::Full(_)) => Some(shard_path),
+			// A shard is only replaced by the one this node should store
+			(Some(DataBlockPath::Shard(shard_path)), StoredBlock::Shard(shard)) => {
+				if !mgr.is_expected_shard(hash, &shard.header)
+					|| mgr.is_expected_shard_file(hash, &shard_path).await
+				{
+					return Ok(());
+				}
+				Some(shard_path)
+			}
+		};
 
-		fs::create_dir_all(&directory).await?;
+		let directory = mgr.data_layout.load().primary_block_dir(hash);
+		let path = self.write_block_file(hash, data, directory, mgr).await?;
 
-		let to_delete = match (mgr.is_block_compressed(hash).await, compressed) {
-			(Ok(true), _) => return Ok(()),
-			(Ok(false), false) => return Ok(()),
-			(Ok(false), true) => {
-				let path_to_delete = path.clone();
-				path.set_extension("zst");
-				Some(path_to_delete)
+		if let Some(to_delete) = to_delete {
+			if to_delete != path {
+				fs::remove_file(to_delete).await?;
 			}
-			(Err(_), compressed) => {
-				if compressed {
+		}
+
+		Ok(())
+	}
+
+	/// Write a block in a directory, returning the path of the file written
+	async fn write_block_file(
+		&self,
+		hash: &Hash,
+		data: &StoredBlock,
+		directory: PathBuf,
+		mgr: &BlockManager,
+	) -> Result<PathBuf, Error> {
+		let mut path = directory.clone();
+		path.push(hex::encode(hash));
+		let content = match data {
+			StoredBlock::Full(block) => {
+				if block.is_compressed() {
 					path.set_extension("zst");
 				}
-				None
+				block.inner_buffer().to_vec().into()
+			}
+			StoredBlock::Shard(shard) => {
+				path.set_extension("shard");
+				shard.to_file_content()?
 			}
 		};
 
+		fs::create_dir_all(&directory).await?;
+
 		let mut path2 = path.clone();
 		path2.set_extension("tmp");
 		let mut f = fs::File::create(&path2).await?;
-		f.write_all(data).await?;
-		f.sync_all().await?;
+		f.write_all(&content).await?;
+		if mgr.data_fsync {
+			f.sync_all().await?;
+		}
 		drop(f);
 
-		fs::rename(path2, path).await?;
-		if let Some(to_delete) = to_delete {
-			fs::remove_file(to_delete).await?;
-		}
+		fs::rename(path2, &path).await?;
 
-		// We want to ensure that when this function returns, data is properly persisted
-		// to disk. The first step is the sync_all above that does an fsync on the data file.
-		// Now, we do an fsync on the containing directory, to ensure that the rename
-		// is persisted properly. See:
+		// If data_fsync is enabled, we want to ensure that when this function returns,
+		// data is properly persisted to disk. The first step is the sync_all above that
+		// does an fsync on the data file. Now, we do an fsync on the containing directory,
+		// to ensure that the rename is persisted properly. See:
 		// http://thedjbway.b0llix.net/qmail/syncdir.html
-		let dir = fs::OpenOptions::new()
-			.read(true)
-			.mode(0)
-			.open(directory)
-			.await?;
-		dir.sync_all().await?;
-		drop(dir);
+		if mgr.data_fsync {
+			let dir = fs::OpenOptions::new()
+				.read(true)
+				.mode(0)
+				.open(directory)
+				.await?;
+			dir.sync_all().await?;
+			drop(dir);
+		}
 
-		Ok(())
+		Ok(path)
 	}
 
 	async fn move_block_to_corrupted(&self, hash: &Hash, mgr: &BlockManager) -> Result<(), Error> {
@@ -636,31 +1348,167 @@ impl BlockManagerLocked {
 			"Block {:?} is corrupted. Renaming to .corrupted and resyncing.",
 			hash
 		);
-		let mut path = mgr.block_path(hash);
-		let mut path2 = path.clone();
-		if mgr.is_block_compressed(hash).await? {
-			path.set_extension("zst");
-			path2.set_extension("zst.corrupted");
-		} else {
-			path2.set_extension("corrupted");
-		}
-		fs::rename(path, path2).await?;
+		let block_path = mgr
+			.find_block(hash)
+			.await
+			.ok_or_message("block to move to corrupted not found")?;
+		let mut path2 = block_path.path().clone();
+		match block_path {
+			DataBlockPath::Compressed(_) => path2.set_extension("zst.corrupted"),
+			DataBlockPath::Plain(_) => path2.set_extension("corrupted"),
+			DataBlockPath::Shard(_) => path2.set_extension("shard.corrupted"),
+		};
+		fs::rename(block_path.path(), path2).await?;
 		Ok(())
 	}
 
 	async fn delete_if_unneeded(&self, hash: &Hash, mgr: &BlockManager) -> Result<(), Error> {
-		let BlockStatus { exists, needed } = self.check_block_status(hash, mgr).await?;
+		let BlockStatus { stored, needed, .. } = self.check_block_status(hash, mgr).await?;
 
-		if exists && needed.is_deletable() {
-			let mut path = mgr.block_path(hash);
-			if mgr.is_block_compressed(hash).await? {
-				path.set_extension("zst");
+		if stored && needed.is_deletable() {
+			// Delete all copies of the block, including those that are not
+			// yet in their primary location
+			while let Some(block_path) = mgr.find_block(hash).await {
+				fs::remove_file(block_path.path()).await?;
+			}
+			if let Some(cold_tier) = &mgr.cold_tier {
+				cold_tier.delete(hash).await?;
 			}
-			fs::remove_file(path).await?;
 			mgr.metrics.delete_counter.add(1);
 		}
 		Ok(())
 	}
+
+	async fn move_to_cold_tier(&self, hash: &Hash, mgr: &BlockManager) -> Result<u64, Error> {
+		if !mgr.can_move_to_cold_tier(hash).await? {
+			return Ok(0);
+		}
+		let block = match mgr.find_block(hash).await.unwrap().read().await? {
+			StoredBlock::Full(block) => block,
+			StoredBlock::Shard(_) => return Ok(0),
+		};
+		block.verify(*hash)?;
+
+		mgr.cold_tier.as_ref().unwrap().write(hash, &block).await?;
+		// Delete all full copies of the block, including those that are not
+		// yet in their primary location
+		while let Some(DataBlockPath::Plain(path) | DataBlockPath::Compressed(path)) =
+			mgr.find_block(hash).await
+		{
+			fs::remove_file(path).await?;
+		}
+		mgr.metrics.cold_tier_moved_counter.add(1);
+
+		Ok(block.inner_buffer().len() as u64)
+	}
+
+	async fn fix_block_location(&self, hash: &Hash, mgr: &BlockManager) -> Result<u64, Error> {
+		let block_path = match mgr.find_block(hash).await {
+			Some(p) => p,
+			None => return Ok(0),
+		};
+		let data_layout = mgr.data_layout.load();
+		let primary_dir = data_layout.primary_block_dir(hash);
+		if block_path.path().parent() == Some(primary_dir.as_path()) {
+			return Ok(0);
+		}
+		if let Some(min_free_space) = &mgr.min_free_space {
+			min_free_space
+				.check(data_layout.primary_data_dir(hash))
+				.err_context(format!("Cannot move block {:?}", hash))?;
+		}
+
+		let data = block_path.read().await?;
+		self.write_block_file(hash, &data, primary_dir, mgr).await?;
+		fs::remove_file(block_path.path()).await?;
+
+		Ok(data.len() as u64)
+	}
+}
+
+/// Location of a block in the data directories
+enum DataBlockPath {
+	Plain(PathBuf),
+	Compressed(PathBuf),
+	Shard(PathBuf),
+}
+
+impl DataBlockPath {
+	fn path(&self) -> &PathBuf {
+		match self {
+			Self::Plain(p) | Self::Compressed(p) | Self::Shard(p) => p,
+		}
+	}
+
+	async fn read(&self) -> Result<StoredBlock, Error> {
+		let mut f = fs::File::open(self.path()).await?;
+		let mut data = vec![];
+		f.read_to_end(&mut data).await?;
+		drop(f);
+
+		Ok(match self {
+			Self::Plain(_) => StoredBlock::Full(DataBlock::Plain(data.into())),
+			Self::Compressed(_) => StoredBlock::Full(DataBlock::Compressed(data.into())),
+			Self::Shard(_) => StoredBlock::Shard(DataShard::from_file_content(data.into())?),
+		})
+	}
+}
+
+/// Read a shard sent by a node in a response to GetBlock, and add it
+/// to the shards collected so far if it is valid
+async fn collect_shard(
+	hash: &Hash,
+	shards: &mut Vec<DataShard>,
+	header: DataShardHeader,
+	stream: ByteStream,
+	node: &Uuid,
+) {
+	let data = match read_stream_to_end(stream).await {
+		Ok(data) => data,
+		Err(e) => {
+			debug!("Error reading stream from node {:?}: {}", node, e);
+			return;
+		}
+	};
+	let shard = DataShard { header, data };
+	if shard.verify(*hash).is_err() {
+		debug!("Node {:?} returned a corrupted shard", node);
+		return;
+	}
+	if !shard.is_placeholder() && !shards.iter().any(|s| s.header.index == header.index) {
+		shards.push(shard);
+	}
+}
+
+/// Reconstruct a block once enough distinct shards of it have been collected
+async fn decode_shards(hash: &Hash, shards: &[DataShard]) -> Option<(DataBlock, BlockStorageMode)> {
+	let header = shards.first()?.header;
+	let data_shards = header.data_shards;
+	if distinct_shards(shards) < data_shards {
+		return None;
+	}
+
+	let shards = shards.to_vec();
+	let block = tokio::task::spawn_blocking(move || decode_block(&shards))
+		.await
+		.unwrap();
+	match block {
+		Ok(block) if block.verify(*hash).is_ok() => Some((
+			block,
+			BlockStorageMode::ErasureCoded {
+				data_shards,
+				parity_shards: header.parity_shards,
+			},
+		)),
+		Ok(_) => {
+			debug!("Block {:?} reconstructed from shards is corrupted", hash);
+			None
+		}
+		Err(e) => {
+			debug!("Unable to reconstruct block {:?}: {}", hash, e);
+			None
+		}
+	}
 }
 
 async fn read_stream_to_end(mut stream: ByteStream) -> Result<Bytes, Error> {
@@ -19,6 +19,19 @@ pub struct BlockManagerMetrics {
 	pub(crate) block_write_duration: BoundValueRecorder<f64>,
 	pub(crate) delete_counter: BoundCounter<u64>,
 
+	pub(crate) compression_input_bytes: Counter<u64>,
+	pub(crate) compression_output_bytes: Counter<u64>,
+
+	pub(crate) cache_hit_counter: Counter<u64>,
+	pub(crate) cache_miss_counter: BoundCounter<u64>,
+
+	pub(crate) hedged_read_counter: BoundCounter<u64>,
+
+	pub(crate) refused_put_counter: BoundCounter<u64>,
+
+	pub(crate) cold_tier_moved_counter: BoundCounter<u64>,
+	pub(crate) cold_tier_read_counter: BoundCounter<u64>,
+
 	pub(crate) corruption_counter: BoundCounter<u64>,
 }
 
@@ -92,6 +105,52 @@ impl BlockManagerMetrics {
 				.init()
 				.bind(&[]),
 
+			compression_input_bytes: meter
+				.u64_counter("block.compression_input_bytes")
+				.with_description("Size of new blocks before compression")
+				.init(),
+			compression_output_bytes: meter
+				.u64_counter("block.compression_output_bytes")
+				.with_description("Size of new blocks as they are stored, i.e. after compression if they are compressed")
+				.init(),
+
+			cache_hit_counter: meter
+				.u64_counter("block.cache_hit_counter")
+				.with_description("Number of blocks read from the block cache")
+				.init(),
+			cache_miss_counter: meter
+				.u64_counter("block.cache_miss_counter")
+				.with_description("Number of blocks that were not in the block cache when read")
+				.init()
+				.bind(&[]),
+
+			refused_put_counter: meter
+				.u64_counter("block.refused_put_counter")
+				.with_description(
+					"Number of blocks refused because there was not enough free space",
+				)
+				.init()
+				.bind(&[]),
+
+			cold_tier_moved_counter: meter
+				.u64_counter("block.cold_tier_moved_counter")
+				.with_description("Number of blocks moved to the cold tier")
+				.init()
+				.bind(&[]),
+			cold_tier_read_counter: meter
+				.u64_counter("block.cold_tier_read_counter")
+				.with_description("Number of blocks read from the cold tier")
+				.init()
+				.bind(&[]),
+
+			hedged_read_counter: meter
+				.u64_counter("block.hedged_read_counter")
+				.with_description(
+					"Number of block requests sent to another node because a block read was slow",
+				)
+				.init()
+				.bind(&[]),
+
 			corruption_counter: meter
 				.u64_counter("block.corruption_counter")
 				.with_description("Data corruptions detected on block reads")
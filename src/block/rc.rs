@@ -93,7 +93,7 @@ pub(crate) enum RcEntry {
 }
 
 impl RcEntry {
-	fn parse(bytes: &[u8]) -> Self {
+	pub(crate) fn parse(bytes: &[u8]) -> Self {
 		if bytes.len() == 8 {
 			RcEntry::Present {
 				count: u64::from_be_bytes(bytes.try_into().unwrap()),
@@ -158,6 +158,13 @@ impl RcEntry {
 		!self.is_zero()
 	}
 
+	pub(crate) fn as_u64(&self) -> u64 {
+		match self {
+			RcEntry::Present { count } => *count,
+			_ => 0,
+		}
+	}
+
 	pub(crate) fn is_deletable(&self) -> bool {
 		match self {
 			RcEntry::Present { .. } => false,
@@ -1,6 +1,6 @@
 use core::ops::Bound;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -17,6 +17,7 @@ use garage_util::persister::Persister;
 use garage_util::time::*;
 use garage_util::tranquilizer::Tranquilizer;
 
+use crate::layout::*;
 use crate::manager::*;
 
 // Full scrub every 30 days
@@ -24,6 +25,11 @@ const SCRUB_INTERVAL: Duration = Duration::from_secs(3600 * 24 * 30);
 // Scrub tranquility is initially set to 4, but can be changed in the CLI
 // and the updated version is persisted over Garage restarts
 const INITIAL_SCRUB_TRANQUILITY: u32 = 4;
+// Number of scrub reports kept in the metadata directory
+const MAX_SCRUB_REPORTS: usize = 10;
+// The report of the running scrub is saved to disk every time
+// this many blocks have been checked
+const SCRUB_REPORT_SAVE_INTERVAL: u64 = 1000;
 
 // ---- ---- ----
 // FIRST KIND OF REPAIR: FINDING MISSING BLOCKS/USELESS BLOCKS
@@ -189,6 +195,117 @@ pub enum ScrubWorkerCommand {
 	SetTranquility(u32),
 }
 
+/// Report of a single run of the scrub worker
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScrubReport {
+	/// Time at which the scrub was started, in milliseconds since UNIX Epoch
+	pub start_time: u64,
+	/// Time at which the scrub finished or was cancelled
+	pub end_time: Option<u64>,
+	pub status: ScrubRunStatus,
+	pub blocks_checked: u64,
+	pub bytes_checked: u64,
+	/// Blocks that were found corrupted during this run
+	pub corrupted_blocks: Vec<ScrubCorruptedBlock>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrubRunStatus {
+	Running,
+	Paused,
+	Completed,
+	Cancelled,
+	/// Garage was restarted while the scrub was running
+	Interrupted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScrubCorruptedBlock {
+	pub hash: Hash,
+	/// Time at which the corruption was detected, in milliseconds since UNIX Epoch
+	pub detected: u64,
+}
+
+/// The reports of the last scrub runs of this node, most recent last,
+/// shared between the scrub worker and the block manager
+pub(crate) struct ScrubReports {
+	persister: Persister<Vec<ScrubReport>>,
+	reports: Mutex<Vec<ScrubReport>>,
+}
+
+impl ScrubReports {
+	pub(crate) fn new(metadata_dir: &Path) -> Self {
+		let persister = Persister::new(metadata_dir, "scrub_reports");
+		let mut reports: Vec<ScrubReport> = persister.load().unwrap_or_default();
+
+		// A scrub that was in progress when Garage stopped is not resumed
+		let mut interrupted = false;
+		for report in reports.iter_mut() {
+			if matches!(
+				report.status,
+				ScrubRunStatus::Running | ScrubRunStatus::Paused
+			) {
+				report.status = ScrubRunStatus::Interrupted;
+				interrupted = true;
+			}
+		}
+		if interrupted {
+			if let Err(e) = persister.save(&reports) {
+				error!("Could not save scrub reports: {}", e);
+			}
+		}
+
+		Self {
+			persister,
+			reports: Mutex::new(reports),
+		}
+	}
+
+	pub(crate) fn list(&self) -> Vec<ScrubReport> {
+		self.reports.lock().unwrap().clone()
+	}
+
+	fn start(&self) {
+		let mut reports = self.reports.lock().unwrap();
+		reports.push(ScrubReport {
+			start_time: now_msec(),
+			end_time: None,
+			status: ScrubRunStatus::Running,
+			blocks_checked: 0,
+			bytes_checked: 0,
+			corrupted_blocks: vec![],
+		});
+		if reports.len() > MAX_SCRUB_REPORTS {
+			let excess = reports.len() - MAX_SCRUB_REPORTS;
+			reports.drain(..excess);
+		}
+	}
+
+	/// Apply a modification to the report of the last scrub run
+	fn update_last<F: FnOnce(&mut ScrubReport)>(&self, f: F) {
+		if let Some(report) = self.reports.lock().unwrap().last_mut() {
+			f(report);
+		}
+	}
+
+	fn set_status(&self, status: ScrubRunStatus) {
+		self.update_last(|report| {
+			report.status = status;
+			if matches!(
+				status,
+				ScrubRunStatus::Completed | ScrubRunStatus::Cancelled
+			) {
+				report.end_time = Some(now_msec());
+			}
+		});
+	}
+
+	async fn save(&self) -> Result<(), Error> {
+		let reports = self.list();
+		self.persister.save_async(&reports).await
+	}
+}
+
 impl ScrubWorker {
 	pub fn new(manager: Arc<BlockManager>, rx_cmd: mpsc::Receiver<ScrubWorkerCommand>) -> Self {
 		let persister = Persister::new(&manager.system.metadata_dir, "scrub_info");
@@ -215,6 +332,8 @@ impl ScrubWorker {
 			ScrubWorkerCommand::Start => {
 				self.work = match std::mem::take(&mut self.work) {
 					ScrubWorkerState::Finished => {
+						self.manager.scrub_reports.start();
+						self.save_report().await;
 						let iterator = BlockStoreIterator::new(&self.manager);
 						ScrubWorkerState::Running(iterator)
 					}
@@ -227,6 +346,10 @@ impl ScrubWorker {
 			ScrubWorkerCommand::Pause(dur) => {
 				self.work = match std::mem::take(&mut self.work) {
 					ScrubWorkerState::Running(it) | ScrubWorkerState::Paused(it, _) => {
+						self.manager
+							.scrub_reports
+							.set_status(ScrubRunStatus::Paused);
+						self.save_report().await;
 						ScrubWorkerState::Paused(it, now_msec() + dur.as_millis() as u64)
 					}
 					work => {
@@ -237,7 +360,13 @@ impl ScrubWorker {
 			}
 			ScrubWorkerCommand::Resume => {
 				self.work = match std::mem::take(&mut self.work) {
-					ScrubWorkerState::Paused(it, _) => ScrubWorkerState::Running(it),
+					ScrubWorkerState::Paused(it, _) => {
+						self.manager
+							.scrub_reports
+							.set_status(ScrubRunStatus::Running);
+						self.save_report().await;
+						ScrubWorkerState::Running(it)
+					}
 					work => {
 						error!("Cannot resume scrub worker: not paused!");
 						work
@@ -247,6 +376,10 @@ impl ScrubWorker {
 			ScrubWorkerCommand::Cancel => {
 				self.work = match std::mem::take(&mut self.work) {
 					ScrubWorkerState::Running(_) | ScrubWorkerState::Paused(_, _) => {
+						self.manager
+							.scrub_reports
+							.set_status(ScrubRunStatus::Cancelled);
+						self.save_report().await;
 						ScrubWorkerState::Finished
 					}
 					work => {
@@ -263,6 +396,12 @@ impl ScrubWorker {
 			}
 		}
 	}
+
+	async fn save_report(&self) {
+		if let Err(e) = self.manager.scrub_reports.save().await {
+			error!("Could not save scrub report: {}", e);
+		}
+	}
 }
 
 #[async_trait]
@@ -307,14 +446,33 @@ impl Worker for ScrubWorker {
 			ScrubWorkerState::Running(bsi) => {
 				self.tranquilizer.reset();
 				if let Some(hash) = bsi.next().await? {
+					let reports = &self.manager.scrub_reports;
 					match self.manager.read_block(&hash).await {
+						Ok(data) => {
+							let mut checked = 0;
+							reports.update_last(|report| {
+								report.blocks_checked += 1;
+								report.bytes_checked += data.len() as u64;
+								checked = report.blocks_checked;
+							});
+							if checked % SCRUB_REPORT_SAVE_INTERVAL == 0 {
+								reports.save().await?;
+							}
+						}
 						Err(Error::CorruptData(_)) => {
 							error!("Found corrupt data block during scrub: {:?}", hash);
 							self.persisted.corruptions_detected += 1;
 							self.persister.save_async(&self.persisted).await?;
+							reports.update_last(|report| {
+								report.blocks_checked += 1;
+								report.corrupted_blocks.push(ScrubCorruptedBlock {
+									hash,
+									detected: now_msec(),
+								});
+							});
+							reports.save().await?;
 						}
 						Err(e) => return Err(e),
-						_ => (),
 					};
 					Ok(self
 						.tranquilizer
@@ -322,6 +480,10 @@ impl Worker for ScrubWorker {
 				} else {
 					self.persisted.time_last_complete_scrub = now_msec();
 					self.persister.save_async(&self.persisted).await?;
+					self.manager
+						.scrub_reports
+						.set_status(ScrubRunStatus::Completed);
+					self.manager.scrub_reports.save().await?;
 					self.work = ScrubWorkerState::Finished;
 					self.tranquilizer.clear();
 					Ok(WorkerState::Idle)
@@ -363,11 +525,107 @@ impl Worker for ScrubWorker {
 	}
 }
 
+// ---- ---- ----
+// THIRD KIND OF REPAIR: REBALANCING DATA BLOCKS
+// between multiple data directories of a node. This is a one-shot
+// operation, launched automatically when data directories have changed,
+// that moves blocks to the directory where they should now be stored.
+// ---- ---- ----
+
+pub struct RebalanceWorker {
+	manager: Arc<BlockManager>,
+	data_layout: Arc<DataLayout>,
+	block_iter: BlockStoreIterator,
+	moved: usize,
+	moved_bytes: u64,
+	/// Blocks that could not be moved, e.g. because there was not enough free space
+	not_moved: usize,
+}
+
+impl RebalanceWorker {
+	pub fn new(manager: Arc<BlockManager>) -> Self {
+		let data_layout = manager.data_layout.load_full();
+		let block_iter = BlockStoreIterator::new(&manager);
+		Self {
+			manager,
+			data_layout,
+			block_iter,
+			moved: 0,
+			moved_bytes: 0,
+			not_moved: 0,
+		}
+	}
+}
+
+#[async_trait]
+impl Worker for RebalanceWorker {
+	fn name(&self) -> String {
+		"Block rebalance worker".into()
+	}
+
+	fn info(&self) -> Option<String> {
+		Some(format!(
+			"{:.2}% done, {} blocks moved ({}), {} could not be moved",
+			self.block_iter.progress() * 100.,
+			self.moved,
+			bytesize::ByteSize::b(self.moved_bytes).to_string_as(true),
+			self.not_moved
+		))
+	}
+
+	async fn work(&mut self, _must_exit: &mut watch::Receiver<bool>) -> Result<WorkerState, Error> {
+		if let Some(hash) = self.block_iter.next().await? {
+			match self.manager.fix_block_location(&hash).await {
+				Ok(0) => (),
+				Ok(moved_bytes) => {
+					self.moved += 1;
+					self.moved_bytes += moved_bytes;
+				}
+				Err(e) => {
+					warn!("Could not move block {:?}: {}", hash, e);
+					self.not_moved += 1;
+				}
+			}
+			Ok(WorkerState::Busy)
+		} else if self.not_moved > 0 {
+			// Blocks left in their previous location must still be found there,
+			// until a later rebalance manages to move them
+			warn!(
+				"Block rebalance finished: {} blocks moved ({}), {} could not be moved, run `garage repair rebalance` to retry",
+				self.moved,
+				bytesize::ByteSize::b(self.moved_bytes).to_string_as(true),
+				self.not_moved
+			);
+			Ok(WorkerState::Done)
+		} else {
+			// All blocks are now in their primary location: we no longer
+			// need to look for them in the previous ones
+			self.manager
+				.clear_secondary_locations(&self.data_layout)
+				.await?;
+			info!(
+				"Block rebalance finished: {} blocks moved ({})",
+				self.moved,
+				bytesize::ByteSize::b(self.moved_bytes).to_string_as(true)
+			);
+			Ok(WorkerState::Done)
+		}
+	}
+
+	async fn wait_for_work(&mut self, _must_exit: &watch::Receiver<bool>) -> WorkerState {
+		// The worker is never idle: it is done once all blocks have been checked
+		WorkerState::Done
+	}
+}
+
 // ---- ---- ----
 // UTILITY FOR ENUMERATING THE BLOCK STORE
 // ---- ---- ----
 
 struct BlockStoreIterator {
+	/// Data directories that have not been enumerated yet
+	todo: Vec<PathBuf>,
+	n_data_dirs: usize,
 	path: Vec<ReadingDir>,
 }
 
@@ -381,15 +639,24 @@ enum ReadingDir {
 
 impl BlockStoreIterator {
 	fn new(manager: &BlockManager) -> Self {
-		let root_dir = manager.data_dir.clone();
+		let mut todo = manager
+			.data_layout
+			.load()
+			.data_dirs
+			.iter()
+			.map(|d| d.path.clone())
+			.collect::<Vec<_>>();
+		todo.reverse();
 		Self {
-			path: vec![ReadingDir::Pending(root_dir)],
+			n_data_dirs: todo.len(),
+			todo,
+			path: vec![],
 		}
 	}
 
 	/// Returns progress done, between 0 and 1
 	fn progress(&self) -> f32 {
-		if self.path.is_empty() {
+		if self.path.is_empty() && self.todo.is_empty() {
 			1.0
 		} else {
 			let mut ret = 0.0;
@@ -403,16 +670,20 @@ impl BlockStoreIterator {
 					}
 				}
 			}
-			ret
+			let done = self.n_data_dirs - self.todo.len() - (!self.path.is_empty()) as usize;
+			(done as f32 + ret) / self.n_data_dirs as f32
 		}
 	}
 
 	async fn next(&mut self) -> Result<Option<Hash>, Error> {
 		loop {
-			let last_path = match self.path.last_mut() {
-				None => return Ok(None),
-				Some(lp) => lp,
-			};
+			if self.path.is_empty() {
+				match self.todo.pop() {
+					Some(data_dir) => self.path.push(ReadingDir::Pending(data_dir)),
+					None => return Ok(None),
+				}
+			}
+			let last_path = self.path.last_mut().unwrap();
 
 			if let ReadingDir::Pending(path) = last_path {
 				let mut reader = fs::read_dir(&path).await?;
@@ -450,7 +721,10 @@ impl BlockStoreIterator {
 			};
 			let ent_type = data_dir_ent.file_type().await?;
 
-			let name = name.strip_suffix(".zst").unwrap_or(&name);
+			let name = name
+				.strip_suffix(".zst")
+				.or_else(|| name.strip_suffix(".shard"))
+				.unwrap_or(&name);
 			if name.len() == 2 && hex::decode(&name).is_ok() && ent_type.is_dir() {
 				let path = data_dir_ent.path();
 				self.path.push(ReadingDir::Pending(path));
@@ -31,6 +31,7 @@ use garage_rpc::*;
 
 use garage_table::replication::TableReplication;
 
+use crate::erasure::*;
 use crate::manager::*;
 
 // The delay between the time where a resync operation fails
@@ -123,6 +124,25 @@ impl BlockResyncManager {
 		Ok(self.errors.len())
 	}
 
+	/// Clear the exponential backoff delay of a block that is in an error
+	/// state, and put it in the resync queue to be retried immediately
+	pub fn clear_backoff(&self, hash: &Hash) -> Result<(), Error> {
+		let now = now_msec();
+		if let Some(ec) = self.errors.get(hash.as_slice())? {
+			let mut ec = ErrorCounter::decode(&ec);
+			if ec.errors > 0 {
+				ec.last_try = now.saturating_sub(ec.delay_msec());
+				self.errors.insert(hash.as_slice(), ec.encode())?;
+				self.put_to_resync_at(hash, now)?;
+				return Ok(());
+			}
+		}
+		Err(Error::Message(format!(
+			"Block {:?} is not in an error state",
+			hash
+		)))
+	}
+
 	// ---- Resync loop ----
 
 	// This part manages a queue of blocks that need to be
@@ -310,7 +330,11 @@ impl BlockResyncManager {
 	}
 
 	async fn resync_block(&self, manager: &BlockManager, hash: &Hash) -> Result<(), Error> {
-		let BlockStatus { exists, needed } = manager.check_block_status(hash).await?;
+		let BlockStatus {
+			exists,
+			stored,
+			needed,
+		} = manager.check_block_status(hash).await?;
 
 		if exists != needed.is_needed() || exists != needed.is_nonzero() {
 			debug!(
@@ -322,7 +346,8 @@ impl BlockResyncManager {
 			);
 		}
 
-		if exists && needed.is_deletable() {
+		// Unneeded data is deleted even if it is a shard this node should not store
+		if stored && needed.is_deletable() {
 			info!("Resync block {:?}: offloading and deleting", hash);
 
 			let mut who = manager.replication.write_nodes(hash);
@@ -370,20 +395,22 @@ impl BlockResyncManager {
 						.add(1, &[KeyValue::new("to", format!("{:?}", node))]);
 				}
 
-				let block = manager.read_block(hash).await?;
-				let (header, bytes) = block.into_parts();
-				let put_block_message = Req::new(BlockRpc::PutBlock {
-					hash: *hash,
-					header,
-				})?
-				.with_stream_from_buffer(bytes);
+				// If we only store a shard of the block, the block is reconstructed
+				// with the shards of the other nodes so that the nodes that need it
+				// can be sent the shard they should store
+				let (block, mode) = match manager.read_block(hash).await? {
+					StoredBlock::Full(block) => (block, BlockStorageMode::Replicated),
+					StoredBlock::Shard(shard) => manager
+						.rpc_get_raw_block(hash, None, vec![shard])
+						.await
+						.err_context("reconstructing erasure coded block")?,
+				};
 				manager
-					.system
-					.rpc
-					.try_call_many(
-						&manager.endpoint,
+					.rpc_send_block(
+						*hash,
+						block,
+						mode,
 						&need_nodes[..],
-						put_block_message,
 						RequestStrategy::with_priority(PRIO_BACKGROUND)
 							.with_quorum(need_nodes.len()),
 					)
@@ -408,11 +435,14 @@ impl BlockResyncManager {
 				hash
 			);
 
-			let block_data = manager.rpc_get_raw_block(hash, None).await?;
+			// If the disk is nearly full, the block is fetched later, when the
+			// resync is retried, rather than risking to fill it
+			manager.check_free_space(hash).await?;
+			let (block_data, mode) = manager.rpc_get_raw_block(hash, None, vec![]).await?;
 
 			manager.metrics.resync_recv_counter.add(1);
 
-			manager.write_block(hash, &block_data).await?;
+			manager.write_fetched_block(hash, block_data, mode).await?;
 		}
 
 		Ok(())
@@ -541,13 +571,27 @@ impl Worker for ResyncWorker {
 	}
 }
 
+/// Information about a block that had an error during its last resync
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockResyncErrorInfo {
+	pub hash: Hash,
+	/// Reference count of the block on this node
+	pub refcount: u64,
+	/// Number of consecutive resync errors
+	pub error_count: u64,
+	/// Time of the last resync attempt, in milliseconds since UNIX Epoch
+	pub last_try: u64,
+	/// Time of the next resync attempt, in milliseconds since UNIX Epoch
+	pub next_try: u64,
+}
+
 /// Counts the number of errors when resyncing a block,
 /// and the time of the last try.
 /// Used to implement exponential backoff.
 #[derive(Clone, Copy, Debug)]
-struct ErrorCounter {
-	errors: u64,
-	last_try: u64,
+pub(crate) struct ErrorCounter {
+	pub(crate) errors: u64,
+	pub(crate) last_try: u64,
 }
 
 impl ErrorCounter {
@@ -558,7 +602,7 @@ impl ErrorCounter {
 		}
 	}
 
-	fn decode(data: &[u8]) -> Self {
+	pub(crate) fn decode(data: &[u8]) -> Self {
 		Self {
 			errors: u64::from_be_bytes(data[0..8].try_into().unwrap()),
 			last_try: u64::from_be_bytes(data[8..16].try_into().unwrap()),
@@ -583,7 +627,7 @@ impl ErrorCounter {
 		(RESYNC_RETRY_DELAY.as_millis() as u64)
 			<< std::cmp::min(self.errors - 1, RESYNC_RETRY_DELAY_MAX_BACKOFF_POWER)
 	}
-	fn next_try(&self) -> u64 {
+	pub(crate) fn next_try(&self) -> u64 {
 		self.last_try + self.delay_msec()
 	}
 }
@@ -0,0 +1,431 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::watch;
+
+use rusoto_core::{DispatchSignedRequest, HttpClient};
+use rusoto_credential::AwsCredentials;
+use rusoto_signature::region::Region;
+use rusoto_signature::signature::SignedRequest;
+
+use garage_db as db;
+
+use garage_util::background::*;
+use garage_util::config::{ColdTierConfig, ColdTierS3Config};
+use garage_util::data::*;
+use garage_util::error::*;
+use garage_util::persister::Persister;
+use garage_util::tranquilizer::Tranquilizer;
+
+use crate::block::*;
+use crate::manager::*;
+
+const INITIAL_TIERING_TRANQUILITY: u32 = 2;
+
+/// Timeout of requests to a S3-compatible cold tier
+const S3_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Storage for the content of data blocks (as stored in data files),
+/// identified by a name of the form `<node id>/<prefix>/<hash>[.zst]`
+#[async_trait]
+pub trait BlockStore: Send + Sync {
+	/// Read an object, returning None if it does not exist
+	async fn get(&self, name: &str) -> Result<Option<Bytes>, Error>;
+	/// Write an object, replacing it if it exists
+	async fn put(&self, name: &str, data: Bytes) -> Result<(), Error>;
+	/// Delete an object, if it exists
+	async fn delete(&self, name: &str) -> Result<(), Error>;
+}
+
+/// Block store in a directory of the local filesystem
+pub struct LocalBlockStore {
+	dir: PathBuf,
+}
+
+impl LocalBlockStore {
+	pub fn new(dir: PathBuf) -> Self {
+		Self { dir }
+	}
+}
+
+#[async_trait]
+impl BlockStore for LocalBlockStore {
+	async fn get(&self, name: &str) -> Result<Option<Bytes>, Error> {
+		match fs::read(self.dir.join(name)).await {
+			Ok(data) => Ok(Some(data.into())),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn put(&self, name: &str, data: Bytes) -> Result<(), Error> {
+		let path = self.dir.join(name);
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).await?;
+		}
+
+		let mut tmp_path = OsString::from(&path);
+		tmp_path.push(".tmp");
+		let mut f = fs::File::create(&tmp_path).await?;
+		f.write_all(&data).await?;
+		f.sync_all().await?;
+		drop(f);
+		fs::rename(tmp_path, path).await?;
+		Ok(())
+	}
+
+	async fn delete(&self, name: &str) -> Result<(), Error> {
+		match fs::remove_file(self.dir.join(name)).await {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Block store in a bucket of a S3-compatible object store
+pub struct S3BlockStore {
+	client: HttpClient,
+	region: Region,
+	bucket: String,
+	creds: AwsCredentials,
+}
+
+impl S3BlockStore {
+	pub fn new(config: &ColdTierS3Config) -> Result<Self, Error> {
+		// The default client of rusoto only accepts https endpoints,
+		// but object stores on the local network are often reached over http
+		let connector = hyper_rustls::HttpsConnectorBuilder::new()
+			.with_native_roots()
+			.https_or_http()
+			.enable_http1()
+			.enable_http2()
+			.build();
+		let client = HttpClient::from_connector(connector);
+		Ok(Self {
+			client,
+			region: Region::Custom {
+				name: config.region.clone(),
+				endpoint: config.endpoint.clone(),
+			},
+			bucket: config.bucket.clone(),
+			creds: AwsCredentials::new(
+				config.access_key_id.clone(),
+				config.secret_access_key.clone(),
+				None,
+				None,
+			),
+		})
+	}
+
+	/// Send a request for an object, returning the response body,
+	/// or None if the object does not exist
+	async fn request(
+		&self,
+		method: &str,
+		name: &str,
+		payload: Option<Bytes>,
+	) -> Result<Option<Bytes>, Error> {
+		let mut req = SignedRequest::new(
+			method,
+			"s3",
+			&self.region,
+			&format!("/{}/{}", self.bucket, name),
+		);
+		req.set_payload(payload);
+		req.sign(&self.creds);
+
+		let res = self
+			.client
+			.dispatch(req, Some(S3_TIMEOUT))
+			.await
+			.map_err(|e| Error::Message(format!("Request to the cold tier failed: {}", e)))?;
+		let status = res.status;
+		let mut body = vec![];
+		res.body.into_async_read().read_to_end(&mut body).await?;
+
+		if status.as_u16() == 404 {
+			Ok(None)
+		} else if status.is_success() {
+			Ok(Some(body.into()))
+		} else {
+			Err(Error::Message(format!(
+				"Cold tier returned {} to {} {}: {}",
+				status,
+				method,
+				name,
+				String::from_utf8_lossy(&body)
+			)))
+		}
+	}
+}
+
+#[async_trait]
+impl BlockStore for S3BlockStore {
+	async fn get(&self, name: &str) -> Result<Option<Bytes>, Error> {
+		self.request("GET", name, None).await
+	}
+
+	async fn put(&self, name: &str, data: Bytes) -> Result<(), Error> {
+		self.request("PUT", name, Some(data)).await?;
+		Ok(())
+	}
+
+	async fn delete(&self, name: &str) -> Result<(), Error> {
+		self.request("DELETE", name, None).await?;
+		Ok(())
+	}
+}
+
+// ---- Cold tier of the block manager ----
+
+/// The cold storage tier, where blocks of this node that are rarely read are moved.
+/// Blocks of each node are stored under a prefix that is the ID of the node,
+/// so that each node manages the copies of blocks it is responsible for
+/// independently, as it does in its data directories.
+/// Blocks in the cold tier are not scrubbed, they are only checked when read.
+pub(crate) struct ColdTier {
+	store: Box<dyn BlockStore>,
+	/// Blocks of this node that are in the cold tier,
+	/// with whether they are compressed
+	index: db::Tree,
+	node_prefix: String,
+}
+
+impl ColdTier {
+	pub(crate) fn new(config: &ColdTierConfig, db: &db::Db, node_id: Uuid) -> Result<Self, Error> {
+		let store: Box<dyn BlockStore> = match (&config.path, &config.s3) {
+			(Some(path), None) => Box::new(LocalBlockStore::new(path.clone())),
+			(None, Some(s3)) => Box::new(S3BlockStore::new(s3)?),
+			_ => {
+				return Err(Error::Message(
+					"Exactly one of path and s3 must be set in the cold_tier section".into(),
+				))
+			}
+		};
+		let index = db
+			.open_tree("block_cold_tier")
+			.expect("Unable to open block_cold_tier tree");
+		Ok(Self {
+			store,
+			index,
+			node_prefix: hex::encode(node_id),
+		})
+	}
+
+	fn name(&self, hash: &Hash, compressed: bool) -> String {
+		let hash = hex::encode(hash);
+		format!(
+			"{}/{}/{}{}",
+			self.node_prefix,
+			&hash[..2],
+			hash,
+			if compressed { ".zst" } else { "" }
+		)
+	}
+
+	/// Whether a block is in the cold tier, and if so, whether it is compressed
+	fn get_index(&self, hash: &Hash) -> Result<Option<bool>, Error> {
+		Ok(self.index.get(hash)?.map(|v| v.first() == Some(&1)))
+	}
+
+	pub(crate) fn contains(&self, hash: &Hash) -> Result<bool, Error> {
+		Ok(self.get_index(hash)?.is_some())
+	}
+
+	/// Number of blocks of this node in the cold tier
+	pub(crate) fn len(&self) -> Result<usize, Error> {
+		Ok(self.index.len()?)
+	}
+
+	/// Read a block from the cold tier. If it is in the index but cannot be found,
+	/// it is removed from the index and None is returned.
+	pub(crate) async fn read(&self, hash: &Hash) -> Result<Option<DataBlock>, Error> {
+		let compressed = match self.get_index(hash)? {
+			Some(c) => c,
+			None => return Ok(None),
+		};
+		match self.store.get(&self.name(hash, compressed)).await? {
+			Some(data) if compressed => Ok(Some(DataBlock::Compressed(data))),
+			Some(data) => Ok(Some(DataBlock::Plain(data))),
+			None => {
+				warn!("Block {:?} is missing from the cold tier", hash);
+				self.index.remove(hash)?;
+				Ok(None)
+			}
+		}
+	}
+
+	pub(crate) async fn write(&self, hash: &Hash, block: &DataBlock) -> Result<(), Error> {
+		let compressed = block.is_compressed();
+		self.store
+			.put(
+				&self.name(hash, compressed),
+				Bytes::copy_from_slice(block.inner_buffer()),
+			)
+			.await?;
+		self.index.insert(hash, [compressed as u8])?;
+		Ok(())
+	}
+
+	pub(crate) async fn delete(&self, hash: &Hash) -> Result<(), Error> {
+		if let Some(compressed) = self.get_index(hash)? {
+			self.store.delete(&self.name(hash, compressed)).await?;
+			self.index.remove(hash)?;
+		}
+		Ok(())
+	}
+}
+
+// ---- Tiering policy and worker ----
+
+/// Decides which blocks are moved to the cold tier
+#[async_trait]
+pub trait TieringPolicy: Send + Sync {
+	/// Whether a block is only used by data that should be in the cold tier
+	async fn is_cold(&self, hash: &Hash) -> Result<bool, Error>;
+}
+
+/// Parameters of the tiering workers that can be changed at runtime
+pub struct TieringParams {
+	persister: Persister<TieringPersistedConfig>,
+	tranquility: AtomicU32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TieringPersistedConfig {
+	tranquility: u32,
+}
+
+impl TieringParams {
+	pub(crate) fn new(metadata_dir: &std::path::Path) -> Self {
+		let persister = Persister::new(metadata_dir, "tiering_cfg");
+		let tranquility = match persister.load() {
+			Ok(TieringPersistedConfig { tranquility }) => tranquility,
+			Err(_) => INITIAL_TIERING_TRANQUILITY,
+		};
+		Self {
+			persister,
+			tranquility: AtomicU32::new(tranquility),
+		}
+	}
+
+	pub fn tranquility(&self) -> u32 {
+		self.tranquility.load(Ordering::Relaxed)
+	}
+
+	pub async fn set_tranquility(&self, tranquility: u32) -> Result<(), Error> {
+		self.persister
+			.save_async(&TieringPersistedConfig { tranquility })
+			.await?;
+		self.tranquility.store(tranquility, Ordering::Relaxed);
+		Ok(())
+	}
+}
+
+/// Worker that goes through the blocks stored on this node, and moves
+/// those that the tiering policy considers cold to the cold tier
+pub struct TieringWorker {
+	manager: Arc<BlockManager>,
+	policy: Arc<dyn TieringPolicy>,
+	tranquilizer: Tranquilizer,
+	/// Delay between passes, or None to make a single pass
+	interval: Option<Duration>,
+	next_pass: Instant,
+	/// Position in the current pass, if one is running
+	pos: Option<Vec<u8>>,
+	moved: usize,
+	moved_bytes: u64,
+}
+
+impl TieringWorker {
+	pub fn new(
+		manager: Arc<BlockManager>,
+		policy: Arc<dyn TieringPolicy>,
+		interval: Option<Duration>,
+	) -> Self {
+		Self {
+			manager,
+			policy,
+			tranquilizer: Tranquilizer::new(30),
+			interval,
+			next_pass: Instant::now(),
+			pos: None,
+			moved: 0,
+			moved_bytes: 0,
+		}
+	}
+}
+
+#[async_trait]
+impl Worker for TieringWorker {
+	fn name(&self) -> String {
+		"Block tiering worker".into()
+	}
+
+	fn info(&self) -> Option<String> {
+		Some(format!(
+			"{}{} blocks moved to the cold tier ({})",
+			if self.pos.is_some() { "running, " } else { "" },
+			self.moved,
+			bytesize::ByteSize::b(self.moved_bytes).to_string_as(true)
+		))
+	}
+
+	async fn work(&mut self, _must_exit: &mut watch::Receiver<bool>) -> Result<WorkerState, Error> {
+		let pos = match &self.pos {
+			Some(pos) => pos.clone(),
+			None if Instant::now() >= self.next_pass => vec![],
+			None => return Ok(WorkerState::Idle),
+		};
+
+		let hash = match self.manager.rc.rc.get_gt(&pos)? {
+			Some((hash, _)) => Hash::try_from(&hash[..]).ok_or_message("Invalid block hash")?,
+			None => {
+				info!(
+					"Block tiering pass finished: {} blocks moved to the cold tier so far ({})",
+					self.moved,
+					bytesize::ByteSize::b(self.moved_bytes).to_string_as(true)
+				);
+				self.pos = None;
+				return match self.interval {
+					Some(interval) => {
+						self.next_pass = Instant::now() + interval;
+						Ok(WorkerState::Idle)
+					}
+					None => Ok(WorkerState::Done),
+				};
+			}
+		};
+		self.pos = Some(hash.to_vec());
+
+		self.tranquilizer.reset();
+		if self.manager.get_block_rc(&hash)? > 0
+			&& self.manager.can_move_to_cold_tier(&hash).await?
+			&& self.policy.is_cold(&hash).await?
+		{
+			let moved_bytes = self.manager.move_to_cold_tier(&hash).await?;
+			if moved_bytes > 0 {
+				self.moved += 1;
+				self.moved_bytes += moved_bytes;
+			}
+		}
+		Ok(self
+			.tranquilizer
+			.tranquilize_worker(self.manager.tiering.tranquility()))
+	}
+
+	async fn wait_for_work(&mut self, _must_exit: &watch::Receiver<bool>) -> WorkerState {
+		if self.pos.is_none() {
+			tokio::time::sleep_until(self.next_pass.into()).await;
+		}
+		WorkerState::Busy
+	}
+}
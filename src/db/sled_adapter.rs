@@ -35,17 +35,32 @@ impl From<sled::Error> for TxOpError {
 pub struct SledDb {
 	db: sled::Db,
 	trees: RwLock<(Vec<sled::Tree>, HashMap<String, usize>)>,
+	fsync: bool,
 }
 
 impl SledDb {
 	pub fn init(db: sled::Db) -> Db {
+		Self::init_with_fsync(db, false)
+	}
+
+	/// Open a database that, if `fsync` is set, flushes its content to disk
+	/// after each write (in addition to the periodic flushes configured in sled)
+	pub fn init_with_fsync(db: sled::Db, fsync: bool) -> Db {
 		let s = Self {
 			db,
 			trees: RwLock::new((Vec::new(), HashMap::new())),
+			fsync,
 		};
 		Db(Arc::new(s))
 	}
 
+	fn flush_if_fsync(&self) -> Result<()> {
+		if self.fsync {
+			self.db.flush()?;
+		}
+		Ok(())
+	}
+
 	fn get_tree(&self, i: usize) -> Result<sled::Tree> {
 		self.trees
 			.read()
@@ -104,19 +119,21 @@ impl IDb for SledDb {
 	fn insert(&self, tree: usize, key: &[u8], value: &[u8]) -> Result<Option<Value>> {
 		let tree = self.get_tree(tree)?;
 		let old_val = tree.insert(key, value)?;
+		self.flush_if_fsync()?;
 		Ok(old_val.map(|x| x.to_vec()))
 	}
 
 	fn remove(&self, tree: usize, key: &[u8]) -> Result<Option<Value>> {
 		let tree = self.get_tree(tree)?;
 		let old_val = tree.remove(key)?;
+		self.flush_if_fsync()?;
 		Ok(old_val.map(|x| x.to_vec()))
 	}
 
 	fn clear(&self, tree: usize) -> Result<()> {
 		let tree = self.get_tree(tree)?;
 		tree.clear()?;
-		Ok(())
+		self.flush_if_fsync()
 	}
 
 	fn iter(&self, tree: usize) -> Result<ValueIter<'_>> {
@@ -181,7 +198,7 @@ impl IDb for SledDb {
 			}
 		});
 		match res {
-			Ok(()) => Ok(()),
+			Ok(()) => self.flush_if_fsync().map_err(TxError::Db),
 			Err(TransactionError::Abort(())) => Err(TxError::Abort(())),
 			Err(TransactionError::Storage(s)) => Err(TxError::Db(s.into())),
 		}
@@ -101,6 +101,17 @@ fn test_sled_db() {
 	drop(path);
 }
 
+#[test]
+#[cfg(feature = "sled")]
+fn test_sled_db_fsync() {
+	use crate::sled_adapter::SledDb;
+
+	let path = mktemp::Temp::new_dir().unwrap();
+	let db = SledDb::init_with_fsync(sled::open(path.to_path_buf()).unwrap(), true);
+	test_suite(db);
+	drop(path);
+}
+
 #[test]
 #[cfg(feature = "sqlite")]
 fn test_sqlite_db() {
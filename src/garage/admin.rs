@@ -3,8 +3,10 @@ use std::fmt::Write;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
 
+use garage_util::background::WorkerInfo;
 use garage_util::crdt::*;
 use garage_util::data::*;
 use garage_util::error::Error as GarageError;
@@ -13,32 +15,58 @@ use garage_util::time::*;
 use garage_table::replication::*;
 use garage_table::*;
 
+use garage_rpc::layout::LayoutSimulation;
 use garage_rpc::*;
 
+use garage_block::manager::BlockDedupStats;
 use garage_block::repair::ScrubWorkerCommand;
+use garage_block::resync::BlockResyncErrorInfo;
 
+use garage_model::audit_table::*;
 use garage_model::bucket_alias_table::*;
 use garage_model::bucket_table::*;
 use garage_model::garage::Garage;
 use garage_model::helper::error::{Error, OkOrBadRequest};
+use garage_model::helper::object::ObjectInfo;
 use garage_model::key_table::*;
 use garage_model::migrate::Migrate;
 use garage_model::permission::*;
+use garage_model::rate_limits::RateLimits;
+use garage_model::s3::block_ref_table::BlockRef;
+use garage_model::s3::object_table::*;
+use garage_model::s3::version_table::*;
+
+use garage_api::admin::maintenance::*;
 
 use crate::cli::*;
 use crate::repair::online::launch_online_repair;
 
 pub const ADMIN_RPC_PATH: &str = "garage/admin_rpc.rs/Rpc";
 
+/// Number of block references fetched at once from the block ref table
+const BLOCK_REFS_PAGE_SIZE: usize = 1000;
+
+/// Maximum number of objects listed for each corrupted block in scrub reports
+const MAX_CORRUPTED_BLOCK_OBJECTS: usize = 100;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum AdminRpc {
 	BucketOperation(BucketOperation),
 	KeyOperation(KeyOperation),
+	ObjectOperation(ObjectOperation),
 	LaunchRepair(RepairOpt),
 	Migrate(MigrateOpt),
 	Stats(StatsOpt),
 	Worker(WorkerOpt),
+	BlockOperation(BlockOperation),
+	AuditOperation(AuditOperation),
+	SimulateLayout,
+	RecordAudit {
+		operation: String,
+		target: String,
+		parameters: Vec<(String, String)>,
+	},
 
 	// Replies
 	Ok(String),
@@ -50,12 +78,49 @@ pub enum AdminRpc {
 	},
 	KeyList(Vec<(String, String)>),
 	KeyInfo(Key, HashMap<Uuid, Bucket>),
+	ObjectInfo(ObjectInfo),
+	AuditLog(Vec<AuditEntry>),
+	BlockErrorList(Vec<BlockResyncErrorInfo>),
+	BlockInfo(BlockInfo),
+	ScrubReports(Vec<ScrubReportInfo>),
+	LayoutSimulation(LayoutSimulation),
+	NodeStats(NodeStats),
+	ClusterStats(Vec<(Uuid, Result<NodeStats, String>)>),
 	WorkerList(
 		HashMap<usize, garage_util::background::WorkerInfo>,
 		WorkerListOpt,
 	),
 }
 
+/// Statistics about the metadata and data stored on a node
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeStats {
+	pub garage_version: String,
+	pub garage_features: Option<Vec<String>>,
+	pub db_engine: String,
+	/// Number of partitions stored by each node of the ring
+	pub ring_partitions: HashMap<Uuid, usize>,
+	pub tables: Vec<TableStats>,
+	/// Number of entries in the block reference counter (only in detailed statistics)
+	pub block_rc_entries: Option<usize>,
+	/// Deduplication statistics of the blocks (only in detailed statistics)
+	#[serde(default)]
+	pub block_dedup: Option<BlockDedupStats>,
+	pub resync_queue_length: usize,
+	pub resync_errors: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TableStats {
+	pub name: String,
+	/// Number of items in the table (only in detailed statistics)
+	pub items: Option<usize>,
+	/// Size of the Merkle tree (only in detailed statistics)
+	pub merkle_tree_size: Option<usize>,
+	pub merkle_todo_length: usize,
+	pub gc_todo_length: usize,
+}
+
 impl Rpc for AdminRpc {
 	type Response = Result<AdminRpc, Error>;
 }
@@ -73,8 +138,67 @@ impl AdminRpcHandler {
 		admin
 	}
 
+	/// Send an admin RPC to a node, which can be the local node
+	async fn call_node(&self, node: Uuid, msg: AdminRpc) -> Result<AdminRpc, Error> {
+		self.endpoint.call(&node.into(), msg, PRIO_NORMAL).await?
+	}
+
+	/// Send an admin RPC to a node and return the message of its answer
+	async fn call_node_ok(&self, node: Uuid, msg: AdminRpc) -> Result<String, Error> {
+		match self.call_node(node, msg).await? {
+			AdminRpc::Ok(s) => Ok(s),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
 	async fn handle_bucket_cmd(&self, cmd: &BucketOperation) -> Result<AdminRpc, Error> {
-		match cmd {
+		let audit = match cmd {
+			BucketOperation::List | BucketOperation::Info(_) => None,
+			BucketOperation::Create(query) => {
+				Some(self.cli_audit_entry("bucket create", query, "name"))
+			}
+			BucketOperation::Delete(query) => {
+				Some(self.cli_audit_entry("bucket delete", query, "name"))
+			}
+			BucketOperation::Alias(query) => {
+				Some(self.cli_audit_entry("bucket alias", query, "existing_bucket"))
+			}
+			BucketOperation::Unalias(query) => {
+				Some(self.cli_audit_entry("bucket unalias", query, "name"))
+			}
+			BucketOperation::Allow(query) => {
+				Some(self.cli_audit_entry("bucket allow", query, "bucket"))
+			}
+			BucketOperation::Deny(query) => {
+				Some(self.cli_audit_entry("bucket deny", query, "bucket"))
+			}
+			BucketOperation::Website(query) => {
+				Some(self.cli_audit_entry("bucket website", query, "bucket"))
+			}
+			BucketOperation::SetQuotas(query) => {
+				Some(self.cli_audit_entry("bucket set-quotas", query, "bucket"))
+			}
+			BucketOperation::SetRateLimits(query) => {
+				Some(self.cli_audit_entry("bucket set-rate-limits", query, "bucket"))
+			}
+			BucketOperation::SetErasureCoding(query) => {
+				Some(self.cli_audit_entry("bucket set-erasure-coding", query, "bucket"))
+			}
+			BucketOperation::SetChunking(query) => {
+				Some(self.cli_audit_entry("bucket set-chunking", query, "bucket"))
+			}
+			BucketOperation::SetCompression(query) => {
+				Some(self.cli_audit_entry("bucket set-compression", query, "bucket"))
+			}
+			BucketOperation::SetColdTier(query) => {
+				Some(self.cli_audit_entry("bucket set-cold-tier", query, "bucket"))
+			}
+			BucketOperation::CleanupIncompleteUploads(query) => {
+				Some(self.cli_audit_entry("bucket cleanup-incomplete-uploads", query, "buckets"))
+			}
+		};
+
+		let res = match cmd {
 			BucketOperation::List => self.handle_list_buckets().await,
 			BucketOperation::Info(query) => self.handle_bucket_info(query).await,
 			BucketOperation::Create(query) => self.handle_create_bucket(&query.name).await,
@@ -85,10 +209,26 @@ impl AdminRpcHandler {
 			BucketOperation::Deny(query) => self.handle_bucket_deny(query).await,
 			BucketOperation::Website(query) => self.handle_bucket_website(query).await,
 			BucketOperation::SetQuotas(query) => self.handle_bucket_set_quotas(query).await,
+			BucketOperation::SetRateLimits(query) => {
+				self.handle_bucket_set_rate_limits(query).await
+			}
+			BucketOperation::SetErasureCoding(query) => {
+				self.handle_bucket_set_erasure_coding(query).await
+			}
+			BucketOperation::SetChunking(query) => self.handle_bucket_set_chunking(query).await,
+			BucketOperation::SetCompression(query) => {
+				self.handle_bucket_set_compression(query).await
+			}
+			BucketOperation::SetColdTier(query) => self.handle_bucket_set_cold_tier(query).await,
 			BucketOperation::CleanupIncompleteUploads(query) => {
 				self.handle_bucket_cleanup_incomplete_uploads(query).await
 			}
+		};
+
+		if let (Ok(_), Some(audit)) = (&res, audit) {
+			self.garage.record_audit(audit).await;
 		}
+		res
 	}
 
 	async fn handle_list_buckets(&self) -> Result<AdminRpc, Error> {
@@ -361,27 +501,16 @@ impl AdminRpcHandler {
 			.get_existing_matching_key(&query.key_pattern)
 			.await?;
 
-		let allow_read = query.read || key.allow_read(&bucket_id);
-		let allow_write = query.write || key.allow_write(&bucket_id);
-		let allow_owner = query.owner || key.allow_owner(&bucket_id);
+		let mut perm = key.bucket_permissions(&bucket_id);
+		perm.timestamp = now_msec();
+		perm.allow(&perm_capabilities(query), &query.prefixes)?;
 
+		let msg = format_new_permissions(&key.key_id, &query.bucket, &perm);
 		helper
-			.set_bucket_key_permissions(
-				bucket_id,
-				&key.key_id,
-				BucketKeyPerm {
-					timestamp: now_msec(),
-					allow_read,
-					allow_write,
-					allow_owner,
-				},
-			)
+			.set_bucket_key_permissions(bucket_id, &key.key_id, perm)
 			.await?;
 
-		Ok(AdminRpc::Ok(format!(
-			"New permissions for {} on {}: read {}, write {}, owner {}.",
-			&key.key_id, &query.bucket, allow_read, allow_write, allow_owner
-		)))
+		Ok(AdminRpc::Ok(msg))
 	}
 
 	async fn handle_bucket_deny(&self, query: &PermBucketOpt) -> Result<AdminRpc, Error> {
@@ -396,27 +525,16 @@ impl AdminRpcHandler {
 			.get_existing_matching_key(&query.key_pattern)
 			.await?;
 
-		let allow_read = !query.read && key.allow_read(&bucket_id);
-		let allow_write = !query.write && key.allow_write(&bucket_id);
-		let allow_owner = !query.owner && key.allow_owner(&bucket_id);
+		let mut perm = key.bucket_permissions(&bucket_id);
+		perm.timestamp = now_msec();
+		perm.deny(&perm_capabilities(query), &query.prefixes)?;
 
+		let msg = format_new_permissions(&key.key_id, &query.bucket, &perm);
 		helper
-			.set_bucket_key_permissions(
-				bucket_id,
-				&key.key_id,
-				BucketKeyPerm {
-					timestamp: now_msec(),
-					allow_read,
-					allow_write,
-					allow_owner,
-				},
-			)
+			.set_bucket_key_permissions(bucket_id, &key.key_id, perm)
 			.await?;
 
-		Ok(AdminRpc::Ok(format!(
-			"New permissions for {} on {}: read {}, write {}, owner {}.",
-			&key.key_id, &query.bucket, allow_read, allow_write, allow_owner
-		)))
+		Ok(AdminRpc::Ok(msg))
 	}
 
 	async fn handle_bucket_website(&self, query: &WebsiteOpt) -> Result<AdminRpc, Error> {
@@ -515,6 +633,207 @@ impl AdminRpcHandler {
 		)))
 	}
 
+	async fn handle_bucket_set_rate_limits(
+		&self,
+		query: &BucketSetRateLimitsOpt,
+	) -> Result<AdminRpc, Error> {
+		let bucket_id = self
+			.garage
+			.bucket_helper()
+			.resolve_global_bucket_name(&query.bucket)
+			.await?
+			.ok_or_bad_request("Bucket not found")?;
+
+		let mut bucket = self
+			.garage
+			.bucket_helper()
+			.get_existing_bucket(bucket_id)
+			.await?;
+		let bucket_state = bucket.state.as_option_mut().unwrap();
+
+		let mut rate_limits = *bucket_state.rate_limits.get();
+		apply_rate_limits_opt(&mut rate_limits, &query.limits)?;
+
+		bucket_state.rate_limits.update(rate_limits);
+		self.garage.bucket_table.insert(&bucket).await?;
+
+		Ok(AdminRpc::Ok(format!(
+			"Rate limits updated for {}",
+			&query.bucket
+		)))
+	}
+
+	async fn handle_bucket_set_erasure_coding(
+		&self,
+		query: &SetErasureCodingOpt,
+	) -> Result<AdminRpc, Error> {
+		let erasure_coding = match query.mode.as_str() {
+			"enabled" => Some(true),
+			"disabled" => Some(false),
+			"default" => None,
+			m => {
+				return Err(Error::BadRequest(format!(
+					"Invalid erasure coding mode: {} (expected enabled, disabled or default)",
+					m
+				)))
+			}
+		};
+		if erasure_coding == Some(true) && self.garage.config.erasure_coding.is_none() {
+			return Err(Error::BadRequest(
+				"Erasure coding is not configured on this cluster (see the [erasure_coding] section of the configuration file)".to_string(),
+			));
+		}
+
+		let bucket_id = self
+			.garage
+			.bucket_helper()
+			.resolve_global_bucket_name(&query.bucket)
+			.await?
+			.ok_or_bad_request("Bucket not found")?;
+
+		let mut bucket = self
+			.garage
+			.bucket_helper()
+			.get_existing_bucket(bucket_id)
+			.await?;
+		let bucket_state = bucket.state.as_option_mut().unwrap();
+
+		bucket_state.erasure_coding.update(erasure_coding);
+		self.garage.bucket_table.insert(&bucket).await?;
+
+		Ok(AdminRpc::Ok(format!(
+			"Erasure coding set to {} for {}. This only applies to data written from now on.",
+			query.mode, &query.bucket
+		)))
+	}
+
+	async fn handle_bucket_set_chunking(&self, query: &SetChunkingOpt) -> Result<AdminRpc, Error> {
+		let content_defined = match query.mode.as_str() {
+			"content-defined" => Some(true),
+			"fixed" => Some(false),
+			"default" => None,
+			m => {
+				return Err(Error::BadRequest(format!(
+					"Invalid chunking mode: {} (expected content-defined, fixed or default)",
+					m
+				)))
+			}
+		};
+		if content_defined == Some(true) && self.garage.config.content_defined_chunking.is_none() {
+			return Err(Error::BadRequest(
+				"Content-defined chunking is not configured on this cluster (see the [content_defined_chunking] section of the configuration file)".to_string(),
+			));
+		}
+
+		let bucket_id = self
+			.garage
+			.bucket_helper()
+			.resolve_global_bucket_name(&query.bucket)
+			.await?
+			.ok_or_bad_request("Bucket not found")?;
+
+		let mut bucket = self
+			.garage
+			.bucket_helper()
+			.get_existing_bucket(bucket_id)
+			.await?;
+		let bucket_state = bucket.state.as_option_mut().unwrap();
+
+		bucket_state
+			.content_defined_chunking
+			.update(content_defined);
+		self.garage.bucket_table.insert(&bucket).await?;
+
+		Ok(AdminRpc::Ok(format!(
+			"Chunking set to {} for {}. This only applies to data written from now on.",
+			query.mode, &query.bucket
+		)))
+	}
+
+	async fn handle_bucket_set_compression(
+		&self,
+		query: &SetCompressionOpt,
+	) -> Result<AdminRpc, Error> {
+		let compression = match query.level.as_str() {
+			"none" => Some(BucketCompression::Off),
+			"default" => None,
+			l => Some(
+				l.parse::<i32>()
+					.ok()
+					.and_then(BucketCompression::level)
+					.ok_or_bad_request(format!(
+						"Invalid compression level: {} (expected a zstd level, none or default)",
+						l
+					))?,
+			),
+		};
+
+		let bucket_id = self
+			.garage
+			.bucket_helper()
+			.resolve_global_bucket_name(&query.bucket)
+			.await?
+			.ok_or_bad_request("Bucket not found")?;
+
+		let mut bucket = self
+			.garage
+			.bucket_helper()
+			.get_existing_bucket(bucket_id)
+			.await?;
+		let bucket_state = bucket.state.as_option_mut().unwrap();
+
+		bucket_state.compression.update(compression);
+		self.garage.bucket_table.insert(&bucket).await?;
+
+		Ok(AdminRpc::Ok(format!(
+			"Compression set to {} for {}. This only applies to data written from now on.",
+			query.level, &query.bucket
+		)))
+	}
+
+	async fn handle_bucket_set_cold_tier(&self, query: &SetColdTierOpt) -> Result<AdminRpc, Error> {
+		let after_days = match query.days.as_str() {
+			"none" => None,
+			d => Some(d.parse::<u64>().ok().ok_or_bad_request(format!(
+				"Invalid number of days: {} (expected a number of days or none)",
+				d
+			))?),
+		};
+		if after_days.is_some() && self.garage.config.cold_tier.is_none() {
+			return Err(Error::BadRequest(
+				"No cold tier is configured on this cluster (see the [cold_tier] section of the configuration file)".to_string(),
+			));
+		}
+
+		let bucket_id = self
+			.garage
+			.bucket_helper()
+			.resolve_global_bucket_name(&query.bucket)
+			.await?
+			.ok_or_bad_request("Bucket not found")?;
+
+		let mut bucket = self
+			.garage
+			.bucket_helper()
+			.get_existing_bucket(bucket_id)
+			.await?;
+		let bucket_state = bucket.state.as_option_mut().unwrap();
+
+		bucket_state.cold_tier_after_days.update(after_days);
+		self.garage.bucket_table.insert(&bucket).await?;
+
+		Ok(AdminRpc::Ok(match after_days {
+			Some(days) => format!(
+				"Data of objects of {} will be moved to the cold tier after {} days.",
+				&query.bucket, days
+			),
+			None => format!(
+				"Data of objects of {} will not be moved to the cold tier.",
+				&query.bucket
+			),
+		}))
+	}
+
 	async fn handle_bucket_cleanup_incomplete_uploads(
 		&self,
 		query: &CleanupIncompleteUploadsOpt,
@@ -552,16 +871,55 @@ impl AdminRpcHandler {
 	}
 
 	async fn handle_key_cmd(&self, cmd: &KeyOperation) -> Result<AdminRpc, Error> {
-		match cmd {
+		let audit = match cmd {
+			KeyOperation::List | KeyOperation::Info(_) => None,
+			KeyOperation::New(query) => Some(self.cli_audit_entry("key new", query, "name")),
+			KeyOperation::Rename(query) => {
+				Some(self.cli_audit_entry("key rename", query, "key_pattern"))
+			}
+			KeyOperation::Set(query) => Some(self.cli_audit_entry("key set", query, "key_pattern")),
+			KeyOperation::Rotate(query) => {
+				Some(self.cli_audit_entry("key rotate", query, "key_pattern"))
+			}
+			KeyOperation::Delete(query) => {
+				Some(self.cli_audit_entry("key delete", query, "key_pattern"))
+			}
+			KeyOperation::Allow(query) => {
+				Some(self.cli_audit_entry("key allow", query, "key_pattern"))
+			}
+			KeyOperation::Deny(query) => {
+				Some(self.cli_audit_entry("key deny", query, "key_pattern"))
+			}
+			KeyOperation::Import(query) => {
+				Some(self.cli_audit_entry("key import", query, "key_id"))
+			}
+			KeyOperation::SetRateLimits(query) => {
+				Some(self.cli_audit_entry("key set-rate-limits", query, "key_pattern"))
+			}
+		};
+
+		let res = match cmd {
 			KeyOperation::List => self.handle_list_keys().await,
 			KeyOperation::Info(query) => self.handle_key_info(query).await,
 			KeyOperation::New(query) => self.handle_create_key(query).await,
 			KeyOperation::Rename(query) => self.handle_rename_key(query).await,
+			KeyOperation::Set(query) => self.handle_set_key(query).await,
+			KeyOperation::Rotate(query) => self.handle_rotate_key(query).await,
 			KeyOperation::Delete(query) => self.handle_delete_key(query).await,
 			KeyOperation::Allow(query) => self.handle_allow_key(query).await,
 			KeyOperation::Deny(query) => self.handle_deny_key(query).await,
 			KeyOperation::Import(query) => self.handle_import_key(query).await,
+			KeyOperation::SetRateLimits(query) => self.handle_key_set_rate_limits(query).await,
+		};
+
+		if let (Ok(r), Some(mut audit)) = (&res, audit) {
+			// Record the ID of the key rather than the pattern used to find it
+			if let AdminRpc::KeyInfo(key, _) = r {
+				audit.target = key.key_id.clone();
+			}
+			self.garage.record_audit(audit).await;
 		}
+		res
 	}
 
 	async fn handle_list_keys(&self) -> Result<AdminRpc, Error> {
@@ -592,7 +950,11 @@ impl AdminRpcHandler {
 	}
 
 	async fn handle_create_key(&self, query: &KeyNewOpt) -> Result<AdminRpc, Error> {
-		let key = Key::new(&query.name);
+		let mut key = Key::new(&query.name);
+		if let Some(expires_at) = &query.expires_at {
+			let expires_at = parse_key_expiration(expires_at)?;
+			key.params_mut().unwrap().expires_at.update(expires_at);
+		}
 		self.garage.key_table.insert(&key).await?;
 		self.key_info_result(key).await
 	}
@@ -611,6 +973,103 @@ impl AdminRpcHandler {
 		self.key_info_result(key).await
 	}
 
+	async fn handle_set_key(&self, query: &KeySetOpt) -> Result<AdminRpc, Error> {
+		if query.enable && query.disable {
+			return Err(Error::BadRequest(
+				"You cannot both --enable and --disable a key.".to_string(),
+			));
+		}
+		if query.allow_any_ip && !query.allow_ips.is_empty() {
+			return Err(Error::BadRequest(
+				"You cannot both use --allow-ip and --allow-any-ip.".to_string(),
+			));
+		}
+		if !query.enable
+			&& !query.disable
+			&& query.expires_at.is_none()
+			&& query.allow_ips.is_empty()
+			&& !query.allow_any_ip
+		{
+			return Err(Error::BadRequest(
+				"You must specify --enable, --disable, --expires-at, --allow-ip or --allow-any-ip for this command to do something.".to_string(),
+			));
+		}
+		let allowed_ips = query
+			.allow_ips
+			.iter()
+			.map(|net| {
+				net.parse::<IpNetwork>()
+					.ok_or_bad_request(format!("Invalid IP address or network: {}", net))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let mut key = self
+			.garage
+			.key_helper()
+			.get_existing_matching_key(&query.key_pattern)
+			.await?;
+		let key_state = key.params_mut().unwrap();
+
+		if query.enable {
+			key_state.enabled.update(true);
+		}
+		if query.disable {
+			key_state.enabled.update(false);
+		}
+		if let Some(expires_at) = &query.expires_at {
+			key_state
+				.expires_at
+				.update(parse_key_expiration(expires_at)?);
+		}
+		if !allowed_ips.is_empty() || query.allow_any_ip {
+			key_state.allowed_ips.update(IpAllowlist(allowed_ips));
+		}
+
+		self.garage.key_table.insert(&key).await?;
+		self.key_info_result(key).await
+	}
+
+	async fn handle_rotate_key(&self, query: &KeyRotateOpt) -> Result<AdminRpc, Error> {
+		let grace_period = parse_duration::parse::parse(&query.grace_period)
+			.ok_or_bad_request("Invalid duration passed for --grace-period parameter")?;
+		let expires_at = match &query.expires_at {
+			Some(d) => parse_key_expiration(d)?,
+			None => None,
+		};
+
+		let mut key = self
+			.garage
+			.key_helper()
+			.get_existing_matching_key(&query.key_pattern)
+			.await?;
+		key.params_mut().unwrap().rotate_secret(
+			expires_at,
+			now_msec().saturating_add(grace_period.as_millis() as u64),
+		);
+
+		self.garage.key_table.insert(&key).await?;
+		self.key_info_result(key).await
+	}
+
+	async fn handle_key_set_rate_limits(
+		&self,
+		query: &KeySetRateLimitsOpt,
+	) -> Result<AdminRpc, Error> {
+		let mut key = self
+			.garage
+			.key_helper()
+			.get_existing_matching_key(&query.key_pattern)
+			.await?;
+		let key_state = key.params_mut().unwrap();
+
+		let mut rate_limits = *key_state.rate_limits.get();
+		apply_rate_limits_opt(&mut rate_limits, &query.limits)?;
+
+		key_state.rate_limits.update(rate_limits);
+		self.garage.key_table.insert(&key).await?;
+		self.key_info_result(key).await
+	}
+
 	async fn handle_delete_key(&self, query: &KeyDeleteOpt) -> Result<AdminRpc, Error> {
 		let key_helper = self.garage.key_helper();
 
@@ -688,6 +1147,79 @@ impl AdminRpcHandler {
 		Ok(AdminRpc::KeyInfo(key, relevant_buckets))
 	}
 
+	// ================ AUDIT LOG COMMANDS ====================
+
+	async fn handle_audit_cmd(&self, cmd: &AuditOperation) -> Result<AdminRpc, Error> {
+		match cmd {
+			AuditOperation::List(query) => self.handle_audit_list(query).await,
+		}
+	}
+
+	async fn handle_audit_list(&self, query: &AuditListOpt) -> Result<AdminRpc, Error> {
+		let since = query
+			.since
+			.as_deref()
+			.map(parse_cli_date)
+			.transpose()?
+			.unwrap_or(0);
+		let filter = AuditFilter {
+			until: query.until.as_deref().map(parse_cli_date).transpose()?,
+			actor: query.actor.clone(),
+		};
+
+		let entries = self
+			.garage
+			.audit_table
+			.get_range(
+				&EmptyKey,
+				Some(AuditEntry::sort_key_prefix(since)),
+				Some(filter),
+				query.limit,
+				EnumerationOrder::Forward,
+			)
+			.await?;
+
+		Ok(AdminRpc::AuditLog(entries))
+	}
+
+	/// Build the audit log entry of a CLI operation from its options:
+	/// the option named `target_field` is the target of the operation,
+	/// the other ones are its parameters.
+	fn cli_audit_entry(
+		&self,
+		operation: &str,
+		opt: &impl Serialize,
+		target_field: &str,
+	) -> AuditEntry {
+		let mut entry = self.garage.audit_entry(AuditActor::Cli, operation, "");
+		if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(opt) {
+			for (name, value) in fields {
+				let value = match value {
+					serde_json::Value::Null | serde_json::Value::Bool(false) => continue,
+					serde_json::Value::Array(a) if a.is_empty() => continue,
+					serde_json::Value::String(s) => s,
+					serde_json::Value::Array(a) => a
+						.iter()
+						.map(|v| {
+							v.as_str()
+								.map(String::from)
+								.unwrap_or_else(|| v.to_string())
+						})
+						.collect::<Vec<_>>()
+						.join(", "),
+					v => v.to_string(),
+				};
+				if name == target_field {
+					entry.target = value;
+				} else if name != "secret_key" {
+					// (secrets are never recorded in the audit log)
+					entry.parameters.push((name, value));
+				}
+			}
+		}
+		entry
+	}
+
 	async fn handle_migrate(self: &Arc<Self>, opt: MigrateOpt) -> Result<AdminRpc, Error> {
 		if !opt.yes {
 			return Err(Error::BadRequest(
@@ -705,6 +1237,17 @@ impl AdminRpcHandler {
 	}
 
 	async fn handle_launch_repair(self: &Arc<Self>, opt: RepairOpt) -> Result<AdminRpc, Error> {
+		if let RepairWhat::Scrub {
+			cmd: ScrubCmd::Status,
+		} = opt.what
+		{
+			if opt.all_nodes {
+				return Err(Error::BadRequest(
+					"Scrub status can only be shown for one node at a time".to_string(),
+				));
+			}
+			return Ok(AdminRpc::ScrubReports(self.scrub_reports().await?));
+		}
 		if !opt.yes {
 			return Err(Error::BadRequest(
 				"Please provide the --yes flag to initiate repair operations.".to_string(),
@@ -747,129 +1290,165 @@ impl AdminRpcHandler {
 		}
 	}
 
+	async fn scrub_reports(&self) -> Result<Vec<ScrubReportInfo>, Error> {
+		let block_manager = &self.garage.block_manager;
+		let mut reports = vec![];
+		for report in block_manager.scrub_reports() {
+			let mut corrupted_blocks = vec![];
+			for block in report.corrupted_blocks {
+				let (objects, objects_truncated) = self.objects_using_block(&block.hash).await?;
+				corrupted_blocks.push(CorruptedBlockInfo {
+					hash: block.hash,
+					detected: block.detected,
+					status: block_manager.corrupted_block_status(&block.hash).await?,
+					objects,
+					objects_truncated,
+				});
+			}
+			reports.push(ScrubReportInfo {
+				start_time: report.start_time,
+				end_time: report.end_time,
+				status: report.status,
+				blocks_checked: report.blocks_checked,
+				bytes_checked: report.bytes_checked,
+				corrupted_blocks,
+			});
+		}
+		Ok(reports)
+	}
+
+	/// Find the bucket and key of the objects whose current version uses a block,
+	/// and whether there were more than `MAX_CORRUPTED_BLOCK_OBJECTS` of them
+	async fn objects_using_block(&self, hash: &Hash) -> Result<(Vec<(Uuid, String)>, bool), Error> {
+		let mut objects = vec![];
+		for br in self.block_refs(hash).await? {
+			if br.deleted.get() {
+				continue;
+			}
+			let version = match self
+				.garage
+				.version_table
+				.get(&br.version, &EmptyKey)
+				.await?
+			{
+				Some(v) if !v.deleted.get() => v,
+				_ => continue,
+			};
+			if objects.contains(&(version.bucket_id, version.key.clone())) {
+				continue;
+			}
+			let object = self
+				.garage
+				.object_table
+				.get(&version.bucket_id, &version.key)
+				.await?;
+			let is_current = object
+				.as_ref()
+				.and_then(|o| o.versions().iter().rev().find(|v| v.is_complete()))
+				.map(|v| v.uuid == version.uuid && v.is_data())
+				.unwrap_or(false);
+			if is_current {
+				if objects.len() == MAX_CORRUPTED_BLOCK_OBJECTS {
+					return Ok((objects, true));
+				}
+				objects.push((version.bucket_id, version.key));
+			}
+		}
+		Ok((objects, false))
+	}
+
 	async fn handle_stats(&self, opt: StatsOpt) -> Result<AdminRpc, Error> {
 		if opt.all_nodes {
-			let mut ret = String::new();
+			let mut ret = vec![];
 			let ring = self.garage.system.ring.borrow().clone();
 
 			for node in ring.layout.node_ids().iter() {
 				let mut opt = opt.clone();
 				opt.all_nodes = false;
 
-				writeln!(&mut ret, "\n======================").unwrap();
-				writeln!(&mut ret, "Stats for node {:?}:", node).unwrap();
-
 				let node_id = (*node).into();
-				match self
+				let stats = match self
 					.endpoint
 					.call(&node_id, AdminRpc::Stats(opt), PRIO_NORMAL)
 					.await?
 				{
-					Ok(AdminRpc::Ok(s)) => writeln!(&mut ret, "{}", s).unwrap(),
-					Ok(x) => writeln!(&mut ret, "Bad answer: {:?}", x).unwrap(),
-					Err(e) => writeln!(&mut ret, "Error: {}", e).unwrap(),
-				}
+					Ok(AdminRpc::NodeStats(s)) => Ok(s),
+					Ok(x) => Err(format!("Bad answer: {:?}", x)),
+					Err(e) => Err(format!("Error: {}", e)),
+				};
+				ret.push((*node, stats));
 			}
-			Ok(AdminRpc::Ok(ret))
+			Ok(AdminRpc::ClusterStats(ret))
 		} else {
-			Ok(AdminRpc::Ok(self.gather_stats_local(opt)?))
+			Ok(AdminRpc::NodeStats(self.gather_stats_local(opt)?))
 		}
 	}
 
-	fn gather_stats_local(&self, opt: StatsOpt) -> Result<String, Error> {
-		let mut ret = String::new();
-		writeln!(
-			&mut ret,
-			"\nGarage version: {} [features: {}]",
-			garage_util::version::garage_version(),
-			garage_util::version::garage_features()
-				.map(|list| list.join(", "))
-				.unwrap_or_else(|| "(unknown)".into()),
-		)
-		.unwrap();
-		writeln!(&mut ret, "\nDatabase engine: {}", self.garage.db.engine()).unwrap();
-
+	fn gather_stats_local(&self, opt: StatsOpt) -> Result<NodeStats, Error> {
 		// Gather ring statistics
 		let ring = self.garage.system.ring.borrow().clone();
-		let mut ring_nodes = HashMap::new();
+		let mut ring_partitions = HashMap::new();
 		for (_i, loc) in ring.partitions().iter() {
 			for n in ring.get_nodes(loc, ring.replication_factor).iter() {
-				if !ring_nodes.contains_key(n) {
-					ring_nodes.insert(*n, 0usize);
-				}
-				*ring_nodes.get_mut(n).unwrap() += 1;
+				*ring_partitions.entry(*n).or_insert(0usize) += 1;
 			}
 		}
-		writeln!(&mut ret, "\nRing nodes & partition count:").unwrap();
-		for (n, c) in ring_nodes.iter() {
-			writeln!(&mut ret, "  {:?} {}", n, c).unwrap();
-		}
 
-		self.gather_table_stats(&mut ret, &self.garage.bucket_table, &opt)?;
-		self.gather_table_stats(&mut ret, &self.garage.key_table, &opt)?;
-		self.gather_table_stats(&mut ret, &self.garage.object_table, &opt)?;
-		self.gather_table_stats(&mut ret, &self.garage.version_table, &opt)?;
-		self.gather_table_stats(&mut ret, &self.garage.block_ref_table, &opt)?;
-
-		writeln!(&mut ret, "\nBlock manager stats:").unwrap();
-		if opt.detailed {
-			writeln!(
-				&mut ret,
-				"  number of RC entries (~= number of blocks): {}",
-				self.garage.block_manager.rc_len()?
-			)
-			.unwrap();
-		}
-		writeln!(
-			&mut ret,
-			"  resync queue length: {}",
-			self.garage.block_manager.resync.queue_len()?
-		)
-		.unwrap();
-		writeln!(
-			&mut ret,
-			"  blocks with resync errors: {}",
-			self.garage.block_manager.resync.errors_len()?
-		)
-		.unwrap();
+		let tables = vec![
+			self.gather_table_stats(&self.garage.bucket_table, &opt)?,
+			self.gather_table_stats(&self.garage.key_table, &opt)?,
+			self.gather_table_stats(&self.garage.object_table, &opt)?,
+			self.gather_table_stats(&self.garage.version_table, &opt)?,
+			self.gather_table_stats(&self.garage.block_ref_table, &opt)?,
+		];
+
+		let block_rc_entries = match opt.detailed {
+			true => Some(self.garage.block_manager.rc_len()?),
+			false => None,
+		};
+		let block_dedup = match opt.detailed {
+			true => Some(self.garage.block_manager.dedup_stats()?),
+			false => None,
+		};
 
-		Ok(ret)
+		Ok(NodeStats {
+			garage_version: garage_util::version::garage_version().to_string(),
+			garage_features: garage_util::version::garage_features()
+				.map(|list| list.iter().map(|f| f.to_string()).collect()),
+			db_engine: self.garage.db.engine(),
+			ring_partitions,
+			tables,
+			block_rc_entries,
+			block_dedup,
+			resync_queue_length: self.garage.block_manager.resync.queue_len()?,
+			resync_errors: self.garage.block_manager.resync.errors_len()?,
+		})
 	}
 
 	fn gather_table_stats<F, R>(
 		&self,
-		to: &mut String,
 		t: &Arc<Table<F, R>>,
 		opt: &StatsOpt,
-	) -> Result<(), Error>
+	) -> Result<TableStats, Error>
 	where
 		F: TableSchema + 'static,
 		R: TableReplication + 'static,
 	{
-		writeln!(to, "\nTable stats for {}", F::TABLE_NAME).unwrap();
-		if opt.detailed {
-			writeln!(
-				to,
-				"  number of items: {}",
-				t.data.store.len().map_err(GarageError::from)?
-			)
-			.unwrap();
-			writeln!(
-				to,
-				"  Merkle tree size: {}",
-				t.merkle_updater.merkle_tree_len()?
-			)
-			.unwrap();
-		}
-		writeln!(
-			to,
-			"  Merkle updater todo queue length: {}",
-			t.merkle_updater.todo_len()?
-		)
-		.unwrap();
-		writeln!(to, "  GC todo queue length: {}", t.data.gc_todo_len()?).unwrap();
+		let (items, merkle_tree_size) = match opt.detailed {
+			true => (
+				Some(t.data.store.len().map_err(GarageError::from)?),
+				Some(t.merkle_updater.merkle_tree_len()?),
+			),
+			false => (None, None),
+		};
 
-		Ok(())
+		Ok(TableStats {
+			name: F::TABLE_NAME.to_string(),
+			items,
+			merkle_tree_size,
+			merkle_todo_length: t.merkle_updater.todo_len()?,
+			gc_todo_length: t.data.gc_todo_len()?,
+		})
 	}
 
 	// ----
@@ -905,9 +1484,361 @@ impl AdminRpcHandler {
 						.await?;
 					Ok(AdminRpc::Ok("Resync tranquility updated".into()))
 				}
+				WorkerSetCmd::TieringTranquility { tranquility } => {
+					self.garage
+						.block_manager
+						.tiering
+						.set_tranquility(tranquility)
+						.await?;
+					Ok(AdminRpc::Ok("Tiering tranquility updated".into()))
+				}
 			},
 		}
 	}
+
+	// ================ OBJECT COMMANDS ====================
+
+	async fn handle_object_cmd(&self, cmd: &ObjectOperation) -> Result<AdminRpc, Error> {
+		match cmd {
+			ObjectOperation::Info(query) => {
+				let bucket_id = self
+					.garage
+					.bucket_helper()
+					.resolve_global_bucket_name(&query.bucket)
+					.await?
+					.ok_or_bad_request("Bucket not found")?;
+				let info = self
+					.garage
+					.object_helper()
+					.get_object_info(bucket_id, &query.key)
+					.await?
+					.ok_or_bad_request("Object not found")?;
+				Ok(AdminRpc::ObjectInfo(info))
+			}
+		}
+	}
+
+	// ================ LAYOUT COMMANDS ====================
+
+	async fn handle_simulate_layout(&self) -> Result<AdminRpc, Error> {
+		let layout = self.garage.system.get_cluster_layout();
+		let sizes = self.garage.block_manager.estimate_partition_sizes().await?;
+		let simulation = layout.simulate_staged_changes(sizes.as_deref())?;
+		Ok(AdminRpc::LayoutSimulation(simulation))
+	}
+
+	// ================ BLOCK COMMANDS ====================
+
+	async fn handle_block_cmd(&self, cmd: &BlockOperation) -> Result<AdminRpc, Error> {
+		match cmd {
+			BlockOperation::ListErrors => Ok(AdminRpc::BlockErrorList(
+				self.garage.block_manager.list_resync_errors()?,
+			)),
+			BlockOperation::Info { hash } => self.handle_block_info(hash).await,
+			BlockOperation::RetryNow { all, blocks } => {
+				self.handle_block_retry_now(*all, blocks).await
+			}
+			BlockOperation::Purge { yes, blocks } => self.handle_block_purge(*yes, blocks).await,
+		}
+	}
+
+	async fn handle_block_info(&self, hash: &str) -> Result<AdminRpc, Error> {
+		let hash = self
+			.garage
+			.block_manager
+			.find_block_hash_by_prefix(hash)
+			.map_err(|e| Error::BadRequest(e.to_string()))?;
+		let refcount = self.garage.block_manager.get_block_rc(&hash)?;
+		let block_refs = self.block_refs(&hash).await?;
+		let mut versions = vec![];
+		for br in block_refs {
+			if let Some(v) = self
+				.garage
+				.version_table
+				.get(&br.version, &EmptyKey)
+				.await?
+			{
+				versions.push(Ok(v));
+			} else {
+				versions.push(Err(br.version));
+			}
+		}
+		Ok(AdminRpc::BlockInfo(BlockInfo {
+			hash,
+			refcount,
+			versions,
+		}))
+	}
+
+	/// Get all the references to a block, fetching them page by page
+	async fn block_refs(&self, hash: &Hash) -> Result<Vec<BlockRef>, Error> {
+		let mut block_refs: Vec<BlockRef> = vec![];
+		loop {
+			let start = block_refs.last().map(|br| br.version);
+			let page = self
+				.garage
+				.block_ref_table
+				.get_range(
+					hash,
+					start,
+					None,
+					BLOCK_REFS_PAGE_SIZE,
+					EnumerationOrder::Forward,
+				)
+				.await?;
+			let full_page = page.len() == BLOCK_REFS_PAGE_SIZE;
+			// The start of the range is included in the page
+			block_refs.extend(page.into_iter().filter(|br| Some(br.version) != start));
+			if !full_page {
+				break;
+			}
+		}
+		Ok(block_refs)
+	}
+
+	async fn handle_block_retry_now(
+		&self,
+		all: bool,
+		blocks: &[String],
+	) -> Result<AdminRpc, Error> {
+		let hashes = if all {
+			if !blocks.is_empty() {
+				return Err(Error::BadRequest(
+					"--all was specified, cannot also specify blocks".into(),
+				));
+			}
+			self.garage
+				.block_manager
+				.list_resync_errors()?
+				.into_iter()
+				.map(|b| b.hash)
+				.collect::<Vec<_>>()
+		} else {
+			blocks
+				.iter()
+				.map(|h| parse_block_hash(h))
+				.collect::<Result<Vec<_>, _>>()?
+		};
+
+		for hash in hashes.iter() {
+			self.garage.block_manager.resync.clear_backoff(hash)?;
+		}
+		Ok(AdminRpc::Ok(format!(
+			"{} blocks returned in queue for a retry now (check logs to see results)",
+			hashes.len()
+		)))
+	}
+
+	/// Delete the objects that reference blocks that cannot be recovered,
+	/// and mark their versions as deleted so that the references to the
+	/// blocks are removed
+	async fn handle_block_purge(&self, yes: bool, blocks: &[String]) -> Result<AdminRpc, Error> {
+		if !yes {
+			return Err(Error::BadRequest(
+				"Pass the --yes flag to confirm block purge operation.".into(),
+			));
+		}
+
+		let hashes = blocks
+			.iter()
+			.map(|h| parse_block_hash(h))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let mut obj_dels = 0;
+		let mut ver_dels = 0;
+
+		for hash in hashes.iter() {
+			let block_refs = self.block_refs(hash).await?;
+
+			for br in block_refs {
+				let version = match self
+					.garage
+					.version_table
+					.get(&br.version, &EmptyKey)
+					.await?
+				{
+					Some(v) => v,
+					None => continue,
+				};
+
+				if let Some(object) = self
+					.garage
+					.object_table
+					.get(&version.bucket_id, &version.key)
+					.await?
+				{
+					if let Some(deleted_object) = purged_object(&object, version.uuid) {
+						self.garage.object_table.insert(&deleted_object).await?;
+						obj_dels += 1;
+					}
+				}
+
+				if !version.deleted.get() {
+					let deleted_version =
+						Version::new(version.uuid, version.bucket_id, version.key.clone(), true);
+					self.garage.version_table.insert(&deleted_version).await?;
+					ver_dels += 1;
+				}
+			}
+		}
+
+		Ok(AdminRpc::Ok(format!(
+			"Purged {} blocks, {} versions, {} objects",
+			hashes.len(),
+			ver_dels,
+			obj_dels,
+		)))
+	}
+}
+
+fn parse_block_hash(hash: &str) -> Result<Hash, Error> {
+	hex::decode(hash)
+		.ok()
+		.and_then(|h| Hash::try_from(&h))
+		.ok_or_bad_request(format!("Invalid block hash: {}", hash))
+}
+
+/// Build the update of an object that removes a version containing a purged
+/// block: the version is aborted if it is still being uploaded, and a delete
+/// marker is added if it is the current version of the object.
+/// Returns None if the object does not need to be changed.
+fn purged_object(object: &Object, version_uuid: Uuid) -> Option<Object> {
+	let ov = object.versions().iter().find(|v| v.uuid == version_uuid)?;
+	let new_version = match &ov.state {
+		ObjectVersionState::Uploading(_) => ObjectVersion {
+			uuid: ov.uuid,
+			timestamp: ov.timestamp,
+			state: ObjectVersionState::Aborted,
+		},
+		ObjectVersionState::Complete(_) => {
+			let current = object.versions().iter().rev().find(|v| v.is_complete())?;
+			if current.uuid != version_uuid {
+				return None;
+			}
+			ObjectVersion {
+				uuid: gen_uuid(),
+				timestamp: ov.timestamp + 1,
+				state: ObjectVersionState::Complete(ObjectVersionData::DeleteMarker),
+			}
+		}
+		ObjectVersionState::Aborted => return None,
+	};
+	Some(Object::new(
+		object.bucket_id,
+		object.key.clone(),
+		vec![new_version],
+	))
+}
+
+fn perm_capabilities(query: &PermBucketOpt) -> Vec<BucketCapability> {
+	let mut caps = vec![];
+	if query.read {
+		caps.extend(BucketCapability::READ);
+	}
+	if query.write {
+		caps.extend(BucketCapability::WRITE);
+	}
+	if query.owner {
+		caps.extend(BucketCapability::OWNER);
+	}
+	for (flag, cap) in [
+		(query.list, BucketCapability::List),
+		(query.create, BucketCapability::Create),
+		(query.overwrite, BucketCapability::Overwrite),
+		(query.delete, BucketCapability::Delete),
+	] {
+		if flag {
+			caps.push(cap);
+		}
+	}
+	caps
+}
+
+fn format_new_permissions(key_id: &str, bucket: &str, perm: &BucketKeyPerm) -> String {
+	let caps = perm.capabilities();
+	let caps = if caps.is_empty() {
+		"none".to_string()
+	} else {
+		caps.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ")
+	};
+	let mut msg = format!("New permissions for {} on {}: {}", key_id, bucket, caps);
+	if !perm.prefixes.is_empty() {
+		write!(&mut msg, ", on prefixes: {}", perm.prefixes.join(", ")).unwrap();
+	}
+	msg.push('.');
+	msg
+}
+
+fn parse_key_expiration(value: &str) -> Result<Option<u64>, Error> {
+	if value == "none" {
+		return Ok(None);
+	}
+	parse_cli_date(value).map(Some)
+}
+
+/// Parse a RFC3339 date and time, or a date such as 2023-01-31
+/// meaning midnight UTC at the start of that day
+fn parse_cli_date(value: &str) -> Result<u64, Error> {
+	rfc3339_to_msec(value)
+		.or_else(|| rfc3339_to_msec(&format!("{}T00:00:00Z", value)))
+		.ok_or_bad_request(format!("Invalid date specified: {}", value))
+}
+
+fn apply_rate_limits_opt(limits: &mut RateLimits, opt: &RateLimitsOpt) -> Result<(), Error> {
+	if opt.read_requests.is_none()
+		&& opt.write_requests.is_none()
+		&& opt.list_requests.is_none()
+		&& opt.upload_bytes.is_none()
+		&& opt.download_bytes.is_none()
+	{
+		return Err(Error::BadRequest(
+			"You must specify at least one of --read-requests, --write-requests, --list-requests, --upload-bytes or --download-bytes for this command to do something.".to_string(),
+		));
+	}
+
+	let parse_count = |v: &Option<String>, limit: &mut Option<u64>| -> Result<(), Error> {
+		match v.as_ref().map(String::as_ref) {
+			Some("none") => *limit = None,
+			Some(v) => {
+				let n = v
+					.parse::<u64>()
+					.ok_or_bad_request(format!("Invalid number specified: {}", v))?;
+				if n == 0 {
+					return Err(Error::BadRequest(
+						"Rate limits must be greater than zero".to_string(),
+					));
+				}
+				*limit = Some(n);
+			}
+			None => (),
+		}
+		Ok(())
+	};
+	let parse_size = |v: &Option<String>, limit: &mut Option<u64>| -> Result<(), Error> {
+		match v.as_ref().map(String::as_ref) {
+			Some("none") => *limit = None,
+			Some(v) => {
+				let bs = v
+					.parse::<bytesize::ByteSize>()
+					.ok_or_bad_request(format!("Invalid size specified: {}", v))?;
+				if bs.as_u64() == 0 {
+					return Err(Error::BadRequest(
+						"Bandwidth limits must be greater than zero".to_string(),
+					));
+				}
+				*limit = Some(bs.as_u64());
+			}
+			None => (),
+		}
+		Ok(())
+	};
+
+	parse_count(&opt.read_requests, &mut limits.read_requests)?;
+	parse_count(&opt.write_requests, &mut limits.write_requests)?;
+	parse_count(&opt.list_requests, &mut limits.list_requests)?;
+	parse_size(&opt.upload_bytes, &mut limits.upload_bytes)?;
+	parse_size(&opt.download_bytes, &mut limits.download_bytes)?;
+	Ok(())
 }
 
 #[async_trait]
@@ -920,11 +1851,159 @@ impl EndpointHandler<AdminRpc> for AdminRpcHandler {
 		match message {
 			AdminRpc::BucketOperation(bo) => self.handle_bucket_cmd(bo).await,
 			AdminRpc::KeyOperation(ko) => self.handle_key_cmd(ko).await,
+			AdminRpc::ObjectOperation(oo) => self.handle_object_cmd(oo).await,
 			AdminRpc::Migrate(opt) => self.handle_migrate(opt.clone()).await,
 			AdminRpc::LaunchRepair(opt) => self.handle_launch_repair(opt.clone()).await,
 			AdminRpc::Stats(opt) => self.handle_stats(opt.clone()).await,
 			AdminRpc::Worker(opt) => self.handle_worker_cmd(opt.clone()).await,
+			AdminRpc::BlockOperation(bo) => self.handle_block_cmd(bo).await,
+			AdminRpc::AuditOperation(ao) => self.handle_audit_cmd(ao).await,
+			AdminRpc::SimulateLayout => self.handle_simulate_layout().await,
+			AdminRpc::RecordAudit {
+				operation,
+				target,
+				parameters,
+			} => {
+				let mut entry = self.garage.audit_entry(AuditActor::Cli, operation, target);
+				entry.parameters = parameters.clone();
+				self.garage.record_audit(entry).await;
+				Ok(AdminRpc::Ok("Recorded in audit log.".into()))
+			}
 			m => Err(GarageError::unexpected_rpc_message(m).into()),
 		}
 	}
 }
+
+#[async_trait]
+impl NodeMaintenance for AdminRpcHandler {
+	async fn list_workers(&self, node: Uuid) -> Result<HashMap<usize, WorkerInfo>, Error> {
+		let cmd = WorkerCmd::List {
+			opt: WorkerListOpt {
+				busy: false,
+				errors: false,
+			},
+		};
+		match self
+			.call_node(node, AdminRpc::Worker(WorkerOpt { cmd }))
+			.await?
+		{
+			AdminRpc::WorkerList(workers, _) => Ok(workers),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
+	async fn set_worker_variable(
+		&self,
+		node: Uuid,
+		variable: WorkerVariable,
+	) -> Result<String, Error> {
+		let opt = match variable {
+			WorkerVariable::ScrubTranquility(tranquility) => {
+				WorkerSetCmd::ScrubTranquility { tranquility }
+			}
+			WorkerVariable::ResyncWorkerCount(n_workers) => {
+				WorkerSetCmd::ResyncNWorkers { n_workers }
+			}
+			WorkerVariable::ResyncTranquility(tranquility) => {
+				WorkerSetCmd::ResyncTranquility { tranquility }
+			}
+			WorkerVariable::TieringTranquility(tranquility) => {
+				WorkerSetCmd::TieringTranquility { tranquility }
+			}
+		};
+		let cmd = WorkerCmd::Set { opt };
+		self.call_node_ok(node, AdminRpc::Worker(WorkerOpt { cmd }))
+			.await
+	}
+
+	async fn launch_repair(&self, node: Uuid, repair: RepairType) -> Result<String, Error> {
+		let what = match repair {
+			RepairType::Tables => RepairWhat::Tables,
+			RepairType::Blocks => RepairWhat::Blocks,
+			RepairType::Versions => RepairWhat::Versions,
+			RepairType::BlockRefs => RepairWhat::BlockRefs,
+			RepairType::Rebalance => RepairWhat::Rebalance,
+			RepairType::Tiering => RepairWhat::Tiering,
+		};
+		self.call_node_ok(node, AdminRpc::LaunchRepair(repair_opt(what)))
+			.await
+	}
+
+	async fn control_scrub(&self, node: Uuid, command: ScrubCommand) -> Result<String, Error> {
+		let cmd = match command {
+			ScrubCommand::Start => ScrubCmd::Start,
+			ScrubCommand::Pause => ScrubCmd::Pause,
+			ScrubCommand::Resume => ScrubCmd::Resume,
+			ScrubCommand::Cancel => ScrubCmd::Cancel,
+			ScrubCommand::SetTranquility(tranquility) => ScrubCmd::SetTranquility { tranquility },
+		};
+		let what = RepairWhat::Scrub { cmd };
+		self.call_node_ok(node, AdminRpc::LaunchRepair(repair_opt(what)))
+			.await
+	}
+
+	async fn node_statistics(&self, node: Uuid, detailed: bool) -> Result<String, Error> {
+		let opt = StatsOpt {
+			all_nodes: false,
+			detailed,
+		};
+		match self.call_node(node, AdminRpc::Stats(opt)).await? {
+			AdminRpc::NodeStats(stats) => Ok(format_node_stats(&stats)),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
+	async fn list_block_errors(&self, node: Uuid) -> Result<Vec<BlockResyncErrorInfo>, Error> {
+		let msg = AdminRpc::BlockOperation(BlockOperation::ListErrors);
+		match self.call_node(node, msg).await? {
+			AdminRpc::BlockErrorList(el) => Ok(el),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
+	async fn block_info(&self, node: Uuid, hash: String) -> Result<BlockInfo, Error> {
+		let msg = AdminRpc::BlockOperation(BlockOperation::Info { hash });
+		match self.call_node(node, msg).await? {
+			AdminRpc::BlockInfo(info) => Ok(info),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
+	async fn scrub_reports(&self, node: Uuid) -> Result<Vec<ScrubReportInfo>, Error> {
+		let what = RepairWhat::Scrub {
+			cmd: ScrubCmd::Status,
+		};
+		match self
+			.call_node(node, AdminRpc::LaunchRepair(repair_opt(what)))
+			.await?
+		{
+			AdminRpc::ScrubReports(reports) => Ok(reports),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+
+	async fn retry_block_resync(
+		&self,
+		node: Uuid,
+		all: bool,
+		blocks: Vec<String>,
+	) -> Result<String, Error> {
+		let msg = AdminRpc::BlockOperation(BlockOperation::RetryNow { all, blocks });
+		self.call_node_ok(node, msg).await
+	}
+
+	async fn purge_blocks(&self, blocks: Vec<String>) -> Result<String, Error> {
+		match self.handle_block_purge(true, &blocks).await? {
+			AdminRpc::Ok(s) => Ok(s),
+			r => Err(GarageError::unexpected_rpc_message(r).into()),
+		}
+	}
+}
+
+fn repair_opt(what: RepairWhat) -> RepairOpt {
+	RepairOpt {
+		all_nodes: false,
+		yes: true,
+		what,
+	}
+}
@@ -1,6 +1,8 @@
 use std::collections::HashSet;
 use std::time::Duration;
 
+use serde::Serialize;
+
 use garage_util::error::*;
 use garage_util::formater::format_table;
 
@@ -18,34 +20,105 @@ pub async fn cli_command_dispatch(
 	system_rpc_endpoint: &Endpoint<SystemRpc, ()>,
 	admin_rpc_endpoint: &Endpoint<AdminRpc, ()>,
 	rpc_host: NodeID,
+	json: bool,
 ) -> Result<(), HelperError> {
 	match cmd {
-		Command::Status => Ok(cmd_status(system_rpc_endpoint, rpc_host).await?),
+		Command::Status => Ok(cmd_status(system_rpc_endpoint, rpc_host, json).await?),
 		Command::Node(NodeOperation::Connect(connect_opt)) => {
-			Ok(cmd_connect(system_rpc_endpoint, rpc_host, connect_opt).await?)
+			Ok(cmd_connect(system_rpc_endpoint, rpc_host, connect_opt, json).await?)
+		}
+		Command::Layout(LayoutOperation::Simulate) => {
+			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::SimulateLayout, json).await
 		}
 		Command::Layout(layout_opt) => {
-			Ok(cli_layout_command_dispatch(layout_opt, system_rpc_endpoint, rpc_host).await?)
+			let audit = layout_audit_record(&layout_opt);
+			cli_layout_command_dispatch(layout_opt, system_rpc_endpoint, rpc_host, json).await?;
+			if let Some(audit) = audit {
+				admin_rpc_endpoint
+					.call(&rpc_host, audit, PRIO_NORMAL)
+					.await??;
+			}
+			Ok(())
 		}
 		Command::Bucket(bo) => {
-			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::BucketOperation(bo)).await
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::BucketOperation(bo),
+				json,
+			)
+			.await
 		}
 		Command::Key(ko) => {
-			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::KeyOperation(ko)).await
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::KeyOperation(ko),
+				json,
+			)
+			.await
+		}
+		Command::Object(oo) => {
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::ObjectOperation(oo),
+				json,
+			)
+			.await
 		}
 		Command::Migrate(mo) => {
-			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Migrate(mo)).await
+			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Migrate(mo), json).await
 		}
 		Command::Repair(ro) => {
-			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::LaunchRepair(ro)).await
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::LaunchRepair(ro),
+				json,
+			)
+			.await
+		}
+		Command::Stats(so) => {
+			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Stats(so), json).await
+		}
+		Command::Worker(wo) => {
+			cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Worker(wo), json).await
+		}
+		Command::Block(bo) => {
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::BlockOperation(bo),
+				json,
+			)
+			.await
+		}
+		Command::Audit(ao) => {
+			cmd_admin(
+				admin_rpc_endpoint,
+				rpc_host,
+				AdminRpc::AuditOperation(ao),
+				json,
+			)
+			.await
 		}
-		Command::Stats(so) => cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Stats(so)).await,
-		Command::Worker(wo) => cmd_admin(admin_rpc_endpoint, rpc_host, AdminRpc::Worker(wo)).await,
 		_ => unreachable!(),
 	}
 }
 
-pub async fn cmd_status(rpc_cli: &Endpoint<SystemRpc, ()>, rpc_host: NodeID) -> Result<(), Error> {
+/// Output of `garage status` in JSON format
+#[derive(Serialize)]
+struct StatusOutput<'a> {
+	known_nodes: &'a [KnownNodeInfo],
+	layout: &'a ClusterLayout,
+}
+
+pub async fn cmd_status(
+	rpc_cli: &Endpoint<SystemRpc, ()>,
+	rpc_host: NodeID,
+	json: bool,
+) -> Result<(), Error> {
 	let status = match rpc_cli
 		.call(&rpc_host, SystemRpc::GetKnownNodes, PRIO_NORMAL)
 		.await??
@@ -55,19 +128,29 @@ pub async fn cmd_status(rpc_cli: &Endpoint<SystemRpc, ()>, rpc_host: NodeID) ->
 	};
 	let layout = fetch_layout(rpc_cli, rpc_host).await?;
 
+	if json {
+		return print_json(&StatusOutput {
+			known_nodes: &status,
+			layout: &layout,
+		});
+	}
+
 	println!("==== HEALTHY NODES ====");
-	let mut healthy_nodes = vec!["ID\tHostname\tAddress\tTags\tZone\tCapacity".to_string()];
+	let mut healthy_nodes =
+		vec!["ID\tHostname\tAddress\tTags\tZone\tCapacity\tDataAvail\tMetaAvail".to_string()];
 	for adv in status.iter().filter(|adv| adv.is_up) {
 		match layout.roles.get(&adv.id) {
 			Some(NodeRoleV(Some(cfg))) => {
 				healthy_nodes.push(format!(
-					"{id:?}\t{host}\t{addr}\t[{tags}]\t{zone}\t{capacity}",
+					"{id:?}\t{host}\t{addr}\t[{tags}]\t{zone}\t{capacity}\t{data_avail}\t{meta_avail}",
 					id = adv.id,
 					host = adv.status.hostname,
 					addr = adv.addr,
 					tags = cfg.tags.join(","),
 					zone = cfg.zone,
 					capacity = cfg.capacity_string(),
+					data_avail = disk_space_string(adv.status.data_disk_avail),
+					meta_avail = disk_space_string(adv.status.meta_disk_avail),
 				));
 			}
 			_ => {
@@ -147,11 +230,13 @@ pub async fn cmd_connect(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
 	args: ConnectNodeOpt,
+	json: bool,
 ) -> Result<(), Error> {
 	match rpc_cli
 		.call(&rpc_host, SystemRpc::Connect(args.node), PRIO_NORMAL)
 		.await??
 	{
+		SystemRpc::Ok if json => print_json(&AdminRpc::Ok("Success.".into())),
 		SystemRpc::Ok => {
 			println!("Success.");
 			Ok(())
@@ -164,8 +249,13 @@ pub async fn cmd_admin(
 	rpc_cli: &Endpoint<AdminRpc, ()>,
 	rpc_host: NodeID,
 	args: AdminRpc,
+	json: bool,
 ) -> Result<(), HelperError> {
-	match rpc_cli.call(&rpc_host, args, PRIO_NORMAL).await?? {
+	let reply = rpc_cli.call(&rpc_host, args, PRIO_NORMAL).await??;
+	if json {
+		return Ok(print_json(&reply)?);
+	}
+	match reply {
 		AdminRpc::Ok(msg) => {
 			println!("{}", msg);
 		}
@@ -188,6 +278,30 @@ pub async fn cmd_admin(
 		AdminRpc::WorkerList(wi, wlo) => {
 			print_worker_info(wi, wlo);
 		}
+		AdminRpc::ObjectInfo(info) => {
+			print_object_info(&info);
+		}
+		AdminRpc::BlockErrorList(el) => {
+			print_block_error_list(el);
+		}
+		AdminRpc::BlockInfo(info) => {
+			print_block_info(info);
+		}
+		AdminRpc::ScrubReports(reports) => {
+			print_scrub_reports(&reports);
+		}
+		AdminRpc::AuditLog(entries) => {
+			print_audit_log(&entries);
+		}
+		AdminRpc::LayoutSimulation(simulation) => {
+			print_layout_simulation(&simulation);
+		}
+		AdminRpc::NodeStats(stats) => {
+			println!("{}", format_node_stats(&stats));
+		}
+		AdminRpc::ClusterStats(list) => {
+			print_cluster_stats(list);
+		}
 		r => {
 			error!("Unexpected response: {:?}", r);
 		}
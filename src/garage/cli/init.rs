@@ -2,9 +2,12 @@ use std::path::PathBuf;
 
 use garage_util::error::*;
 
+use crate::admin::AdminRpc;
+use crate::cli::util::print_json;
+
 pub const READ_KEY_ERROR: &str = "Unable to read node key. It will be generated by your garage node the first time is it launched. Ensure that your garage node is currently running. (The node key is supposed to be stored in your metadata directory.)";
 
-pub fn node_id_command(config_file: PathBuf, quiet: bool) -> Result<(), Error> {
+pub fn node_id_command(config_file: PathBuf, quiet: bool, json: bool) -> Result<(), Error> {
 	let config = garage_util::config::read_config(config_file.clone()).err_context(format!(
 		"Unable to read configuration file {}",
 		config_file.to_string_lossy(),
@@ -13,13 +16,21 @@ pub fn node_id_command(config_file: PathBuf, quiet: bool) -> Result<(), Error> {
 	let node_id =
 		garage_rpc::system::read_node_id(&config.metadata_dir).err_context(READ_KEY_ERROR)?;
 
+	let print_id = |idstr: &str| match json {
+		true => print_json(&AdminRpc::Ok(idstr.to_string())),
+		false => {
+			println!("{}", idstr);
+			Ok(())
+		}
+	};
+
 	let idstr = if let Some(addr) = config.rpc_public_addr {
 		let idstr = format!("{}@{}", hex::encode(&node_id), addr);
-		println!("{}", idstr);
+		print_id(&idstr)?;
 		idstr
 	} else {
 		let idstr = hex::encode(&node_id);
-		println!("{}", idstr);
+		print_id(&idstr)?;
 
 		if !quiet {
 			warn!("WARNING: I don't know the public address to reach this node.");
@@ -6,26 +6,31 @@ use garage_rpc::layout::*;
 use garage_rpc::system::*;
 use garage_rpc::*;
 
+use crate::admin::AdminRpc;
 use crate::cli::*;
 
 pub async fn cli_layout_command_dispatch(
 	cmd: LayoutOperation,
 	system_rpc_endpoint: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
+	json: bool,
 ) -> Result<(), Error> {
 	match cmd {
 		LayoutOperation::Assign(configure_opt) => {
-			cmd_assign_role(system_rpc_endpoint, rpc_host, configure_opt).await
+			cmd_assign_role(system_rpc_endpoint, rpc_host, configure_opt, json).await
 		}
 		LayoutOperation::Remove(remove_opt) => {
-			cmd_remove_role(system_rpc_endpoint, rpc_host, remove_opt).await
+			cmd_remove_role(system_rpc_endpoint, rpc_host, remove_opt, json).await
 		}
-		LayoutOperation::Show => cmd_show_layout(system_rpc_endpoint, rpc_host).await,
+		LayoutOperation::Show => cmd_show_layout(system_rpc_endpoint, rpc_host, json).await,
+		// The simulation needs data that only the node has,
+		// it is sent as an admin RPC in cli_command_dispatch
+		LayoutOperation::Simulate => unreachable!(),
 		LayoutOperation::Apply(apply_opt) => {
-			cmd_apply_layout(system_rpc_endpoint, rpc_host, apply_opt).await
+			cmd_apply_layout(system_rpc_endpoint, rpc_host, apply_opt, json).await
 		}
 		LayoutOperation::Revert(revert_opt) => {
-			cmd_revert_layout(system_rpc_endpoint, rpc_host, revert_opt).await
+			cmd_revert_layout(system_rpc_endpoint, rpc_host, revert_opt, json).await
 		}
 	}
 }
@@ -34,6 +39,7 @@ pub async fn cmd_assign_role(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
 	args: AssignRoleOpt,
+	json: bool,
 ) -> Result<(), Error> {
 	let status = match rpc_cli
 		.call(&rpc_host, SystemRpc::GetKnownNodes, PRIO_NORMAL)
@@ -129,8 +135,11 @@ pub async fn cmd_assign_role(
 			.merge(&roles.update_mutator(added_node, NodeRoleV(Some(new_entry))));
 	}
 
-	send_layout(rpc_cli, rpc_host, layout).await?;
+	send_layout(rpc_cli, rpc_host, layout.clone()).await?;
 
+	if json {
+		return print_json(&layout);
+	}
 	println!("Role changes are staged but not yet commited.");
 	println!("Use `garage layout show` to view staged role changes,");
 	println!("and `garage layout apply` to enact staged changes.");
@@ -141,6 +150,7 @@ pub async fn cmd_remove_role(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
 	args: RemoveRoleOpt,
+	json: bool,
 ) -> Result<(), Error> {
 	let mut layout = fetch_layout(rpc_cli, rpc_host).await?;
 
@@ -154,8 +164,11 @@ pub async fn cmd_remove_role(
 		.staging
 		.merge(&roles.update_mutator(deleted_node, NodeRoleV(None)));
 
-	send_layout(rpc_cli, rpc_host, layout).await?;
+	send_layout(rpc_cli, rpc_host, layout.clone()).await?;
 
+	if json {
+		return print_json(&layout);
+	}
 	println!("Role removal is staged but not yet commited.");
 	println!("Use `garage layout show` to view staged role changes,");
 	println!("and `garage layout apply` to enact staged changes.");
@@ -165,9 +178,14 @@ pub async fn cmd_remove_role(
 pub async fn cmd_show_layout(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
+	json: bool,
 ) -> Result<(), Error> {
 	let mut layout = fetch_layout(rpc_cli, rpc_host).await?;
 
+	if json {
+		return print_json(&layout);
+	}
+
 	println!("==== CURRENT CLUSTER LAYOUT ====");
 	if !print_cluster_layout(&layout) {
 		println!("No nodes currently have a role in the cluster.");
@@ -188,7 +206,11 @@ pub async fn cmd_show_layout(
 
 		// this will print the stats of what partitions
 		// will move around when we apply
-		if layout.calculate_partition_assignation() {
+		if layout.calculate_partition_assignation(true) {
+			println!("To estimate how much data will be moved, type:");
+			println!();
+			println!("    garage layout simulate");
+			println!();
 			println!("To enact the staged role changes, type:");
 			println!();
 			println!("    garage layout apply --version {}", layout.version + 1);
@@ -210,12 +232,17 @@ pub async fn cmd_apply_layout(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
 	apply_opt: ApplyLayoutOpt,
+	json: bool,
 ) -> Result<(), Error> {
 	let layout = fetch_layout(rpc_cli, rpc_host).await?;
 
-	let layout = layout.apply_staged_changes(apply_opt.version)?;
+	let layout = layout.apply_staged_changes(apply_opt.version, !json)?;
+
+	send_layout(rpc_cli, rpc_host, layout.clone()).await?;
 
-	send_layout(rpc_cli, rpc_host, layout).await?;
+	if json {
+		return print_json(&layout);
+	}
 
 	println!("New cluster layout with updated role assignation has been applied in cluster.");
 	println!("Data will now be moved around between nodes accordingly.");
@@ -227,17 +254,66 @@ pub async fn cmd_revert_layout(
 	rpc_cli: &Endpoint<SystemRpc, ()>,
 	rpc_host: NodeID,
 	revert_opt: RevertLayoutOpt,
+	json: bool,
 ) -> Result<(), Error> {
 	let layout = fetch_layout(rpc_cli, rpc_host).await?;
 
 	let layout = layout.revert_staged_changes(revert_opt.version)?;
 
-	send_layout(rpc_cli, rpc_host, layout).await?;
+	send_layout(rpc_cli, rpc_host, layout.clone()).await?;
+
+	if json {
+		return print_json(&layout);
+	}
 
 	println!("All proposed role changes in cluster layout have been canceled.");
 	Ok(())
 }
 
+/// Build the audit log record of a layout operation that changes the layout,
+/// it is sent to the node once the operation has succeeded
+pub fn layout_audit_record(cmd: &LayoutOperation) -> Option<AdminRpc> {
+	let (operation, target, parameters) = match cmd {
+		LayoutOperation::Show | LayoutOperation::Simulate => return None,
+		LayoutOperation::Assign(opt) => {
+			let mut parameters = vec![];
+			if let Some(zone) = &opt.zone {
+				parameters.push(("zone".to_string(), zone.clone()));
+			}
+			if let Some(capacity) = opt.capacity {
+				parameters.push(("capacity".to_string(), capacity.to_string()));
+			}
+			if opt.gateway {
+				parameters.push(("gateway".to_string(), "true".to_string()));
+			}
+			if !opt.tags.is_empty() {
+				parameters.push(("tags".to_string(), opt.tags.join(", ")));
+			}
+			if !opt.replace.is_empty() {
+				parameters.push(("replace".to_string(), opt.replace.join(", ")));
+			}
+			("layout assign", opt.node_ids.join(", "), parameters)
+		}
+		LayoutOperation::Remove(opt) => ("layout remove", opt.node_id.clone(), vec![]),
+		LayoutOperation::Apply(opt) => ("layout apply", layout_version_target(opt.version), vec![]),
+		LayoutOperation::Revert(opt) => {
+			("layout revert", layout_version_target(opt.version), vec![])
+		}
+	};
+	Some(AdminRpc::RecordAudit {
+		operation: operation.to_string(),
+		target,
+		parameters,
+	})
+}
+
+fn layout_version_target(version: Option<u64>) -> String {
+	match version {
+		Some(v) => format!("layout version {}", v),
+		None => "layout".to_string(),
+	}
+}
+
 // --- utility ---
 
 pub async fn fetch_layout(
@@ -268,6 +344,54 @@ pub async fn send_layout(
 	Ok(())
 }
 
+pub fn print_layout_simulation(simulation: &LayoutSimulation) {
+	println!(
+		"==== ESTIMATED CHANGES FOR LAYOUT VERSION {} ====",
+		simulation.new_version
+	);
+	let bytes = |b: Option<u64>| match b {
+		Some(b) => bytesize::ByteSize::b(b).to_string_as(true),
+		None => "?".to_string(),
+	};
+	let mut table = vec!["ID\tPartitions\tGained\tLost\tTo receive\tTo remove".to_string()];
+	for node in simulation.nodes.iter() {
+		table.push(format!(
+			"{:?}\t{} -> {}\t{}\t{}\t{}\t{}",
+			node.id,
+			node.partitions_before,
+			node.partitions_after,
+			node.partitions_gained,
+			node.partitions_lost,
+			bytes(node.bytes_gained),
+			bytes(node.bytes_lost),
+		));
+	}
+	format_table(table);
+	println!();
+
+	println!(
+		"Partitions that move: {} of {}",
+		simulation.moved_partitions,
+		1 << garage_rpc::ring::PARTITION_BITS
+	);
+	let total = simulation
+		.nodes
+		.iter()
+		.map(|n| n.bytes_gained)
+		.sum::<Option<u64>>();
+	match total {
+		Some(_) => println!("Estimated data to transfer: {}", bytes(total)),
+		None => println!(
+			"The amount of data to transfer cannot be estimated, as this node stores no data."
+		),
+	}
+	if simulation.zone_redundant {
+		println!("Each partition is stored in as many different zones as possible.");
+	} else {
+		println!("WARNING: some partitions are not stored in as many different zones as possible.");
+	}
+}
+
 pub fn print_cluster_layout(layout: &ClusterLayout) -> bool {
 	let mut table = vec!["ID\tTags\tZone\tCapacity".to_string()];
 	for (id, _, role) in layout.roles.items().iter() {
@@ -29,6 +29,10 @@ pub enum Command {
 	#[structopt(name = "key", version = garage_version())]
 	Key(KeyOperation),
 
+	/// Inspect objects stored in buckets
+	#[structopt(name = "object", version = garage_version())]
+	Object(ObjectOperation),
+
 	/// Run migrations from previous Garage version
 	/// (DO NOT USE WITHOUT READING FULL DOCUMENTATION)
 	#[structopt(name = "migrate", version = garage_version())]
@@ -50,6 +54,14 @@ pub enum Command {
 	/// Manage background workers
 	#[structopt(name = "worker", version = garage_version())]
 	Worker(WorkerOpt),
+
+	/// Low-level debug operations on data blocks
+	#[structopt(name = "block", version = garage_version())]
+	Block(BlockOperation),
+
+	/// Read the audit log of administrative operations
+	#[structopt(name = "audit", version = garage_version())]
+	Audit(AuditOperation),
 }
 
 #[derive(StructOpt, Debug)]
@@ -91,6 +103,10 @@ pub enum LayoutOperation {
 	#[structopt(name = "show", version = garage_version())]
 	Show,
 
+	/// Estimate the data movement caused by applying the staged changes
+	#[structopt(name = "simulate", version = garage_version())]
+	Simulate,
+
 	/// Apply staged changes to cluster layout
 	#[structopt(name = "apply", version = garage_version())]
 	Apply(ApplyLayoutOpt),
@@ -190,6 +206,26 @@ pub enum BucketOperation {
 	#[structopt(name = "set-quotas", version = garage_version())]
 	SetQuotas(SetQuotasOpt),
 
+	/// Set the request and bandwidth rate limits for this bucket
+	#[structopt(name = "set-rate-limits", version = garage_version())]
+	SetRateLimits(BucketSetRateLimitsOpt),
+
+	/// Set whether the data blocks of this bucket are erasure coded
+	#[structopt(name = "set-erasure-coding", version = garage_version())]
+	SetErasureCoding(SetErasureCodingOpt),
+
+	/// Set how data uploaded to this bucket is split in blocks
+	#[structopt(name = "set-chunking", version = garage_version())]
+	SetChunking(SetChunkingOpt),
+
+	/// Set the compression level of the data blocks of this bucket
+	#[structopt(name = "set-compression", version = garage_version())]
+	SetCompression(SetCompressionOpt),
+
+	/// Set after how many days the data of objects of this bucket is moved to the cold tier
+	#[structopt(name = "set-cold-tier", version = garage_version())]
+	SetColdTier(SetColdTierOpt),
+
 	/// Clean up (abort) old incomplete multipart uploads
 	#[structopt(name = "cleanup-incomplete-uploads", version = garage_version())]
 	CleanupIncompleteUploads(CleanupIncompleteUploadsOpt),
@@ -262,11 +298,11 @@ pub struct PermBucketOpt {
 	#[structopt(long = "key")]
 	pub key_pattern: String,
 
-	/// Allow/deny read operations
+	/// Allow/deny read operations (reading and listing objects)
 	#[structopt(long = "read")]
 	pub read: bool,
 
-	/// Allow/deny write operations
+	/// Allow/deny write operations (creating, overwriting and deleting objects)
 	#[structopt(long = "write")]
 	pub write: bool,
 
@@ -275,6 +311,28 @@ pub struct PermBucketOpt {
 	#[structopt(long = "owner")]
 	pub owner: bool,
 
+	/// Allow/deny listing objects only
+	#[structopt(long = "list")]
+	pub list: bool,
+
+	/// Allow/deny creating new objects only
+	#[structopt(long = "create")]
+	pub create: bool,
+
+	/// Allow/deny overwriting or modifying existing objects only
+	#[structopt(long = "overwrite")]
+	pub overwrite: bool,
+
+	/// Allow/deny deleting objects only
+	#[structopt(long = "delete")]
+	pub delete: bool,
+
+	/// Restrict the permissions to objects whose key starts with this prefix
+	/// (can be given several times). When denying, all permissions
+	/// on the given prefixes are removed.
+	#[structopt(long = "prefix")]
+	pub prefixes: Vec<String>,
+
 	/// Bucket name
 	pub bucket: String,
 }
@@ -294,6 +352,81 @@ pub struct SetQuotasOpt {
 	pub max_objects: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct SetErasureCodingOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	/// Storage mode for new data blocks of the bucket: `enabled` (erasure coded),
+	/// `disabled` (full copies) or `default` (use the default of the cluster)
+	pub mode: String,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct SetChunkingOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	/// Chunking mode for data uploaded to the bucket: `content-defined`,
+	/// `fixed` (blocks of block_size bytes) or `default` (use the default of the cluster)
+	pub mode: String,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct SetCompressionOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	/// Zstd compression level for new data blocks of the bucket, `none` to store
+	/// them uncompressed, or `default` (use the compression level of the cluster)
+	#[structopt(allow_hyphen_values = true)]
+	pub level: String,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct SetColdTierOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	/// Age in days after which the data blocks of objects of the bucket are moved
+	/// to the cold tier, or `none` to keep them in the data directories
+	pub days: String,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct BucketSetRateLimitsOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	#[structopt(flatten)]
+	pub limits: RateLimitsOpt,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct RateLimitsOpt {
+	/// Maximum number of read requests per second (or `none` for no restriction)
+	#[structopt(long = "read-requests")]
+	pub read_requests: Option<String>,
+
+	/// Maximum number of write requests per second (or `none` for no restriction)
+	#[structopt(long = "write-requests")]
+	pub write_requests: Option<String>,
+
+	/// Maximum number of list requests per second (or `none` for no restriction)
+	#[structopt(long = "list-requests")]
+	pub list_requests: Option<String>,
+
+	/// Maximum upload bandwidth, as a size per second e.g. in KiB or MiB
+	/// (or `none` for no restriction)
+	#[structopt(long = "upload-bytes")]
+	pub upload_bytes: Option<String>,
+
+	/// Maximum download bandwidth, as a size per second e.g. in KiB or MiB
+	/// (or `none` for no restriction)
+	#[structopt(long = "download-bytes")]
+	pub download_bytes: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, StructOpt, Debug)]
 pub struct CleanupIncompleteUploadsOpt {
 	/// Abort multipart uploads older than this value
@@ -323,6 +456,14 @@ pub enum KeyOperation {
 	#[structopt(name = "rename", version = garage_version())]
 	Rename(KeyRenameOpt),
 
+	/// Change the settings of a key
+	#[structopt(name = "set", version = garage_version())]
+	Set(KeySetOpt),
+
+	/// Generate a new secret for a key, previous secrets expire after a grace period
+	#[structopt(name = "rotate", version = garage_version())]
+	Rotate(KeyRotateOpt),
+
 	/// Delete key
 	#[structopt(name = "delete", version = garage_version())]
 	Delete(KeyDeleteOpt),
@@ -338,6 +479,10 @@ pub enum KeyOperation {
 	/// Import key
 	#[structopt(name = "import", version = garage_version())]
 	Import(KeyImportOpt),
+
+	/// Set the request and bandwidth rate limits for key
+	#[structopt(name = "set-rate-limits", version = garage_version())]
+	SetRateLimits(KeySetRateLimitsOpt),
 }
 
 #[derive(Serialize, Deserialize, StructOpt, Debug)]
@@ -351,6 +496,11 @@ pub struct KeyNewOpt {
 	/// Name of the key
 	#[structopt(long = "name", default_value = "Unnamed key")]
 	pub name: String,
+
+	/// Date at which the key stops working (RFC3339 date and time,
+	/// or date such as 2023-01-31 meaning midnight UTC at the start of that day)
+	#[structopt(long = "expires-at")]
+	pub expires_at: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, StructOpt, Debug)]
@@ -362,6 +512,61 @@ pub struct KeyRenameOpt {
 	pub new_name: String,
 }
 
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct KeySetOpt {
+	/// ID or name of the key
+	pub key_pattern: String,
+
+	/// Enable the key
+	#[structopt(long = "enable")]
+	pub enable: bool,
+
+	/// Disable the key, it can no longer be used to make requests
+	#[structopt(long = "disable")]
+	pub disable: bool,
+
+	/// Date at which the key stops working (RFC3339 date and time,
+	/// or date such as 2023-01-31 meaning midnight UTC at the start of that day),
+	/// or `none` for a key that never expires
+	#[structopt(long = "expires-at")]
+	pub expires_at: Option<String>,
+
+	/// Only accept requests made with the key from this network (IP address
+	/// or CIDR such as 10.0.0.0/8, can be given several times).
+	/// Replaces the current list of allowed networks.
+	#[structopt(long = "allow-ip")]
+	pub allow_ips: Vec<String>,
+
+	/// Accept requests made with the key from any IP address
+	#[structopt(long = "allow-any-ip")]
+	pub allow_any_ip: bool,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct KeyRotateOpt {
+	/// ID or name of the key
+	pub key_pattern: String,
+
+	/// Time during which the previous secrets of the key remain valid
+	/// (e.g. 1h or 7d, use 0s to invalidate them immediately)
+	#[structopt(long = "grace-period", default_value = "1d")]
+	pub grace_period: String,
+
+	/// Date at which the new secret stops working (RFC3339 date and time,
+	/// or date such as 2023-01-31 meaning midnight UTC at the start of that day)
+	#[structopt(long = "expires-at")]
+	pub expires_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct KeySetRateLimitsOpt {
+	/// ID or name of the key
+	pub key_pattern: String,
+
+	#[structopt(flatten)]
+	pub limits: RateLimitsOpt,
+}
+
 #[derive(Serialize, Deserialize, StructOpt, Debug)]
 pub struct KeyDeleteOpt {
 	/// ID or name of the key
@@ -412,6 +617,22 @@ pub enum MigrateWhat {
 	Buckets050,
 }
 
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub enum ObjectOperation {
+	/// Show the versions of an object, its data blocks and the nodes that store them
+	#[structopt(name = "info", version = garage_version())]
+	Info(ObjectInfoOpt),
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct ObjectInfoOpt {
+	/// Bucket name
+	pub bucket: String,
+
+	/// Key of the object
+	pub key: String,
+}
+
 #[derive(Serialize, Deserialize, StructOpt, Debug, Clone)]
 pub struct RepairOpt {
 	/// Launch repair operation on all nodes
@@ -440,6 +661,14 @@ pub enum RepairWhat {
 	/// Only redo the propagation of version deletions to the block ref table (extremely slow)
 	#[structopt(name = "block_refs", version = garage_version())]
 	BlockRefs,
+	/// Move blocks to the data directory where they should be stored
+	/// (launched automatically when data directories have changed)
+	#[structopt(name = "rebalance", version = garage_version())]
+	Rebalance,
+	/// Move the blocks of old objects to the cold tier
+	/// (launched automatically every day when a cold tier is configured)
+	#[structopt(name = "tiering", version = garage_version())]
+	Tiering,
 	/// Verify integrity of all blocks on disc (extremely slow, i/o intensive)
 	#[structopt(name = "scrub", version = garage_version())]
 	Scrub {
@@ -468,6 +697,9 @@ pub enum ScrubCmd {
 		#[structopt()]
 		tranquility: u32,
 	},
+	/// Show the reports of the last scrubs, with the objects affected by corrupted blocks
+	#[structopt(name = "status", version = garage_version())]
+	Status,
 }
 
 #[derive(Serialize, Deserialize, StructOpt, Debug, Clone)]
@@ -545,4 +777,71 @@ pub enum WorkerSetCmd {
 	/// Set tranquility of block resync operations
 	#[structopt(name = "resync-tranquility", version = garage_version())]
 	ResyncTranquility { tranquility: u32 },
+	/// Set tranquility of the moves of blocks to the cold tier
+	#[structopt(name = "tiering-tranquility", version = garage_version())]
+	TieringTranquility { tranquility: u32 },
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug, Eq, PartialEq, Clone)]
+pub enum BlockOperation {
+	/// List all blocks that currently have a resync error
+	#[structopt(name = "list-errors", version = garage_version())]
+	ListErrors,
+	/// Get detailed information about a single block, including
+	/// the objects that reference it
+	#[structopt(name = "info", version = garage_version())]
+	Info {
+		/// Hash of the block for which to retrieve information
+		/// (a prefix of at least 4 characters is enough)
+		#[structopt()]
+		hash: String,
+	},
+	/// Retry now the resync of one or many blocks
+	#[structopt(name = "retry-now", version = garage_version())]
+	RetryNow {
+		/// Retry all blocks that have a resync error
+		#[structopt(long = "all")]
+		all: bool,
+		/// Hashes of the block to retry to resync now
+		#[structopt()]
+		blocks: Vec<String>,
+	},
+	/// Delete all objects referencing a missing block
+	#[structopt(name = "purge", version = garage_version())]
+	Purge {
+		/// Mandatory to confirm this operation
+		#[structopt(long = "yes")]
+		yes: bool,
+		/// Hashes of the blocks to purge
+		#[structopt(required = true)]
+		blocks: Vec<String>,
+	},
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub enum AuditOperation {
+	/// List entries of the audit log, oldest first
+	#[structopt(name = "list", version = garage_version())]
+	List(AuditListOpt),
+}
+
+#[derive(Serialize, Deserialize, StructOpt, Debug)]
+pub struct AuditListOpt {
+	/// Only show operations performed at or after this date (RFC3339 date and time,
+	/// or date such as 2023-01-31 meaning midnight UTC at the start of that day)
+	#[structopt(long = "since")]
+	pub since: Option<String>,
+
+	/// Only show operations performed at or before this date
+	#[structopt(long = "until")]
+	pub until: Option<String>,
+
+	/// Only show operations performed by this actor: either a kind of actor
+	/// (`cli`, `admin` or `s3`) or a full actor name (e.g. `admin:portal` or `s3:GK...`)
+	#[structopt(long = "actor")]
+	pub actor: Option<String>,
+
+	/// Maximum number of entries to show
+	#[structopt(long = "limit", default_value = "100")]
+	pub limit: usize,
 }
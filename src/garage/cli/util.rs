@@ -1,6 +1,9 @@
 use std::collections::HashMap;
+use std::fmt::Write;
 use std::time::Duration;
 
+use serde::Serialize;
+
 use garage_util::background::*;
 use garage_util::crdt::*;
 use garage_util::data::Uuid;
@@ -8,10 +11,22 @@ use garage_util::error::*;
 use garage_util::formater::format_table;
 use garage_util::time::*;
 
+use garage_rpc::system::DiskSpace;
+
+use garage_block::manager::CorruptedBlockStatus;
+use garage_block::resync::BlockResyncErrorInfo;
+
+use garage_model::audit_table::AuditEntry;
 use garage_model::bucket_table::*;
+use garage_model::helper::object::ObjectInfo;
 use garage_model::key_table::*;
-use garage_model::s3::object_table::{BYTES, OBJECTS, UNFINISHED_UPLOADS};
+use garage_model::permission::{BucketCapability, BucketKeyPerm};
+use garage_model::rate_limits::RateLimits;
+use garage_model::s3::object_table::*;
+
+use garage_api::admin::maintenance::{BlockInfo, ScrubReportInfo};
 
+use crate::admin::NodeStats;
 use crate::cli::structs::WorkerListOpt;
 
 pub fn print_bucket_list(bl: Vec<Bucket>) {
@@ -55,6 +70,68 @@ pub fn print_key_list(kl: Vec<(String, String)>) {
 	format_table(table);
 }
 
+fn print_rate_limits(limits: &RateLimits) {
+	if limits.is_unlimited() {
+		return;
+	}
+	println!("\nRate limits:");
+	if let Some(n) = limits.read_requests {
+		println!(" read requests: {}/s", n);
+	}
+	if let Some(n) = limits.write_requests {
+		println!(" write requests: {}/s", n);
+	}
+	if let Some(n) = limits.list_requests {
+		println!(" list requests: {}/s", n);
+	}
+	if let Some(b) = limits.upload_bytes {
+		println!(" upload: {}/s", bytesize::ByteSize::b(b).to_string_as(true));
+	}
+	if let Some(b) = limits.download_bytes {
+		println!(
+			" download: {}/s",
+			bytesize::ByteSize::b(b).to_string_as(true)
+		);
+	}
+}
+
+/// Permission flags: upper case if the key has the whole permission,
+/// lower case if it only has some of the capabilities it includes
+fn format_perm_flags(perm: &BucketKeyPerm) -> String {
+	[
+		(&BucketCapability::READ[..], 'R'),
+		(&BucketCapability::WRITE[..], 'W'),
+		(&BucketCapability::OWNER[..], 'O'),
+	]
+	.iter()
+	.map(|(caps, flag)| {
+		if perm.allows_all(caps) {
+			*flag
+		} else if caps.iter().any(|c| perm.allows(*c)) {
+			flag.to_ascii_lowercase()
+		} else {
+			' '
+		}
+	})
+	.collect()
+}
+
+fn format_perm_restrictions(perm: &BucketKeyPerm) -> String {
+	let mut restrictions = vec![];
+	if !perm.denied.is_empty() {
+		let caps = perm
+			.capabilities()
+			.iter()
+			.map(|c| c.name())
+			.collect::<Vec<_>>();
+		restrictions.push(format!("only: {}", caps.join(", ")));
+	}
+	if !perm.prefixes.is_empty() {
+		restrictions.push(format!("prefixes: {}", perm.prefixes.join(", ")));
+	}
+	restrictions.join("; ")
+}
+
 pub fn print_key_info(key: &Key, relevant_buckets: &HashMap<Uuid, Bucket>) {
 	let bucket_global_aliases = |b: &Uuid| {
 		if let Some(bucket) = relevant_buckets.get(b) {
@@ -77,8 +154,49 @@ pub fn print_key_info(key: &Key, relevant_buckets: &HashMap<Uuid, Bucket>) {
 		Deletable::Present(p) => {
 			println!("Key name: {}", p.name.get());
 			println!("Key ID: {}", key.key_id);
-			println!("Secret key: {}", p.secret_key);
+			println!("Secret key: {}", p.current_secret());
 			println!("Can create buckets: {}", p.allow_create_bucket.get());
+			println!("Enabled: {}", p.enabled.get());
+			match p.expires_at.get() {
+				Some(t) if p.is_expired(now_msec()) => {
+					println!("Expires at: {} (expired)", msec_to_rfc3339(*t))
+				}
+				Some(t) => println!("Expires at: {}", msec_to_rfc3339(*t)),
+				None => println!("Expires at: never"),
+			}
+			let allowed_ips = &p.allowed_ips.get().0;
+			if allowed_ips.is_empty() {
+				println!("Allowed IPs: any");
+			} else {
+				let allowed_ips = allowed_ips
+					.iter()
+					.map(|n| n.to_string())
+					.collect::<Vec<_>>();
+				println!("Allowed IPs: {}", allowed_ips.join(", "));
+			}
+			print_rate_limits(p.rate_limits.get());
+
+			let secrets = p.all_secrets();
+			if secrets.len() > 1 {
+				println!("\nSecrets:");
+				let now = now_msec();
+				let mut table = vec![];
+				for (secret, v) in secrets.iter() {
+					let created = if v.created > 0 {
+						msec_to_rfc3339(v.created)
+					} else {
+						"-".to_string()
+					};
+					let validity = match v.expires_at {
+						Some(t) if !v.is_valid(now) => format!("expired {}", msec_to_rfc3339(t)),
+						Some(t) => format!("expires {}", msec_to_rfc3339(t)),
+						None => "no expiration".to_string(),
+					};
+					table.push(format!("\t{}\t{}\t{}", secret, created, validity));
+				}
+				format_table(table);
+			}
+
 			println!("\nKey-specific bucket aliases:");
 			let mut table = vec![];
 			for (alias_name, _, alias) in p.local_aliases.items().iter() {
@@ -99,9 +217,6 @@ pub fn print_key_info(key: &Key, relevant_buckets: &HashMap<Uuid, Bucket>) {
 				if !perm.is_any() {
 					continue;
 				}
-				let rflag = if perm.allow_read { "R" } else { " " };
-				let wflag = if perm.allow_write { "W" } else { " " };
-				let oflag = if perm.allow_owner { "O" } else { " " };
 				let local_aliases = p
 					.local_aliases
 					.items()
@@ -111,13 +226,12 @@ pub fn print_key_info(key: &Key, relevant_buckets: &HashMap<Uuid, Bucket>) {
 					.collect::<Vec<_>>()
 					.join(", ");
 				table.push(format!(
-					"\t{}{}{}\t{}\t{}\t{:?}",
-					rflag,
-					wflag,
-					oflag,
+					"\t{}\t{}\t{}\t{:?}\t{}",
+					format_perm_flags(perm),
 					bucket_global_aliases(bucket_id),
 					local_aliases,
-					bucket_id
+					bucket_id,
+					format_perm_restrictions(perm)
 				));
 			}
 			format_table(table);
@@ -181,6 +295,28 @@ pub fn print_bucket_info(
 				}
 			}
 
+			print_rate_limits(p.rate_limits.get());
+
+			match p.erasure_coding.get() {
+				Some(true) => println!("\nErasure coding: enabled"),
+				Some(false) => println!("\nErasure coding: disabled"),
+				None => println!("\nErasure coding: cluster default"),
+			}
+			match p.content_defined_chunking.get() {
+				Some(true) => println!("Chunking: content-defined"),
+				Some(false) => println!("Chunking: fixed"),
+				None => println!("Chunking: cluster default"),
+			}
+			match p.compression.get() {
+				Some(BucketCompression::Off) => println!("Compression: none"),
+				Some(BucketCompression::Level(level)) => println!("Compression: level {}", level),
+				None => println!("Compression: cluster default"),
+			}
+			match p.cold_tier_after_days.get() {
+				Some(days) => println!("Cold tier: after {} days", days),
+				None => println!("Cold tier: never"),
+			}
+
 			println!("\nGlobal aliases:");
 			for (alias, _, active) in p.aliases.items().iter() {
 				if *active {
@@ -203,16 +339,12 @@ pub fn print_bucket_info(
 				if !perm.is_any() {
 					continue;
 				}
-				let rflag = if perm.allow_read { "R" } else { " " };
-				let wflag = if perm.allow_write { "W" } else { " " };
-				let oflag = if perm.allow_owner { "O" } else { " " };
 				table.push(format!(
-					"\t{}{}{}\t{}\t{}",
-					rflag,
-					wflag,
-					oflag,
+					"\t{}\t{}\t{}\t{}",
+					format_perm_flags(perm),
 					k,
-					key_name(k)
+					key_name(k),
+					format_perm_restrictions(perm)
 				));
 			}
 			format_table(table);
@@ -293,3 +425,316 @@ pub fn print_worker_info(wi: HashMap<usize, WorkerInfo>, wlo: WorkerListOpt) {
 	}
 	format_table(table);
 }
+
+pub fn print_object_info(info: &ObjectInfo) {
+	println!("Bucket: {}", hex::encode(info.bucket_id));
+	println!("Key: {}", info.key);
+
+	for v in info.versions.iter() {
+		let ov = &v.object_version;
+		println!();
+		println!(
+			"Version {} ({})",
+			hex::encode(ov.uuid),
+			msec_to_rfc3339(ov.timestamp)
+		);
+		match &ov.state {
+			ObjectVersionState::Uploading(_) => println!("  State: uploading"),
+			ObjectVersionState::Aborted => println!("  State: aborted"),
+			ObjectVersionState::Complete(ObjectVersionData::DeleteMarker) => {
+				println!("  State: complete, delete marker")
+			}
+			ObjectVersionState::Complete(ObjectVersionData::Inline(meta, _)) => {
+				println!(
+					"  State: complete, {} inline, etag {}",
+					bytesize::ByteSize::b(meta.size),
+					meta.etag
+				)
+			}
+			ObjectVersionState::Complete(ObjectVersionData::FirstBlock(meta, _)) => {
+				println!(
+					"  State: complete, {} in blocks, etag {}",
+					bytesize::ByteSize::b(meta.size),
+					meta.etag
+				)
+			}
+		}
+
+		let expects_blocks = matches!(
+			ov.state,
+			ObjectVersionState::Uploading(_)
+				| ObjectVersionState::Complete(ObjectVersionData::FirstBlock(_, _))
+		);
+		match &v.version {
+			None if expects_blocks => {
+				println!("  Warning: version not found in the version table");
+				continue;
+			}
+			None => continue,
+			Some(ver) if ver.deleted.get() => {
+				println!("  Warning: version is marked as deleted in the version table")
+			}
+			Some(_) => (),
+		}
+
+		let mut table = vec!["  Part\tOffset\tHash\tSize\tNode\tStatus".to_string()];
+		for b in v.blocks.iter() {
+			let mut block_cols = format!(
+				"  {}\t{}\t{}\t{}",
+				b.part_number,
+				b.offset,
+				hex::encode(b.hash),
+				b.size
+			);
+			for r in b.replicas.iter() {
+				let status = match &r.status {
+					Ok(st) if st.stored => format!("stored, refcount {}", st.refcount),
+					Ok(st) => format!("MISSING, refcount {}", st.refcount),
+					Err(e) => format!("error: {}", e),
+				};
+				table.push(format!("{}\t{:?}\t{}", block_cols, r.node, status));
+				block_cols = "  \t\t\t".into();
+			}
+		}
+		format_table(table);
+	}
+}
+
+/// Print a value as a JSON document, this is the output
+/// of all commands when the `--json` flag is given
+pub fn print_json<T: Serialize>(value: &T) -> Result<(), Error> {
+	println!("{}", serde_json::to_string_pretty(value)?);
+	Ok(())
+}
+
+/// Format the available and total space of a node's disk,
+/// e.g. `120.5 GiB/1.8 TiB (6.5%)`, or `?` if it is unknown
+pub fn disk_space_string(space: Option<DiskSpace>) -> String {
+	match space {
+		Some(space) if space.total > 0 => format!(
+			"{}/{} ({:.1}%)",
+			bytesize::ByteSize::b(space.available).to_string_as(true),
+			bytesize::ByteSize::b(space.total).to_string_as(true),
+			space.available as f64 * 100.0 / space.total as f64
+		),
+		_ => "?".to_string(),
+	}
+}
+
+pub fn format_node_stats(stats: &NodeStats) -> String {
+	let mut ret = String::new();
+	writeln!(
+		&mut ret,
+		"\nGarage version: {} [features: {}]",
+		stats.garage_version,
+		stats
+			.garage_features
+			.as_ref()
+			.map(|list| list.join(", "))
+			.unwrap_or_else(|| "(unknown)".into()),
+	)
+	.unwrap();
+	writeln!(&mut ret, "\nDatabase engine: {}", stats.db_engine).unwrap();
+
+	writeln!(&mut ret, "\nRing nodes & partition count:").unwrap();
+	for (n, c) in stats.ring_partitions.iter() {
+		writeln!(&mut ret, "  {:?} {}", n, c).unwrap();
+	}
+
+	for table in stats.tables.iter() {
+		writeln!(&mut ret, "\nTable stats for {}", table.name).unwrap();
+		if let Some(items) = table.items {
+			writeln!(&mut ret, "  number of items: {}", items).unwrap();
+		}
+		if let Some(size) = table.merkle_tree_size {
+			writeln!(&mut ret, "  Merkle tree size: {}", size).unwrap();
+		}
+		writeln!(
+			&mut ret,
+			"  Merkle updater todo queue length: {}",
+			table.merkle_todo_length
+		)
+		.unwrap();
+		writeln!(&mut ret, "  GC todo queue length: {}", table.gc_todo_length).unwrap();
+	}
+
+	writeln!(&mut ret, "\nBlock manager stats:").unwrap();
+	if let Some(rc_entries) = stats.block_rc_entries {
+		writeln!(
+			&mut ret,
+			"  number of RC entries (~= number of blocks): {}",
+			rc_entries
+		)
+		.unwrap();
+	}
+	if let Some(dedup) = &stats.block_dedup {
+		writeln!(
+			&mut ret,
+			"  referenced blocks: {}, references: {} (deduplication ratio: {:.2})",
+			dedup.blocks,
+			dedup.references,
+			dedup.ratio()
+		)
+		.unwrap();
+	}
+	writeln!(
+		&mut ret,
+		"  resync queue length: {}",
+		stats.resync_queue_length
+	)
+	.unwrap();
+	writeln!(
+		&mut ret,
+		"  blocks with resync errors: {}",
+		stats.resync_errors
+	)
+	.unwrap();
+
+	ret
+}
+
+pub fn print_cluster_stats(list: Vec<(Uuid, Result<NodeStats, String>)>) {
+	for (node, stats) in list {
+		println!("\n======================");
+		println!("Stats for node {:?}:", node);
+		match stats {
+			Ok(s) => println!("{}", format_node_stats(&s)),
+			Err(e) => println!("{}", e),
+		}
+	}
+	println!();
+}
+
+pub fn print_block_error_list(el: Vec<BlockResyncErrorInfo>) {
+	let now = now_msec();
+	let tf = timeago::Formatter::new();
+	let mut tf2 = timeago::Formatter::new();
+	tf2.ago("");
+
+	let mut table = vec!["Hash\tRC\tErrors\tLast error\tNext try".into()];
+	for e in el {
+		table.push(format!(
+			"{}\t{}\t{}\t{}\tin about {}",
+			hex::encode(e.hash.as_slice()),
+			e.refcount,
+			e.error_count,
+			tf.convert(Duration::from_millis(now.saturating_sub(e.last_try))),
+			tf2.convert(Duration::from_millis(e.next_try.saturating_sub(now)))
+		));
+	}
+	format_table(table);
+}
+
+pub fn print_block_info(info: BlockInfo) {
+	println!("Block hash: {}", hex::encode(info.hash.as_slice()));
+	println!("Refcount: {}", info.refcount);
+	println!();
+
+	let mut table = vec!["Version\tBucket\tKey\tDeleted".into()];
+	let mut nondeleted_count = 0;
+	for v in info.versions.iter() {
+		match v {
+			Ok(ver) => {
+				table.push(format!(
+					"{:?}\t{:?}\t{}\t{:?}",
+					ver.uuid,
+					ver.bucket_id,
+					ver.key,
+					ver.deleted.get()
+				));
+				if !ver.deleted.get() {
+					nondeleted_count += 1;
+				}
+			}
+			Err(vh) => {
+				table.push(format!("{:?}\t\t\tyes", vh));
+			}
+		}
+	}
+	format_table(table);
+
+	if info.refcount != nondeleted_count {
+		println!();
+		println!("Warning: refcount does not match number of non-deleted versions");
+	}
+}
+
+pub fn print_scrub_reports(reports: &[ScrubReportInfo]) {
+	if reports.is_empty() {
+		println!("No scrub has been run on this node yet.");
+		return;
+	}
+
+	for report in reports.iter() {
+		println!(
+			"Scrub started {}: {:?}",
+			msec_to_rfc3339(report.start_time),
+			report.status
+		);
+		if let Some(end_time) = report.end_time {
+			println!("  Ended: {}", msec_to_rfc3339(end_time));
+		}
+		println!(
+			"  Checked: {} blocks, {}",
+			report.blocks_checked,
+			bytesize::ByteSize::b(report.bytes_checked).to_string_as(true)
+		);
+		println!(
+			"  Corrupted: {} blocks ({} repaired, {} unrepairable)",
+			report.corrupted_blocks.len(),
+			report.count_corrupted(CorruptedBlockStatus::Repaired),
+			report.count_corrupted(CorruptedBlockStatus::Unrepairable)
+		);
+		if !report.corrupted_blocks.is_empty() {
+			let mut table = vec!["  Hash\tDetected\tStatus\tBucket\tKey".to_string()];
+			for block in report.corrupted_blocks.iter() {
+				let hash = hex::encode(block.hash.as_slice());
+				let detected = msec_to_rfc3339(block.detected);
+				if block.objects.is_empty() {
+					table.push(format!("  {}\t{}\t{:?}\t\t", hash, detected, block.status));
+				}
+				for (bucket_id, key) in block.objects.iter() {
+					table.push(format!(
+						"  {}\t{}\t{:?}\t{:?}\t{}",
+						hash, detected, block.status, bucket_id, key
+					));
+				}
+				if block.objects_truncated {
+					table.push(format!(
+						"  {}\t{}\t{:?}\t...\t(more objects not listed)",
+						hash, detected, block.status
+					));
+				}
+			}
+			format_table(table);
+		}
+		println!();
+	}
+}
+
+pub fn print_audit_log(entries: &[AuditEntry]) {
+	if entries.is_empty() {
+		println!("No matching entries in the audit log.");
+		return;
+	}
+
+	let mut table = vec!["Date\tNode\tActor\tOperation\tTarget\tParameters".to_string()];
+	for e in entries.iter() {
+		let parameters = e
+			.parameters
+			.iter()
+			.map(|(k, v)| format!("{}={}", k, v))
+			.collect::<Vec<_>>()
+			.join(" ");
+		table.push(format!(
+			"{}\t{:?}\t{}\t{}\t{}\t{}",
+			msec_to_rfc3339(e.timestamp),
+			e.node,
+			e.actor,
+			e.operation,
+			e.target,
+			parameters
+		));
+	}
+	format_table(table);
+}
@@ -59,6 +59,10 @@ struct Opt {
 	)]
 	pub config_file: PathBuf,
 
+	/// Output the result of commands as JSON documents
+	#[structopt(long = "json", global = true)]
+	pub json: bool,
+
 	#[structopt(subcommand)]
 	cmd: Command,
 }
@@ -139,6 +143,7 @@ async fn main() {
 
 	// Parse arguments and dispatch command line
 	let opt = Opt::from_clap(&Opt::clap().version(version.as_str()).get_matches());
+	let json = opt.json;
 
 	let res = match opt.cmd {
 		Command::Server => server::run_server(opt.config_file).await,
@@ -146,13 +151,17 @@ async fn main() {
 			repair::offline::offline_repair(opt.config_file, repair_opt).await
 		}
 		Command::Node(NodeOperation::NodeId(node_id_opt)) => {
-			node_id_command(opt.config_file, node_id_opt.quiet)
+			node_id_command(opt.config_file, node_id_opt.quiet, json)
 		}
 		_ => cli_command(opt).await,
 	};
 
 	if let Err(e) = res {
-		eprintln!("Error: {}", e);
+		if json {
+			println!("{}", serde_json::json!({ "error": e.to_string() }));
+		} else {
+			eprintln!("Error: {}", e);
+		}
 		std::process::exit(1);
 	}
 }
@@ -217,7 +226,15 @@ async fn cli_command(opt: Opt) -> Result<(), Error> {
 	let system_rpc_endpoint = netapp.endpoint::<SystemRpc, ()>(SYSTEM_RPC_PATH.into());
 	let admin_rpc_endpoint = netapp.endpoint::<AdminRpc, ()>(ADMIN_RPC_PATH.into());
 
-	match cli_command_dispatch(opt.cmd, &system_rpc_endpoint, &admin_rpc_endpoint, id).await {
+	match cli_command_dispatch(
+		opt.cmd,
+		&system_rpc_endpoint,
+		&admin_rpc_endpoint,
+		id,
+		opt.json,
+	)
+	.await
+	{
 		Err(HelperError::Internal(i)) => Err(Error::Message(format!("Internal error: {}", i))),
 		Err(HelperError::BadRequest(b)) => Err(Error::Message(b)),
 		Err(e) => Err(Error::Message(format!("{}", e))),
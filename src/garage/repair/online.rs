@@ -5,10 +5,12 @@ use async_trait::async_trait;
 use tokio::sync::watch;
 
 use garage_block::repair::ScrubWorkerCommand;
+use garage_block::tier::TieringWorker;
 use garage_model::garage::Garage;
 use garage_model::s3::block_ref_table::*;
 use garage_model::s3::object_table::*;
 use garage_model::s3::version_table::*;
+use garage_model::tiering::ObjectAgeTieringPolicy;
 use garage_table::*;
 use garage_util::background::*;
 use garage_util::error::Error;
@@ -45,6 +47,26 @@ pub async fn launch_online_repair(garage: Arc<Garage>, opt: RepairOpt) {
 					garage.block_manager.clone(),
 				));
 		}
+		RepairWhat::Rebalance => {
+			info!("Rebalancing the stored blocks among data directories");
+			garage
+				.background
+				.spawn_worker(garage_block::repair::RebalanceWorker::new(
+					garage.block_manager.clone(),
+				));
+		}
+		RepairWhat::Tiering => {
+			if !garage.block_manager.has_cold_tier() {
+				error!("No cold tier is configured on this node, not moving blocks");
+				return;
+			}
+			info!("Moving the blocks of old objects to the cold tier");
+			garage.background.spawn_worker(TieringWorker::new(
+				garage.block_manager.clone(),
+				Arc::new(ObjectAgeTieringPolicy(garage.clone())),
+				None,
+			));
+		}
 		RepairWhat::Scrub { cmd } => {
 			let cmd = match cmd {
 				ScrubCmd::Start => ScrubWorkerCommand::Start,
@@ -54,6 +76,8 @@ pub async fn launch_online_repair(garage: Arc<Garage>, opt: RepairOpt) {
 				ScrubCmd::SetTranquility { tranquility } => {
 					ScrubWorkerCommand::SetTranquility(tranquility)
 				}
+				// Scrub reports are returned directly by the admin RPC handler
+				ScrubCmd::Status => return,
 			};
 			info!("Sending command to scrub worker: {:?}", cmd);
 			garage.block_manager.send_scrub_command(cmd).await;
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use tokio::sync::watch;
 
@@ -7,6 +8,7 @@ use garage_util::config::*;
 use garage_util::error::Error;
 
 use garage_api::admin::api_server::AdminApiServer;
+use garage_api::rate_limit::RateLimiter;
 use garage_api::s3::api_server::S3ApiServer;
 use garage_model::garage::Garage;
 use garage_web::WebServer;
@@ -52,9 +54,13 @@ pub async fn run_server(config_file: PathBuf) -> Result<(), Error> {
 		error!("Garage was built without OTLP exporter, admin.trace_sink is ignored.");
 	}
 
+	info!("Create admin RPC handler...");
+	let admin_rpc = AdminRpcHandler::new(garage.clone());
+
 	info!("Initialize Admin API server and metrics collector...");
 	let admin_server = AdminApiServer::new(
 		garage.clone(),
+		admin_rpc,
 		#[cfg(feature = "metrics")]
 		metrics_exporter,
 	);
@@ -62,21 +68,24 @@ pub async fn run_server(config_file: PathBuf) -> Result<(), Error> {
 	info!("Launching internal Garage cluster communications...");
 	let run_system = tokio::spawn(garage.system.clone().run(watch_cancel.clone()));
 
-	info!("Create admin RPC handler...");
-	AdminRpcHandler::new(garage.clone());
-
 	// ---- Launch public-facing API servers ----
 
 	let mut servers = vec![];
 
+	// Rate limits of buckets are shared between the S3 API and the web server
+	let rate_limiter = Arc::new(RateLimiter::new());
+
 	if let Some(s3_bind_addr) = &config.s3_api.api_bind_addr {
 		info!("Initializing S3 API server...");
 		servers.push((
 			"S3 API",
 			tokio::spawn(S3ApiServer::run(
 				garage.clone(),
-				*s3_bind_addr,
+				s3_bind_addr.clone(),
+				config.s3_api.unix_socket_mode,
+				config.s3_api.client_addr.clone(),
 				config.s3_api.s3_region.clone(),
+				rate_limiter.clone(),
 				wait_from(watch_cancel.clone()),
 			)),
 		));
@@ -90,7 +99,9 @@ pub async fn run_server(config_file: PathBuf) -> Result<(), Error> {
 				"K2V API",
 				tokio::spawn(K2VApiServer::run(
 					garage.clone(),
-					config.k2v_api.as_ref().unwrap().api_bind_addr,
+					config.k2v_api.as_ref().unwrap().api_bind_addr.clone(),
+					config.k2v_api.as_ref().unwrap().unix_socket_mode,
+					config.k2v_api.as_ref().unwrap().client_addr.clone(),
 					config.s3_api.s3_region.clone(),
 					wait_from(watch_cancel.clone()),
 				)),
@@ -106,8 +117,11 @@ pub async fn run_server(config_file: PathBuf) -> Result<(), Error> {
 			"Web",
 			tokio::spawn(WebServer::run(
 				garage.clone(),
-				web_config.bind_addr,
+				web_config.bind_addr.clone(),
+				web_config.unix_socket_mode,
+				web_config.client_addr.clone(),
 				web_config.root_domain.clone(),
+				rate_limiter.clone(),
 				wait_from(watch_cancel.clone()),
 			)),
 		));
@@ -117,7 +131,12 @@ pub async fn run_server(config_file: PathBuf) -> Result<(), Error> {
 		info!("Launching Admin API server...");
 		servers.push((
 			"Admin",
-			tokio::spawn(admin_server.run(*admin_bind_addr, wait_from(watch_cancel.clone()))),
+			tokio::spawn(admin_server.run(
+				admin_bind_addr.clone(),
+				config.admin.unix_socket_mode,
+				config.admin.client_addr.clone(),
+				wait_from(watch_cancel.clone()),
+			)),
 		));
 	}
 
@@ -1,5 +1,35 @@
 use crate::common;
 use crate::common::ext::*;
+use aws_sdk_s3::types::ByteStream;
+use http::{Method, Request, Response, StatusCode};
+use hyper::{
+	body::{to_bytes, Body},
+	Client,
+};
+use serde_json::json;
+
+async fn admin_request(
+	garage: &common::garage::Instance,
+	method: Method,
+	path: &str,
+	token: &str,
+	body: Option<serde_json::Value>,
+) -> hyper::Result<Response<Body>> {
+	let req = Request::builder()
+		.method(method)
+		.uri(format!("http://127.0.0.1:{}{}", garage.admin_port, path))
+		.header("Authorization", format!("Bearer {}", token))
+		.body(
+			body.map(|b| Body::from(b.to_string()))
+				.unwrap_or_else(Body::empty),
+		)
+		.unwrap();
+	Client::new().request(req).await
+}
+
+async fn json_body(resp: &mut Response<Body>) -> serde_json::Value {
+	serde_json::from_slice(&to_bytes(resp.body_mut()).await.unwrap()).unwrap()
+}
 
 const BCKT_NAME: &str = "seau";
 
@@ -72,3 +102,1722 @@ async fn test_admin_bucket_perms() {
 
 	assert!(hb().await.is_err());
 }
+
+#[tokio::test]
+async fn test_admin_key_enabled_expiration() {
+	const BCKT_NAME: &str = "cle-expiree";
+	let ctx = common::context();
+
+	let key = ctx.garage.new_key("key_expiration");
+	let client = common::client::build_client_with_key(ctx.garage, &key);
+
+	ctx.garage
+		.command()
+		.args(["bucket", "create", BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not create bucket");
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--read", "--key", &key.id, BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	let hb = || client.head_bucket().bucket(BCKT_NAME).send();
+	let set_key = |args: &[&str]| {
+		ctx.garage
+			.command()
+			.args(["key", "set", &key.id])
+			.args(args)
+			.quiet()
+			.expect_success_status("Could not change key settings");
+	};
+
+	assert!(hb().await.is_ok());
+
+	set_key(&["--disable"]);
+	assert!(hb().await.is_err());
+
+	set_key(&["--enable"]);
+	assert!(hb().await.is_ok());
+
+	set_key(&["--expires-at", "2000-01-01"]);
+	assert!(hb().await.is_err());
+
+	set_key(&["--expires-at", "none"]);
+	assert!(hb().await.is_ok());
+
+	set_key(&["--expires-at", "2100-01-01T00:00:00Z"]);
+	assert!(hb().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_admin_key_allowed_ips() {
+	const BCKT_NAME: &str = "cle-filtree";
+	let ctx = common::context();
+
+	let key = ctx.garage.new_key("key_allowed_ips");
+	let client = common::client::build_client_with_key(ctx.garage, &key);
+
+	ctx.garage
+		.command()
+		.args(["bucket", "create", BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not create bucket");
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--read", "--key", &key.id, BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	let hb = || client.head_bucket().bucket(BCKT_NAME).send();
+	let set_key = |args: &[&str]| {
+		ctx.garage
+			.command()
+			.args(["key", "set", &key.id])
+			.args(args)
+			.quiet()
+			.expect_success_status("Could not change key settings");
+	};
+
+	assert!(hb().await.is_ok());
+
+	set_key(&["--allow-ip", "10.0.0.0/8", "--allow-ip", "fc00::/7"]);
+	assert!(hb().await.is_err());
+
+	set_key(&["--allow-ip", "127.0.0.0/8"]);
+	assert!(hb().await.is_ok());
+
+	set_key(&["--allow-ip", "192.0.2.1"]);
+	assert!(hb().await.is_err());
+
+	set_key(&["--allow-any-ip"]);
+	assert!(hb().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_admin_key_rotation() {
+	const BCKT_NAME: &str = "cle-tournante";
+	let ctx = common::context();
+
+	let key = ctx.garage.new_key("key_rotation");
+
+	ctx.garage
+		.command()
+		.args(["bucket", "create", BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not create bucket");
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--read", "--key", &key.id, BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	let rotate = |grace_period: &str| {
+		let output = ctx
+			.garage
+			.command()
+			.args(["key", "rotate", "--grace-period", grace_period, &key.id])
+			.expect_success_output("Could not rotate key");
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		let secret = stdout
+			.lines()
+			.find_map(|l| l.strip_prefix("Secret key: "))
+			.expect("No secret key in output")
+			.to_string();
+		common::garage::Key {
+			name: key.name.clone(),
+			id: key.id.clone(),
+			secret,
+		}
+	};
+	let hb = |key: &common::garage::Key| {
+		let client = common::client::build_client_with_key(ctx.garage, key);
+		async move { client.head_bucket().bucket(BCKT_NAME).send().await }
+	};
+
+	assert!(hb(&key).await.is_ok());
+
+	// Both secrets are valid during the grace period
+	let key2 = rotate("1h");
+	assert_ne!(key.secret, key2.secret);
+	assert!(hb(&key).await.is_ok());
+	assert!(hb(&key2).await.is_ok());
+
+	// Previous secrets are invalidated immediately
+	let key3 = rotate("0s");
+	assert!(hb(&key).await.is_err());
+	assert!(hb(&key2).await.is_err());
+	assert!(hb(&key3).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_admin_bucket_allow_prefix() {
+	let ctx = common::context();
+	let bucket = ctx.create_bucket("prefixes");
+
+	for k in ["private/secret", "public/a", "public/sub/b"] {
+		ctx.client
+			.put_object()
+			.bucket(&bucket)
+			.key(k)
+			.body(ByteStream::from_static(b"hello"))
+			.send()
+			.await
+			.unwrap();
+	}
+
+	let key = ctx.garage.new_key("key_prefixes");
+	let client = common::client::build_client_with_key(ctx.garage, &key);
+
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--read", "--write"])
+		.args(["--prefix", "public/", "--key", &key.id, &bucket])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	let get = |k: &'static str| client.get_object().bucket(&bucket).key(k).send();
+	assert!(get("public/a").await.is_ok());
+	assert!(get("private/secret").await.is_err());
+
+	let put = |k: &'static str| {
+		client
+			.put_object()
+			.bucket(&bucket)
+			.key(k)
+			.body(ByteStream::from_static(b"world"))
+			.send()
+	};
+	assert!(put("public/c").await.is_ok());
+	assert!(put("private/c").await.is_err());
+
+	let r = client
+		.list_objects_v2()
+		.bucket(&bucket)
+		.send()
+		.await
+		.unwrap();
+	let keys = r
+		.contents
+		.unwrap()
+		.into_iter()
+		.map(|o| o.key.unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(keys, ["public/a", "public/c", "public/sub/b"]);
+
+	let r = client
+		.list_objects_v2()
+		.bucket(&bucket)
+		.delimiter("/")
+		.send()
+		.await
+		.unwrap();
+	assert!(r.contents.is_none());
+	let prefixes = r
+		.common_prefixes
+		.unwrap()
+		.into_iter()
+		.map(|p| p.prefix.unwrap())
+		.collect::<Vec<_>>();
+	assert_eq!(prefixes, ["public/"]);
+
+	// Removing the only prefix removes all permissions of the key
+	ctx.garage
+		.command()
+		.args(["bucket", "deny", "--read", "--write"])
+		.args(["--prefix", "public/", "--key", &key.id, &bucket])
+		.quiet()
+		.expect_success_status("Could not deny key");
+	assert!(get("public/a").await.is_err());
+}
+
+#[tokio::test]
+async fn test_admin_bucket_allow_append_only() {
+	let ctx = common::context();
+	let bucket = ctx.create_bucket("append-only");
+
+	let key = ctx.garage.new_key("key_append_only");
+	let client = common::client::build_client_with_key(ctx.garage, &key);
+
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--create", "--key", &key.id, &bucket])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	let put = |k: &'static str| {
+		client
+			.put_object()
+			.bucket(&bucket)
+			.key(k)
+			.body(ByteStream::from_static(b"backup"))
+			.send()
+	};
+	assert!(put("backup-1").await.is_ok());
+	assert!(put("backup-2").await.is_ok());
+	// Existing objects cannot be overwritten or deleted
+	assert!(put("backup-1").await.is_err());
+	assert!(client
+		.delete_object()
+		.bucket(&bucket)
+		.key("backup-1")
+		.send()
+		.await
+		.is_err());
+	// Objects cannot be read or listed
+	assert!(client
+		.get_object()
+		.bucket(&bucket)
+		.key("backup-1")
+		.send()
+		.await
+		.is_err());
+	assert!(client
+		.list_objects_v2()
+		.bucket(&bucket)
+		.send()
+		.await
+		.is_err());
+
+	ctx.garage
+		.command()
+		.args(["bucket", "allow", "--list", "--key", &key.id, &bucket])
+		.quiet()
+		.expect_success_status("Could not allow key");
+	let r = client
+		.list_objects_v2()
+		.bucket(&bucket)
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(r.contents.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_admin_api_tokens() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+
+	// Names starting with `@` are reserved
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/token",
+		admin_token,
+		Some(json!({"name": "@admin_token", "scopes": ["status"]})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	// A token for a self-service portal, that can manage buckets only
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/token",
+		admin_token,
+		Some(json!({"name": "portal", "scopes": ["buckets", "status"]})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let info = json_body(&mut resp).await;
+	let token_id = info["id"].as_str().unwrap().to_string();
+	let portal_token = info["secretToken"].as_str().unwrap().to_string();
+	assert_eq!(info["scopes"], json!(["status", "buckets"]));
+
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/bucket",
+		&portal_token,
+		Some(json!({"globalAlias": "portail"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+
+	let resp = admin_request(ctx.garage, Method::GET, "/v0/status", &portal_token, None)
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+
+	// Out of scope operations are refused
+	for (method, path) in [
+		(Method::POST, "/v0/layout/apply"),
+		(Method::GET, "/v0/key"),
+		(Method::GET, "/v0/token"),
+	] {
+		let resp = admin_request(ctx.garage, method, path, &portal_token, Some(json!({})))
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+	}
+
+	// The secret is checked, not only the token ID
+	let resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/status",
+		&format!("{}.bad", token_id),
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+	// Deleted tokens can no longer be used
+	let resp = admin_request(
+		ctx.garage,
+		Method::DELETE,
+		&format!("/v0/token?id={}", token_id),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+	let resp = admin_request(ctx.garage, Method::GET, "/v0/status", &portal_token, None)
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_admin_audit_log() {
+	const BCKT_NAME: &str = "journal";
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+
+	// An operation through the CLI
+	ctx.garage
+		.command()
+		.args(["bucket", "create", BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not create bucket");
+	ctx.garage
+		.command()
+		.args([
+			"bucket",
+			"allow",
+			"--owner",
+			"--key",
+			&ctx.garage.key.id,
+			BCKT_NAME,
+		])
+		.quiet()
+		.expect_success_status("Could not allow key");
+
+	// An operation through the admin API
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/key",
+		admin_token,
+		Some(json!({"name": "cle-journal"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+
+	// An operation through the S3 API
+	ctx.client
+		.delete_bucket_website()
+		.bucket(BCKT_NAME)
+		.send()
+		.await
+		.unwrap();
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/audit?limit=1000",
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let log = json_body(&mut resp).await;
+	let find = |actor: &str, operation: &str, target: &str| {
+		log.as_array()
+			.unwrap()
+			.iter()
+			.find(|e| e["actor"] == actor && e["operation"] == operation && e["target"] == target)
+	};
+
+	let create = find("cli", "bucket create", BCKT_NAME).expect("CLI operation not logged");
+	let allow = find("cli", "bucket allow", BCKT_NAME).expect("CLI operation not logged");
+	assert_eq!(allow["parameters"]["owner"], "true");
+	assert_eq!(
+		allow["parameters"]["key_pattern"],
+		ctx.garage.key.id.as_str()
+	);
+	find("admin:@admin_token", "CreateKey", "cle-journal").expect("admin operation not logged");
+	let delete = find(
+		&format!("s3:{}", ctx.garage.key.id),
+		"DeleteBucketWebsite",
+		BCKT_NAME,
+	)
+	.expect("S3 operation not logged");
+	assert!(create["timestamp"].as_str().unwrap() <= delete["timestamp"].as_str().unwrap());
+
+	// Filters on the actor and the date
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!(
+			"/v0/audit?limit=1000&actor=s3&since={}",
+			delete["timestamp"].as_str().unwrap()
+		),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	let log = json_body(&mut resp).await;
+	assert!(!log.as_array().unwrap().is_empty());
+	assert!(log.as_array().unwrap().iter().all(|e| e["actor"]
+		.as_str()
+		.unwrap()
+		.starts_with("s3:")
+		&& e["timestamp"].as_str().unwrap() >= delete["timestamp"].as_str().unwrap()));
+
+	// Listing through the CLI
+	let output = ctx
+		.garage
+		.command()
+		.args(["audit", "list", "--actor", "cli", "--limit", "1000"])
+		.expect_success_output("Could not list audit log");
+	let output = String::from_utf8(output.stdout).unwrap();
+	assert!(output.contains("bucket create"));
+	// (the layout of the test instance is applied through the CLI)
+	assert!(output.contains("layout apply"));
+	assert!(!output.contains("DeleteBucketWebsite"));
+}
+
+#[tokio::test]
+async fn test_admin_maintenance() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+
+	let mut resp = admin_request(ctx.garage, Method::GET, "/v0/status", admin_token, None)
+		.await
+		.unwrap();
+	let node_id = json_body(&mut resp).await["node"]
+		.as_str()
+		.unwrap()
+		.to_string();
+
+	// Workers of the local node
+	let mut resp = admin_request(ctx.garage, Method::GET, "/v0/worker", admin_token, None)
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let workers = json_body(&mut resp).await;
+	assert_eq!(workers["error"], json!({}));
+	let workers = workers["success"][&node_id].as_array().unwrap();
+	assert!(workers.iter().any(|w| w["name"]
+		.as_str()
+		.unwrap()
+		.starts_with("Block resync worker")));
+
+	// Operations forwarded to a node designated by a prefix of its ID,
+	// or to all nodes of the layout
+	for (method, path, body) in [
+		(
+			Method::POST,
+			format!("/v0/worker/set?node={}", &node_id[..8]),
+			Some(json!({"resyncTranquility": 3})),
+		),
+		(
+			Method::POST,
+			"/v0/worker/set?node=self".to_string(),
+			Some(json!({"tieringTranquility": 1})),
+		),
+		(
+			Method::POST,
+			"/v0/repair?node=*".to_string(),
+			Some(json!({"repairType": "tables"})),
+		),
+		(
+			Method::POST,
+			"/v0/scrub?node=self".to_string(),
+			Some(json!({"command": {"setTranquility": 2}})),
+		),
+	] {
+		let mut resp = admin_request(ctx.garage, method, &path, admin_token, body)
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), StatusCode::OK, "{}", path);
+		let res = json_body(&mut resp).await;
+		assert_eq!(res["error"], json!({}), "{}", path);
+		assert!(res["success"][&node_id]["message"].is_string(), "{}", path);
+	}
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/stats?detailed=true",
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let stats = json_body(&mut resp).await;
+	assert!(stats["success"][&node_id]["freeform"]
+		.as_str()
+		.unwrap()
+		.contains("Garage version"));
+
+	let resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/worker?node=zzzz",
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	// The status scope allows reading workers, but not changing them
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/token",
+		admin_token,
+		Some(json!({"name": "monitoring", "scopes": ["status"]})),
+	)
+	.await
+	.unwrap();
+	let status_token = json_body(&mut resp).await["secretToken"]
+		.as_str()
+		.unwrap()
+		.to_string();
+
+	let resp = admin_request(ctx.garage, Method::GET, "/v0/worker", &status_token, None)
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/repair",
+		&status_token,
+		Some(json!({"repairType": "tables"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_admin_block_purge() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("purge");
+
+	// Large enough not to be inlined in the object table
+	let data = vec![42u8; 10_000];
+	let hash = hex::encode(garage_util::data::blake2sum(&data));
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("victime")
+		.body(ByteStream::from(data))
+		.send()
+		.await
+		.unwrap();
+
+	let mut resp = admin_request(ctx.garage, Method::GET, "/v0/status", admin_token, None)
+		.await
+		.unwrap();
+	let node_id = json_body(&mut resp).await["node"]
+		.as_str()
+		.unwrap()
+		.to_string();
+
+	// The block is known and referenced by the object
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/block?hash={}", &hash[..8]),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let info = json_body(&mut resp).await;
+	let info = &info["success"][&node_id];
+	assert_eq!(info["blockHash"], hash.as_str());
+	assert_eq!(info["refcount"], 1);
+	let versions = info["versions"].as_array().unwrap();
+	assert_eq!(versions.len(), 1);
+	assert_eq!(versions[0]["key"], "victime");
+	assert_eq!(versions[0]["deleted"], false);
+
+	// It has no resync error, so it cannot be retried
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/block/errors",
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let errors = json_body(&mut resp).await;
+	assert!(!errors["success"][&node_id]
+		.as_array()
+		.unwrap()
+		.iter()
+		.any(|e| e["blockHash"] == hash.as_str()));
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/block/retry",
+		admin_token,
+		Some(json!({ "blockHashes": [hash] })),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert!(json_body(&mut resp).await["error"][&node_id].is_string());
+
+	// Invalid hashes are rejected
+	let resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/block?hash={}%C3%A9", &hash[..7]),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	// The purge must be confirmed
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/block/purge",
+		admin_token,
+		Some(json!({ "blockHashes": [hash] })),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+	ctx.client
+		.get_object()
+		.bucket(&bucket)
+		.key("victime")
+		.send()
+		.await
+		.unwrap();
+
+	// Purging the block deletes the object
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/block/purge",
+		admin_token,
+		Some(json!({ "blockHashes": [hash], "confirm": true })),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(
+		json_body(&mut resp).await["message"],
+		"Purged 1 blocks, 1 versions, 1 objects"
+	);
+
+	ctx.client
+		.get_object()
+		.bucket(&bucket)
+		.key("victime")
+		.send()
+		.await
+		.unwrap_err();
+
+	// The purge must be confirmed on the command line
+	let status = ctx
+		.garage
+		.command()
+		.args(["block", "purge", &hash])
+		.quiet()
+		.status()
+		.unwrap();
+	assert!(!status.success());
+}
+
+#[tokio::test]
+async fn test_admin_object_info() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("inspection");
+
+	let data = vec![7u8; 10_000];
+	let hash = hex::encode(garage_util::data::blake2sum(&data));
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("objet")
+		.body(ByteStream::from(data))
+		.send()
+		.await
+		.unwrap();
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/object?bucket={}&key=objet", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let info = json_body(&mut resp).await;
+	assert_eq!(info["key"], "objet");
+	let versions = info["versions"].as_array().unwrap();
+	assert_eq!(versions.len(), 1);
+	assert_eq!(versions[0]["state"], "complete");
+	assert_eq!(versions[0]["size"], 10_000);
+	assert_eq!(versions[0]["versionDeleted"], false);
+	let blocks = versions[0]["blocks"].as_array().unwrap();
+	assert_eq!(blocks.len(), 1);
+	assert_eq!(blocks[0]["hash"], hash.as_str());
+	assert_eq!(blocks[0]["partNumber"], 1);
+	let replicas = blocks[0]["replicas"].as_array().unwrap();
+	assert_eq!(replicas.len(), 1);
+	assert_eq!(replicas[0]["stored"], true);
+	assert_eq!(replicas[0]["refcount"], 1);
+
+	let resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/object?bucket={}&key=absent", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["object", "info", &bucket, "objet"])
+		.expect_success_output("Could not get object info");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains(&hash));
+	assert!(stdout.contains("stored, refcount 1"));
+}
+
+#[tokio::test]
+async fn test_admin_layout_simulation() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("simulation");
+
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("data")
+		.body(ByteStream::from(vec![3u8; 10_000]))
+		.send()
+		.await
+		.unwrap();
+
+	let mut resp = admin_request(ctx.garage, Method::GET, "/v0/status", admin_token, None)
+		.await
+		.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let status = json_body(&mut resp).await;
+	let node = status["node"].as_str().unwrap().to_string();
+	let version = status["layout"]["version"].as_u64().unwrap();
+
+	// Stage the replacement of the test node by a new node,
+	// which takes all partitions
+	let new_node = hex::encode([1u8; 32]);
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/layout",
+		admin_token,
+		Some(json!({
+			&node: null,
+			&new_node: { "zone": "elsewhere", "capacity": 1, "tags": [] },
+		})),
+	)
+	.await
+	.unwrap();
+	assert!(resp.status().is_success());
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		"/v0/layout/simulate",
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let simulation = json_body(&mut resp).await;
+	assert_eq!(simulation["newVersion"], version + 1);
+	assert_eq!(simulation["zoneRedundant"], true);
+	assert_eq!(simulation["movedPartitions"], 256);
+	let nodes = simulation["nodes"].as_array().unwrap();
+	let removed = nodes.iter().find(|n| n["id"] == node.as_str()).unwrap();
+	assert_eq!(removed["partitionsBefore"], 256);
+	assert_eq!(removed["partitionsLost"], 256);
+	assert_eq!(removed["partitionsAfter"], 0);
+	let added = nodes.iter().find(|n| n["id"] == new_node.as_str()).unwrap();
+	assert_eq!(added["partitionsBefore"], 0);
+	assert_eq!(added["partitionsGained"], 256);
+	assert!(added["bytesGained"].as_u64().unwrap() > 0);
+	assert_eq!(added["bytesGained"], removed["bytesLost"]);
+	assert_eq!(simulation["bytesToTransfer"], added["bytesGained"]);
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["layout", "simulate"])
+		.expect_success_output("Could not simulate layout");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("Partitions that move: 256 of 256"));
+	assert!(stdout.contains("Estimated data to transfer"));
+
+	// The simulation does not change the layout
+	let resp = admin_request(
+		ctx.garage,
+		Method::POST,
+		"/v0/layout/revert",
+		admin_token,
+		Some(json!({ "version": version + 1 })),
+	)
+	.await
+	.unwrap();
+	assert!(resp.status().is_success());
+}
+
+#[tokio::test]
+async fn test_admin_bucket_erasure_coding() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("codage");
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/bucket?globalAlias={}", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	let info = json_body(&mut resp).await;
+	assert_eq!(info["erasureCoding"], "default");
+	let bucket_id = info["id"].as_str().unwrap().to_string();
+
+	// Erasure coding is not configured on the test cluster (replication factor 1)
+	let resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"erasureCoding": "enabled"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	let status = ctx
+		.garage
+		.command()
+		.args(["bucket", "set-erasure-coding", &bucket, "enabled"])
+		.quiet()
+		.status()
+		.unwrap();
+	assert!(!status.success());
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"erasureCoding": "disabled"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(json_body(&mut resp).await["erasureCoding"], "disabled");
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["bucket", "info", &bucket])
+		.expect_success_output("Could not get bucket info");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("Erasure coding: disabled"));
+
+	// Data is still stored and read back as usual
+	let data = vec![9u8; 12_345];
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("data")
+		.body(ByteStream::from(data.clone()))
+		.send()
+		.await
+		.unwrap();
+	let res = ctx
+		.client
+		.get_object()
+		.bucket(&bucket)
+		.key("data")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(res.body.collect().await.unwrap().into_bytes(), data);
+}
+
+async fn object_block_hashes(
+	garage: &common::garage::Instance,
+	bucket: &str,
+	key: &str,
+) -> Vec<String> {
+	let mut resp = admin_request(
+		garage,
+		Method::GET,
+		&format!("/v0/object?bucket={}&key={}", bucket, key),
+		common::garage::GARAGE_TEST_ADMIN_TOKEN,
+		None,
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	json_body(&mut resp).await["versions"][0]["blocks"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.map(|b| b["hash"].as_str().unwrap().to_string())
+		.collect()
+}
+
+fn find_block_file(dir: &std::path::Path, hash: &str) -> Option<std::path::PathBuf> {
+	for ent in std::fs::read_dir(dir).ok()?.flatten() {
+		let path = ent.path();
+		if path.is_dir() {
+			if let Some(p) = find_block_file(&path, hash) {
+				return Some(p);
+			}
+		} else if ent.file_name().to_string_lossy().starts_with(hash) {
+			return Some(path);
+		}
+	}
+	None
+}
+
+#[tokio::test]
+async fn test_admin_bucket_content_defined_chunking() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("dedup");
+
+	ctx.garage
+		.command()
+		.args(["bucket", "set-chunking", &bucket, "content-defined"])
+		.quiet()
+		.expect_success_status("Could not enable content-defined chunking");
+
+	// Pseudo-random data, so that chunk boundaries depend on the content
+	let mut x = 12345u32;
+	let data = (0..3_000_000)
+		.map(|_| {
+			x = x.wrapping_mul(1103515245).wrapping_add(12345);
+			(x >> 16) as u8
+		})
+		.collect::<Vec<_>>();
+	let mut shifted = vec![42u8];
+	shifted.extend_from_slice(&data);
+
+	for (key, body) in [("original", data.clone()), ("shifted", shifted.clone())] {
+		ctx.client
+			.put_object()
+			.bucket(&bucket)
+			.key(key)
+			.body(ByteStream::from(body))
+			.send()
+			.await
+			.unwrap();
+	}
+
+	// Inserting a byte at the beginning only changes the first block
+	let original = object_block_hashes(ctx.garage, &bucket, "original").await;
+	let shifted_blocks = object_block_hashes(ctx.garage, &bucket, "shifted").await;
+	assert!(original.len() > 3);
+	let common = shifted_blocks
+		.iter()
+		.filter(|h| original.contains(h))
+		.count();
+	assert!(common >= original.len() - 1);
+
+	let res = ctx
+		.client
+		.get_object()
+		.bucket(&bucket)
+		.key("shifted")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(res.body.collect().await.unwrap().into_bytes(), shifted);
+
+	// The setting is also available in the admin API
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/bucket?globalAlias={}", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	let info = json_body(&mut resp).await;
+	assert_eq!(info["contentDefinedChunking"], "enabled");
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", info["id"].as_str().unwrap()),
+		admin_token,
+		Some(json!({"contentDefinedChunking": "default"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(
+		json_body(&mut resp).await["contentDefinedChunking"],
+		"default"
+	);
+}
+
+#[tokio::test]
+async fn test_admin_bucket_compression() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("compression");
+
+	ctx.garage
+		.command()
+		.args(["bucket", "set-compression", &bucket, "none"])
+		.quiet()
+		.expect_success_status("Could not disable compression");
+
+	let status = ctx
+		.garage
+		.command()
+		.args(["bucket", "set-compression", &bucket, "1000"])
+		.quiet()
+		.status()
+		.unwrap();
+	assert!(!status.success());
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["bucket", "info", &bucket])
+		.expect_success_output("Could not get bucket info");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("Compression: none"));
+
+	// Highly compressible data is stored and read back as usual
+	let data = b"compression test ".repeat(20_000);
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("text")
+		.body(ByteStream::from(data.clone()))
+		.send()
+		.await
+		.unwrap();
+	let res = ctx
+		.client
+		.get_object()
+		.bucket(&bucket)
+		.key("text")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(res.body.collect().await.unwrap().into_bytes(), data);
+
+	// Its blocks are stored uncompressed
+	let blocks = object_block_hashes(ctx.garage, &bucket, "text").await;
+	assert!(!blocks.is_empty());
+	for hash in blocks.iter() {
+		let block_file = find_block_file(&ctx.garage.path.join("data"), hash).unwrap();
+		assert_eq!(block_file.file_name().unwrap().to_str().unwrap(), hash);
+	}
+
+	// The setting is also available in the admin API
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/bucket?globalAlias={}", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	let info = json_body(&mut resp).await;
+	assert_eq!(info["compression"], "none");
+	let bucket_id = info["id"].as_str().unwrap();
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"compression": 19})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(json_body(&mut resp).await["compression"], 19);
+
+	let resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"compression": 1000})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"compression": "default"})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(json_body(&mut resp).await["compression"], "default");
+}
+
+#[tokio::test]
+async fn test_admin_zero_rate_limit() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("zerolimit");
+
+	for args in [["--download-bytes", "0"], ["--read-requests", "0"]] {
+		let status = ctx
+			.garage
+			.command()
+			.args(["bucket", "set-rate-limits", &bucket])
+			.args(args)
+			.quiet()
+			.status()
+			.unwrap();
+		assert!(!status.success());
+	}
+
+	let mut resp = admin_request(
+		ctx.garage,
+		Method::GET,
+		&format!("/v0/bucket?globalAlias={}", bucket),
+		admin_token,
+		None,
+	)
+	.await
+	.unwrap();
+	let bucket_id = json_body(&mut resp).await["id"]
+		.as_str()
+		.unwrap()
+		.to_string();
+	let resp = admin_request(
+		ctx.garage,
+		Method::PUT,
+		&format!("/v0/bucket?id={}", bucket_id),
+		admin_token,
+		Some(json!({"rateLimits": {"uploadBytes": 0}})),
+	)
+	.await
+	.unwrap();
+	assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+	// Objects can still be written and read
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("data")
+		.body(ByteStream::from(vec![1u8; 10_000]))
+		.send()
+		.await
+		.unwrap();
+	ctx.client
+		.get_object()
+		.bucket(&bucket)
+		.key("data")
+		.send()
+		.await
+		.unwrap();
+}
+
+#[tokio::test]
+async fn test_post_object_rate_limit() {
+	use hmac::Mac;
+
+	let ctx = common::context();
+	let bucket = ctx.create_bucket("postlimit");
+	ctx.garage
+		.command()
+		.args(["bucket", "set-rate-limits", &bucket])
+		.args(["--write-requests", "1"])
+		.quiet()
+		.expect_success_status("Could not set bucket rate limits");
+
+	let post_object = |object: &str| {
+		let now = chrono::Utc::now();
+		let region = common::REGION;
+		let region = region.as_ref();
+		let credential = format!(
+			"{}/{}",
+			ctx.garage.key.id,
+			garage_api::signature::compute_scope(&now, region, "s3")
+		);
+		let date = now.format(garage_api::signature::LONG_DATETIME).to_string();
+		let policy = base64::encode(
+			json!({
+				"expiration": (now + chrono::Duration::hours(1)).to_rfc3339(),
+				"conditions": [
+					{"key": object},
+					{"x-amz-credential": credential},
+					{"x-amz-date": date},
+				],
+			})
+			.to_string(),
+		);
+		let mut signer =
+			garage_api::signature::signing_hmac(&now, &ctx.garage.key.secret, region, "s3")
+				.unwrap();
+		signer.update(policy.as_bytes());
+		let signature = hex::encode(signer.finalize().into_bytes());
+
+		let mut body = String::new();
+		for (name, value) in [
+			("key", object),
+			("x-amz-credential", &credential),
+			("x-amz-date", &date),
+			("policy", &policy),
+			("x-amz-signature", &signature),
+		] {
+			body += &format!(
+				"--BOUNDARY\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+				name, value
+			);
+		}
+		body += "--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"data\"\r\n\r\nhello\r\n--BOUNDARY--\r\n";
+
+		let req = Request::builder()
+			.method(Method::POST)
+			.uri(format!("{}{}", ctx.garage.s3_uri(), bucket))
+			.header("Content-Type", "multipart/form-data; boundary=BOUNDARY")
+			.body(Body::from(body))
+			.unwrap();
+		Client::new().request(req)
+	};
+
+	// Form uploads count as write requests on the bucket
+	let resp = post_object("a").await.unwrap();
+	assert!(resp.status().is_success());
+	let resp = post_object("b").await.unwrap();
+	assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_block_cache() {
+	// The cache is enabled on a dedicated instance, so that blocks
+	// of other tests are read from other nodes as usual
+	let garage = common::garage::Instance::dedicated(
+		"blockcache",
+		1,
+		r#"
+[block_cache]
+ram_size = "1M"
+disk_dir = "{path}/cache"
+disk_size = "8M"
+"#,
+	);
+	let client = common::client::build_client(&garage);
+	let bucket = "blockcache";
+	garage.create_bucket(bucket);
+
+	let data = (0..2_000_000u32)
+		.map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+		.collect::<Vec<_>>();
+	client
+		.put_object()
+		.bucket(bucket)
+		.key("hot")
+		.body(ByteStream::from(data.clone()))
+		.send()
+		.await
+		.unwrap();
+
+	// The first read fills the cache, the second one is served from it
+	for _ in 0..2 {
+		let res = client
+			.get_object()
+			.bucket(bucket)
+			.key("hot")
+			.send()
+			.await
+			.unwrap();
+		assert_eq!(res.body.collect().await.unwrap().into_bytes(), data);
+	}
+
+	let blocks = object_block_hashes(&garage, bucket, "hot").await;
+	assert!(blocks.len() >= 2);
+	for hash in blocks {
+		assert!(garage.path.join("cache").join(&hash).exists());
+	}
+}
+
+#[tokio::test]
+async fn test_cold_tier() {
+	let ctx = common::context();
+	let bucket = ctx.create_bucket("coldtier");
+
+	ctx.garage
+		.command()
+		.args(["bucket", "set-cold-tier", &bucket, "0"])
+		.quiet()
+		.expect_success_status("Could not set cold tier");
+	let output = ctx
+		.garage
+		.command()
+		.args(["bucket", "info", &bucket])
+		.expect_success_output("Could not get bucket info");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("Cold tier: after 0 days"));
+
+	let data = (0..1_500_000u32)
+		.map(|i| (i.wrapping_mul(2246822519) >> 11) as u8)
+		.collect::<Vec<_>>();
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("old")
+		.body(ByteStream::from(data.clone()))
+		.send()
+		.await
+		.unwrap();
+	let blocks = object_block_hashes(ctx.garage, &bucket, "old").await;
+	assert!(blocks.len() >= 2);
+
+	ctx.garage
+		.command()
+		.args(["repair", "--yes", "tiering"])
+		.quiet()
+		.expect_success_status("Could not launch tiering");
+
+	// Blocks are stored under <node id>/<prefix>/<hash>[.zst] in the cold tier
+	let cold_dir = ctx.garage.path.join("cold");
+	let in_cold_tier = |hash: &str| {
+		std::fs::read_dir(&cold_dir)
+			.into_iter()
+			.flatten()
+			.filter_map(|node_dir| node_dir.ok())
+			.any(|node_dir| {
+				let dir = node_dir.path().join(&hash[..2]);
+				dir.join(hash).exists() || dir.join(format!("{}.zst", hash)).exists()
+			})
+	};
+	for _ in 0..50 {
+		if blocks.iter().all(|h| in_cold_tier(h)) {
+			break;
+		}
+		tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	}
+	assert!(blocks.iter().all(|h| in_cold_tier(h)));
+
+	// Blocks in the cold tier are read transparently
+	let res = ctx
+		.client
+		.get_object()
+		.bucket(&bucket)
+		.key("old")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(res.body.collect().await.unwrap().into_bytes(), data);
+}
+
+#[tokio::test]
+async fn test_cold_tier_s3() {
+	// A dedicated instance uses a bucket of the shared instance as its cold tier
+	let ctx = common::context();
+	let cold_bucket = ctx.create_bucket("coldtiers3");
+	let garage = common::garage::Instance::dedicated(
+		"coldtiers3",
+		2,
+		&format!(
+			r#"
+[cold_tier.s3]
+endpoint = "{}"
+region = "garage-integ-test"
+bucket = "{}"
+access_key_id = "{}"
+secret_access_key = "{}"
+"#,
+			ctx.garage.s3_uri(),
+			cold_bucket,
+			ctx.garage.key.id,
+			ctx.garage.key.secret,
+		),
+	);
+	let client = common::client::build_client(&garage);
+	let bucket = "coldtiers3";
+	garage.create_bucket(bucket);
+	garage
+		.command()
+		.args(["bucket", "set-cold-tier", bucket, "0"])
+		.quiet()
+		.expect_success_status("Could not set cold tier");
+
+	let data = (0..1_500_000u32)
+		.map(|i| (i.wrapping_mul(3266489917) >> 9) as u8)
+		.collect::<Vec<_>>();
+	client
+		.put_object()
+		.bucket(bucket)
+		.key("old")
+		.body(ByteStream::from(data.clone()))
+		.send()
+		.await
+		.unwrap();
+	let blocks = object_block_hashes(&garage, bucket, "old").await;
+	assert!(blocks.len() >= 2);
+
+	garage
+		.command()
+		.args(["repair", "--yes", "tiering"])
+		.quiet()
+		.expect_success_status("Could not launch tiering");
+
+	// Blocks are stored as <node id>/<prefix>/<hash>[.zst] in the bucket
+	let mut cold_keys = vec![];
+	for _ in 0..50 {
+		cold_keys = ctx
+			.client
+			.list_objects_v2()
+			.bucket(&cold_bucket)
+			.send()
+			.await
+			.unwrap()
+			.contents
+			.unwrap_or_default()
+			.into_iter()
+			.filter_map(|o| o.key)
+			.collect::<Vec<_>>();
+		if cold_keys.len() >= blocks.len() {
+			break;
+		}
+		tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	}
+	for hash in blocks.iter() {
+		assert!(cold_keys.iter().any(|k| {
+			let name = k.trim_end_matches(".zst");
+			name.ends_with(&format!("/{}/{}", &hash[..2], hash))
+		}));
+		assert!(find_block_file(&garage.path.join("data"), hash).is_none());
+	}
+
+	// Blocks are read back from the bucket
+	let res = client
+		.get_object()
+		.bucket(bucket)
+		.key("old")
+		.send()
+		.await
+		.unwrap();
+	assert_eq!(res.body.collect().await.unwrap().into_bytes(), data);
+}
+
+#[tokio::test]
+async fn test_cluster_status_disk_space() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+
+	// Nodes advertise their free space with their status, every few seconds
+	let mut node_info = serde_json::Value::Null;
+	for _ in 0..30 {
+		let mut resp = admin_request(ctx.garage, Method::GET, "/v0/status", admin_token, None)
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), StatusCode::OK);
+		let status = json_body(&mut resp).await;
+		let node = status["node"].as_str().unwrap().to_string();
+		node_info = status["knownNodes"][&node].clone();
+		if !node_info["data_partition"].is_null() {
+			break;
+		}
+		tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+	}
+	for partition in ["data_partition", "metadata_partition"] {
+		let available = node_info[partition]["available"].as_u64().unwrap();
+		let total = node_info[partition]["total"].as_u64().unwrap();
+		assert!(available > 0 && available <= total);
+	}
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["status"])
+		.expect_success_output("Could not get cluster status");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("DataAvail"));
+}
+
+#[tokio::test]
+async fn test_metadata_min_free_space() {
+	// The metadata directory of this instance never has enough free space
+	let garage = common::garage::Instance::dedicated(
+		"minfreespace",
+		3,
+		r#"metadata_min_free_space = "99.99%""#,
+	);
+	let client = common::client::build_client(&garage);
+	let bucket = "minfreespace";
+	garage.create_bucket(bucket);
+
+	// Small objects are stored inline in the metadata
+	client
+		.put_object()
+		.bucket(bucket)
+		.key("small")
+		.body(ByteStream::from(b"small object".to_vec()))
+		.send()
+		.await
+		.unwrap();
+
+	// Larger objects need data blocks, which are refused
+	let res = client
+		.put_object()
+		.bucket(bucket)
+		.key("large")
+		.body(ByteStream::from(vec![42u8; 100_000]))
+		.send()
+		.await;
+	assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn test_scrub_report() {
+	let ctx = common::context();
+	let admin_token = common::garage::GARAGE_TEST_ADMIN_TOKEN;
+	let bucket = ctx.create_bucket("scrub");
+
+	let data = (0..20_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+	let hash = hex::encode(garage_util::data::blake2sum(&data));
+	// The block is used by the current version of "damaged", and by
+	// a previous version of "overwritten" that is not reported
+	for key in ["damaged", "overwritten"] {
+		ctx.client
+			.put_object()
+			.bucket(&bucket)
+			.key(key)
+			.body(ByteStream::from(data.clone()))
+			.send()
+			.await
+			.unwrap();
+	}
+	let healthy = (0..20_000u32).map(|i| (i % 241) as u8).collect::<Vec<_>>();
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("overwritten")
+		.body(ByteStream::from(healthy))
+		.send()
+		.await
+		.unwrap();
+
+	// Corrupt the block file in the data directory
+	let block_file = find_block_file(&ctx.garage.path.join("data"), &hash).unwrap();
+	std::fs::write(block_file, b"not the right data").unwrap();
+
+	// Restart the scrub, in case one was already running
+	for cmd in ["cancel", "start"] {
+		ctx.garage
+			.command()
+			.args(["repair", "--yes", "scrub", cmd])
+			.quiet()
+			.expect_success_status("Could not send scrub command");
+	}
+
+	let mut resp = admin_request(ctx.garage, Method::GET, "/v0/status", admin_token, None)
+		.await
+		.unwrap();
+	let node_id = json_body(&mut resp).await["node"]
+		.as_str()
+		.unwrap()
+		.to_string();
+
+	let mut report = serde_json::Value::Null;
+	for _ in 0..60 {
+		let mut resp = admin_request(ctx.garage, Method::GET, "/v0/scrub", admin_token, None)
+			.await
+			.unwrap();
+		assert_eq!(resp.status(), StatusCode::OK);
+		let reports = json_body(&mut resp).await;
+		report = reports["success"][&node_id]
+			.as_array()
+			.unwrap()
+			.last()
+			.cloned()
+			.unwrap_or_default();
+		if report["status"] == "completed" {
+			break;
+		}
+		tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+	}
+	assert_eq!(report["status"], "completed");
+	assert!(report["blocksChecked"].as_u64().unwrap() > 0);
+	assert!(report["bytesChecked"].as_u64().unwrap() > 0);
+
+	// The corrupted block is mapped to the object that uses it
+	let corrupted = report["corruptedBlocks"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.find(|b| b["blockHash"] == hash.as_str())
+		.unwrap();
+	let objects = corrupted["objects"].as_array().unwrap();
+	assert_eq!(objects.len(), 1);
+	assert_eq!(objects[0]["key"], "damaged");
+	assert_eq!(corrupted["objectsTruncated"], false);
+
+	let output = ctx
+		.garage
+		.command()
+		.args(["repair", "scrub", "status"])
+		.expect_success_output("Could not get scrub status");
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains(&hash));
+	assert!(stdout.contains("damaged"));
+}
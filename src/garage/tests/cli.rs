@@ -0,0 +1,79 @@
+use crate::common;
+use crate::common::ext::*;
+
+fn json_output(args: &[&str]) -> (bool, serde_json::Value) {
+	let ctx = common::context();
+	let output = ctx
+		.garage
+		.command()
+		.arg("--json")
+		.args(args)
+		.output()
+		.unwrap();
+	let value = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+		panic!(
+			"Invalid JSON output for {:?} ({}): {}",
+			args,
+			e,
+			String::from_utf8_lossy(&output.stdout)
+		)
+	});
+	(output.status.success(), value)
+}
+
+#[test]
+fn test_cli_json_output() {
+	let ctx = common::context();
+	let bucket = ctx.create_bucket("json-output");
+
+	let (ok, status) = json_output(&["status"]);
+	assert!(ok);
+	let node = ctx.garage.node_id();
+	let node = node.trim().split('@').next().unwrap();
+	assert!(status["known_nodes"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.any(|n| n["id"] == node));
+	assert!(status["layout"]["version"].as_u64().unwrap() >= 1);
+
+	let (ok, info) = json_output(&["bucket", "info", &bucket]);
+	assert!(ok);
+	let bucket_id = info["BucketInfo"]["bucket"]["id"]
+		.as_str()
+		.unwrap()
+		.to_string();
+	assert_eq!(bucket_id.len(), 64);
+
+	let (ok, list) = json_output(&["bucket", "list"]);
+	assert!(ok);
+	assert!(list["BucketList"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.any(|b| b["id"] == bucket_id.as_str()));
+
+	let (ok, stats) = json_output(&["stats", "--detailed"]);
+	assert!(ok);
+	let tables = stats["NodeStats"]["tables"].as_array().unwrap();
+	assert!(tables.iter().any(|t| t["name"] == "bucket_v2"));
+	assert!(stats["NodeStats"]["block_rc_entries"].is_u64());
+	assert!(stats["NodeStats"]["block_dedup"]["references"].is_u64());
+
+	let (ok, id) = json_output(&["node", "id", "-q"]);
+	assert!(ok);
+	assert!(id["Ok"].as_str().unwrap().starts_with(node));
+
+	// Errors are also given as JSON, with a non-zero exit code
+	let (ok, error) = json_output(&["bucket", "info", "does-not-exist"]);
+	assert!(!ok);
+	assert!(!error["error"].as_str().unwrap().is_empty());
+
+	// The human-readable output is unchanged
+	let output = ctx
+		.garage
+		.command()
+		.args(["bucket", "info", &bucket])
+		.expect_success_output("Could not get bucket info");
+	assert!(serde_json::from_slice::<serde_json::Value>(&output.stdout).is_err());
+}
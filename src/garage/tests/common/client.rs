@@ -1,15 +1,13 @@
 use aws_sdk_s3::{Client, Config, Credentials, Endpoint};
 
-use super::garage::Instance;
+use super::garage::{Instance, Key};
 
 pub fn build_client(instance: &Instance) -> Client {
-	let credentials = Credentials::new(
-		&instance.key.id,
-		&instance.key.secret,
-		None,
-		None,
-		"garage-integ-test",
-	);
+	build_client_with_key(instance, &instance.key)
+}
+
+pub fn build_client_with_key(instance: &Instance, key: &Key) -> Client {
+	let credentials = Credentials::new(&key.id, &key.secret, None, None, "garage-integ-test");
 	let endpoint = Endpoint::immutable(instance.s3_uri());
 
 	let config = Config::builder()
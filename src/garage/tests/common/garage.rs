@@ -11,6 +11,15 @@ pub const DEFAULT_PORT: u16 = 49995;
 static GARAGE_TEST_SECRET: &str =
 	"c3ea8cb80333d04e208d136698b1a01ae370d463f0d435ab2177510b3478bf44";
 
+pub static GARAGE_TEST_ADMIN_TOKEN: &str = "garage-integ-test-admin-token";
+
+/// Configuration of the instance shared by all tests
+/// that is not used by dedicated instances
+static SHARED_INSTANCE_CONFIG: &str = r#"
+[cold_tier]
+path = "{path}/cold"
+"#;
+
 #[derive(Debug, Default, Clone)]
 pub struct Key {
 	pub name: String,
@@ -25,13 +34,29 @@ pub struct Instance {
 	pub s3_port: u16,
 	pub k2v_port: u16,
 	pub web_port: u16,
+	pub admin_port: u16,
 }
 
 impl Instance {
 	fn new() -> Instance {
+		Self::start(None, SHARED_INSTANCE_CONFIG)
+	}
+
+	/// Start a garage instance distinct from the one shared by all tests,
+	/// with some additional configuration that is inserted before the sections
+	/// of the base configuration, so that it can set top-level options.
+	/// `index` must be different for each dedicated instance, its ports
+	/// being derived from it. The instance is terminated when dropped.
+	pub fn dedicated(name: &str, index: u16, extra_config: &str) -> Instance {
+		let mut instance = Self::start(Some((name, index)), extra_config);
+		instance.setup();
+		instance
+	}
+
+	fn start(dedicated: Option<(&str, u16)>, extra_config: &str) -> Instance {
 		use std::{env, fs};
 
-		let port = env::var("GARAGE_TEST_INTEGRATION_PORT")
+		let base_port = env::var("GARAGE_TEST_INTEGRATION_PORT")
 			.map(|value| value.parse().expect("Invalid port provided"))
 			.ok()
 			.unwrap_or(DEFAULT_PORT);
@@ -39,7 +64,15 @@ impl Instance {
 		let path = env::var("GARAGE_TEST_INTEGRATION_PATH")
 			.map(PathBuf::from)
 			.ok()
-			.unwrap_or_else(|| env::temp_dir().join(format!("garage-integ-test-{}", port)));
+			.unwrap_or_else(|| env::temp_dir().join(format!("garage-integ-test-{}", base_port)));
+
+		let (port, path) = match dedicated {
+			Some((name, index)) => (
+				base_port + 10 * index,
+				PathBuf::from(format!("{}-{}", path.display(), name)),
+			),
+			None => (base_port, path),
+		};
 
 		// Clean test runtime directory
 		if path.exists() {
@@ -58,6 +91,11 @@ rpc_bind_addr = "127.0.0.1:{rpc_port}"
 rpc_public_addr = "127.0.0.1:{rpc_port}"
 rpc_secret = "{secret}"
 
+{extra_config}
+
+[content_defined_chunking]
+default = false
+
 [s3_api]
 s3_region = "{region}"
 api_bind_addr = "127.0.0.1:{s3_port}"
@@ -73,9 +111,12 @@ index = "index.html"
 
 [admin]
 api_bind_addr = "127.0.0.1:{admin_port}"
+admin_token = "{admin_token}"
 "#,
 			path = path.display(),
+			extra_config = extra_config.replace("{path}", &path.display().to_string()),
 			secret = GARAGE_TEST_SECRET,
+			admin_token = GARAGE_TEST_ADMIN_TOKEN,
 			region = super::REGION,
 			s3_port = port,
 			k2v_port = port + 1,
@@ -105,6 +146,7 @@ api_bind_addr = "127.0.0.1:{admin_port}"
 			s3_port: port,
 			k2v_port: port + 1,
 			web_port: port + 3,
+			admin_port: port + 4,
 		}
 	}
 
@@ -161,6 +203,21 @@ api_bind_addr = "127.0.0.1:{admin_port}"
 			.expect("Could not build garage endpoint URI")
 	}
 
+	/// Create a bucket on which the key of the instance has all permissions
+	pub fn create_bucket(&self, name: &str) {
+		self.command()
+			.args(["bucket", "create", name])
+			.quiet()
+			.expect_success_status("Could not create bucket");
+		self.command()
+			.args(["bucket", "allow"])
+			.args(["--owner", "--read", "--write"])
+			.arg(name)
+			.args(["--key", &self.key.name])
+			.quiet()
+			.expect_success_status("Could not allow key for bucket");
+	}
+
 	pub fn k2v_uri(&self) -> http::Uri {
 		format!("http://127.0.0.1:{k2v_port}", k2v_port = self.k2v_port)
 			.parse()
@@ -197,6 +254,15 @@ api_bind_addr = "127.0.0.1:{admin_port}"
 	}
 }
 
+impl Drop for Instance {
+	fn drop(&mut self) {
+		// Only dedicated instances are dropped, the shared one
+		// is terminated by `terminate_instance`
+		let _ = self.process.kill();
+		let _ = self.process.wait();
+	}
+}
+
 static mut INSTANCE: MaybeUninit<Instance> = MaybeUninit::uninit();
 static INSTANCE_INIT: Once = Once::new();
 
@@ -1,5 +1,4 @@
 use aws_sdk_s3::{Client, Region};
-use ext::*;
 
 #[macro_use]
 pub mod macros;
@@ -11,7 +10,7 @@ pub mod garage;
 
 use custom_requester::CustomRequester;
 
-const REGION: Region = Region::from_static("garage-integ-test");
+pub const REGION: Region = Region::from_static("garage-integ-test");
 
 pub struct Context {
 	pub garage: &'static garage::Instance,
@@ -46,21 +45,7 @@ impl Context {
 	/// Return the created bucket full name.
 	pub fn create_bucket(&self, name: &str) -> String {
 		let bucket_name = name.to_owned();
-
-		self.garage
-			.command()
-			.args(["bucket", "create", &bucket_name])
-			.quiet()
-			.expect_success_status("Could not create bucket");
-		self.garage
-			.command()
-			.args(["bucket", "allow"])
-			.args(["--owner", "--read", "--write"])
-			.arg(&bucket_name)
-			.args(["--key", &self.garage.key.name])
-			.quiet()
-			.expect_success_status("Could not allow key for bucket");
-
+		self.garage.create_bucket(&bucket_name);
 		bucket_name
 	}
 }
@@ -3,6 +3,7 @@ mod common;
 
 mod admin;
 mod bucket;
+mod cli;
 
 mod s3;
 
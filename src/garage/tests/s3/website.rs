@@ -322,3 +322,45 @@ async fn test_website_s3_api() {
 		);
 	}
 }
+
+#[tokio::test]
+async fn test_website_bandwidth_limit() {
+	const BCKT_NAME: &str = "my-limited-website";
+	let ctx = common::context();
+	let bucket = ctx.create_bucket(BCKT_NAME);
+
+	ctx.client
+		.put_object()
+		.bucket(&bucket)
+		.key("index.html")
+		.body(ByteStream::from(vec![0u8; 300_000]))
+		.send()
+		.await
+		.unwrap();
+
+	ctx.garage
+		.command()
+		.args(["bucket", "website", "--allow", BCKT_NAME])
+		.quiet()
+		.expect_success_status("Could not allow website on bucket");
+	ctx.garage
+		.command()
+		.args(["bucket", "set-rate-limits", BCKT_NAME])
+		.args(["--download-bytes", "100000"])
+		.quiet()
+		.expect_success_status("Could not set bucket rate limits");
+
+	let req = Request::builder()
+		.method("GET")
+		.uri(format!("http://127.0.0.1:{}/", ctx.garage.web_port))
+		.header("Host", format!("{}.web.garage", BCKT_NAME))
+		.body(Body::empty())
+		.unwrap();
+
+	// The first 100k are allowed as a burst, the rest takes two seconds
+	let start = std::time::Instant::now();
+	let mut resp = Client::new().request(req).await.unwrap();
+	assert_eq!(resp.status(), StatusCode::OK);
+	assert_eq!(to_bytes(resp.body_mut()).await.unwrap().len(), 300_000);
+	assert!(start.elapsed() >= std::time::Duration::from_millis(1500));
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+
+use garage_table::crdt::*;
+use garage_table::*;
+use garage_util::data::*;
+use garage_util::time::now_msec;
+
+/// Part of the admin API that an admin token gives access to
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum AdminScope {
+	/// Read the status and layout of the cluster
+	Status,
+	/// Connect nodes and change the cluster layout
+	Layout,
+	/// Manage access keys
+	Keys,
+	/// Manage buckets, their aliases and the permissions of keys on them
+	Buckets,
+	/// Manage admin tokens
+	Tokens,
+	/// Read the metrics of the node
+	Metrics,
+	/// Read the audit log
+	Audit,
+	/// Configure background workers and launch repair operations
+	Maintenance,
+}
+
+impl AdminScope {
+	pub const ALL: [Self; 8] = [
+		Self::Status,
+		Self::Layout,
+		Self::Keys,
+		Self::Buckets,
+		Self::Tokens,
+		Self::Metrics,
+		Self::Audit,
+		Self::Maintenance,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Status => "status",
+			Self::Layout => "layout",
+			Self::Keys => "keys",
+			Self::Buckets => "buckets",
+			Self::Tokens => "tokens",
+			Self::Metrics => "metrics",
+			Self::Audit => "audit",
+			Self::Maintenance => "maintenance",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.iter().copied().find(|s| s.name() == name)
+	}
+}
+
+/// Set of scopes of an admin token (sorted, without duplicates)
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AdminScopes(pub Vec<AdminScope>);
+
+impl AdminScopes {
+	pub fn new(mut scopes: Vec<AdminScope>) -> Self {
+		scopes.sort();
+		scopes.dedup();
+		Self(scopes)
+	}
+
+	pub fn contains(&self, scope: AdminScope) -> bool {
+		self.0.contains(&scope)
+	}
+}
+
+impl AutoCrdt for AdminScopes {
+	const WARN_IF_DIFFERENT: bool = true;
+}
+
+/// An admin API token stored in the cluster
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct AdminApiToken {
+	/// The id of the token (immutable), used as sort key.
+	/// It is also the first part of the token given to clients.
+	pub token_id: String,
+
+	/// Internal state of the token
+	pub state: crdt::Deletable<AdminApiTokenParams>,
+}
+
+/// Configuration of an admin token
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct AdminApiTokenParams {
+	/// Hash of the secret part of the token (immutable),
+	/// the secret itself is never stored
+	pub secret_hash: Hash,
+
+	/// Creation date of the token, in milliseconds since UNIX Epoch
+	pub created: u64,
+
+	/// Name of the token
+	pub name: crdt::Lww<String>,
+
+	/// Parts of the admin API that the token gives access to
+	pub scopes: crdt::Lww<AdminScopes>,
+
+	/// Date after which the token can no longer be used,
+	/// in milliseconds since UNIX Epoch
+	pub expires_at: crdt::Lww<Option<u64>>,
+}
+
+impl Crdt for AdminApiTokenParams {
+	fn merge(&mut self, o: &Self) {
+		self.name.merge(&o.name);
+		self.scopes.merge(&o.scopes);
+		self.expires_at.merge(&o.expires_at);
+	}
+}
+
+impl AdminApiToken {
+	/// Create a new token with a random identifier and secret.
+	/// Returns the token entry and the full token to give to the client,
+	/// which is the only time the secret is known.
+	pub fn new(name: &str, scopes: AdminScopes, expires_at: Option<u64>) -> (Self, String) {
+		let token_id = format!("GT{}", hex::encode(&rand::random::<[u8; 12]>()[..]));
+		let secret = hex::encode(&rand::random::<[u8; 32]>()[..]);
+		let token = Self {
+			token_id: token_id.clone(),
+			state: crdt::Deletable::present(AdminApiTokenParams {
+				secret_hash: sha256sum(secret.as_bytes()),
+				created: now_msec(),
+				name: crdt::Lww::new(name.to_string()),
+				scopes: crdt::Lww::new(scopes),
+				expires_at: crdt::Lww::new(expires_at),
+			}),
+		};
+		(token, format!("{}.{}", token_id, secret))
+	}
+
+	/// Split a token given by a client into its identifier and secret parts
+	pub fn parse(token: &str) -> Option<(&str, &str)> {
+		token.split_once('.')
+	}
+
+	pub fn params(&self) -> Option<&AdminApiTokenParams> {
+		self.state.as_option()
+	}
+
+	pub fn params_mut(&mut self) -> Option<&mut AdminApiTokenParams> {
+		self.state.as_option_mut()
+	}
+
+	pub fn is_deleted(&self) -> bool {
+		self.state.is_deleted()
+	}
+
+	/// Check the secret given by a client, returns the scopes of the token
+	/// if the secret is correct and the token is still valid
+	pub fn check_secret(&self, secret: &str) -> Option<&AdminScopes> {
+		let p = self.params()?;
+		if p.secret_hash != sha256sum(secret.as_bytes()) {
+			return None;
+		}
+		if matches!(p.expires_at.get(), Some(t) if *t <= now_msec()) {
+			return None;
+		}
+		Some(p.scopes.get())
+	}
+}
+
+impl Entry<EmptyKey, String> for AdminApiToken {
+	fn partition_key(&self) -> &EmptyKey {
+		&EmptyKey
+	}
+	fn sort_key(&self) -> &String {
+		&self.token_id
+	}
+}
+
+impl Crdt for AdminApiToken {
+	fn merge(&mut self, other: &Self) {
+		self.state.merge(&other.state);
+	}
+}
+
+pub struct AdminApiTokenTable;
+
+impl TableSchema for AdminApiTokenTable {
+	const TABLE_NAME: &'static str = "admin_token";
+
+	type P = EmptyKey;
+	type S = String;
+	type E = AdminApiToken;
+	type Filter = DeletedFilter;
+
+	fn matches_filter(entry: &Self::E, filter: &Self::Filter) -> bool {
+		filter.apply(entry.is_deleted())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_admin_token_secret() {
+		let scopes = AdminScopes::new(vec![AdminScope::Buckets, AdminScope::Status]);
+		let (token, full) = AdminApiToken::new("portal", scopes.clone(), None);
+
+		let (id, secret) = AdminApiToken::parse(&full).unwrap();
+		assert_eq!(id, token.token_id);
+		assert_eq!(token.check_secret(secret), Some(&scopes));
+		assert_eq!(token.check_secret("wrong"), None);
+
+		let (expired, full) = AdminApiToken::new("old", scopes, Some(now_msec() - 1000));
+		let (_, secret) = AdminApiToken::parse(&full).unwrap();
+		assert_eq!(expired.check_secret(secret), None);
+	}
+}
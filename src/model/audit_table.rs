@@ -0,0 +1,311 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use garage_table::crdt::*;
+use garage_table::replication::TableFullReplication;
+use garage_table::*;
+use garage_util::background::*;
+use garage_util::data::*;
+use garage_util::error::Error;
+use garage_util::time::now_msec;
+
+/// Interval between two removals of the entries older than the retention period
+const AUDIT_TRIM_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Number of entries removed at once
+const AUDIT_TRIM_BATCH_SIZE: usize = 1000;
+
+/// Who performed an operation recorded in the audit log
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum AuditActor {
+	/// The `garage` command line interface, which is authenticated
+	/// by the RPC secret of the cluster
+	Cli,
+	/// The admin API, with the name of the admin token that was used
+	AdminApi(String),
+	/// The S3 API, with the ID of the access key that was used
+	S3Key(String),
+}
+
+impl AuditActor {
+	/// Name of the kind of actor, that can be used to filter entries
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Self::Cli => "cli",
+			Self::AdminApi(_) => "admin",
+			Self::S3Key(_) => "s3",
+		}
+	}
+
+	/// Check if this actor is designated by a filter, which is either the
+	/// kind of actor (e.g. `s3`) or its full name (e.g. `s3:GK...`)
+	pub fn matches(&self, filter: &str) -> bool {
+		filter == self.kind() || filter == self.to_string()
+	}
+}
+
+impl fmt::Display for AuditActor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Cli => write!(f, "cli"),
+			Self::AdminApi(token) => write!(f, "admin:{}", token),
+			Self::S3Key(key_id) => write!(f, "s3:{}", key_id),
+		}
+	}
+}
+
+/// An entry of the audit log. Entries are never modified once written.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+	/// Sort key, made of the timestamp in hexadecimal followed by random
+	/// bytes, so that entries are sorted by date
+	pub id: String,
+
+	/// Date of the operation, in milliseconds since UNIX Epoch
+	pub timestamp: u64,
+	/// Node that handled the operation
+	pub node: Uuid,
+	/// Who performed the operation
+	pub actor: AuditActor,
+	/// Name of the operation (e.g. `CreateBucket`)
+	pub operation: String,
+	/// Object of the operation, such as a bucket or key identifier
+	pub target: String,
+	/// Parameters of the operation
+	pub parameters: Vec<(String, String)>,
+	/// Whether the entry has been removed because it is older
+	/// than the retention period
+	#[serde(default)]
+	pub deleted: bool,
+}
+
+impl AuditEntry {
+	pub fn new(node: Uuid, actor: AuditActor, operation: &str, target: &str) -> Self {
+		let timestamp = now_msec();
+		Self {
+			id: format!(
+				"{}{}",
+				Self::sort_key_prefix(timestamp),
+				hex::encode(&rand::random::<[u8; 8]>()[..])
+			),
+			timestamp,
+			node,
+			actor,
+			operation: operation.to_string(),
+			target: target.to_string(),
+			parameters: vec![],
+			deleted: false,
+		}
+	}
+
+	pub fn with_param(mut self, name: &str, value: impl ToString) -> Self {
+		self.parameters.push((name.to_string(), value.to_string()));
+		self
+	}
+
+	/// Sort key from which to start listing entries written after a given date
+	pub fn sort_key_prefix(timestamp: u64) -> String {
+		format!("{:016x}", timestamp)
+	}
+}
+
+impl Entry<EmptyKey, String> for AuditEntry {
+	fn partition_key(&self) -> &EmptyKey {
+		&EmptyKey
+	}
+	fn sort_key(&self) -> &String {
+		&self.id
+	}
+	fn is_tombstone(&self) -> bool {
+		self.deleted
+	}
+}
+
+impl Crdt for AuditEntry {
+	fn merge(&mut self, other: &Self) {
+		// Entries are immutable once written, until they are deleted
+		if other.deleted && !self.deleted {
+			*self = other.clone();
+		}
+	}
+}
+
+/// Filter on audit log entries
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+	/// Only return entries written before this date
+	pub until: Option<u64>,
+	/// Only return entries of actors matching this filter (see `AuditActor::matches`)
+	pub actor: Option<String>,
+}
+
+pub struct AuditTable;
+
+impl TableSchema for AuditTable {
+	const TABLE_NAME: &'static str = "audit";
+
+	type P = EmptyKey;
+	type S = String;
+	type E = AuditEntry;
+	type Filter = AuditFilter;
+
+	fn matches_filter(entry: &Self::E, filter: &Self::Filter) -> bool {
+		!entry.deleted
+			&& filter.until.map(|t| entry.timestamp <= t).unwrap_or(true)
+			&& filter
+				.actor
+				.as_ref()
+				.map(|a| entry.actor.matches(a))
+				.unwrap_or(true)
+	}
+
+	fn is_past_filter_range(
+		entry: &Self::E,
+		filter: &Self::Filter,
+		enumeration_order: EnumerationOrder,
+	) -> bool {
+		// Entries are sorted by date
+		enumeration_order == EnumerationOrder::Forward
+			&& filter.until.map(|t| entry.timestamp > t).unwrap_or(false)
+	}
+}
+
+/// Worker that removes the entries of the audit log
+/// that are older than the retention period
+pub struct AuditTrimWorker {
+	audit_table: Arc<Table<AuditTable, TableFullReplication>>,
+	retention: Duration,
+	next_run: Instant,
+	trimmed: usize,
+}
+
+impl AuditTrimWorker {
+	pub fn new(
+		audit_table: Arc<Table<AuditTable, TableFullReplication>>,
+		retention: Duration,
+	) -> Self {
+		Self {
+			audit_table,
+			retention,
+			next_run: Instant::now(),
+			trimmed: 0,
+		}
+	}
+}
+
+#[async_trait]
+impl Worker for AuditTrimWorker {
+	fn name(&self) -> String {
+		"Audit log trim worker".into()
+	}
+
+	fn info(&self) -> Option<String> {
+		Some(format!("{} entries removed", self.trimmed))
+	}
+
+	async fn work(&mut self, _must_exit: &mut watch::Receiver<bool>) -> Result<WorkerState, Error> {
+		if Instant::now() < self.next_run {
+			return Ok(WorkerState::Idle);
+		}
+
+		let filter = AuditFilter {
+			until: Some(now_msec().saturating_sub(self.retention.as_millis() as u64)),
+			actor: None,
+		};
+		let entries = self
+			.audit_table
+			.get_range(
+				&EmptyKey,
+				None,
+				Some(filter),
+				AUDIT_TRIM_BATCH_SIZE,
+				EnumerationOrder::Forward,
+			)
+			.await?;
+		if entries.is_empty() {
+			self.next_run = Instant::now() + AUDIT_TRIM_INTERVAL;
+			return Ok(WorkerState::Idle);
+		}
+
+		let entries = entries
+			.into_iter()
+			.map(|e| AuditEntry {
+				parameters: vec![],
+				deleted: true,
+				..e
+			})
+			.collect::<Vec<_>>();
+		self.audit_table.insert_many(&entries).await?;
+		self.trimmed += entries.len();
+		Ok(WorkerState::Busy)
+	}
+
+	async fn wait_for_work(&mut self, _must_exit: &watch::Receiver<bool>) -> WorkerState {
+		tokio::time::sleep_until(self.next_run.into()).await;
+		WorkerState::Busy
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_audit_filter() {
+		let node = Uuid::from([0u8; 32]);
+		let entry = AuditEntry::new(node, AuditActor::S3Key("GK123".into()), "DeleteBucket", "b");
+		assert!(entry
+			.id
+			.starts_with(&AuditEntry::sort_key_prefix(entry.timestamp)));
+		assert!(entry.id > AuditEntry::sort_key_prefix(entry.timestamp - 1));
+
+		let filter = |until: Option<u64>, actor: Option<&str>| AuditFilter {
+			until,
+			actor: actor.map(str::to_string),
+		};
+		assert!(AuditTable::matches_filter(&entry, &filter(None, None)));
+		assert!(AuditTable::matches_filter(
+			&entry,
+			&filter(None, Some("s3"))
+		));
+		assert!(AuditTable::matches_filter(
+			&entry,
+			&filter(None, Some("s3:GK123"))
+		));
+		assert!(!AuditTable::matches_filter(
+			&entry,
+			&filter(None, Some("s3:GK456"))
+		));
+		assert!(!AuditTable::matches_filter(
+			&entry,
+			&filter(None, Some("cli"))
+		));
+		assert!(!AuditTable::matches_filter(
+			&entry,
+			&filter(Some(entry.timestamp - 1), None)
+		));
+		assert!(AuditTable::is_past_filter_range(
+			&entry,
+			&filter(Some(entry.timestamp - 1), None),
+			EnumerationOrder::Forward
+		));
+		assert!(!AuditTable::is_past_filter_range(
+			&entry,
+			&filter(Some(entry.timestamp), None),
+			EnumerationOrder::Forward
+		));
+
+		let mut deleted = entry.clone();
+		deleted.merge(&AuditEntry {
+			deleted: true,
+			..entry.clone()
+		});
+		assert!(deleted.is_tombstone());
+		assert!(!AuditTable::matches_filter(&deleted, &filter(None, None)));
+	}
+}
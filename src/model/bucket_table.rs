@@ -6,6 +6,7 @@ use garage_util::data::*;
 use garage_util::time::*;
 
 use crate::permission::BucketKeyPerm;
+use crate::rate_limits::RateLimits;
 
 /// A bucket is a collection of objects
 ///
@@ -47,6 +48,45 @@ pub struct BucketParams {
 	/// Bucket quotas
 	#[serde(default)]
 	pub quotas: crdt::Lww<BucketQuotas>,
+	/// Rate limits applied to all requests made on this bucket
+	#[serde(default)]
+	pub rate_limits: crdt::Lww<RateLimits>,
+	/// Whether data blocks of this bucket are erasure coded
+	/// (None = use the default of the cluster)
+	#[serde(default)]
+	pub erasure_coding: crdt::Lww<Option<bool>>,
+	/// Whether data uploaded to this bucket is split using content-defined chunking
+	/// (None = use the default of the cluster)
+	#[serde(default)]
+	pub content_defined_chunking: crdt::Lww<Option<bool>>,
+	/// Compression of the data blocks of this bucket
+	/// (None = use the compression level of the cluster)
+	#[serde(default)]
+	pub compression: crdt::Lww<Option<BucketCompression>>,
+	/// Number of days after which the data blocks of objects of this bucket
+	/// are moved to the cold tier (None = never)
+	#[serde(default)]
+	pub cold_tier_after_days: crdt::Lww<Option<u64>>,
+}
+
+/// Compression of the data blocks of a bucket, overriding the level of the cluster
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BucketCompression {
+	/// Blocks are stored uncompressed
+	Off,
+	/// Blocks are compressed with the given zstd level
+	Level(i32),
+}
+
+impl BucketCompression {
+	/// Compression with the given zstd level, if it is a level supported by zstd
+	pub fn level(level: i32) -> Option<Self> {
+		if zstd::compression_level_range().contains(&level) {
+			Some(Self::Level(level))
+		} else {
+			None
+		}
+	}
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -88,6 +128,11 @@ impl BucketParams {
 			website_config: crdt::Lww::new(None),
 			cors_config: crdt::Lww::new(None),
 			quotas: crdt::Lww::new(BucketQuotas::default()),
+			rate_limits: crdt::Lww::new(RateLimits::default()),
+			erasure_coding: crdt::Lww::new(None),
+			content_defined_chunking: crdt::Lww::new(None),
+			compression: crdt::Lww::new(None),
+			cold_tier_after_days: crdt::Lww::new(None),
 		}
 	}
 }
@@ -103,6 +148,12 @@ impl Crdt for BucketParams {
 		self.website_config.merge(&o.website_config);
 		self.cors_config.merge(&o.cors_config);
 		self.quotas.merge(&o.quotas);
+		self.rate_limits.merge(&o.rate_limits);
+		self.erasure_coding.merge(&o.erasure_coding);
+		self.content_defined_chunking
+			.merge(&o.content_defined_chunking);
+		self.compression.merge(&o.compression);
+		self.cold_tier_after_days.merge(&o.cold_tier_after_days);
 	}
 }
 
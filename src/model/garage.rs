@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use netapp::NetworkKey;
 
@@ -10,7 +11,9 @@ use garage_util::error::*;
 
 use garage_rpc::system::System;
 
+use garage_block::erasure::BlockStorageMode;
 use garage_block::manager::*;
+use garage_block::tier::TieringWorker;
 use garage_table::replication::ReplicationMode;
 use garage_table::replication::TableFullReplication;
 use garage_table::replication::TableShardedReplication;
@@ -20,15 +23,21 @@ use crate::s3::block_ref_table::*;
 use crate::s3::object_table::*;
 use crate::s3::version_table::*;
 
+use crate::admin_token_table::*;
+use crate::audit_table::*;
 use crate::bucket_alias_table::*;
 use crate::bucket_table::*;
 use crate::helper;
 use crate::index_counter::*;
 use crate::key_table::*;
+use crate::tiering::ObjectAgeTieringPolicy;
 
 #[cfg(feature = "k2v")]
 use crate::k2v::{item_table::*, poll::*, rpc::*};
 
+/// Delay between two passes of the worker that moves blocks to the cold tier
+const TIERING_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
 /// An entire Garage full of data
 pub struct Garage {
 	/// The parsed configuration Garage is running
@@ -49,6 +58,10 @@ pub struct Garage {
 	pub bucket_alias_table: Arc<Table<BucketAliasTable, TableFullReplication>>,
 	/// Table containing api keys
 	pub key_table: Arc<Table<KeyTable, TableFullReplication>>,
+	/// Table containing admin API tokens
+	pub admin_token_table: Arc<Table<AdminApiTokenTable, TableFullReplication>>,
+	/// Table containing the audit log of administrative operations
+	pub audit_table: Arc<Table<AuditTable, TableFullReplication>>,
 
 	/// Table containing S3 objects
 	pub object_table: Arc<Table<ObjectTable, TableShardedReplication>>,
@@ -63,6 +76,20 @@ pub struct Garage {
 	pub k2v: GarageK2V,
 }
 
+/// How uploaded data is split in blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunking {
+	/// Blocks of the given size (except for the last one)
+	FixedSize(usize),
+	/// Blocks cut at positions determined by the content of the data,
+	/// so that identical data in different objects gives identical blocks
+	ContentDefined {
+		min_size: usize,
+		avg_size: usize,
+		max_size: usize,
+	},
+}
+
 #[cfg(feature = "k2v")]
 pub struct GarageK2V {
 	/// Table containing K2V items
@@ -79,8 +106,10 @@ impl Garage {
 		// Create meta dir and data dir if they don't exist already
 		std::fs::create_dir_all(&config.metadata_dir)
 			.ok_or_message("Unable to create Garage metadata directory")?;
-		std::fs::create_dir_all(&config.data_dir)
-			.ok_or_message("Unable to create Garage data directory")?;
+		for data_dir in config.data_dir.paths() {
+			std::fs::create_dir_all(data_dir)
+				.ok_or_message("Unable to create Garage data directory")?;
+		}
 
 		info!("Opening database...");
 		let mut db_path = config.metadata_dir.clone();
@@ -96,7 +125,10 @@ impl Garage {
 					.flush_every_ms(Some(config.sled_flush_every_ms))
 					.open()
 					.expect("Unable to open sled DB");
-				db::sled_adapter::SledDb::init(db)
+				db::sled_adapter::SledDb::init_with_fsync(
+					db,
+					config.metadata_fsync.unwrap_or(false),
+				)
 			}
 			#[cfg(not(feature = "sled"))]
 			"sled" => return Err(Error::Message("sled db not available in this build".into())),
@@ -107,6 +139,11 @@ impl Garage {
 				info!("Opening Sqlite database at: {}", db_path.display());
 				let db = db::sqlite_adapter::rusqlite::Connection::open(db_path)
 					.expect("Unable to open sqlite DB");
+				// Sqlite syncs all transactions to disk by default (synchronous = FULL)
+				if config.metadata_fsync == Some(false) {
+					db.pragma_update(None, "synchronous", &"OFF")
+						.expect("Unable to set sqlite synchronous mode");
+				}
 				db::sqlite_adapter::SqliteDb::init(db)
 			}
 			#[cfg(not(feature = "sqlite"))]
@@ -128,9 +165,12 @@ impl Garage {
 				env_builder.max_dbs(100);
 				env_builder.max_readers(500);
 				env_builder.map_size(map_size);
-				unsafe {
-					env_builder.flag(heed::flags::Flags::MdbNoSync);
-					env_builder.flag(heed::flags::Flags::MdbNoMetaSync);
+				// LMDB is not synced to disk after each transaction unless metadata_fsync is set
+				if !config.metadata_fsync.unwrap_or(false) {
+					unsafe {
+						env_builder.flag(heed::flags::Flags::MdbNoSync);
+						env_builder.flag(heed::flags::Flags::MdbNoMetaSync);
+					}
 				}
 				let db = env_builder.open(&db_path).expect("Unable to open LMDB DB");
 				db::lmdb_adapter::LmdbDb::init(db)
@@ -163,6 +203,38 @@ impl Garage {
 		let replication_mode = ReplicationMode::parse(&config.replication_mode)
 			.expect("Invalid replication_mode in config file.");
 
+		if let Some(ec) = &config.erasure_coding {
+			let replication_factor = replication_mode.replication_factor();
+			let parity_shards = ec.parity_shards(replication_factor);
+			// Writes wait for all nodes but parity_shards - 1 of them: with a
+			// single parity shard, no node could be unavailable when writing
+			if ec.data_shards == 0
+				|| parity_shards < 2
+				|| ec.data_shards + parity_shards > replication_factor
+			{
+				return Err(Error::Message(format!(
+					"Invalid erasure coding configuration: there must be at least one data shard and two parity shards, and at most {} shards in total (the replication factor)",
+					replication_factor
+				)));
+			}
+		}
+
+		if let Some(cdc) = &config.content_defined_chunking {
+			// Bounds of the FastCDC algorithm, and chunks must not be stored inline
+			let (min_size, avg_size, max_size) = cdc.sizes(config.block_size);
+			if !(INLINE_THRESHOLD..=1 << 20).contains(&min_size)
+				|| !(256..=4 << 20).contains(&avg_size)
+				|| !(1024..=16 << 20).contains(&max_size)
+				|| min_size > avg_size
+				|| avg_size > max_size
+			{
+				return Err(Error::Message(format!(
+					"Invalid content-defined chunking configuration: sizes must satisfy {} <= min_size <= avg_size <= max_size, with min_size <= 1MiB, 256 <= avg_size <= 4MiB and 1KiB <= max_size <= 16MiB",
+					INLINE_THRESHOLD
+				)));
+			}
+		}
+
 		info!("Initialize membership management system...");
 		let system = System::new(
 			network_key,
@@ -191,13 +263,7 @@ impl Garage {
 		};
 
 		info!("Initialize block manager...");
-		let block_manager = BlockManager::new(
-			&db,
-			config.data_dir.clone(),
-			config.compression_level,
-			data_rep_param,
-			system.clone(),
-		);
+		let block_manager = BlockManager::new(&db, &config, data_rep_param, system.clone())?;
 
 		// ---- admin tables ----
 		info!("Initialize bucket_table...");
@@ -211,7 +277,16 @@ impl Garage {
 			&db,
 		);
 		info!("Initialize key_table_table...");
-		let key_table = Table::new(KeyTable, control_rep_param, system.clone(), &db);
+		let key_table = Table::new(KeyTable, control_rep_param.clone(), system.clone(), &db);
+		info!("Initialize admin_token_table...");
+		let admin_token_table = Table::new(
+			AdminApiTokenTable,
+			control_rep_param.clone(),
+			system.clone(),
+			&db,
+		);
+		info!("Initialize audit_table...");
+		let audit_table = Table::new(AuditTable, control_rep_param, system.clone(), &db);
 
 		// ---- S3 tables ----
 		info!("Initialize block_ref_table...");
@@ -256,7 +331,7 @@ impl Garage {
 		let k2v = GarageK2V::new(system.clone(), &db, meta_rep_param);
 
 		// -- done --
-		Ok(Arc::new(Self {
+		let garage = Arc::new(Self {
 			config,
 			db,
 			background,
@@ -265,13 +340,33 @@ impl Garage {
 			bucket_table,
 			bucket_alias_table,
 			key_table,
+			admin_token_table,
+			audit_table,
 			object_table,
 			object_counter_table,
 			version_table,
 			block_ref_table,
 			#[cfg(feature = "k2v")]
 			k2v,
-		}))
+		});
+
+		if let Some(days) = garage.config.admin.audit_retention_days {
+			garage.background.spawn_worker(AuditTrimWorker::new(
+				garage.audit_table.clone(),
+				Duration::from_secs(days * 24 * 3600),
+			));
+		}
+
+		if garage.block_manager.has_cold_tier() {
+			info!("Launching block tiering worker...");
+			garage.background.spawn_worker(TieringWorker::new(
+				garage.block_manager.clone(),
+				Arc::new(ObjectAgeTieringPolicy(garage.clone())),
+				Some(TIERING_INTERVAL),
+			));
+		}
+
+		Ok(garage)
 	}
 
 	pub fn bucket_helper(&self) -> helper::bucket::BucketHelper {
@@ -281,6 +376,88 @@ impl Garage {
 	pub fn key_helper(&self) -> helper::key::KeyHelper {
 		helper::key::KeyHelper(self)
 	}
+
+	pub fn object_helper(&self) -> helper::object::ObjectHelper<'_> {
+		helper::object::ObjectHelper(self)
+	}
+
+	/// How the data blocks of a bucket are stored
+	pub fn block_storage_mode(&self, bucket: &Bucket) -> BlockStorageMode {
+		let ec = match &self.config.erasure_coding {
+			Some(ec) => ec,
+			None => return BlockStorageMode::Replicated,
+		};
+		let enabled = bucket
+			.state
+			.as_option()
+			.and_then(|p| *p.erasure_coding.get())
+			.unwrap_or(ec.default);
+		if enabled {
+			BlockStorageMode::ErasureCoded {
+				data_shards: ec.data_shards,
+				parity_shards: ec.parity_shards(self.block_manager.replication.replication_factor),
+			}
+		} else {
+			BlockStorageMode::Replicated
+		}
+	}
+
+	/// Zstd compression level of the data blocks of a bucket
+	pub fn compression_level(&self, bucket: &Bucket) -> Option<i32> {
+		match bucket.state.as_option().and_then(|p| *p.compression.get()) {
+			Some(BucketCompression::Off) => None,
+			Some(BucketCompression::Level(level)) => Some(level),
+			None => self.config.compression_level,
+		}
+	}
+
+	/// Parameters for storing the data blocks of a bucket
+	pub fn put_block_params(&self, bucket: &Bucket) -> PutBlockParams {
+		PutBlockParams {
+			storage_mode: self.block_storage_mode(bucket),
+			compression_level: self.compression_level(bucket),
+			bucket_id: bucket.id,
+		}
+	}
+
+	/// How data uploaded to a bucket is split in blocks
+	pub fn chunking(&self, bucket: &Bucket) -> Chunking {
+		let cdc = match &self.config.content_defined_chunking {
+			Some(cdc) => cdc,
+			None => return Chunking::FixedSize(self.config.block_size),
+		};
+		let enabled = bucket
+			.state
+			.as_option()
+			.and_then(|p| *p.content_defined_chunking.get())
+			.unwrap_or(cdc.default);
+		if enabled {
+			let (min_size, avg_size, max_size) = cdc.sizes(self.config.block_size);
+			Chunking::ContentDefined {
+				min_size,
+				avg_size,
+				max_size,
+			}
+		} else {
+			Chunking::FixedSize(self.config.block_size)
+		}
+	}
+
+	/// Start an audit log entry for an operation handled by this node
+	pub fn audit_entry(&self, actor: AuditActor, operation: &str, target: &str) -> AuditEntry {
+		AuditEntry::new(self.system.id, actor, operation, target)
+	}
+
+	/// Write an entry to the audit log. The operation has already been
+	/// performed at this point, so failures are logged but not returned.
+	pub async fn record_audit(&self, entry: AuditEntry) {
+		if let Err(e) = self.audit_table.insert(&entry).await {
+			error!(
+				"Could not write audit log entry for {} on {}: {}",
+				entry.operation, entry.target, e
+			);
+		}
+	}
 }
 
 #[cfg(feature = "k2v")]
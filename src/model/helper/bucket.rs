@@ -420,7 +420,7 @@ impl<'a> BucketHelper<'a> {
 		// ---- timestamp-ensured causality barrier ----
 
 		if let Some(bstate) = bucket.state.as_option_mut() {
-			bstate.authorized_keys = Map::put_mutator(key_id.clone(), perm);
+			bstate.authorized_keys = Map::put_mutator(key_id.clone(), perm.clone());
 			self.0.bucket_table.insert(&bucket).await?;
 		}
 
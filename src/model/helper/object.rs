@@ -0,0 +1,121 @@
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use garage_block::manager::BlockReplicaStatus;
+use garage_table::util::*;
+use garage_util::data::*;
+
+use crate::garage::Garage;
+use crate::helper::error::*;
+use crate::s3::object_table::*;
+use crate::s3::version_table::*;
+
+/// Number of blocks whose replicas are queried at the same time
+const REPLICA_STATUS_CONCURRENCY: usize = 16;
+
+/// Everything that is known about an object, used to understand
+/// why it cannot be read
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectInfo {
+	pub bucket_id: Uuid,
+	pub key: String,
+	/// Versions of the object, oldest first
+	pub versions: Vec<ObjectVersionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectVersionInfo {
+	/// The version as stored in the object table
+	pub object_version: ObjectVersion,
+	/// The version as stored in the version table, for versions that have
+	/// data blocks (None if it could not be found)
+	pub version: Option<Version>,
+	/// Data blocks of the version, in the order of the object
+	pub blocks: Vec<ObjectBlockInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectBlockInfo {
+	pub part_number: u64,
+	pub offset: u64,
+	pub hash: Hash,
+	pub size: u64,
+	/// State of the block on each of the nodes that should store it
+	pub replicas: Vec<BlockReplicaStatus>,
+}
+
+pub struct ObjectHelper<'a>(pub(crate) &'a Garage);
+
+impl<'a> ObjectHelper<'a> {
+	/// Gather the versions of an object, their data blocks and the state
+	/// of these blocks on the nodes that should store them.
+	/// Returns None if the object does not exist.
+	pub async fn get_object_info(
+		&self,
+		bucket_id: Uuid,
+		key: &str,
+	) -> Result<Option<ObjectInfo>, Error> {
+		let object = match self
+			.0
+			.object_table
+			.get(&bucket_id, &key.to_string())
+			.await?
+		{
+			Some(o) => o,
+			None => return Ok(None),
+		};
+
+		let mut versions = vec![];
+		for ov in object.versions().iter() {
+			let has_blocks = matches!(
+				ov.state,
+				ObjectVersionState::Uploading(_)
+					| ObjectVersionState::Complete(ObjectVersionData::FirstBlock(_, _))
+			);
+			let version = match has_blocks {
+				true => self.0.version_table.get(&ov.uuid, &EmptyKey).await?,
+				false => None,
+			};
+
+			let blocks = match &version {
+				Some(v) => {
+					let blocks = v
+						.blocks
+						.items()
+						.iter()
+						.map(|(bk, vb)| (bk.part_number, bk.offset, vb.hash, vb.size))
+						.collect::<Vec<_>>();
+					stream::iter(blocks)
+						.map(|(part_number, offset, hash, size)| {
+							let block_manager = self.0.block_manager.clone();
+							async move {
+								Ok::<_, Error>(ObjectBlockInfo {
+									part_number,
+									offset,
+									hash,
+									size,
+									replicas: block_manager.rpc_get_replica_status(&hash).await?,
+								})
+							}
+						})
+						.buffered(REPLICA_STATUS_CONCURRENCY)
+						.try_collect()
+						.await?
+				}
+				None => vec![],
+			};
+
+			versions.push(ObjectVersionInfo {
+				object_version: ov.clone(),
+				version,
+				blocks,
+			});
+		}
+
+		Ok(Some(ObjectInfo {
+			bucket_id,
+			key: key.to_string(),
+			versions,
+		}))
+	}
+}
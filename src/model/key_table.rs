@@ -1,10 +1,16 @@
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Serialize};
 
 use garage_table::crdt::*;
 use garage_table::*;
 use garage_util::data::*;
+use garage_util::net::canonical_ip;
+use garage_util::time::{increment_logical_clock, now_msec};
 
-use crate::permission::BucketKeyPerm;
+use crate::permission::{BucketCapability, BucketKeyPerm};
+use crate::rate_limits::RateLimits;
 
 use crate::prev::v051::key_table as old;
 
@@ -21,15 +27,30 @@ pub struct Key {
 /// Configuration for a key
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct KeyParams {
-	/// The secret_key associated (immutable)
+	/// The secret_key the key was created with (immutable),
+	/// its validity period may be overriden by an entry in `secrets`
 	pub secret_key: String,
 
+	/// All secret keys generated for this key, with their validity period
+	#[serde(default)]
+	pub secrets: crdt::LwwMap<String, KeySecret>,
+
 	/// Name for the key
 	pub name: crdt::Lww<String>,
 
 	/// Flag to allow users having this key to create buckets
 	pub allow_create_bucket: crdt::Lww<bool>,
 
+	/// Flag to enable or disable the key, a disabled key
+	/// cannot be used to make any request
+	#[serde(default = "default_enabled")]
+	pub enabled: crdt::Lww<bool>,
+
+	/// Date after which the key can no longer be used,
+	/// in milliseconds since UNIX Epoch
+	#[serde(default)]
+	pub expires_at: crdt::Lww<Option<u64>>,
+
 	/// If the key is present: it gives some permissions,
 	/// a map of bucket IDs (uuids) to permissions.
 	/// Otherwise no permissions are granted to key
@@ -38,26 +59,193 @@ pub struct KeyParams {
 	/// A key can have a local view of buckets names it is
 	/// the only one to see, this is the namespace for these aliases
 	pub local_aliases: crdt::LwwMap<String, Option<Uuid>>,
+
+	/// Rate limits applied to all requests made with this key
+	#[serde(default)]
+	pub rate_limits: crdt::Lww<RateLimits>,
+
+	/// Networks from which requests can be made with this key
+	#[serde(default)]
+	pub allowed_ips: crdt::Lww<IpAllowlist>,
+}
+
+/// List of networks from which a key can be used,
+/// if it is empty the key can be used from anywhere
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IpAllowlist(pub Vec<IpNetwork>);
+
+impl IpAllowlist {
+	/// Returns true if requests from the given address are allowed.
+	/// If the address of the client is not known, requests are only
+	/// allowed if there are no restrictions. IPv4-mapped IPv6 addresses,
+	/// which is how IPv4 clients appear on a dual-stack socket, are
+	/// matched as IPv4 addresses.
+	pub fn allows(&self, ip: Option<IpAddr>) -> bool {
+		match ip {
+			_ if self.0.is_empty() => true,
+			Some(ip) => {
+				let ip = canonical_ip(ip);
+				self.0.iter().any(|net| net.contains(ip))
+			}
+			None => false,
+		}
+	}
+}
+
+impl AutoCrdt for IpAllowlist {
+	const WARN_IF_DIFFERENT: bool = true;
+}
+
+/// Validity period of a secret key
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct KeySecret {
+	/// Creation date of the secret, in milliseconds since UNIX Epoch
+	/// (0 if unknown, for keys created by older versions of Garage)
+	pub created: u64,
+	/// Date after which the secret can no longer be used
+	pub expires_at: Option<u64>,
+}
+
+impl KeySecret {
+	/// Returns true if the secret can be used at the given time
+	pub fn is_valid(&self, now: u64) -> bool {
+		!matches!(self.expires_at, Some(t) if t <= now)
+	}
+}
+
+impl AutoCrdt for KeySecret {
+	const WARN_IF_DIFFERENT: bool = true;
 }
 
 impl KeyParams {
 	fn new(secret_key: &str, name: &str) -> Self {
+		let mut secrets = crdt::LwwMap::new();
+		secrets.update_in_place(
+			secret_key.to_string(),
+			KeySecret {
+				created: now_msec(),
+				expires_at: None,
+			},
+		);
 		KeyParams {
 			secret_key: secret_key.to_string(),
+			secrets,
 			name: crdt::Lww::new(name.to_string()),
 			allow_create_bucket: crdt::Lww::new(false),
+			enabled: crdt::Lww::new(true),
+			expires_at: crdt::Lww::new(None),
 			authorized_buckets: crdt::Map::new(),
 			local_aliases: crdt::LwwMap::new(),
+			rate_limits: crdt::Lww::new(RateLimits::default()),
+			allowed_ips: crdt::Lww::new(IpAllowlist::default()),
+		}
+	}
+
+	/// Returns true if the key is expired at the given time
+	pub fn is_expired(&self, now: u64) -> bool {
+		matches!(self.expires_at.get(), Some(t) if *t <= now)
+	}
+
+	/// Returns true if the key is enabled and not expired
+	pub fn is_active(&self) -> bool {
+		*self.enabled.get() && !self.is_expired(now_msec())
+	}
+
+	/// List all secrets of the key, including the initial secret key,
+	/// from the oldest to the most recent one
+	pub fn all_secrets(&self) -> Vec<(&str, KeySecret)> {
+		let mut ret = self
+			.secrets
+			.items()
+			.iter()
+			.map(|(k, _, v)| (k.as_str(), *v))
+			.collect::<Vec<_>>();
+		if self.secrets.get(&self.secret_key).is_none() {
+			ret.push((
+				self.secret_key.as_str(),
+				KeySecret {
+					created: 0,
+					expires_at: None,
+				},
+			));
 		}
+		ret.sort_by_key(|(_, v)| v.created);
+		ret
 	}
+
+	/// List the secrets that can be used to sign requests at the given time
+	pub fn valid_secrets(&self, now: u64) -> Vec<&str> {
+		self.all_secrets()
+			.into_iter()
+			.filter(|(_, v)| v.is_valid(now))
+			.map(|(k, _)| k)
+			.collect()
+	}
+
+	/// The most recent valid secret, which is the one that should be given to users
+	pub fn current_secret(&self) -> &str {
+		self.valid_secrets(now_msec())
+			.last()
+			.copied()
+			.unwrap_or(&self.secret_key)
+	}
+
+	/// Generate a new secret for this key, and make all the secrets that are
+	/// currently valid expire at `previous_expire_at` at the latest.
+	/// Returns the new secret.
+	pub fn rotate_secret(&mut self, expires_at: Option<u64>, previous_expire_at: u64) -> String {
+		let now = now_msec();
+		let to_expire = self
+			.all_secrets()
+			.into_iter()
+			.filter(|(_, v)| v.is_valid(now))
+			.filter(|(_, v)| !matches!(v.expires_at, Some(t) if t <= previous_expire_at))
+			.map(|(k, v)| (k.to_string(), v))
+			.collect::<Vec<_>>();
+		for (k, v) in to_expire {
+			self.secrets.update_in_place(
+				k,
+				KeySecret {
+					expires_at: Some(previous_expire_at),
+					..v
+				},
+			);
+		}
+
+		// Make sure the new secret is strictly the most recent one
+		let last_created = self
+			.all_secrets()
+			.iter()
+			.map(|(_, v)| v.created)
+			.max()
+			.unwrap_or(0);
+		let new_secret = hex::encode(&rand::random::<[u8; 32]>()[..]);
+		self.secrets.update_in_place(
+			new_secret.clone(),
+			KeySecret {
+				created: increment_logical_clock(last_created),
+				expires_at,
+			},
+		);
+		new_secret
+	}
+}
+
+fn default_enabled() -> crdt::Lww<bool> {
+	crdt::Lww::raw(0, true)
 }
 
 impl Crdt for KeyParams {
 	fn merge(&mut self, o: &Self) {
 		self.name.merge(&o.name);
+		self.secrets.merge(&o.secrets);
 		self.allow_create_bucket.merge(&o.allow_create_bucket);
+		self.enabled.merge(&o.enabled);
+		self.expires_at.merge(&o.expires_at);
 		self.authorized_buckets.merge(&o.authorized_buckets);
 		self.local_aliases.merge(&o.local_aliases);
+		self.rate_limits.merge(&o.rate_limits);
+		self.allowed_ips.merge(&o.allowed_ips);
 	}
 }
 
@@ -111,19 +299,16 @@ impl Key {
 			.unwrap_or(BucketKeyPerm::NO_PERMISSIONS)
 	}
 
-	/// Check if `Key` is allowed to read in bucket
-	pub fn allow_read(&self, bucket: &Uuid) -> bool {
-		self.bucket_permissions(bucket).allow_read
-	}
-
-	/// Check if `Key` is allowed to write in bucket
-	pub fn allow_write(&self, bucket: &Uuid) -> bool {
-		self.bucket_permissions(bucket).allow_write
+	/// Check if `Key` can be used from the given client address
+	pub fn allows_client_ip(&self, ip: Option<IpAddr>) -> bool {
+		self.params()
+			.map(|p| p.allowed_ips.get().allows(ip))
+			.unwrap_or(false)
 	}
 
-	/// Check if `Key` is owner of bucket
-	pub fn allow_owner(&self, bucket: &Uuid) -> bool {
-		self.bucket_permissions(bucket).allow_owner
+	/// Check if `Key` has the given capability on the bucket
+	pub fn allows(&self, bucket: &Uuid, cap: BucketCapability) -> bool {
+		self.bucket_permissions(bucket).allows(cap)
 	}
 }
 
@@ -186,10 +371,15 @@ impl TableSchema for KeyTable {
 			// garage/migrate.rs
 			crdt::Deletable::Present(KeyParams {
 				secret_key: old_k.secret_key,
+				secrets: crdt::LwwMap::new(),
 				name,
 				allow_create_bucket: crdt::Lww::new(false),
+				enabled: crdt::Lww::new(true),
+				expires_at: crdt::Lww::new(None),
 				authorized_buckets: crdt::Map::new(),
 				local_aliases: crdt::LwwMap::new(),
+				rate_limits: crdt::Lww::new(RateLimits::default()),
+				allowed_ips: crdt::Lww::new(IpAllowlist::default()),
 			})
 		};
 		Some(Key {
@@ -198,3 +388,69 @@ impl TableSchema for KeyTable {
 		})
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_rotate_secret() {
+		let mut key = Key::new("test");
+		let params = key.params_mut().unwrap();
+		let initial = params.secret_key.clone();
+		let now = now_msec();
+		assert_eq!(params.valid_secrets(now), vec![initial.as_str()]);
+
+		// Old secret stays valid during the grace period
+		let second = params.rotate_secret(None, now + 3_600_000);
+		assert_eq!(
+			params.valid_secrets(now),
+			vec![initial.as_str(), second.as_str()]
+		);
+		assert_eq!(params.current_secret(), second);
+		assert_eq!(params.valid_secrets(now + 3_600_000), vec![second.as_str()]);
+
+		// Immediate rotation invalidates all previous secrets
+		let third = params.rotate_secret(None, now);
+		assert_eq!(params.valid_secrets(now), vec![third.as_str()]);
+		assert_eq!(params.current_secret(), third);
+	}
+
+	#[test]
+	fn test_secret_of_old_key() {
+		let mut key = Key::new("test");
+		let params = key.params_mut().unwrap();
+		// Keys created by older versions have no entry in `secrets`
+		params.secrets = crdt::LwwMap::new();
+		let initial = params.secret_key.clone();
+		assert_eq!(params.current_secret(), initial);
+
+		let now = now_msec();
+		let new = params.rotate_secret(None, now);
+		assert_eq!(params.valid_secrets(now), vec![new.as_str()]);
+		assert_eq!(params.all_secrets().len(), 2);
+	}
+
+	#[test]
+	fn test_ip_allowlist() {
+		let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+		let any = IpAllowlist::default();
+		assert!(any.allows(ip("192.0.2.1")));
+		assert!(any.allows(None));
+
+		let list = IpAllowlist(vec![
+			"10.0.0.0/8".parse().unwrap(),
+			"2001:db8::1".parse().unwrap(),
+		]);
+		assert!(list.allows(ip("10.1.2.3")));
+		assert!(list.allows(ip("2001:db8::1")));
+		assert!(!list.allows(ip("2001:db8::2")));
+		assert!(!list.allows(ip("192.0.2.1")));
+		assert!(!list.allows(None));
+
+		// IPv4 clients on a dual-stack socket
+		assert!(list.allows(ip("::ffff:10.1.2.3")));
+		assert!(!list.allows(ip("::ffff:192.0.2.1")));
+	}
+}
@@ -5,9 +5,12 @@ extern crate tracing;
 pub(crate) mod prev;
 
 pub mod permission;
+pub mod rate_limits;
 
 pub mod index_counter;
 
+pub mod admin_token_table;
+pub mod audit_table;
 pub mod bucket_alias_table;
 pub mod bucket_table;
 pub mod key_table;
@@ -19,3 +22,4 @@ pub mod s3;
 pub mod garage;
 pub mod helper;
 pub mod migrate;
+pub mod tiering;
@@ -78,6 +78,11 @@ impl Migrate {
 					website_config: Lww::new(website),
 					cors_config: Lww::new(None),
 					quotas: Lww::new(Default::default()),
+					rate_limits: Lww::new(Default::default()),
+					erasure_coding: Lww::new(None),
+					content_defined_chunking: Lww::new(None),
+					compression: Lww::new(None),
+					cold_tier_after_days: Lww::new(None),
 				}),
 			})
 			.await?;
@@ -98,6 +103,8 @@ impl Migrate {
 						allow_read: perm.allow_read,
 						allow_write: perm.allow_write,
 						allow_owner: false,
+						prefixes: vec![],
+						denied: vec![],
 					},
 				)
 				.await?;
@@ -4,8 +4,55 @@ use serde::{Deserialize, Serialize};
 
 use garage_util::crdt::*;
 
-/// Permission given to a key in a bucket
+use crate::helper::error::Error;
+
+/// Fine-grained operation that a key can be allowed to perform on a bucket.
+/// The read, write and owner permissions are each made of one or several
+/// of these capabilities.
 #[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BucketCapability {
+	/// Read objects (part of the read permission)
+	Read,
+	/// List objects and uploads (part of the read permission)
+	List,
+	/// Write new objects (part of the write permission)
+	Create,
+	/// Replace or modify existing objects (part of the write permission)
+	Overwrite,
+	/// Delete objects (part of the write permission)
+	Delete,
+	/// Control other aspects of the bucket (the owner permission)
+	Owner,
+}
+
+impl BucketCapability {
+	pub const READ: [Self; 2] = [Self::Read, Self::List];
+	pub const WRITE: [Self; 3] = [Self::Create, Self::Overwrite, Self::Delete];
+	pub const OWNER: [Self; 1] = [Self::Owner];
+
+	/// All the capabilities of the permission this capability belongs to
+	fn group(self) -> &'static [Self] {
+		match self {
+			Self::Read | Self::List => &Self::READ,
+			Self::Create | Self::Overwrite | Self::Delete => &Self::WRITE,
+			Self::Owner => &Self::OWNER,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Read => "read",
+			Self::List => "list",
+			Self::Create => "create",
+			Self::Overwrite => "overwrite",
+			Self::Delete => "delete",
+			Self::Owner => "owner",
+		}
+	}
+}
+
+/// Permission given to a key in a bucket
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct BucketKeyPerm {
 	/// Timestamp at which the permission was given
 	pub timestamp: u64,
@@ -18,6 +65,19 @@ pub struct BucketKeyPerm {
 	/// - enable / disable website access
 	/// - delete bucket
 	pub allow_owner: bool,
+
+	/// If not empty, the permissions given above only apply to objects
+	/// whose key starts with one of these prefixes (sorted, without duplicates).
+	/// Operations on the bucket itself are not affected.
+	#[serde(default)]
+	pub prefixes: Vec<String>,
+
+	/// Capabilities that are part of the read or write permission given above,
+	/// but that are withheld from the key (sorted, without duplicates).
+	/// For instance, an append-only key has the write permission but
+	/// not the `Overwrite` and `Delete` capabilities.
+	#[serde(default)]
+	pub denied: Vec<BucketCapability>,
 }
 
 impl BucketKeyPerm {
@@ -26,6 +86,8 @@ impl BucketKeyPerm {
 		allow_read: false,
 		allow_write: false,
 		allow_owner: false,
+		prefixes: Vec::new(),
+		denied: Vec::new(),
 	};
 
 	pub const ALL_PERMISSIONS: Self = Self {
@@ -33,18 +95,135 @@ impl BucketKeyPerm {
 		allow_read: true,
 		allow_write: true,
 		allow_owner: true,
+		prefixes: Vec::new(),
+		denied: Vec::new(),
 	};
 
 	pub fn is_any(&self) -> bool {
 		self.allow_read || self.allow_write || self.allow_owner
 	}
+
+	/// Returns true if the key has the given capability on the bucket
+	pub fn allows(&self, cap: BucketCapability) -> bool {
+		self.permission_flag(cap) && !self.denied.contains(&cap)
+	}
+
+	/// Returns true if the key has all the capabilities of the given list
+	pub fn allows_all(&self, caps: &[BucketCapability]) -> bool {
+		caps.iter().all(|c| self.allows(*c))
+	}
+
+	/// List of all the capabilities the key has on the bucket
+	pub fn capabilities(&self) -> Vec<BucketCapability> {
+		[
+			&BucketCapability::READ[..],
+			&BucketCapability::WRITE[..],
+			&BucketCapability::OWNER[..],
+		]
+		.concat()
+		.into_iter()
+		.filter(|c| self.allows(*c))
+		.collect()
+	}
+
+	/// Returns true if the permissions apply to the object with the given key
+	pub fn applies_to_object(&self, key: &str) -> bool {
+		self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+	}
+
+	/// Grant additional capabilities. If `prefixes` is empty, they are given
+	/// on the same scope as the existing ones (the whole bucket if there were none),
+	/// otherwise they are given on these prefixes in addition to the existing ones.
+	pub fn allow(&mut self, caps: &[BucketCapability], prefixes: &[String]) -> Result<(), Error> {
+		if !prefixes.is_empty() {
+			if self.is_any() && self.prefixes.is_empty() {
+				return Err(Error::BadRequest("The key already has permissions on the whole bucket, they must be removed before permissions can be given on prefixes only".into()));
+			}
+			if prefixes.iter().any(String::is_empty) {
+				return Err(Error::BadRequest("Prefixes cannot be empty".into()));
+			}
+			self.prefixes.extend(prefixes.iter().cloned());
+			self.prefixes.sort();
+			self.prefixes.dedup();
+		}
+		for cap in caps {
+			if !self.permission_flag(*cap) {
+				// Only the requested capabilities of this permission are given
+				*self.permission_flag_mut(*cap) = true;
+				self.denied.extend(cap.group());
+			}
+			self.denied.retain(|c| c != cap);
+		}
+		self.normalize();
+		Ok(())
+	}
+
+	/// Revoke capabilities. If `prefixes` is empty, the given capabilities are revoked,
+	/// otherwise all capabilities are revoked on these prefixes, and if no prefix remains
+	/// the key loses all its permissions on the bucket.
+	pub fn deny(&mut self, caps: &[BucketCapability], prefixes: &[String]) -> Result<(), Error> {
+		if prefixes.is_empty() {
+			self.denied.extend(caps);
+		} else {
+			if self.is_any() && self.prefixes.is_empty() {
+				return Err(Error::BadRequest("The key has permissions on the whole bucket, they cannot be removed on prefixes only".into()));
+			}
+			self.prefixes.retain(|p| !prefixes.contains(p));
+			if self.prefixes.is_empty() {
+				self.allow_read = false;
+				self.allow_write = false;
+				self.allow_owner = false;
+			}
+		}
+		self.normalize();
+		Ok(())
+	}
+
+	fn permission_flag(&self, cap: BucketCapability) -> bool {
+		match cap.group()[0] {
+			BucketCapability::Read => self.allow_read,
+			BucketCapability::Create => self.allow_write,
+			_ => self.allow_owner,
+		}
+	}
+
+	fn permission_flag_mut(&mut self, cap: BucketCapability) -> &mut bool {
+		match cap.group()[0] {
+			BucketCapability::Read => &mut self.allow_read,
+			BucketCapability::Create => &mut self.allow_write,
+			_ => &mut self.allow_owner,
+		}
+	}
+
+	/// Make sure there is only one way to represent a given set of capabilities:
+	/// a permission is removed when all its capabilities are denied, and
+	/// denied capabilities are only listed for permissions that are given.
+	fn normalize(&mut self) {
+		self.denied.sort();
+		self.denied.dedup();
+		for group in [
+			&BucketCapability::READ[..],
+			&BucketCapability::WRITE[..],
+			&BucketCapability::OWNER[..],
+		] {
+			if group.iter().all(|c| self.denied.contains(c)) {
+				*self.permission_flag_mut(group[0]) = false;
+			}
+			if !self.permission_flag(group[0]) {
+				self.denied.retain(|c| !group.contains(c));
+			}
+		}
+		if !self.is_any() {
+			self.prefixes.clear();
+		}
+	}
 }
 
 impl Crdt for BucketKeyPerm {
 	fn merge(&mut self, other: &Self) {
 		match other.timestamp.cmp(&self.timestamp) {
 			Ordering::Greater => {
-				*self = *other;
+				*self = other.clone();
 			}
 			Ordering::Equal if other != self => {
 				warn!("Different permission sets with same timestamp: {:?} and {:?}, merging to most restricted permission set.", self, other);
@@ -57,8 +236,71 @@ impl Crdt for BucketKeyPerm {
 				if !other.allow_owner {
 					self.allow_owner = false;
 				}
+				if self.prefixes.is_empty() {
+					self.prefixes = other.prefixes.clone();
+				} else if !other.prefixes.is_empty() {
+					self.prefixes.retain(|p| other.prefixes.contains(p));
+					if self.prefixes.is_empty() {
+						// No common prefix: an empty list would mean the whole bucket
+						self.allow_read = false;
+						self.allow_write = false;
+						self.allow_owner = false;
+					}
+				}
+				self.denied.extend(other.denied.iter());
+				self.normalize();
 			}
 			_ => (),
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use BucketCapability::*;
+
+	#[test]
+	fn test_capabilities() {
+		let mut perm = BucketKeyPerm::NO_PERMISSIONS;
+
+		// Append-only key
+		perm.allow(&[Create, Read], &[]).unwrap();
+		assert!(perm.allow_read && perm.allow_write && !perm.allow_owner);
+		assert!(perm.allows_all(&[Read, Create]));
+		assert!(!perm.allows(List) && !perm.allows(Overwrite) && !perm.allows(Delete));
+		assert_eq!(perm.denied, vec![List, Overwrite, Delete]);
+
+		perm.allow(&BucketCapability::READ, &[]).unwrap();
+		assert_eq!(perm.denied, vec![Overwrite, Delete]);
+
+		// Revoking the last capability of a permission removes the permission
+		perm.deny(&[Create], &[]).unwrap();
+		assert!(!perm.allow_write);
+		assert!(perm.denied.is_empty());
+
+		perm.allow(&BucketCapability::WRITE, &[]).unwrap();
+		perm.deny(&[Delete], &[]).unwrap();
+		assert!(perm.allows_all(&[Read, List, Create, Overwrite]));
+		assert!(!perm.allows(Delete));
+	}
+
+	#[test]
+	fn test_merge_same_timestamp() {
+		let mut a = BucketKeyPerm::NO_PERMISSIONS;
+		a.allow(&[Read, Create, Overwrite], &[]).unwrap();
+		let mut b = BucketKeyPerm::NO_PERMISSIONS;
+		b.allow(&[List, Create, Delete], &[]).unwrap();
+
+		let mut ab = a.clone();
+		ab.merge(&b);
+		let mut ba = b.clone();
+		ba.merge(&a);
+		assert_eq!(ab, ba);
+
+		// Most restricted permission set: no read capability remains
+		assert!(!ab.allow_read);
+		assert!(ab.allows(Create));
+		assert!(!ab.allows(Overwrite) && !ab.allows(Delete));
+	}
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use garage_table::crdt::*;
+
+/// Rate limits that can be set on an access key or on a bucket.
+/// A value of `None` means that no limit is enforced for that class.
+#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RateLimits {
+	/// Maximum number of read requests (GetObject, HeadObject, ...) per second
+	pub read_requests: Option<u64>,
+	/// Maximum number of write requests (PutObject, DeleteObject, ...) per second
+	pub write_requests: Option<u64>,
+	/// Maximum number of list requests (ListObjects, ListParts, ...) per second
+	pub list_requests: Option<u64>,
+	/// Maximum number of bytes per second uploaded in request bodies
+	pub upload_bytes: Option<u64>,
+	/// Maximum number of bytes per second downloaded in object bodies
+	pub download_bytes: Option<u64>,
+}
+
+impl RateLimits {
+	/// Returns true if no limit at all is set
+	pub fn is_unlimited(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+impl AutoCrdt for RateLimits {
+	const WARN_IF_DIFFERENT: bool = true;
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use garage_table::*;
+use garage_util::data::*;
+use garage_util::error::*;
+use garage_util::time::now_msec;
+
+use garage_block::tier::TieringPolicy;
+
+use crate::garage::Garage;
+
+/// Maximum number of references to a block that are checked by the tiering policy.
+/// Blocks that are referenced more often than that are never considered cold.
+const MAX_BLOCK_REFS: usize = 1000;
+
+/// Tiering policy that considers a block cold when all objects that use it
+/// are older than the `cold_tier_after_days` setting of their bucket
+pub struct ObjectAgeTieringPolicy(pub Arc<Garage>);
+
+#[async_trait]
+impl TieringPolicy for ObjectAgeTieringPolicy {
+	async fn is_cold(&self, hash: &Hash) -> Result<bool, Error> {
+		let garage = &self.0;
+		let block_refs = garage
+			.block_ref_table
+			.get_range(
+				hash,
+				None,
+				Some(DeletedFilter::NotDeleted),
+				MAX_BLOCK_REFS,
+				EnumerationOrder::Forward,
+			)
+			.await?;
+		if block_refs.is_empty() || block_refs.len() == MAX_BLOCK_REFS {
+			return Ok(false);
+		}
+
+		for block_ref in block_refs {
+			let version = match garage
+				.version_table
+				.get(&block_ref.version, &EmptyKey)
+				.await?
+			{
+				Some(v) if v.deleted.get() => continue,
+				Some(v) => v,
+				None => return Ok(false),
+			};
+
+			let after_days = garage
+				.bucket_table
+				.get(&EmptyKey, &version.bucket_id)
+				.await?
+				.and_then(|b| b.state.into_option())
+				.and_then(|p| *p.cold_tier_after_days.get());
+			let after_days = match after_days {
+				Some(d) => d,
+				None => return Ok(false),
+			};
+
+			let timestamp = garage
+				.object_table
+				.get(&version.bucket_id, &version.key)
+				.await?
+				.and_then(|o| {
+					o.versions()
+						.iter()
+						.find(|v| v.uuid == version.uuid)
+						.map(|v| v.timestamp)
+				});
+			let cold_before =
+				now_msec().saturating_sub(after_days.saturating_mul(24 * 3600 * 1000));
+			match timestamp {
+				Some(ts) if ts <= cold_before => (),
+				_ => return Ok(false),
+			}
+		}
+		Ok(true)
+	}
+}
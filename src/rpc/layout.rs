@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
@@ -56,6 +56,35 @@ pub struct NodeRole {
 	pub tags: Vec<String>,
 }
 
+/// Estimated effect of applying the staged changes of a cluster layout
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LayoutSimulation {
+	/// Version of the layout once the staged changes are applied
+	pub new_version: u64,
+	/// Number of partitions whose set of nodes changes
+	pub moved_partitions: usize,
+	/// Whether all partitions of the new layout are stored in as many
+	/// different zones as possible
+	pub zone_redundant: bool,
+	/// Changes for each node that stores data in the current or in the new layout
+	pub nodes: Vec<NodeLayoutChange>,
+}
+
+/// Partitions that a node gains and loses when a new layout is applied
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeLayoutChange {
+	pub id: Uuid,
+	pub partitions_before: usize,
+	pub partitions_after: usize,
+	pub partitions_gained: usize,
+	pub partitions_lost: usize,
+	/// Estimated number of bytes that the node has to receive,
+	/// None if no estimate of partition sizes is available
+	pub bytes_gained: Option<u64>,
+	/// Estimated number of bytes that the node will no longer store
+	pub bytes_lost: Option<u64>,
+}
+
 impl NodeRole {
 	pub fn capacity_string(&self) -> String {
 		match self.capacity {
@@ -101,7 +130,13 @@ impl ClusterLayout {
 		}
 	}
 
-	pub fn apply_staged_changes(mut self, version: Option<u64>) -> Result<Self, Error> {
+	/// Apply the staged role changes and calculate the new assignation of
+	/// partitions, printing statistics about it if `verbose` is set
+	pub fn apply_staged_changes(
+		mut self,
+		version: Option<u64>,
+		verbose: bool,
+	) -> Result<Self, Error> {
 		match version {
 			None => {
 				let error = r#"
@@ -120,7 +155,7 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		self.roles.merge(&self.staging);
 		self.roles.retain(|(_, _, v)| v.0.is_some());
 
-		if !self.calculate_partition_assignation() {
+		if !self.calculate_partition_assignation(verbose) {
 			return Err(Error::Message("Could not calculate new assignation of partitions to nodes. This can happen if there are less nodes than the desired number of copies of your data (see the replication_mode configuration parameter).".into()));
 		}
 
@@ -132,6 +167,63 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		Ok(self)
 	}
 
+	/// Compute the layout that results from applying the staged changes,
+	/// without modifying this layout, and report how partitions move
+	/// between nodes. `partition_sizes` gives the estimated number of bytes
+	/// stored in each partition, if known.
+	pub fn simulate_staged_changes(
+		&self,
+		partition_sizes: Option<&[u64]>,
+	) -> Result<LayoutSimulation, Error> {
+		let new_layout = self
+			.clone()
+			.apply_staged_changes(Some(self.version + 1), false)?;
+
+		let old_partitions = self.partition_nodes();
+		let new_partitions = new_layout.partition_nodes();
+
+		let mut moved_partitions = 0;
+		let mut changes = BTreeMap::<Uuid, NodeLayoutChange>::new();
+		for (i, (old_nodes, new_nodes)) in
+			old_partitions.iter().zip(new_partitions.iter()).enumerate()
+		{
+			let size = partition_sizes.and_then(|s| s.get(i).copied());
+			let mut moved = false;
+			for node in old_nodes.iter() {
+				let change = changes
+					.entry(*node)
+					.or_insert_with(|| NodeLayoutChange::new(*node, partition_sizes.is_some()));
+				change.partitions_before += 1;
+				if !new_nodes.contains(node) {
+					change.partitions_lost += 1;
+					change.bytes_lost = change.bytes_lost.map(|b| b + size.unwrap_or(0));
+					moved = true;
+				}
+			}
+			for node in new_nodes.iter() {
+				let change = changes
+					.entry(*node)
+					.or_insert_with(|| NodeLayoutChange::new(*node, partition_sizes.is_some()));
+				change.partitions_after += 1;
+				if !old_nodes.contains(node) {
+					change.partitions_gained += 1;
+					change.bytes_gained = change.bytes_gained.map(|b| b + size.unwrap_or(0));
+					moved = true;
+				}
+			}
+			if moved {
+				moved_partitions += 1;
+			}
+		}
+
+		Ok(LayoutSimulation {
+			new_version: new_layout.version,
+			moved_partitions,
+			zone_redundant: new_layout.is_zone_redundant(),
+			nodes: changes.into_values().collect(),
+		})
+	}
+
 	pub fn revert_staged_changes(mut self, version: Option<u64>) -> Result<Self, Error> {
 		match version {
 			None => {
@@ -174,6 +266,21 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		}
 	}
 
+	/// Returns true if each partition is stored on nodes in as many
+	/// different zones as possible, i.e. in `replication_factor` zones
+	/// or in all zones if there are less zones than that
+	pub fn is_zone_redundant(&self) -> bool {
+		let (_, zones) = self.configured_nodes_and_zones();
+		let target = std::cmp::min(self.replication_factor, zones.len());
+		self.parse_assignation_data().iter().all(|part| {
+			part.nodes
+				.iter()
+				.filter_map(|(_, info)| info.map(|i| i.zone.as_str()))
+				.collect::<HashSet<&str>>()
+				.len() >= target
+		})
+	}
+
 	/// Check a cluster layout for internal consistency
 	/// returns true if consistent, false if error
 	pub fn check(&self) -> bool {
@@ -220,13 +327,16 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		true
 	}
 
-	/// Calculate an assignation of partitions to nodes
-	pub fn calculate_partition_assignation(&mut self) -> bool {
+	/// Calculate an assignation of partitions to nodes,
+	/// if `verbose` is set the steps of the calculation are printed
+	pub fn calculate_partition_assignation(&mut self, verbose: bool) -> bool {
 		let (configured_nodes, zones) = self.configured_nodes_and_zones();
 		let n_zones = zones.len();
 
-		println!("Calculating updated partition assignation, this may take some time...");
-		println!();
+		if verbose {
+			println!("Calculating updated partition assignation, this may take some time...");
+			println!();
+		}
 
 		// Get old partition assignation
 		let old_partitions = self.parse_assignation_data();
@@ -291,11 +401,13 @@ To know the correct value of the new layout version, invoke `garage layout show`
 
 		let mut partitions_per_node = self.partitions_per_node(&partitions[..]);
 
-		println!("Target number of partitions per node:");
-		for (node, npart) in target_partitions_per_node.iter() {
-			println!("{:?}\t{}", node, npart);
+		if verbose {
+			println!("Target number of partitions per node:");
+			for (node, npart) in target_partitions_per_node.iter() {
+				println!("{:?}\t{}", node, npart);
+			}
+			println!();
 		}
-		println!();
 
 		// Shuffle partitions between nodes so that nodes will reach (or better approach)
 		// their target number of stored partitions
@@ -373,7 +485,32 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		let new_partitions_per_node = self.partitions_per_node(&partitions[..]);
 		assert!(new_partitions_per_node == partitions_per_node);
 
-		// Show statistics
+		if verbose {
+			self.print_assignation_statistics(
+				&old_partitions[..],
+				&partitions[..],
+				&partitions_per_node,
+				&target_partitions_per_node,
+			);
+		}
+
+		// Calculate and save new assignation data
+		let (nodes, assignation_data) =
+			self.compute_assignation_data(&configured_nodes[..], &partitions[..]);
+
+		self.node_id_vec = nodes;
+		self.ring_assignation_data = assignation_data;
+
+		true
+	}
+
+	fn print_assignation_statistics(
+		&self,
+		old_partitions: &[PartitionAss<'_>],
+		partitions: &[PartitionAss<'_>],
+		partitions_per_node: &HashMap<&Uuid, usize>,
+		target_partitions_per_node: &HashMap<&Uuid, usize>,
+	) {
 		println!("New number of partitions per node:");
 		for (node, npart) in partitions_per_node.iter() {
 			let tgt = *target_partitions_per_node.get(node).unwrap();
@@ -402,15 +539,6 @@ To know the correct value of the new layout version, invoke `garage layout show`
 			}
 		}
 		println!();
-
-		// Calculate and save new assignation data
-		let (nodes, assignation_data) =
-			self.compute_assignation_data(&configured_nodes[..], &partitions[..]);
-
-		self.node_id_vec = nodes;
-		self.ring_assignation_data = assignation_data;
-
-		true
 	}
 
 	fn initial_partition_assignation(&self) -> Option<Vec<PartitionAss<'_>>> {
@@ -572,6 +700,15 @@ To know the correct value of the new layout version, invoke `garage layout show`
 		}
 	}
 
+	/// Returns the IDs of the nodes that store each partition,
+	/// which are empty if no partition assignation has been calculated
+	fn partition_nodes(&self) -> Vec<Vec<Uuid>> {
+		self.parse_assignation_data()
+			.iter()
+			.map(|part| part.nodes.iter().map(|(id, _)| **id).collect())
+			.collect()
+	}
+
 	fn partitions_per_node<'a>(&self, partitions: &[PartitionAss<'a>]) -> HashMap<&'a Uuid, usize> {
 		let mut partitions_per_node = HashMap::<&Uuid, usize>::new();
 		for p in partitions.iter() {
@@ -583,6 +720,21 @@ To know the correct value of the new layout version, invoke `garage layout show`
 	}
 }
 
+impl NodeLayoutChange {
+	fn new(id: Uuid, with_sizes: bool) -> Self {
+		let bytes = if with_sizes { Some(0) } else { None };
+		Self {
+			id,
+			partitions_before: 0,
+			partitions_after: 0,
+			partitions_gained: 0,
+			partitions_lost: 0,
+			bytes_gained: bytes,
+			bytes_lost: bytes,
+		}
+	}
+}
+
 // ---- Internal structs for partition assignation in layout ----
 
 #[derive(Clone)]
@@ -664,3 +816,66 @@ impl<'a> PartitionAss<'a> {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn stage_role(layout: &mut ClusterLayout, node: Uuid, zone: &str) {
+		let role = NodeRoleV(Some(NodeRole {
+			zone: zone.to_string(),
+			capacity: Some(1),
+			tags: vec![],
+		}));
+		let update = layout.staging.update_mutator(node, role);
+		layout.staging.merge(&update);
+		layout.staging_hash = blake2sum(&rmp_to_vec_all_named(&layout.staging).unwrap()[..]);
+	}
+
+	#[test]
+	fn test_simulate_staged_changes() {
+		let nodes = (0..4u8).map(|i| Uuid::from([i; 32])).collect::<Vec<_>>();
+
+		let mut layout = ClusterLayout::new(3);
+		for (node, zone) in nodes.iter().zip(["a", "b", "c"]) {
+			stage_role(&mut layout, *node, zone);
+		}
+		let layout = layout.apply_staged_changes(Some(1), false).unwrap();
+		assert!(layout.check());
+		assert!(layout.is_zone_redundant());
+
+		let mut staged = layout.clone();
+		stage_role(&mut staged, nodes[3], "a");
+
+		let sizes = vec![10u64; 1 << PARTITION_BITS];
+		let simulation = staged.simulate_staged_changes(Some(&sizes[..])).unwrap();
+		assert_eq!(simulation.new_version, 2);
+		assert!(simulation.zone_redundant);
+		assert_eq!(staged.version, 1);
+
+		let new_node = simulation.nodes.iter().find(|n| n.id == nodes[3]).unwrap();
+		assert_eq!(new_node.partitions_before, 0);
+		assert!(new_node.partitions_gained > 0);
+		assert_eq!(new_node.partitions_after, new_node.partitions_gained);
+		assert_eq!(
+			new_node.bytes_gained,
+			Some(10 * new_node.partitions_gained as u64)
+		);
+
+		let total_gained = simulation
+			.nodes
+			.iter()
+			.map(|n| n.partitions_gained)
+			.sum::<usize>();
+		let total_lost = simulation
+			.nodes
+			.iter()
+			.map(|n| n.partitions_lost)
+			.sum::<usize>();
+		assert_eq!(total_gained, total_lost);
+		assert_eq!(simulation.moved_partitions, new_node.partitions_gained);
+
+		let simulation = staged.simulate_staged_changes(None).unwrap();
+		assert!(simulation.nodes.iter().all(|n| n.bytes_gained.is_none()));
+	}
+}
@@ -239,6 +239,26 @@ impl RpcHelper {
 		H: StreamingEndpointHandler<M> + 'static,
 		S: Send + 'static,
 	{
+		let msg = msg.into_req().map_err(netapp::error::Error::from)?;
+		let msgs = to.iter().map(|to| (*to, msg.clone())).collect();
+		self.try_call_many_distinct(endpoint, msgs, strategy).await
+	}
+
+	/// Make a RPC call to multiple servers, sending a different message to each of them
+	/// (e.g. a different shard of a block), returning either a Vec of responses,
+	/// or an error if quorum could not be reached due to too many errors
+	pub async fn try_call_many_distinct<M, H, S>(
+		&self,
+		endpoint: &Arc<Endpoint<M, H>>,
+		msgs: Vec<(Uuid, Req<M>)>,
+		strategy: RequestStrategy,
+	) -> Result<Vec<S>, Error>
+	where
+		M: Rpc<Response = Result<S, Error>> + 'static,
+		H: StreamingEndpointHandler<M> + 'static,
+		S: Send + 'static,
+	{
+		let to = msgs.iter().map(|(to, _)| *to).collect::<Vec<_>>();
 		let quorum = strategy.rs_quorum.unwrap_or(to.len());
 
 		let tracer = opentelemetry::global::tracer("garage");
@@ -261,33 +281,29 @@ impl RpcHelper {
 			strategy.rs_interrupt_after_quorum.to_string(),
 		));
 
-		self.try_call_many_internal(endpoint, to, msg, strategy, quorum)
+		self.try_call_many_internal(endpoint, &to, msgs, strategy, quorum)
 			.with_context(Context::current_with_span(span))
 			.await
 	}
 
-	async fn try_call_many_internal<M, N, H, S>(
+	async fn try_call_many_internal<M, H, S>(
 		&self,
 		endpoint: &Arc<Endpoint<M, H>>,
 		to: &[Uuid],
-		msg: N,
+		msgs: Vec<(Uuid, Req<M>)>,
 		strategy: RequestStrategy,
 		quorum: usize,
 	) -> Result<Vec<S>, Error>
 	where
 		M: Rpc<Response = Result<S, Error>> + 'static,
-		N: IntoReq<M>,
 		H: StreamingEndpointHandler<M> + 'static,
 		S: Send + 'static,
 	{
-		let msg = msg.into_req().map_err(netapp::error::Error::from)?;
-
 		// Build future for each request
 		// They are not started now: they are added below in a FuturesUnordered
 		// object that will take care of polling them (see below)
-		let requests = to.iter().cloned().map(|to| {
+		let requests = msgs.into_iter().map(|(to, msg)| {
 			let self2 = self.clone();
-			let msg = msg.clone();
 			let endpoint2 = endpoint.clone();
 			(to, async move {
 				self2.call(&endpoint2, to, msg, strategy).await
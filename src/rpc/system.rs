@@ -1,7 +1,8 @@
 //! Module containing structs related to membership management
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Read, Write};
 use std::net::{IpAddr, SocketAddr};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -113,6 +114,8 @@ pub struct System {
 
 	/// Path to metadata directory
 	pub metadata_dir: PathBuf,
+	/// Paths to data directories
+	data_dirs: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -125,6 +128,40 @@ pub struct NodeStatus {
 	pub cluster_layout_version: u64,
 	/// Hash of cluster layout staging data
 	pub cluster_layout_staging_hash: Hash,
+	/// Disk space available for data blocks on the node
+	#[serde(default)]
+	pub data_disk_avail: Option<DiskSpace>,
+	/// Disk space available for metadata on the node
+	#[serde(default)]
+	pub meta_disk_avail: Option<DiskSpace>,
+}
+
+/// Available and total space of the filesystems containing some directories
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DiskSpace {
+	pub available: u64,
+	pub total: u64,
+}
+
+impl DiskSpace {
+	/// Space of the filesystems containing the given directories,
+	/// each filesystem being counted once
+	pub fn of<P: AsRef<Path>>(paths: &[P]) -> Option<Self> {
+		let mut devices = HashSet::new();
+		let mut space = DiskSpace {
+			available: 0,
+			total: 0,
+		};
+		for path in paths {
+			let path = path.as_ref();
+			let dev = std::fs::metadata(path).ok()?.dev();
+			if devices.insert(dev) {
+				space.available += fs2::available_space(path).ok()?;
+				space.total += fs2::total_space(path).ok()?;
+			}
+		}
+		Some(space)
+	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -241,6 +278,8 @@ impl System {
 			replication_factor,
 			cluster_layout_version: cluster_layout.version,
 			cluster_layout_staging_hash: cluster_layout.staging_hash,
+			data_disk_avail: None,
+			meta_disk_avail: None,
 		};
 
 		let ring = Ring::new(cluster_layout, replication_factor);
@@ -338,6 +377,7 @@ impl System {
 			update_ring: Mutex::new(update_ring),
 			background,
 			metadata_dir: config.metadata_dir.clone(),
+			data_dirs: config.data_dir.paths().into_iter().cloned().collect(),
 		});
 		sys.system_endpoint.set_handler(sys.clone());
 		Ok(sys)
@@ -380,6 +420,8 @@ impl System {
 						replication_factor: 0,
 						cluster_layout_version: 0,
 						cluster_layout_staging_hash: Hash::from([0u8; 32]),
+						data_disk_avail: None,
+						meta_disk_avail: None,
 					}),
 			})
 			.collect::<Vec<_>>();
@@ -496,6 +538,10 @@ impl System {
 		let ring = self.ring.borrow();
 		new_si.cluster_layout_version = ring.layout.version;
 		new_si.cluster_layout_staging_hash = ring.layout.staging_hash;
+
+		new_si.data_disk_avail = DiskSpace::of(&self.data_dirs);
+		new_si.meta_disk_avail = DiskSpace::of(&[&self.metadata_dir]);
+
 		self.local_status.swap(Arc::new(new_si));
 	}
 
@@ -100,20 +100,20 @@ where
 					Some(sk) => self.tree_key(partition_key, sk),
 				};
 				let range = self.store.range(first_key..)?;
-				self.read_range_aux(partition_hash, range, filter, limit)
+				self.read_range_aux(partition_hash, range, filter, limit, enumeration_order)
 			}
 			EnumerationOrder::Reverse => match start {
 				Some(sk) => {
 					let last_key = self.tree_key(partition_key, sk);
 					let range = self.store.range_rev(..=last_key)?;
-					self.read_range_aux(partition_hash, range, filter, limit)
+					self.read_range_aux(partition_hash, range, filter, limit, enumeration_order)
 				}
 				None => {
 					let mut last_key = partition_hash.to_vec();
 					let lower = u128::from_be_bytes(last_key[16..32].try_into().unwrap());
 					last_key[16..32].copy_from_slice(&u128::to_be_bytes(lower + 1));
 					let range = self.store.range_rev(..last_key)?;
-					self.read_range_aux(partition_hash, range, filter, limit)
+					self.read_range_aux(partition_hash, range, filter, limit, enumeration_order)
 				}
 			},
 		}
@@ -125,6 +125,7 @@ where
 		range: db::ValueIter<'a>,
 		filter: &Option<F::Filter>,
 		limit: usize,
+		enumeration_order: EnumerationOrder,
 	) -> Result<Vec<Arc<ByteBuf>>, Error> {
 		let mut ret = vec![];
 		for item in range {
@@ -136,6 +137,9 @@ where
 				None => true,
 				Some(f) => {
 					let entry = self.decode_entry(value.as_ref())?;
+					if F::is_past_filter_range(&entry, f, enumeration_order) {
+						break;
+					}
 					F::matches_filter(&entry, f)
 				}
 			};
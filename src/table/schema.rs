@@ -4,6 +4,7 @@ use garage_db as db;
 use garage_util::data::*;
 
 use crate::crdt::Crdt;
+use crate::util::EnumerationOrder;
 
 /// Trait for field used to partition data
 pub trait PartitionKey {
@@ -98,4 +99,15 @@ pub trait TableSchema: Send + Sync + 'static {
 	}
 
 	fn matches_filter(entry: &Self::E, filter: &Self::Filter) -> bool;
+
+	/// Whether an entry is past the end of the range of entries that a filter
+	/// can match, when enumerating in the given order. Range reads stop at the
+	/// first such entry instead of going through the rest of the partition.
+	fn is_past_filter_range(
+		_entry: &Self::E,
+		_filter: &Self::Filter,
+		_enumeration_order: EnumerationOrder,
+	) -> bool {
+		false
+	}
 }
@@ -1,8 +1,10 @@
 //! Contains type and functions related to Garage configuration file
+use std::fmt;
 use std::io::Read;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
+use ipnetwork::IpNetwork;
 use serde::{de, Deserialize};
 
 use crate::error::Error;
@@ -12,8 +14,25 @@ use crate::error::Error;
 pub struct Config {
 	/// Path where to store metadata. Should be fast, but low volume
 	pub metadata_dir: PathBuf,
-	/// Path where to store data. Can be slower, but need higher volume
-	pub data_dir: PathBuf,
+	/// Path where to store data. Can be slower, but need higher volume.
+	/// Either a single path, or a list of data directories (e.g. one per drive)
+	pub data_dir: DataDirEnum,
+	/// Minimum free space to keep in data directories, below which the node
+	/// refuses new blocks: a size (e.g. "10G") or a percentage (e.g. "5%")
+	#[serde(default)]
+	pub data_min_free_space: Option<String>,
+	/// Minimum free space to keep in the filesystem of the metadata directory,
+	/// below which the node refuses new blocks, in the same format
+	#[serde(default)]
+	pub metadata_min_free_space: Option<String>,
+
+	/// Whether data blocks are synced to disk (fsync) before a write is acknowledged
+	#[serde(default = "default_data_fsync")]
+	pub data_fsync: bool,
+	/// Whether metadata writes are synced to disk (fsync) before they are acknowledged.
+	/// If unset, each database engine keeps its own default.
+	#[serde(default)]
+	pub metadata_fsync: Option<bool>,
 
 	/// Size of data blocks to save to disk
 	#[serde(default = "default_block_size")]
@@ -33,6 +52,28 @@ pub struct Config {
 	)]
 	pub compression_level: Option<i32>,
 
+	/// Erasure coding of data blocks, as an alternative to storing full copies
+	#[serde(default)]
+	pub erasure_coding: Option<ErasureCodingConfig>,
+
+	/// Content-defined chunking of uploaded data, as an alternative to
+	/// splitting it in blocks of `block_size` bytes
+	#[serde(default)]
+	pub content_defined_chunking: Option<ChunkingConfig>,
+
+	/// Cache of frequently read data blocks, in RAM and on a local disk
+	#[serde(default)]
+	pub block_cache: Option<BlockCacheConfig>,
+
+	/// Hedging of block reads: requesting a block from another node
+	/// when the first one is slower than usual to answer
+	#[serde(default)]
+	pub block_read_hedging: Option<BlockReadHedgingConfig>,
+
+	/// Cold storage tier, where the blocks of old objects are moved
+	#[serde(default)]
+	pub cold_tier: Option<ColdTierConfig>,
+
 	/// RPC secret key: 32 bytes hex encoded
 	pub rpc_secret: String,
 
@@ -85,11 +126,151 @@ pub struct Config {
 	pub admin: AdminConfig,
 }
 
+/// Data directories of a node
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DataDirEnum {
+	Single(PathBuf),
+	Multiple(Vec<DataDir>),
+}
+
+/// A data directory, usually corresponding to a drive
+#[derive(Deserialize, Debug, Clone)]
+pub struct DataDir {
+	/// Path of the directory
+	pub path: PathBuf,
+	/// Capacity of the drive, e.g. "2T" (required if the directory is not read-only)
+	#[serde(default)]
+	pub capacity: Option<String>,
+	/// Whether the directory is being retired: blocks are still read from it,
+	/// but no new blocks are written to it and existing blocks are moved away
+	#[serde(default)]
+	pub read_only: bool,
+}
+
+impl DataDirEnum {
+	/// Paths of all data directories
+	pub fn paths(&self) -> Vec<&PathBuf> {
+		match self {
+			Self::Single(path) => vec![path],
+			Self::Multiple(dirs) => dirs.iter().map(|d| &d.path).collect(),
+		}
+	}
+}
+
+/// Configuration for the cache of frequently read data blocks
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlockCacheConfig {
+	/// Maximum size of the blocks cached in RAM, e.g. "256M"
+	#[serde(default)]
+	pub ram_size: Option<String>,
+	/// Directory where blocks are cached on a local disk (e.g. a SSD)
+	#[serde(default)]
+	pub disk_dir: Option<PathBuf>,
+	/// Maximum size of the blocks cached in `disk_dir`, e.g. "20G"
+	#[serde(default)]
+	pub disk_size: Option<String>,
+}
+
+/// Configuration for hedging of block reads
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlockReadHedgingConfig {
+	/// Percentile of the latency of recent block reads after which a block
+	/// is also requested from another node (defaults to 95)
+	#[serde(default)]
+	pub percentile: Option<f64>,
+	/// Minimum delay before a block is requested from another node,
+	/// in milliseconds (defaults to 10)
+	#[serde(default)]
+	pub min_delay_msec: Option<u64>,
+}
+
+/// Configuration of the cold storage tier (exactly one of `path` and `s3` must be set)
+#[derive(Deserialize, Debug, Clone)]
+pub struct ColdTierConfig {
+	/// Directory where cold blocks are stored, e.g. on a network filesystem
+	#[serde(default)]
+	pub path: Option<PathBuf>,
+	/// Bucket of a S3-compatible object store where cold blocks are stored
+	#[serde(default)]
+	pub s3: Option<ColdTierS3Config>,
+}
+
+/// Bucket of a S3-compatible object store used as the cold storage tier
+#[derive(Deserialize, Debug, Clone)]
+pub struct ColdTierS3Config {
+	/// Endpoint of the S3 API, e.g. "https://s3.example.com"
+	pub endpoint: String,
+	/// Region of the S3 API
+	pub region: String,
+	/// Name of the bucket
+	pub bucket: String,
+	/// Access key ID used to access the bucket
+	pub access_key_id: String,
+	/// Secret access key used to access the bucket
+	pub secret_access_key: String,
+}
+
+/// Configuration for erasure coding of data blocks
+#[derive(Deserialize, Debug, Clone)]
+pub struct ErasureCodingConfig {
+	/// Number of data shards each block is split in
+	pub data_shards: usize,
+	/// Number of parity shards computed for each block (defaults to
+	/// the replication factor minus the number of data shards)
+	#[serde(default)]
+	pub parity_shards: Option<usize>,
+	/// Whether blocks of buckets that do not specify a storage mode are erasure coded
+	#[serde(default)]
+	pub default: bool,
+}
+
+impl ErasureCodingConfig {
+	/// Number of parity shards computed for each block, given the replication factor
+	pub fn parity_shards(&self, replication_factor: usize) -> usize {
+		self.parity_shards
+			.unwrap_or_else(|| replication_factor.saturating_sub(self.data_shards))
+	}
+}
+
+/// Configuration for content-defined chunking of uploaded data
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChunkingConfig {
+	/// Minimum size of chunks (defaults to a sixteenth of `block_size`)
+	#[serde(default)]
+	pub min_size: Option<usize>,
+	/// Average size of chunks (defaults to a quarter of `block_size`)
+	#[serde(default)]
+	pub avg_size: Option<usize>,
+	/// Maximum size of chunks (defaults to `block_size`)
+	#[serde(default)]
+	pub max_size: Option<usize>,
+	/// Whether buckets that do not specify a chunking mode use content-defined chunking
+	#[serde(default)]
+	pub default: bool,
+}
+
+impl ChunkingConfig {
+	/// Minimum, average and maximum size of chunks
+	pub fn sizes(&self, block_size: usize) -> (usize, usize, usize) {
+		(
+			self.min_size.unwrap_or(block_size / 16),
+			self.avg_size.unwrap_or(block_size / 4),
+			self.max_size.unwrap_or(block_size),
+		)
+	}
+}
+
 /// Configuration for S3 api
 #[derive(Deserialize, Debug, Clone)]
 pub struct S3ApiConfig {
-	/// Address and port to bind for api serving
-	pub api_bind_addr: Option<SocketAddr>,
+	/// Address and port to bind for api serving, or path of a Unix socket
+	pub api_bind_addr: Option<UnixOrTCPSocketAddress>,
+	/// Permissions to set on the Unix socket, if api_bind_addr is one
+	pub unix_socket_mode: Option<u32>,
+	/// How to determine client addresses when behind a proxy
+	#[serde(flatten)]
+	pub client_addr: ClientAddrConfig,
 	/// S3 region to use
 	pub s3_region: String,
 	/// Suffix to remove from domain name to find bucket. If None,
@@ -100,15 +281,25 @@ pub struct S3ApiConfig {
 /// Configuration for K2V api
 #[derive(Deserialize, Debug, Clone)]
 pub struct K2VApiConfig {
-	/// Address and port to bind for api serving
-	pub api_bind_addr: SocketAddr,
+	/// Address and port to bind for api serving, or path of a Unix socket
+	pub api_bind_addr: UnixOrTCPSocketAddress,
+	/// Permissions to set on the Unix socket, if api_bind_addr is one
+	pub unix_socket_mode: Option<u32>,
+	/// How to determine client addresses when behind a proxy
+	#[serde(flatten)]
+	pub client_addr: ClientAddrConfig,
 }
 
 /// Configuration for serving files as normal web server
 #[derive(Deserialize, Debug, Clone)]
 pub struct WebConfig {
-	/// Address and port to bind for web serving
-	pub bind_addr: SocketAddr,
+	/// Address and port to bind for web serving, or path of a Unix socket
+	pub bind_addr: UnixOrTCPSocketAddress,
+	/// Permissions to set on the Unix socket, if bind_addr is one
+	pub unix_socket_mode: Option<u32>,
+	/// How to determine client addresses when behind a proxy
+	#[serde(flatten)]
+	pub client_addr: ClientAddrConfig,
 	/// Suffix to remove from domain name to find bucket
 	pub root_domain: String,
 }
@@ -116,14 +307,35 @@ pub struct WebConfig {
 /// Configuration for the admin and monitoring HTTP API
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct AdminConfig {
-	/// Address and port to bind for admin API serving
-	pub api_bind_addr: Option<SocketAddr>,
+	/// Address and port to bind for admin API serving, or path of a Unix socket
+	pub api_bind_addr: Option<UnixOrTCPSocketAddress>,
+	/// Permissions to set on the Unix socket, if api_bind_addr is one
+	pub unix_socket_mode: Option<u32>,
+	/// How to determine client addresses when behind a proxy
+	#[serde(flatten)]
+	pub client_addr: ClientAddrConfig,
 	/// Bearer token to use to scrape metrics
 	pub metrics_token: Option<String>,
 	/// Bearer token to use to access Admin API endpoints
 	pub admin_token: Option<String>,
 	/// OTLP server to where to export traces
 	pub trace_sink: Option<String>,
+	/// Number of days after which entries of the audit log are removed
+	/// (if unset, they are kept forever)
+	pub audit_retention_days: Option<u64>,
+}
+
+/// Configuration of how the address of clients is determined, for HTTP endpoints
+/// that are placed behind a reverse proxy or a load balancer
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ClientAddrConfig {
+	/// Expect all connections to start with a PROXY protocol (v1 or v2) header
+	#[serde(default)]
+	pub proxy_protocol: bool,
+	/// Networks of the proxies that are trusted to set the X-Forwarded-For
+	/// and Forwarded headers
+	#[serde(default)]
+	pub trusted_proxies: Vec<IpNetwork>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -154,6 +366,47 @@ pub struct KubernetesDiscoveryConfig {
 	pub skip_crd: bool,
 }
 
+/// Address on which an HTTP server listens: either a TCP socket address,
+/// or the path of a Unix domain socket written as `unix:/path/to/socket`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixOrTCPSocketAddress {
+	TCPSocket(SocketAddr),
+	UnixSocket(PathBuf),
+}
+
+impl fmt::Display for UnixOrTCPSocketAddress {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			UnixOrTCPSocketAddress::TCPSocket(addr) => write!(f, "http://{}", addr),
+			UnixOrTCPSocketAddress::UnixSocket(path) => write!(f, "unix:{}", path.display()),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for UnixOrTCPSocketAddress {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: de::Deserializer<'de>,
+	{
+		let string = String::deserialize(deserializer)?;
+		match string.strip_prefix("unix:") {
+			Some("") => Err(de::Error::custom(
+				"Invalid Unix socket address: path is empty",
+			)),
+			Some(path) => Ok(UnixOrTCPSocketAddress::UnixSocket(PathBuf::from(path))),
+			None => string
+				.parse::<SocketAddr>()
+				.map(UnixOrTCPSocketAddress::TCPSocket)
+				.map_err(|e| {
+					de::Error::custom(format!("Invalid socket address '{}': {}", string, e))
+				}),
+		}
+	}
+}
+
+fn default_data_fsync() -> bool {
+	true
+}
 fn default_db_engine() -> String {
 	"sled".into()
 }
@@ -233,3 +486,93 @@ where
 
 	deserializer.deserialize_any(OptionVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Deserialize)]
+	struct BindAddr {
+		addr: UnixOrTCPSocketAddress,
+	}
+
+	fn parse(s: &str) -> Result<UnixOrTCPSocketAddress, toml::de::Error> {
+		toml::from_str::<BindAddr>(&format!("addr = {:?}", s)).map(|x| x.addr)
+	}
+
+	#[test]
+	fn test_parse_bind_addr() {
+		assert_eq!(
+			parse("[::]:3900").unwrap(),
+			UnixOrTCPSocketAddress::TCPSocket("[::]:3900".parse().unwrap())
+		);
+		assert_eq!(
+			parse("unix:/run/garage/s3.sock").unwrap(),
+			UnixOrTCPSocketAddress::UnixSocket(PathBuf::from("/run/garage/s3.sock"))
+		);
+		assert!(parse("unix:").is_err());
+		assert!(parse("/run/garage/s3.sock").is_err());
+		assert!(parse("localhost").is_err());
+	}
+
+	#[test]
+	fn test_parse_data_dirs() {
+		#[derive(Deserialize)]
+		struct DataDirs {
+			data_dir: DataDirEnum,
+		}
+
+		let single = toml::from_str::<DataDirs>(r#"data_dir = "/var/lib/garage/data""#).unwrap();
+		assert!(
+			matches!(single.data_dir, DataDirEnum::Single(p) if p.to_str() == Some("/var/lib/garage/data"))
+		);
+
+		let multiple = toml::from_str::<DataDirs>(
+			r#"
+			data_dir = [
+				{ path = "/mnt/hdd1", capacity = "2T" },
+				{ path = "/mnt/hdd2", read_only = true },
+			]
+			"#,
+		)
+		.unwrap();
+		match multiple.data_dir {
+			DataDirEnum::Multiple(dirs) => {
+				assert_eq!(dirs.len(), 2);
+				assert_eq!(dirs[0].path, PathBuf::from("/mnt/hdd1"));
+				assert_eq!(dirs[0].capacity.as_deref(), Some("2T"));
+				assert!(!dirs[0].read_only);
+				assert_eq!(dirs[1].capacity, None);
+				assert!(dirs[1].read_only);
+			}
+			_ => panic!("expected multiple data directories"),
+		}
+	}
+
+	#[test]
+	fn test_parse_client_addr_config() {
+		let config = toml::from_str::<WebConfig>(
+			r#"
+			bind_addr = "unix:/run/garage/web.sock"
+			unix_socket_mode = 0o660
+			root_domain = ".web.garage"
+			proxy_protocol = true
+			trusted_proxies = ["10.0.0.0/8", "fc00::1"]
+			"#,
+		)
+		.unwrap();
+		assert_eq!(config.unix_socket_mode, Some(0o660));
+		assert!(config.client_addr.proxy_protocol);
+		assert_eq!(
+			config.client_addr.trusted_proxies,
+			vec![
+				"10.0.0.0/8".parse::<IpNetwork>().unwrap(),
+				"fc00::1/128".parse::<IpNetwork>().unwrap()
+			]
+		);
+
+		let config = toml::from_str::<K2VApiConfig>(r#"api_bind_addr = "[::]:3904""#).unwrap();
+		assert!(!config.client_addr.proxy_protocol);
+		assert!(config.client_addr.trusted_proxies.is_empty());
+	}
+}
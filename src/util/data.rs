@@ -31,7 +31,12 @@ impl<'de> Visitor<'de> for FixedBytes32Visitor {
 	type Value = FixedBytes32;
 
 	fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-		formatter.write_str("a byte slice of size 32")
+		formatter.write_str("a byte slice of size 32 or its hexadecimal representation")
+	}
+
+	fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+		let bytes = hex::decode(value).map_err(E::custom)?;
+		self.visit_bytes(&bytes[..])
 	}
 
 	fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
@@ -50,13 +55,23 @@ impl<'de> Visitor<'de> for FixedBytes32Visitor {
 
 impl<'de> Deserialize<'de> for FixedBytes32 {
 	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FixedBytes32, D::Error> {
-		deserializer.deserialize_bytes(FixedBytes32Visitor)
+		if deserializer.is_human_readable() {
+			deserializer.deserialize_str(FixedBytes32Visitor)
+		} else {
+			deserializer.deserialize_bytes(FixedBytes32Visitor)
+		}
 	}
 }
 
+/// Binary formats such as MessagePack store the raw bytes, human-readable
+/// formats such as JSON use their hexadecimal representation
 impl Serialize for FixedBytes32 {
 	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-		serializer.serialize_bytes(&self.0[..])
+		if serializer.is_human_readable() {
+			serializer.serialize_str(&hex::encode(&self.0[..]))
+		} else {
+			serializer.serialize_bytes(&self.0[..])
+		}
 	}
 }
 
@@ -171,3 +186,22 @@ pub fn debug_serialize<T: Serialize>(x: T) -> String {
 		Err(e) => format!("<JSON serialization error: {}>", e),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fixed_bytes_serialization() {
+		let hash = blake2sum(b"garage");
+
+		let bin = rmp_to_vec_all_named(&hash).unwrap();
+		assert_eq!(bin.len(), 34);
+		assert_eq!(&bin[2..], hash.as_slice());
+		assert_eq!(rmp_serde::decode::from_read_ref::<_, Hash>(&bin).unwrap(), hash);
+
+		let json = serde_json::to_string(&hash).unwrap();
+		assert_eq!(json, format!("\"{}\"", hex::encode(hash.as_slice())));
+		assert_eq!(serde_json::from_str::<Hash>(&json).unwrap(), hash);
+	}
+}
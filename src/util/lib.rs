@@ -11,6 +11,7 @@ pub mod data;
 pub mod error;
 pub mod formater;
 pub mod metrics;
+pub mod net;
 pub mod persister;
 pub mod time;
 pub mod token_bucket;
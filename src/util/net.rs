@@ -0,0 +1,26 @@
+//! Module containing helper functions to manipulate network addresses
+use std::net::IpAddr;
+
+/// Convert an IPv4-mapped IPv6 address (which is how IPv4 clients appear
+/// on a dual-stack socket) to the IPv4 address, leaving others unchanged
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+	match ip {
+		IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+		IpAddr::V4(_) => ip,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_canonical_ip() {
+		let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+		assert_eq!(canonical_ip(ip("::ffff:10.0.0.1")), ip("10.0.0.1"));
+		assert_eq!(canonical_ip(ip("10.0.0.1")), ip("10.0.0.1"));
+		assert_eq!(canonical_ip(ip("2001:db8::1")), ip("2001:db8::1"));
+		// IPv4-compatible addresses are not IPv4-mapped addresses
+		assert_eq!(canonical_ip(ip("::10.0.0.1")), ip("::10.0.0.1"));
+	}
+}
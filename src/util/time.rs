@@ -1,5 +1,6 @@
 //! Module containing helper functions to manipulate time
-use chrono::{SecondsFormat, TimeZone, Utc};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use std::convert::TryFrom;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Returns milliseconds since UNIX Epoch
@@ -28,3 +29,10 @@ pub fn msec_to_rfc3339(msecs: u64) -> String {
 	let timestamp = Utc.timestamp(secs, nanos);
 	timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
 }
+
+/// Parse a RFC3339 representation of a date, such as "2021-01-01T12:30:00Z",
+/// to a timestamp represented as milliseconds since UNIX Epoch
+pub fn rfc3339_to_msec(s: &str) -> Option<u64> {
+	let timestamp = DateTime::parse_from_rfc3339(s).ok()?;
+	u64::try_from(timestamp.timestamp_millis()).ok()
+}
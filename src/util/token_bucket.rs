@@ -5,6 +5,8 @@ use tokio::time::sleep;
 pub struct TokenBucket {
 	// Replenish rate: number of tokens per second
 	replenish_rate: u64,
+	// Maximum number of tokens that can be accumulated
+	capacity: u64,
 	// Current number of tokens
 	tokens: u64,
 	// Last replenish time
@@ -15,26 +17,95 @@ impl TokenBucket {
 	pub fn new(replenish_rate: u64) -> Self {
 		Self {
 			replenish_rate,
+			capacity: u64::MAX,
 			tokens: 0,
 			last_replenish: Instant::now(),
 		}
 	}
 
+	/// Create a token bucket that starts full and that never holds
+	/// more than `capacity` tokens, allowing bursts of at most that size.
+	pub fn new_with_capacity(replenish_rate: u64, capacity: u64) -> Self {
+		Self {
+			replenish_rate,
+			capacity,
+			tokens: capacity,
+			last_replenish: Instant::now(),
+		}
+	}
+
 	pub async fn take(&mut self, tokens: u64) {
-		while self.tokens < tokens {
-			let needed = tokens - self.tokens;
-			let delay = (needed as f64) / (self.replenish_rate as f64);
-			sleep(Duration::from_secs_f64(delay)).await;
+		// Tokens are taken in several steps if more than the capacity
+		// of the bucket is requested at once
+		let mut needed = tokens;
+		loop {
 			self.replenish();
+			let taken = std::cmp::min(self.tokens, needed);
+			self.tokens -= taken;
+			needed -= taken;
+			if needed == 0 || self.replenish_rate == 0 {
+				// A bucket that is never replenished would make us wait forever
+				break;
+			}
+			let wait_for = std::cmp::min(needed, self.capacity);
+			let delay = (wait_for as f64) / (self.replenish_rate as f64);
+			sleep(Duration::from_secs_f64(delay)).await;
+		}
+	}
+
+	/// Take tokens if they are immediately available, returns false otherwise
+	pub fn try_take(&mut self, tokens: u64) -> bool {
+		self.replenish();
+		if self.tokens >= tokens {
+			self.tokens -= tokens;
+			true
+		} else {
+			false
 		}
-		self.tokens -= tokens;
 	}
 
 	pub fn replenish(&mut self) {
 		let now = Instant::now();
 		let new_tokens =
 			((now - self.last_replenish).as_secs_f64() * (self.replenish_rate as f64)) as u64;
-		self.tokens += new_tokens;
-		self.last_replenish = now;
+		self.tokens = std::cmp::min(self.tokens.saturating_add(new_tokens), self.capacity);
+		if self.tokens == self.capacity || self.replenish_rate == 0 {
+			self.last_replenish = now;
+		} else {
+			// Only count the time corresponding to whole tokens, so that
+			// frequent calls do not lose the fractional part
+			self.last_replenish +=
+				Duration::from_secs_f64(new_tokens as f64 / self.replenish_rate as f64);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_try_take_capacity() {
+		let mut bucket = TokenBucket::new_with_capacity(1, 3);
+		assert!(bucket.try_take(2));
+		assert!(bucket.try_take(1));
+		assert!(!bucket.try_take(1));
+	}
+
+	#[tokio::test]
+	async fn test_take_more_than_capacity() {
+		let mut bucket = TokenBucket::new_with_capacity(1000, 10);
+		let start = Instant::now();
+		bucket.take(60).await;
+		let elapsed = Instant::now() - start;
+		assert!(elapsed >= Duration::from_millis(45));
+		assert!(!bucket.try_take(10));
+	}
+
+	#[tokio::test]
+	async fn test_take_zero_rate() {
+		let mut bucket = TokenBucket::new_with_capacity(0, 1);
+		bucket.take(1000).await;
+		assert!(!bucket.try_take(1));
 	}
 }
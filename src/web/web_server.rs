@@ -1,12 +1,10 @@
-use std::{borrow::Cow, convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{borrow::Cow, convert::Infallible, sync::Arc};
 
 use futures::future::Future;
 
 use hyper::{
 	header::{HeaderValue, HOST},
-	server::conn::AddrStream,
-	service::{make_service_fn, service_fn},
-	Body, Method, Request, Response, Server,
+	Body, Method, Request, Response,
 };
 
 use opentelemetry::{
@@ -18,7 +16,10 @@ use opentelemetry::{
 
 use crate::error::*;
 
+use garage_api::client_addr::client_ip;
+use garage_api::generic_server::serve_http;
 use garage_api::helpers::{authority_to_host, host_to_bucket};
+use garage_api::rate_limit::{RateLimitScope, RateLimiter, RequestClass, Transfer};
 use garage_api::s3::cors::{add_cors_headers, find_matching_cors_rule, handle_options_for_bucket};
 use garage_api::s3::error::{
 	CommonErrorDerivative, Error as ApiError, OkOrBadRequest, OkOrInternalError,
@@ -28,6 +29,7 @@ use garage_api::s3::get::{handle_get, handle_head};
 use garage_model::garage::Garage;
 
 use garage_table::*;
+use garage_util::config::{ClientAddrConfig, UnixOrTCPSocketAddress};
 use garage_util::error::Error as GarageError;
 use garage_util::metrics::{gen_trace_id, RecordDuration};
 
@@ -61,14 +63,18 @@ pub struct WebServer {
 	garage: Arc<Garage>,
 	metrics: Arc<WebMetrics>,
 	root_domain: String,
+	rate_limiter: Arc<RateLimiter>,
 }
 
 impl WebServer {
 	/// Run a web server
 	pub async fn run(
 		garage: Arc<Garage>,
-		addr: SocketAddr,
+		addr: UnixOrTCPSocketAddress,
+		unix_socket_mode: Option<u32>,
+		client_addr_config: ClientAddrConfig,
 		root_domain: String,
+		rate_limiter: Arc<RateLimiter>,
 		shutdown_signal: impl Future<Output = ()>,
 	) -> Result<(), GarageError> {
 		let metrics = Arc::new(WebMetrics::new());
@@ -76,33 +82,25 @@ impl WebServer {
 			garage,
 			metrics,
 			root_domain,
+			rate_limiter,
 		});
 
-		let service = make_service_fn(|conn: &AddrStream| {
-			let web_server = web_server.clone();
+		info!("Web server listening on {}", addr);
 
-			let client_addr = conn.remote_addr();
-			async move {
-				Ok::<_, Error>(service_fn(move |req: Request<Body>| {
-					let web_server = web_server.clone();
-
-					web_server.handle_request(req, client_addr)
-				}))
-			}
-		});
-
-		let server = Server::bind(&addr).serve(service);
-		let graceful = server.with_graceful_shutdown(shutdown_signal);
-		info!("Web server listening on http://{}", addr);
-
-		graceful.await?;
-		Ok(())
+		serve_http(
+			&addr,
+			unix_socket_mode,
+			client_addr_config,
+			move |req, client_addr| web_server.clone().handle_request(req, client_addr),
+			shutdown_signal,
+		)
+		.await
 	}
 
 	async fn handle_request(
 		self: Arc<Self>,
 		req: Request<Body>,
-		addr: SocketAddr,
+		addr: String,
 	) -> Result<Response<Body>, Infallible> {
 		info!("{} {} {}", addr, req.method(), req.uri());
 
@@ -114,6 +112,10 @@ impl WebServer {
 			.with_attributes(vec![
 				KeyValue::new("method", format!("{}", req.method())),
 				KeyValue::new("uri", req.uri().to_string()),
+				KeyValue::new(
+					"client_ip",
+					client_ip(&req).map(|ip| ip.to_string()).unwrap_or_default(),
+				),
 			])
 			.start(&tracer);
 
@@ -201,10 +203,28 @@ impl WebServer {
 			bucket_name, bucket_id, key
 		);
 
+		// Website requests are subject to the limits of the bucket they read from
+		let bucket_scope = RateLimitScope::Bucket(bucket_id);
+		let bucket_limits = bucket
+			.params()
+			.map(|p| *p.rate_limits.get())
+			.unwrap_or_default();
+		if !self
+			.rate_limiter
+			.check_request(&bucket_scope, &bucket_limits, RequestClass::Read)
+		{
+			return Err(ApiError::SlowDown.into());
+		}
+		let throttle = self
+			.rate_limiter
+			.bandwidth_throttle(&[(bucket_scope, &bucket_limits)], Transfer::Download);
+
 		let ret_doc = match *req.method() {
 			Method::OPTIONS => handle_options_for_bucket(req, &bucket),
 			Method::HEAD => handle_head(self.garage.clone(), req, bucket_id, &key, None).await,
-			Method::GET => handle_get(self.garage.clone(), req, bucket_id, &key, None).await,
+			Method::GET => {
+				handle_get(self.garage.clone(), req, bucket_id, &key, None, &throttle).await
+			}
 			_ => Err(ApiError::bad_request("HTTP method not supported")),
 		}
 		.map_err(Error::from);
@@ -235,7 +255,15 @@ impl WebServer {
 					.body(Body::empty())
 					.unwrap();
 
-				match handle_get(self.garage.clone(), &req2, bucket_id, &error_document, None).await
+				match handle_get(
+					self.garage.clone(),
+					&req2,
+					bucket_id,
+					&error_document,
+					None,
+					&throttle,
+				)
+				.await
 				{
 					Ok(mut error_doc) => {
 						// The error won't be logged back in handle_request,